@@ -47,10 +47,19 @@ pub const PROTOCOL_FEE_FIXED_DEFAULT: I80F48 = I80F48!(0.01);
 pub const PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG: u64 = 1 << 2;
 
 pub const PYTH_PUSH_MIGRATED: u8 = 1 << 0;
+/// While the oracle's spot price deviates from its EMA by more than `oracle_max_deviation`, value
+/// assets at the lower and liabilities at the higher of the two.
+pub const ORACLE_DEVIATION_WORSE_PRICE: u8 = 1 << 1;
+/// `liquidation_liquidator_fee` was configured, so it applies as-is even when 0
+pub const LIQUIDATOR_FEE_CONFIGURED: u8 = 1 << 2;
+/// `liquidation_insurance_fee` was configured, so it applies as-is even when 0
+pub const INSURANCE_FEE_CONFIGURED: u8 = 1 << 3;
 
 // Some of the Bank's configurations are frozen and cannot be changed.
 pub const FREEZE_SETTINGS: u64 = 1 << 3;
 pub const CLOSE_ENABLED_FLAG: u64 = 1 << 4;
+// Interest compounds continuously instead of accruing as simple interest between updates.
+pub const COMPOUND_INTEREST_FLAG: u64 = 1 << 5;
 
 pub const MIN_PYTH_PUSH_VERIFICATION_LEVEL: VerificationLevel = VerificationLevel::Full;
 
@@ -60,8 +69,17 @@ pub const ZERO_AMOUNT_THRESHOLD: I80F48 = I80F48!(0.0001);
 /// This is USD denominated, so 0.001 = $0.1
 pub const BANKRUPT_THRESHOLD: I80F48 = I80F48!(0.1);
 
+/// Default liquidator fee, for banks that never configured `liquidation_liquidator_fee`
 pub const LIQUIDATION_LIQUIDATOR_FEE: I80F48 = I80F48!(0.025);
+/// Default insurance fee, for banks that never configured `liquidation_insurance_fee`
 pub const LIQUIDATION_INSURANCE_FEE: I80F48 = I80F48!(0.025);
+/// Liquidator and insurance fees combined can't exceed this fraction of the seized collateral
+pub const MAX_LIQUIDATION_FEES: I80F48 = I80F48!(0.5);
+/// Default unhealthiness at which a bank's ramped liquidator fee hits its cap, for banks that
+/// don't configure `liquidation_fee_ramp`
+pub const LIQUIDATION_FEE_RAMP: I80F48 = I80F48!(0.1);
+/// Deleveraging can't cost more than a default liquidation
+pub const MAX_DELEVERAGE_INSURANCE_FEE: I80F48 = I80F48!(0.05);
 
 /// Any balance below 1 SPL token amount is treated as none,
 /// this is to account for any artifacts resulting from binary fraction arithemtic.
@@ -71,22 +89,48 @@ pub const EMISSIONS_FLAG_BORROW_ACTIVE: u64 = 1 << 0;
 pub const EMISSIONS_FLAG_LENDING_ACTIVE: u64 = 1 << 1;
 pub(crate) const EMISSIONS_FLAGS: u64 =
     EMISSIONS_FLAG_BORROW_ACTIVE | EMISSIONS_FLAG_LENDING_ACTIVE;
-pub(crate) const GROUP_FLAGS: u64 =
-    PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG | FREEZE_SETTINGS | CLOSE_ENABLED_FLAG;
+pub(crate) const GROUP_FLAGS: u64 = PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG
+    | FREEZE_SETTINGS
+    | CLOSE_ENABLED_FLAG
+    | COMPOUND_INTEREST_FLAG;
 
 pub const SECONDS_PER_YEAR: I80F48 = I80F48!(31_536_000);
 
+/// The original three-parameter curve (optimal utilization, plateau rate, max rate)
+pub const INTEREST_CURVE_LEGACY: u8 = 0;
+/// A piecewise linear curve with up to `CURVE_POINTS` kinks between 0% and 100% utilization
+pub const INTEREST_CURVE_MULTI_POINT: u8 = 1;
+pub const CURVE_POINTS: usize = 5;
+
 pub const CONF_INTERVAL_MULTIPLE: I80F48 = I80F48!(2.12);
 pub const MAX_CONF_INTERVAL: I80F48 = I80F48!(0.05);
 
 pub const U32_MAX: I80F48 = I80F48!(4_294_967_295);
 pub const U32_MAX_DIV_10: I80F48 = I80F48!(429_496_730);
 pub const STD_DEV_MULTIPLE: I80F48 = I80F48!(1.96);
+/// Bounds for a bank's configured confidence multiples, see `OracleConfidenceSettings`
+pub const MIN_CONF_MULTIPLE: I80F48 = I80F48!(1);
+pub const MAX_CONF_MULTIPLE: I80F48 = I80F48!(10);
+/// Widest confidence interval cap a bank can configure, see `OracleConfidenceSettings`
+pub const MAX_CONF_INTERVAL_LIMIT: I80F48 = I80F48!(0.25);
+/// Fewest Wormhole guardian signatures a bank can accept on a partially verified Pyth update, see
+/// `OracleConfidenceSettings`
+pub const MIN_PYTH_PUSH_PARTIAL_SIGNATURES: u8 = 5;
 
 /// Due to real-world constraints, oracles using an age less than this value are typically too
 /// unreliable, and we want to restrict pools from picking an oracle that is effectively unusable
 pub const ORACLE_MIN_AGE: u16 = 30;
 pub const MAX_PYTH_ORACLE_AGE: u64 = 60;
+/// Default stale-price haircut, for banks that don't configure `oracle_stale_liquidation_haircut`
+pub const ORACLE_STALE_LIQUIDATION_HAIRCUT: I80F48 = I80F48!(0.05);
+
+/// `OracleSetup::Median` sources live in `oracle_keys[0..MAX_MEDIAN_ORACLE_SOURCES]`
+pub const MIN_MEDIAN_ORACLE_SOURCES: usize = 2;
+pub const MAX_MEDIAN_ORACLE_SOURCES: usize = 3;
+
+/// Time constant, in seconds, of the oracle EMA the program maintains for Switchboard pull banks,
+/// see `Bank::oracle_ema_price`. A price held for this long moves the EMA halfway to it.
+pub const ORACLE_EMA_PERIOD: i64 = 3600;
 
 pub const MAX_EXP_10_I80F48: usize = 24;
 pub const EXP_10_I80F48: [I80F48; MAX_EXP_10_I80F48] = [
@@ -147,6 +191,9 @@ cfg_if::cfg_if! {
     }
 }
 
+/// The multi-validator SPL stake pool program (jitoSOL and others)
+pub const SPL_STAKE_POOL_ID: Pubkey = pubkey!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+
 /// Staked SOL assets. Accounts with a STAKED position can only deposit other STAKED assets or SOL
 /// (`ASSET_TAG_SOL`) and can only borrow SOL (`ASSET_TAG_SOL`)
 pub const ASSET_TAG_STAKED: u8 = 2;
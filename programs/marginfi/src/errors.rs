@@ -171,6 +171,38 @@ pub enum MarginfiError {
     BankCannotClose,
     #[msg("Account already migrated")] // 6082
     AccountAlreadyMigrated,
+    #[msg("Liquidation repays more of the liability than the close factor allows")] // 6083
+    LiquidationCloseFactorExceeded,
+    #[msg("Batch liquidation must have at least one leg")] // 6084
+    EmptyLiquidationBatch,
+    #[msg("Deleverage must improve account health")] // 6085
+    WorseHealthPostDeleverage,
+    #[msg("Fixed-term borrowing is disabled for this bank")] // 6086
+    FixedTermBorrowingDisabled,
+    #[msg("Invalid fixed-term duration")] // 6087
+    InvalidFixedTermDuration,
+    #[msg("Account already has a fixed-term borrow in this bank")] // 6088
+    FixedTermPositionExists,
+    #[msg("Account has a matured fixed-term borrow that must be repaid or rolled")] // 6089
+    FixedTermPositionMatured,
+    #[msg("Fixed-term borrow has not matured yet")] // 6090
+    FixedTermPositionNotMatured,
+    #[msg("Too many median oracle sources are stale or invalid")] // 6091
+    OracleMedianInsufficientSources,
+    #[msg("Oracle spot price deviates too far from its EMA")] // 6092
+    OraclePriceDeviationExceeded,
+    #[msg("Oracle price is outside the bank's configured bounds")] // 6093
+    OraclePriceOutOfBounds,
+    #[msg("Exchange rate account has not been updated recently enough")] // 6094
+    ExchangeRateStale,
+    #[msg("Exchange rate moved more than allowed since it was last recorded")] // 6095
+    ExchangeRateChangeExceeded,
+    #[msg("Account is already extended")] // 6096
+    AccountAlreadyExtended,
+    #[msg("Account extension is missing or malformed")] // 6097
+    InvalidAccountExtension,
+    #[msg("Fixed-term rate exceeds the maximum that can be locked in")] // 6098
+    FixedTermRateTooHigh,
 }
 
 impl From<MarginfiError> for ProgramError {
@@ -288,6 +320,22 @@ impl From<u32> for MarginfiError {
             6080 => MarginfiError::OracleMaxConfidenceExceeded,
             6081 => MarginfiError::BankCannotClose,
             6082 => MarginfiError::AccountAlreadyMigrated,
+            6083 => MarginfiError::LiquidationCloseFactorExceeded,
+            6084 => MarginfiError::EmptyLiquidationBatch,
+            6085 => MarginfiError::WorseHealthPostDeleverage,
+            6086 => MarginfiError::FixedTermBorrowingDisabled,
+            6087 => MarginfiError::InvalidFixedTermDuration,
+            6088 => MarginfiError::FixedTermPositionExists,
+            6089 => MarginfiError::FixedTermPositionMatured,
+            6090 => MarginfiError::FixedTermPositionNotMatured,
+            6091 => MarginfiError::OracleMedianInsufficientSources,
+            6092 => MarginfiError::OraclePriceDeviationExceeded,
+            6093 => MarginfiError::OraclePriceOutOfBounds,
+            6094 => MarginfiError::ExchangeRateStale,
+            6095 => MarginfiError::ExchangeRateChangeExceeded,
+            6096 => MarginfiError::AccountAlreadyExtended,
+            6097 => MarginfiError::InvalidAccountExtension,
+            6098 => MarginfiError::FixedTermRateTooHigh,
             _ => MarginfiError::InternalLogicError,
         }
     }
@@ -325,6 +373,11 @@ impl MarginfiError {
                 | MarginfiError::MissingPythOrBankAccount
                 | MarginfiError::PythPushInvalidWindowSize
                 | MarginfiError::OracleMaxConfidenceExceeded
+                | MarginfiError::OracleMedianInsufficientSources
+                | MarginfiError::OraclePriceDeviationExceeded
+                | MarginfiError::OraclePriceOutOfBounds
+                | MarginfiError::ExchangeRateStale
+                | MarginfiError::ExchangeRateChangeExceeded
         )
     }
 
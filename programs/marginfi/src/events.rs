@@ -1,6 +1,9 @@
 use crate::{
     instructions::marginfi_group::StakedSettingsEditConfig,
-    state::{health_cache::HealthCache, marginfi_group::BankConfigOpt},
+    state::{
+        health_cache::{HealthCache, HealthCacheBalance},
+        marginfi_group::BankConfigOpt,
+    },
 };
 use anchor_lang::prelude::*;
 
@@ -10,7 +13,7 @@ pub struct GroupEventHeader {
     pub marginfi_group: Pubkey,
 }
 
-#[derive(AnchorDeserialize, AnchorSerialize)]
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
 pub struct AccountEventHeader {
     pub signer: Option<Pubkey>,
     pub marginfi_account: Pubkey,
@@ -65,6 +68,13 @@ pub struct LendingPoolBankConfigureOracleEvent {
     pub oracle: Pubkey,
 }
 
+#[event]
+pub struct LendingPoolBankConfigureOracleFallbackEvent {
+    pub header: GroupEventHeader,
+    pub bank: Pubkey,
+    pub fallback: Pubkey,
+}
+
 #[event]
 pub struct LendingPoolBankCollectFeesEvent {
     pub header: GroupEventHeader,
@@ -106,6 +116,11 @@ pub struct MarginfiAccountCreateEvent {
     pub header: AccountEventHeader,
 }
 
+#[event]
+pub struct MarginfiAccountExtendEvent {
+    pub header: AccountEventHeader,
+}
+
 #[event]
 pub struct LendingAccountDepositEvent {
     pub header: AccountEventHeader,
@@ -153,6 +168,114 @@ pub struct LendingAccountLiquidateEvent {
     pub liquidatee_post_health: f64,
     pub pre_balances: LiquidationBalances,
     pub post_balances: LiquidationBalances,
+    /// Fraction of the seized collateral's value paid to the liquidator
+    pub liquidator_fee: f64,
+    /// Fraction of the seized collateral's value paid to the insurance fund
+    pub insurance_fee: f64,
+}
+
+/// One (asset bank, liability bank) leg of a batch liquidation
+#[event]
+pub struct LendingAccountLiquidateLegEvent {
+    pub header: AccountEventHeader,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub asset_bank: Pubkey,
+    pub asset_mint: Pubkey,
+    pub liability_bank: Pubkey,
+    pub liability_mint: Pubkey,
+    pub pre_balances: LiquidationBalances,
+    pub post_balances: LiquidationBalances,
+    /// Fraction of the seized collateral's value paid to the liquidator
+    pub liquidator_fee: f64,
+    /// Fraction of the seized collateral's value paid to the insurance fund
+    pub insurance_fee: f64,
+}
+
+/// Emitted once per batch liquidation, after every leg's `LendingAccountLiquidateLegEvent`
+#[event]
+pub struct LendingAccountLiquidateBatchEvent {
+    pub header: AccountEventHeader,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub liquidatee_marginfi_account_authority: Pubkey,
+    pub liquidatee_pre_health: f64,
+    pub liquidatee_post_health: f64,
+    pub legs: u8,
+}
+
+/// A liquidation paid for directly with tokens. The header's account is the liquidatee's, the
+/// header's signer is the liquidator.
+#[event]
+pub struct LendingAccountLiquidateDirectEvent {
+    pub header: AccountEventHeader,
+    pub asset_bank: Pubkey,
+    pub asset_mint: Pubkey,
+    pub liability_bank: Pubkey,
+    pub liability_mint: Pubkey,
+    pub liquidatee_pre_health: f64,
+    pub liquidatee_post_health: f64,
+    /// Collateral paid out to the liquidator, in native asset tokens
+    pub asset_amount: u64,
+    /// Paid in by the liquidator, in native liability tokens
+    pub liability_amount: u64,
+    pub liquidatee_asset_pre_balance: f64,
+    pub liquidatee_asset_post_balance: f64,
+    pub liquidatee_liability_pre_balance: f64,
+    pub liquidatee_liability_post_balance: f64,
+    /// Fraction of the seized collateral's value paid to the liquidator
+    pub liquidator_fee: f64,
+    /// Fraction of the seized collateral's value paid to the insurance fund
+    pub insurance_fee: f64,
+}
+
+#[event]
+pub struct LendingAccountDeleverageEvent {
+    pub header: AccountEventHeader,
+    pub asset_bank: Pubkey,
+    pub asset_mint: Pubkey,
+    pub liability_bank: Pubkey,
+    pub liability_mint: Pubkey,
+    pub pre_health: f64,
+    pub post_health: f64,
+    /// Collateral withdrawn, in native asset tokens
+    pub asset_amount: u64,
+    /// Paid in by the owner, in native liability tokens
+    pub liability_amount: u64,
+    /// Liability repaid after the insurance fee, in native liability tokens
+    pub liability_repaid: f64,
+    /// Fraction of the repayment paid to the insurance fund
+    pub insurance_fee: f64,
+}
+
+#[event]
+pub struct LendingAccountBorrowFixedTermEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Locked APR in basis points
+    pub rate_bps: u16,
+    pub maturity: u32,
+}
+
+#[event]
+pub struct LendingAccountRepayFixedTermEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub close_balance: bool,
+}
+
+#[event]
+pub struct LendingAccountRollFixedTermEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    /// Amount owed when rolled, which becomes the new principal
+    pub amount: u64,
+    /// Locked APR in basis points
+    pub rate_bps: u16,
+    pub maturity: u32,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -175,4 +298,7 @@ pub struct MarginfiAccountTransferToNewAccount {
 pub struct HealthPulseEvent {
     pub account: Pubkey,
     pub health_cache: HealthCache,
+    /// How each active balance was valued, see `HealthCacheBalance`. The only place this is
+    /// available for standard accounts, which have no room to store it.
+    pub balances: Vec<HealthCacheBalance>,
 }
@@ -7,8 +7,11 @@ use crate::{
     prelude::MarginfiResult,
     state::marginfi_group::MarginfiGroup,
     state::{
-        health_cache::HealthCache,
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, ACCOUNT_DISABLED},
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, RiskEngine,
+            ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, BankVaultType},
     },
     utils::{calculate_pre_fee_spl_deposit_amount, maybe_take_bank_mint, validate_asset_tags},
@@ -16,7 +19,6 @@ use crate::{
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{clock::Clock, sysvar::Sysvar};
 use anchor_spl::token_interface::{TokenAccount, TokenInterface};
-use bytemuck::Zeroable;
 use fixed::types::I80F48;
 
 /// 1. Accrue interest
@@ -47,7 +49,7 @@ pub fn lending_account_borrow<'info>(
         token_program.key,
     )?;
 
-    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
     let group = &marginfi_group_loader.load()?;
     let program_fee_rate: I80F48 = group.fee_state_cache.program_fee_rate.into();
 
@@ -67,7 +69,7 @@ pub fn lending_account_borrow<'info>(
     {
         let mut bank = bank_loader.load_mut()?;
 
-        validate_asset_tags(&bank, &marginfi_account)?;
+        validate_asset_tags(&bank, marginfi_account.balances())?;
 
         let liquidity_vault_authority_bump = bank.liquidity_vault_authority_bump;
         let origination_fee_rate: I80F48 = bank
@@ -79,7 +81,7 @@ pub fn lending_account_borrow<'info>(
         let mut bank_account = BankAccountWrapper::find_or_create(
             &bank_loader.key(),
             &mut bank,
-            &mut marginfi_account.lending_account,
+            marginfi_account.balances_mut(),
         )?;
 
         // User needs to borrow amount + fee to receive amount
@@ -137,51 +139,26 @@ pub fn lending_account_borrow<'info>(
     } // release mutable borrow of bank
 
     // The program and/or group fee account gains the origination fee
-    {
-        let mut bank = bank_loader.load_mut()?;
-
-        if !origination_fee.is_zero() {
-            let mut bank_fees_after: I80F48 = bank.collected_group_fees_outstanding.into();
-
-            if !program_fee_rate.is_zero() {
-                // Some portion of the origination fee to goes to program fees
-                let program_fee_amount: I80F48 = origination_fee
-                    .checked_mul(program_fee_rate)
-                    .ok_or_else(math_error!())?;
-                // The remainder of the origination fee goes to group fees
-                bank_fees_after = bank_fees_after
-                    .saturating_add(origination_fee.saturating_sub(program_fee_amount));
-
-                // Update the bank's program fees
-                let program_fees_before: I80F48 = bank.collected_program_fees_outstanding.into();
-                bank.collected_program_fees_outstanding = program_fees_before
-                    .saturating_add(program_fee_amount)
-                    .into();
-            } else {
-                // If program fee rate is zero, add the full origination fee to group fees
-                bank_fees_after = bank_fees_after.saturating_add(origination_fee);
-            }
-
-            // Update the bank's group fees
-            bank.collected_group_fees_outstanding = bank_fees_after.into();
-        }
-    }
+    bank_loader
+        .load_mut()?
+        .collect_origination_fee(origination_fee, program_fee_rate)?;
 
-    let mut health_cache = HealthCache::zeroed();
+    let mut health_cache = ExtendedHealthCache::zeroed();
     health_cache.timestamp = clock.unix_timestamp;
-    marginfi_account.lending_account.sort_balances();
+    marginfi_account.sort_balances();
 
     // Check account health, if below threshold fail transaction
     // Assuming `ctx.remaining_accounts` holds only oracle accounts
     let (risk_result, _engine) = RiskEngine::check_account_init_health(
         &marginfi_account,
+        marginfi_account.balances(),
         ctx.remaining_accounts,
         &mut Some(&mut health_cache),
     );
     risk_result?;
     health_cache.program_version = PROGRAM_VERSION;
     health_cache.set_engine_ok(true);
-    marginfi_account.health_cache = health_cache;
+    marginfi_account.set_health_cache(&health_cache);
 
     Ok(())
 }
@@ -1,11 +1,14 @@
 use crate::{
-    check, errors::MarginfiError, prelude::*, state::marginfi_account::MarginfiAccount,
+    check,
+    errors::MarginfiError,
+    prelude::*,
+    state::marginfi_account::{MarginfiAccount, MarginfiAccountLoader},
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
 
 pub fn close_account(ctx: Context<MarginfiAccountClose>) -> MarginfiResult {
-    let marginfi_account = &ctx.accounts.marginfi_account.load()?;
+    let marginfi_account = &ctx.accounts.marginfi_account.load_data()?;
 
     check!(
         marginfi_account.can_be_closed(),
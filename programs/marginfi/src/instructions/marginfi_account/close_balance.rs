@@ -4,7 +4,9 @@ use crate::{
     check,
     errors::MarginfiError,
     state::{
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, ACCOUNT_DISABLED},
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, MarginfiGroup},
     },
     MarginfiResult,
@@ -18,7 +20,7 @@ pub fn lending_account_close_balance(ctx: Context<LendingAccountCloseBalance>) -
         ..
     } = ctx.accounts;
 
-    let mut marginfi_account = marginfi_account.load_mut()?;
+    let mut marginfi_account = marginfi_account.load_data_mut()?;
     let mut bank = bank_loader.load_mut()?;
 
     check!(
@@ -36,12 +38,14 @@ pub fn lending_account_close_balance(ctx: Context<LendingAccountCloseBalance>) -
 
     bank.update_bank_cache(group)?;
 
-    let lending_account = &mut marginfi_account.lending_account;
-    let mut bank_account =
-        BankAccountWrapper::find(&bank_loader.key(), &mut bank, lending_account)?;
+    let mut bank_account = BankAccountWrapper::find(
+        &bank_loader.key(),
+        &mut bank,
+        marginfi_account.balances_mut(),
+    )?;
 
     bank_account.close_balance()?;
-    lending_account.sort_balances();
+    marginfi_account.sort_balances();
 
     Ok(())
 }
@@ -0,0 +1,376 @@
+use crate::constants::{
+    INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED,
+};
+use crate::events::{AccountEventHeader, LendingAccountDeleverageEvent};
+use crate::state::marginfi_account::{
+    RemainingAccountsIndex, RiskEngine, RiskRequirementType, ACCOUNT_DISABLED,
+};
+use crate::state::marginfi_group::{Bank, BankVaultType, MarginfiGroup};
+use crate::{
+    bank_signer, check,
+    errors::MarginfiError,
+    instructions::marginfi_account::{calc_liquidation_amounts_with_fees, LiquidationAmounts},
+    math_error,
+    prelude::*,
+    state::marginfi_account::{BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader},
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::sysvar::Sysvar;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+
+/// Lets an account owner reduce their leverage in one step, without a flashloan and without
+/// paying a liquidation fee, even if the account is below the initial (or maintenance)
+/// requirement. The two banks can hold different tokens, e.g. SOL collateral against a USDC
+/// liability, with the owner swapping in between (or holding the liability token already):
+///
+/// 1. The owner repays the liability from their token account
+/// 2. The owner withdraws `asset_amount` of collateral worth the repayment at oracle price, less
+///    the group's `deleverage_insurance_fee`, which goes to the liability bank's insurance fund
+/// 3. The account's maintenance health must improve
+///
+/// Remaining accounts: liability mint (Token-2022 only), asset mint (Token-2022 only), then the
+/// account's risk accounts in any order, which include both banks' oracles.
+pub fn lending_account_deleverage<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountDeleverage<'info>>,
+    asset_amount: u64,
+) -> MarginfiResult {
+    check!(asset_amount > 0, MarginfiError::ZeroLiquidationAmount);
+
+    check!(
+        ctx.accounts.asset_bank.key() != ctx.accounts.liab_bank.key(),
+        MarginfiError::SameAssetAndLiabilityBanks
+    );
+
+    let clock = Clock::get()?;
+    let maybe_liab_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.liab_bank.load()?,
+        ctx.accounts.liab_token_program.key,
+    )?;
+    let maybe_asset_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.asset_bank.load()?,
+        ctx.accounts.asset_token_program.key,
+    )?;
+
+    let LendingAccountDeleverage {
+        marginfi_account: marginfi_account_loader,
+        group: marginfi_group_loader,
+        asset_bank: asset_bank_loader,
+        liab_bank: liab_bank_loader,
+        ..
+    } = ctx.accounts;
+
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(ACCOUNT_DISABLED),
+        MarginfiError::AccountDisabled
+    );
+
+    let group = &*marginfi_group_loader.load()?;
+    {
+        asset_bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            asset_bank_loader.key(),
+        )?;
+        liab_bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            liab_bank_loader.key(),
+        )?;
+    }
+
+    marginfi_account.sort_balances();
+
+    let pre_deleverage_health: I80F48 = {
+        let (assets, liabs) = RiskEngine::new(
+            &marginfi_account,
+            marginfi_account.balances(),
+            ctx.remaining_accounts,
+        )?
+        .get_account_health_components(RiskRequirementType::Maintenance, &mut None)?;
+        assets.checked_sub(liabs).ok_or_else(math_error!())?
+    };
+
+    let mut asset_bank = asset_bank_loader.load_mut()?;
+    let mut liab_bank = liab_bank_loader.load_mut()?;
+
+    let (asset_oracle_ais, liab_oracle_ais) = {
+        let remaining_ais = RemainingAccountsIndex::new(ctx.remaining_accounts);
+        (
+            remaining_ais.find_oracle_ais(&asset_bank, &asset_bank_loader.key())?,
+            remaining_ais.find_oracle_ais(&liab_bank, &liab_bank_loader.key())?,
+        )
+    };
+
+    // Same pricing as a liquidation, but the owner gets no discount on their own collateral
+    let insurance_fee = group.get_deleverage_insurance_fee();
+    let LiquidationAmounts {
+        liab_amount_liquidator,
+        liab_amount_final,
+        insurance_fund_fee,
+        ..
+    } = calc_liquidation_amounts_with_fees(
+        &asset_bank,
+        &asset_oracle_ais,
+        &liab_bank,
+        &liab_oracle_ais,
+        I80F48::from_num(asset_amount),
+        I80F48::ZERO,
+        insurance_fee,
+        &clock,
+    )?;
+
+    // The owner pays whole tokens, rounding in the bank's favor
+    let liability_amount: u64 = liab_amount_liquidator
+        .checked_ceil()
+        .and_then(|amount| amount.checked_to_num::<u64>())
+        .ok_or(MarginfiError::MathError)?;
+    check!(liability_amount > 0, MarginfiError::ZeroLiquidationAmount);
+
+    let (insurance_fee_to_transfer, insurance_fee_dust) = (
+        insurance_fund_fee
+            .checked_to_num::<u64>()
+            .ok_or(MarginfiError::MathError)?,
+        insurance_fund_fee.frac(),
+    );
+
+    // Owner repays the liability
+    {
+        let liab_bank_liquidity_authority_bump = liab_bank.liquidity_vault_authority_bump;
+
+        let liability_amount_pre_fee = maybe_liab_bank_mint
+            .as_ref()
+            .map(|mint| {
+                utils::calculate_pre_fee_spl_deposit_amount(
+                    mint.to_account_info(),
+                    liability_amount,
+                    clock.epoch,
+                )
+            })
+            .transpose()?
+            .unwrap_or(liability_amount);
+
+        let mut bank_account = BankAccountWrapper::find(
+            &liab_bank_loader.key(),
+            &mut liab_bank,
+            marginfi_account.balances_mut(),
+        )?;
+
+        bank_account.repay(liab_amount_final)?;
+
+        bank_account.deposit_spl_transfer(
+            liability_amount_pre_fee,
+            ctx.accounts.signer_token_account.to_account_info(),
+            ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            maybe_liab_bank_mint.as_ref(),
+            ctx.accounts.liab_token_program.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
+        // Insurance fund receives fee
+        bank_account.withdraw_spl_transfer(
+            insurance_fee_to_transfer,
+            ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+            ctx.accounts.liab_bank_insurance_vault.to_account_info(),
+            ctx.accounts
+                .liab_bank_liquidity_vault_authority
+                .to_account_info(),
+            maybe_liab_bank_mint.as_ref(),
+            ctx.accounts.liab_token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                liab_bank_loader.key(),
+                liab_bank_liquidity_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+    }
+
+    // Owner withdraws the collateral
+    {
+        let asset_bank_liquidity_authority_bump = asset_bank.liquidity_vault_authority_bump;
+
+        let mut bank_account = BankAccountWrapper::find(
+            &asset_bank_loader.key(),
+            &mut asset_bank,
+            marginfi_account.balances_mut(),
+        )?;
+
+        bank_account.withdraw(I80F48::from_num(asset_amount))?;
+
+        bank_account.withdraw_spl_transfer(
+            asset_amount,
+            ctx.accounts.asset_bank_liquidity_vault.to_account_info(),
+            ctx.accounts.destination_token_account.to_account_info(),
+            ctx.accounts
+                .asset_bank_liquidity_vault_authority
+                .to_account_info(),
+            maybe_asset_bank_mint.as_ref(),
+            ctx.accounts.asset_token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                asset_bank_loader.key(),
+                asset_bank_liquidity_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+    }
+
+    liab_bank.collected_insurance_fees_outstanding =
+        I80F48::from(liab_bank.collected_insurance_fees_outstanding)
+            .checked_add(insurance_fee_dust)
+            .ok_or(MarginfiError::MathError)?
+            .into();
+
+    asset_bank.update_bank_cache(group)?;
+    liab_bank.update_bank_cache(group)?;
+
+    let asset_mint = asset_bank.mint;
+    let liability_mint = liab_bank.mint;
+    drop(asset_bank);
+    drop(liab_bank);
+
+    // ## Risk checks ##
+
+    // Repaying always outweighs the collateral given up, unless the insurance fee is larger than
+    // the haircut on the collateral
+    marginfi_account.sort_balances();
+    let post_deleverage_health: I80F48 = {
+        let (assets, liabs) = RiskEngine::new(
+            &marginfi_account,
+            marginfi_account.balances(),
+            ctx.remaining_accounts,
+        )?
+        .get_account_health_components(RiskRequirementType::Maintenance, &mut None)?;
+        assets.checked_sub(liabs).ok_or_else(math_error!())?
+    };
+
+    if post_deleverage_health <= pre_deleverage_health {
+        msg!(
+            "post_deleverage_health: {}, pre_deleverage_health: {}",
+            post_deleverage_health,
+            pre_deleverage_health
+        );
+        return err!(MarginfiError::WorseHealthPostDeleverage);
+    }
+
+    emit!(LendingAccountDeleverageEvent {
+        header: AccountEventHeader {
+            signer: Some(ctx.accounts.authority.key()),
+            marginfi_account: marginfi_account_loader.key(),
+            marginfi_account_authority: marginfi_account.authority,
+            marginfi_group: marginfi_group_loader.key(),
+        },
+        asset_bank: asset_bank_loader.key(),
+        asset_mint,
+        liability_bank: liab_bank_loader.key(),
+        liability_mint,
+        pre_health: pre_deleverage_health.to_num::<f64>(),
+        post_health: post_deleverage_health.to_num::<f64>(),
+        asset_amount,
+        liability_amount,
+        liability_repaid: liab_amount_final.to_num::<f64>(),
+        insurance_fee: insurance_fee.to_num::<f64>(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountDeleverage<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = authority
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub asset_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub liab_bank: AccountLoader<'info, Bank>,
+
+    /// Pays the liability
+    /// CHECK: Token mint/authority are checked at transfer
+    #[account(mut)]
+    pub signer_token_account: AccountInfo<'info>,
+
+    /// Receives the collateral
+    #[account(mut)]
+    pub destination_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            asset_bank.key().as_ref(),
+        ],
+        bump = asset_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub asset_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_SEED.as_bytes(),
+            asset_bank.key().as_ref(),
+        ],
+        bump = asset_bank.load()?.liquidity_vault_bump
+    )]
+    pub asset_bank_liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub liab_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.liquidity_vault_bump
+    )]
+    pub liab_bank_liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        mut,
+        seeds = [
+            INSURANCE_VAULT_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.insurance_vault_bump
+    )]
+    pub liab_bank_insurance_vault: AccountInfo<'info>,
+
+    pub asset_token_program: Interface<'info, TokenInterface>,
+
+    pub liab_token_program: Interface<'info, TokenInterface>,
+}
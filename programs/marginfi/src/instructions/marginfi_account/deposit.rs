@@ -5,7 +5,9 @@ use crate::{
     math_error,
     prelude::*,
     state::{
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, ACCOUNT_DISABLED},
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, MarginfiGroup},
     },
     utils::{calculate_pre_fee_spl_deposit_amount, maybe_take_bank_mint, validate_asset_tags},
@@ -44,15 +46,23 @@ pub fn lending_account_deposit<'info>(
     let deposit_up_to_limit = deposit_up_to_limit.unwrap_or(false);
 
     let mut bank = bank_loader.load_mut()?;
-    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
 
-    validate_asset_tags(&bank, &marginfi_account)?;
+    validate_asset_tags(&bank, marginfi_account.balances())?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
         MarginfiError::AccountDisabled
     );
 
+    // Fixed-term debt isn't netted against deposits, so the two can't share a bank
+    check!(
+        marginfi_account
+            .get_fixed_term_balance(&bank_loader.key())
+            .is_none(),
+        MarginfiError::FixedTermPositionExists
+    );
+
     let deposit_amount = if deposit_up_to_limit && bank.config.is_deposit_limit_active() {
         let current_asset_amount = bank.get_asset_amount(bank.total_asset_shares.into())?;
         let deposit_limit = I80F48::from_num(bank.config.deposit_limit);
@@ -91,7 +101,7 @@ pub fn lending_account_deposit<'info>(
     let mut bank_account = BankAccountWrapper::find_or_create(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
+        marginfi_account.balances_mut(),
     )?;
 
     bank_account.deposit(I80F48::from_num(deposit_amount))?;
@@ -132,7 +142,7 @@ pub fn lending_account_deposit<'info>(
         amount: deposit_amount,
     });
 
-    marginfi_account.lending_account.sort_balances();
+    marginfi_account.sort_balances();
 
     Ok(())
 }
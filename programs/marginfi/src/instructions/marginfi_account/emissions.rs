@@ -11,7 +11,9 @@ use crate::{
     errors::MarginfiError,
     prelude::MarginfiResult,
     state::{
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, ACCOUNT_DISABLED},
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, MarginfiGroup},
     },
 };
@@ -19,7 +21,7 @@ use crate::{
 pub fn lending_account_withdraw_emissions<'info>(
     ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdrawEmissions<'info>>,
 ) -> MarginfiResult {
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
@@ -31,7 +33,7 @@ pub fn lending_account_withdraw_emissions<'info>(
     let mut balance = BankAccountWrapper::find(
         ctx.accounts.bank.to_account_info().key,
         &mut bank,
-        &mut marginfi_account.lending_account,
+        marginfi_account.balances_mut(),
     )?;
 
     // Settle emissions
@@ -119,13 +121,13 @@ pub struct LendingAccountWithdrawEmissions<'info> {
 pub fn lending_account_settle_emissions(
     ctx: Context<LendingAccountSettleEmissions>,
 ) -> MarginfiResult {
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
     let mut bank = ctx.accounts.bank.load_mut()?;
 
     let mut balance = BankAccountWrapper::find(
         ctx.accounts.bank.to_account_info().key,
         &mut bank,
-        &mut marginfi_account.lending_account,
+        marginfi_account.balances_mut(),
     )?;
 
     balance.claim_emissions(Clock::get()?.unix_timestamp.try_into().unwrap())?;
@@ -149,7 +151,7 @@ pub struct LendingAccountSettleEmissions<'info> {
 pub fn marginfi_account_update_emissions_destination_account<'info>(
     ctx: Context<'_, '_, 'info, 'info, MarginfiAccountUpdateEmissionsDestinationAccount<'info>>,
 ) -> MarginfiResult {
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
@@ -181,7 +183,7 @@ pub struct MarginfiAccountUpdateEmissionsDestinationAccount<'info> {
 pub fn lending_account_withdraw_emissions_permissionless<'info>(
     ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdrawEmissionsPermissionless<'info>>,
 ) -> MarginfiResult {
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
@@ -215,7 +217,7 @@ pub fn lending_account_withdraw_emissions_permissionless<'info>(
     let mut bank_account = BankAccountWrapper::find(
         ctx.accounts.bank.to_account_info().key,
         &mut bank,
-        &mut marginfi_account.lending_account,
+        marginfi_account.balances_mut(),
     )?;
 
     // Settle emissions
@@ -0,0 +1,92 @@
+use crate::{
+    check,
+    events::{AccountEventHeader, MarginfiAccountExtendEvent},
+    prelude::*,
+    state::marginfi_account::{
+        split_extended_account_data, MarginfiAccount, ACCOUNT_EXTENDED, ACCOUNT_IN_FLASHLOAN,
+        MARGINFI_ACCOUNT_EXTENDED_LEN,
+    },
+};
+use anchor_lang::{prelude::*, system_program};
+
+/// Grows the account to `MAX_LENDING_ACCOUNT_BALANCES_EXTENDED` balances. Its existing balances
+/// move into the new `LendingAccountExtension`, so the remaining accounts other instructions
+/// expect don't change.
+pub fn marginfi_account_extend(ctx: Context<MarginfiAccountExtend>) -> MarginfiResult {
+    {
+        let marginfi_account = ctx.accounts.marginfi_account.load()?;
+        check!(
+            !marginfi_account.get_flag(ACCOUNT_EXTENDED),
+            MarginfiError::AccountAlreadyExtended
+        );
+        check!(
+            !marginfi_account.get_flag(ACCOUNT_IN_FLASHLOAN),
+            MarginfiError::AccountInFlashloan
+        );
+    }
+
+    let marginfi_account_ai = ctx.accounts.marginfi_account.to_account_info();
+    realloc_to_extended(
+        &marginfi_account_ai,
+        &ctx.accounts.fee_payer,
+        &ctx.accounts.system_program,
+    )?;
+
+    let mut data = marginfi_account_ai.try_borrow_mut_data()?;
+    let (marginfi_account, extension) = split_extended_account_data(&mut data);
+    marginfi_account.extend(extension);
+
+    emit!(MarginfiAccountExtendEvent {
+        header: AccountEventHeader {
+            signer: Some(ctx.accounts.authority.key()),
+            marginfi_account: marginfi_account_ai.key(),
+            marginfi_account_authority: marginfi_account.authority,
+            marginfi_group: marginfi_account.group,
+        }
+    });
+
+    Ok(())
+}
+
+/// Grows `account_ai` to `MARGINFI_ACCOUNT_EXTENDED_LEN` (zeroing the new bytes), with `payer`
+/// topping up its rent.
+pub(crate) fn realloc_to_extended<'info>(
+    account_ai: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> MarginfiResult {
+    let rent_exempt_lamports = Rent::get()?.minimum_balance(MARGINFI_ACCOUNT_EXTENDED_LEN);
+    let shortfall = rent_exempt_lamports.saturating_sub(account_ai.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account_ai.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+
+    account_ai.realloc(MARGINFI_ACCOUNT_EXTENDED_LEN, true)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiAccountExtend<'info> {
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,511 @@
+use crate::{
+    bank_signer, check,
+    constants::{LIQUIDITY_VAULT_AUTHORITY_SEED, PROGRAM_VERSION},
+    errors::MarginfiError,
+    events::{
+        AccountEventHeader, LendingAccountBorrowFixedTermEvent, LendingAccountRepayFixedTermEvent,
+        LendingAccountRollFixedTermEvent,
+    },
+    math_error,
+    prelude::MarginfiResult,
+    state::{
+        bank_cache::u32_to_apr,
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{
+            BalanceSide, BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader,
+            MarginfiAccountRefMut, RiskEngine, ACCOUNT_DISABLED,
+        },
+        marginfi_group::{Bank, BankVaultType, MarginfiGroup},
+    },
+    utils::{
+        self, calculate_pre_fee_spl_deposit_amount, maybe_take_bank_mint, validate_asset_tags,
+    },
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+
+/// Locked rate and maturity for a fixed-term borrow of `duration` seconds opened (or rolled)
+/// now: the bank's variable borrowing rate once `new_debt` is lent out, counting fixed-term debt
+/// toward utilization, plus its fixed-term premium. The rate is rounded up to basis points.
+fn get_fixed_term(
+    bank: &Bank,
+    group: &MarginfiGroup,
+    new_debt: I80F48,
+    duration: u32,
+    current_timestamp: i64,
+) -> MarginfiResult<(u16, u32)> {
+    check!(
+        bank.fixed_term_max_duration != 0,
+        MarginfiError::FixedTermBorrowingDisabled
+    );
+    check!(
+        duration != 0 && duration <= bank.fixed_term_max_duration,
+        MarginfiError::InvalidFixedTermDuration
+    );
+
+    let total_assets = bank.get_asset_amount(bank.total_asset_shares.into())?;
+    let fixed_term_liabilities = I80F48::from(bank.fixed_term_liabilities)
+        .checked_add(new_debt)
+        .ok_or_else(math_error!())?;
+    let interest_rates = bank
+        .get_interest_rate_calculator(group)?
+        .calc_interest_rate_with_fixed_term(
+            total_assets,
+            bank.get_liability_amount(bank.total_liability_shares.into())?,
+            fixed_term_liabilities,
+        )
+        .ok_or_else(math_error!())?;
+    let rate_bps: u16 = interest_rates
+        .borrowing_rate_apr
+        .checked_add(u32_to_apr(bank.fixed_term_premium))
+        .and_then(|rate| rate.checked_mul(I80F48::from_num(10_000)))
+        .and_then(|rate_bps| rate_bps.checked_ceil())
+        .and_then(|rate_bps| rate_bps.checked_to_num())
+        .ok_or(MarginfiError::FixedTermRateTooHigh)?;
+    let maturity: u32 = current_timestamp
+        .checked_add(duration as i64)
+        .ok_or_else(math_error!())?
+        .try_into()
+        .map_err(|_| MarginfiError::InvalidFixedTermDuration)?;
+
+    Ok((rate_bps, maturity))
+}
+
+/// Runs the initial health check and stores the result in the account's health cache.
+fn check_init_health_and_update_cache<'info>(
+    marginfi_account: &mut MarginfiAccountRefMut,
+    remaining_ais: &'info [AccountInfo<'info>],
+    current_timestamp: i64,
+) -> MarginfiResult {
+    let mut health_cache = ExtendedHealthCache::zeroed();
+    health_cache.timestamp = current_timestamp;
+
+    let (risk_result, _engine) = RiskEngine::check_account_init_health(
+        marginfi_account,
+        marginfi_account.balances(),
+        remaining_ais,
+        &mut Some(&mut health_cache),
+    );
+    risk_result?;
+    health_cache.program_version = PROGRAM_VERSION;
+    health_cache.set_engine_ok(true);
+    marginfi_account.set_health_cache(&health_cache);
+
+    Ok(())
+}
+
+/// 1. Accrue interest
+/// 2. Open a fixed-term borrow for the bank, locking in the current borrowing rate plus the
+///    bank's fixed-term premium until `duration` seconds from now. The bank's origination fee is
+///    added to the principal, like for variable-rate borrows.
+/// 3. Transfer funds from the bank's liquidity vault to the signer's token account
+/// 4. Verify that the user account is in a healthy state
+///
+/// Will error if the account already has a fixed-term borrow or any deposits in this bank.
+pub fn lending_account_borrow_fixed_term<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountBorrowFixedTerm<'info>>,
+    amount: u64,
+    duration: u32,
+) -> MarginfiResult {
+    let LendingAccountBorrowFixedTerm {
+        marginfi_account: marginfi_account_loader,
+        destination_token_account,
+        liquidity_vault: bank_liquidity_vault,
+        token_program,
+        bank_liquidity_vault_authority,
+        bank: bank_loader,
+        group: marginfi_group_loader,
+        ..
+    } = ctx.accounts;
+    let clock = Clock::get()?;
+    let maybe_bank_mint = maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*bank_loader.load()?,
+        token_program.key,
+    )?;
+
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
+    let group = &marginfi_group_loader.load()?;
+    let program_fee_rate: I80F48 = group.fee_state_cache.program_fee_rate.into();
+
+    check!(
+        !marginfi_account.get_flag(ACCOUNT_DISABLED),
+        MarginfiError::AccountDisabled
+    );
+
+    {
+        let mut bank = bank_loader.load_mut()?;
+        bank.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            bank_loader.key(),
+        )?;
+
+        validate_asset_tags(&bank, marginfi_account.balances())?;
+
+        // Fixed-term debt isn't netted against deposits, so the two can't share a bank
+        check!(
+            !marginfi_account.balances().iter().any(|balance| {
+                balance.is_active()
+                    && balance.bank_pk == bank_loader.key()
+                    && !balance.is_empty(BalanceSide::Assets)
+            }),
+            MarginfiError::AssetsInLiabilityBank
+        );
+
+        let liquidity_vault_authority_bump = bank.liquidity_vault_authority_bump;
+
+        // User needs to borrow amount + fee to receive amount
+        let amount_pre_fee = maybe_bank_mint
+            .as_ref()
+            .map(|mint| {
+                calculate_pre_fee_spl_deposit_amount(mint.to_account_info(), amount, clock.epoch)
+            })
+            .transpose()?
+            .unwrap_or(amount);
+
+        // The principal is in whole tokens, so the fee rounds up
+        let origination_fee_rate: I80F48 = bank
+            .config
+            .interest_rate_config
+            .protocol_origination_fee
+            .into();
+        let origination_fee: u64 = I80F48::from_num(amount_pre_fee)
+            .checked_mul(origination_fee_rate)
+            .and_then(|fee| fee.checked_ceil())
+            .and_then(|fee| fee.checked_to_num())
+            .ok_or_else(math_error!())?;
+        let principal = amount_pre_fee
+            .checked_add(origination_fee)
+            .ok_or_else(math_error!())?;
+
+        let (rate_bps, maturity) = get_fixed_term(
+            &bank,
+            group,
+            I80F48::from_num(principal),
+            duration,
+            clock.unix_timestamp,
+        )?;
+
+        let mut bank_account = BankAccountWrapper::create_fixed_term(
+            &bank_loader.key(),
+            &mut bank,
+            marginfi_account.balances_mut(),
+            rate_bps,
+            maturity,
+        )?;
+        // Incurs a borrow that includes the origination fee (but withdraws just the amt)
+        bank_account.borrow_fixed_term(principal)?;
+
+        bank_account.withdraw_spl_transfer(
+            amount_pre_fee,
+            bank_liquidity_vault.to_account_info(),
+            destination_token_account.to_account_info(),
+            bank_liquidity_vault_authority.to_account_info(),
+            maybe_bank_mint.as_ref(),
+            token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                bank_loader.key(),
+                liquidity_vault_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+
+        // The program and/or group fee account gains the origination fee
+        bank.collect_origination_fee(I80F48::from_num(origination_fee), program_fee_rate)?;
+
+        bank.update_bank_cache(group)?;
+
+        emit!(LendingAccountBorrowFixedTermEvent {
+            header: AccountEventHeader {
+                signer: Some(ctx.accounts.authority.key()),
+                marginfi_account: marginfi_account_loader.key(),
+                marginfi_account_authority: marginfi_account.authority,
+                marginfi_group: marginfi_account.group,
+            },
+            bank: bank_loader.key(),
+            mint: bank.mint,
+            amount: principal,
+            rate_bps,
+            maturity,
+        });
+    } // release mutable borrow of bank
+
+    marginfi_account.sort_balances();
+
+    check_init_health_and_update_cache(
+        &mut marginfi_account,
+        ctx.remaining_accounts,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountBorrowFixedTerm<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = authority,
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = liquidity_vault,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    #[account(mut)]
+    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Seed constraint check
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            bank.key().as_ref(),
+        ],
+        bump = bank.load()?.liquidity_vault_authority_bump,
+    )]
+    pub bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// 1. Accrue interest
+/// 2. Find the user's fixed-term borrow for the bank
+/// 3. Record the repayment, closing the balance if repaying in full
+/// 4. Transfer funds from the signer's token account to the bank's liquidity vault
+///
+/// Fixed-term borrows can be repaid at any time, before or after maturity.
+pub fn lending_account_repay_fixed_term<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountRepayFixedTerm<'info>>,
+    amount: u64,
+    repay_all: Option<bool>,
+) -> MarginfiResult {
+    let LendingAccountRepayFixedTerm {
+        marginfi_account: marginfi_account_loader,
+        authority: signer,
+        signer_token_account,
+        liquidity_vault: bank_liquidity_vault,
+        token_program,
+        bank: bank_loader,
+        group: marginfi_group_loader,
+        ..
+    } = ctx.accounts;
+    let clock = Clock::get()?;
+    let maybe_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*bank_loader.load()?,
+        token_program.key,
+    )?;
+
+    let repay_all = repay_all.unwrap_or(false);
+    let mut bank = bank_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
+
+    check!(
+        !marginfi_account.get_flag(ACCOUNT_DISABLED),
+        MarginfiError::AccountDisabled
+    );
+
+    let group = &marginfi_group_loader.load()?;
+    bank.accrue_interest(
+        clock.unix_timestamp,
+        group,
+        #[cfg(not(feature = "client"))]
+        bank_loader.key(),
+    )?;
+
+    let mut bank_account = BankAccountWrapper::find_fixed_term(
+        &bank_loader.key(),
+        &mut bank,
+        marginfi_account.balances_mut(),
+    )?;
+
+    let repay_amount_post_fee = if repay_all {
+        bank_account.repay_all_fixed_term()?
+    } else {
+        bank_account.repay_fixed_term(I80F48::from_num(amount))?;
+
+        amount
+    };
+
+    let repay_amount_pre_fee = maybe_bank_mint
+        .as_ref()
+        .map(|mint| {
+            utils::calculate_pre_fee_spl_deposit_amount(
+                mint.to_account_info(),
+                repay_amount_post_fee,
+                clock.epoch,
+            )
+        })
+        .transpose()?
+        .unwrap_or(repay_amount_post_fee);
+
+    bank.deposit_spl_transfer(
+        repay_amount_pre_fee,
+        signer_token_account.to_account_info(),
+        bank_liquidity_vault.to_account_info(),
+        signer.to_account_info(),
+        maybe_bank_mint.as_ref(),
+        token_program.to_account_info(),
+        ctx.remaining_accounts,
+    )?;
+
+    bank.update_bank_cache(group)?;
+
+    emit!(LendingAccountRepayFixedTermEvent {
+        header: AccountEventHeader {
+            signer: Some(ctx.accounts.authority.key()),
+            marginfi_account: marginfi_account_loader.key(),
+            marginfi_account_authority: marginfi_account.authority,
+            marginfi_group: marginfi_account.group,
+        },
+        bank: bank_loader.key(),
+        mint: bank.mint,
+        amount: repay_amount_post_fee,
+        close_balance: repay_all,
+    });
+
+    marginfi_account.sort_balances();
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountRepayFixedTerm<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = authority,
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = liquidity_vault
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+
+    /// CHECK: Token mint/authority are checked at transfer
+    #[account(mut)]
+    pub signer_token_account: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub liquidity_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// 1. Accrue interest
+/// 2. Roll the user's matured fixed-term borrow for the bank into a new term of `duration`
+///    seconds, at the current borrowing rate plus the bank's fixed-term premium. Interest owed so
+///    far becomes the new principal.
+/// 3. Verify that the user account is in a healthy state
+///
+/// Will error if the borrow has not matured yet.
+pub fn lending_account_roll_fixed_term<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingAccountRollFixedTerm<'info>>,
+    duration: u32,
+) -> MarginfiResult {
+    let LendingAccountRollFixedTerm {
+        marginfi_account: marginfi_account_loader,
+        bank: bank_loader,
+        group: marginfi_group_loader,
+        ..
+    } = ctx.accounts;
+    let clock = Clock::get()?;
+
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
+    let group = &marginfi_group_loader.load()?;
+
+    check!(
+        !marginfi_account.get_flag(ACCOUNT_DISABLED),
+        MarginfiError::AccountDisabled
+    );
+
+    {
+        let mut bank = bank_loader.load_mut()?;
+        bank.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            bank_loader.key(),
+        )?;
+
+        // The rolled debt is already part of the bank's fixed-term liabilities
+        let (rate_bps, maturity) =
+            get_fixed_term(&bank, group, I80F48::ZERO, duration, clock.unix_timestamp)?;
+
+        let mut bank_account = BankAccountWrapper::find_fixed_term(
+            &bank_loader.key(),
+            &mut bank,
+            marginfi_account.balances_mut(),
+        )?;
+        bank_account.roll_fixed_term(rate_bps, maturity)?;
+        let amount = bank_account.balance.fixed_term_principal;
+
+        bank.update_bank_cache(group)?;
+
+        emit!(LendingAccountRollFixedTermEvent {
+            header: AccountEventHeader {
+                signer: Some(ctx.accounts.authority.key()),
+                marginfi_account: marginfi_account_loader.key(),
+                marginfi_account_authority: marginfi_account.authority,
+                marginfi_group: marginfi_account.group,
+            },
+            bank: bank_loader.key(),
+            mint: bank.mint,
+            amount,
+            rate_bps,
+            maturity,
+        });
+    } // release mutable borrow of bank
+
+    check_init_health_and_update_cache(
+        &mut marginfi_account,
+        ctx.remaining_accounts,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountRollFixedTerm<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = authority,
+    )]
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
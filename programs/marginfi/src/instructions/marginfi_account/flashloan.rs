@@ -3,7 +3,7 @@ use crate::{
     errors::MarginfiError,
     prelude::*,
     state::marginfi_account::{
-        MarginfiAccount, RiskEngine, ACCOUNT_DISABLED, ACCOUNT_IN_FLASHLOAN,
+        MarginfiAccount, MarginfiAccountLoader, RiskEngine, ACCOUNT_DISABLED, ACCOUNT_IN_FLASHLOAN,
     },
 };
 use anchor_lang::prelude::*;
@@ -131,12 +131,16 @@ pub fn lending_account_end_flashloan<'info>(
         "End flashloan ix should not be in CPI"
     );
 
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
 
     marginfi_account.unset_flag(ACCOUNT_IN_FLASHLOAN);
 
-    let (risk_result, _engine) =
-        RiskEngine::check_account_init_health(&marginfi_account, ctx.remaining_accounts, &mut None);
+    let (risk_result, _engine) = RiskEngine::check_account_init_health(
+        &marginfi_account,
+        marginfi_account.balances(),
+        ctx.remaining_accounts,
+        &mut None,
+    );
     risk_result?;
 
     Ok(())
@@ -1,24 +1,27 @@
-use crate::constants::{
-    INSURANCE_VAULT_SEED, LIQUIDATION_INSURANCE_FEE, LIQUIDATION_LIQUIDATOR_FEE,
-};
+use crate::constants::INSURANCE_VAULT_SEED;
 use crate::events::{AccountEventHeader, LendingAccountLiquidateEvent, LiquidationBalances};
-use crate::state::marginfi_account::{
-    calc_amount, calc_value, get_remaining_accounts_per_bank, RiskEngine,
+use crate::state::health_cache::ExtendedHealthCache;
+use crate::state::marginfi_account::{calc_amount, calc_value, RemainingAccountsIndex, RiskEngine};
+use crate::state::marginfi_group::{
+    calc_liquidation_unhealthiness, Bank, BankConfig, BankVaultType, MarginfiGroup,
+};
+use crate::state::price::{
+    OracleAgeWindow, OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias,
 };
-use crate::state::marginfi_group::{Bank, BankVaultType, MarginfiGroup};
-use crate::state::price::{OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias};
 use crate::utils::{validate_asset_tags, validate_bank_asset_tags};
 use crate::{
     bank_signer,
     constants::{LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED},
     errors::MarginfiError,
-    state::marginfi_account::{BankAccountWrapper, MarginfiAccount},
+    state::marginfi_account::{
+        BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, MarginfiAccountRefMut,
+    },
 };
-use crate::{check, debug, prelude::*, utils};
+use crate::{check, debug, math_error, prelude::*, utils};
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::clock::Clock;
 use anchor_lang::solana_program::sysvar::Sysvar;
-use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use fixed::types::I80F48;
 
 pub fn lending_account_liquidate<'info>(
@@ -43,12 +46,12 @@ pub fn lending_account_liquidate<'info>(
         // Sanity check user/liquidator accounts will not contain positions with mismatching tags
         // after liquidation.
         // * Note: user will be repaid in liab_bank
-        let user_acc = ctx.accounts.liquidatee_marginfi_account.load()?;
-        validate_asset_tags(&liab_bank, &user_acc)?;
+        let user_acc = ctx.accounts.liquidatee_marginfi_account.load_data()?;
+        validate_asset_tags(&liab_bank, user_acc.balances())?;
         // * Note: Liquidator repays liab bank, and is paid in asset_bank.
-        let liquidator_acc = ctx.accounts.liquidator_marginfi_account.load()?;
-        validate_asset_tags(&liab_bank, &liquidator_acc)?;
-        validate_asset_tags(&asset_bank, &liquidator_acc)?;
+        let liquidator_acc = ctx.accounts.liquidator_marginfi_account.load_data()?;
+        validate_asset_tags(&liab_bank, liquidator_acc.balances())?;
+        validate_asset_tags(&asset_bank, liquidator_acc.balances())?;
     } // release immutable borrow of asset_bank/liab_bank + liquidatee/liquidator user accounts
 
     let LendingAccountLiquidate {
@@ -58,8 +61,8 @@ pub fn lending_account_liquidate<'info>(
         ..
     } = ctx.accounts;
 
-    let mut liquidator_marginfi_account = liquidator_marginfi_account_loader.load_mut()?;
-    let mut liquidatee_marginfi_account = liquidatee_marginfi_account_loader.load_mut()?;
+    let mut liquidator_marginfi_account = liquidator_marginfi_account_loader.load_data_mut()?;
+    let mut liquidatee_marginfi_account = liquidatee_marginfi_account_loader.load_data_mut()?;
     let clock = Clock::get()?;
     let current_timestamp = clock.unix_timestamp;
 
@@ -84,277 +87,102 @@ pub fn lending_account_liquidate<'info>(
         )?;
     }
 
-    let init_liquidatee_remaining_len = liquidatee_marginfi_account.get_remaining_accounts_len()?;
-
-    let liquidatee_accounts_starting_pos =
-        ctx.remaining_accounts.len() - init_liquidatee_remaining_len;
-    let liquidatee_remaining_accounts = &ctx.remaining_accounts[liquidatee_accounts_starting_pos..];
-
-    liquidatee_marginfi_account.lending_account.sort_balances();
+    liquidatee_marginfi_account.sort_balances();
+
+    // Only used to read back the maintenance-weighted liabilities, never persisted
+    let mut pre_liquidation_cache = ExtendedHealthCache::zeroed();
+    let pre_liquidation_health: I80F48 = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_pre_liquidation_condition_and_get_account_health(
+        Some(&ctx.accounts.liab_bank.key()),
+        &mut Some(&mut pre_liquidation_cache),
+    )?;
 
-    let pre_liquidation_health: I80F48 =
-        RiskEngine::new(&liquidatee_marginfi_account, liquidatee_remaining_accounts)?
-            .check_pre_liquidation_condition_and_get_account_health(
-                Some(&ctx.accounts.liab_bank.key()),
-                &mut None,
-            )?;
+    let pre_liquidation_unhealthiness = calc_liquidation_unhealthiness(
+        pre_liquidation_health,
+        pre_liquidation_cache.liability_value_maint.into(),
+    )?;
 
     // ##Accounting changes##
 
-    let (pre_balances, post_balances) = {
-        let asset_amount: I80F48 = I80F48::from_num(asset_amount);
-
-        let mut asset_bank = ctx.accounts.asset_bank.load_mut()?;
-        let asset_bank_remaining_accounts_len = get_remaining_accounts_per_bank(&asset_bank)? - 1;
-
-        let asset_price: I80F48 = {
-            let oracle_ais = &ctx.remaining_accounts[0..asset_bank_remaining_accounts_len];
-            let asset_pf = OraclePriceFeedAdapter::try_from_bank_config(
-                &asset_bank.config,
-                oracle_ais,
-                &clock,
-            )?;
-            asset_pf.get_price_of_type(
-                OraclePriceType::RealTime,
-                Some(PriceBias::Low),
-                asset_bank.config.oracle_max_confidence,
-            )?
-        };
-        check!(asset_price > I80F48::ZERO, MarginfiError::ZeroAssetPrice);
-
-        let mut liab_bank = ctx.accounts.liab_bank.load_mut()?;
-        let liab_bank_remaining_accounts_len = get_remaining_accounts_per_bank(&liab_bank)? - 1;
-        let liab_price: I80F48 = {
-            let oracle_ais = &ctx.remaining_accounts[asset_bank_remaining_accounts_len
-                ..(asset_bank_remaining_accounts_len + liab_bank_remaining_accounts_len)];
-            let liab_pf = OraclePriceFeedAdapter::try_from_bank_config(
-                &liab_bank.config,
-                oracle_ais,
-                &clock,
-            )?;
-            liab_pf.get_price_of_type(
-                OraclePriceType::RealTime,
-                Some(PriceBias::High),
-                liab_bank.config.oracle_max_confidence,
-            )?
-        };
-        check!(liab_price > I80F48::ZERO, MarginfiError::ZeroLiabilityPrice);
-
-        let final_discount: I80F48 =
-            I80F48::ONE - (LIQUIDATION_INSURANCE_FEE + LIQUIDATION_LIQUIDATOR_FEE);
-        let liquidator_discount: I80F48 = I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE;
-
-        // Quantity of liability to be paid off by liquidator
-        let liab_amount_liquidator: I80F48 = calc_amount(
-            calc_value(
-                asset_amount,
-                asset_price,
-                asset_bank.mint_decimals,
-                Some(liquidator_discount),
-            )?,
-            liab_price,
-            liab_bank.mint_decimals,
-        )?;
-
-        // Quantity of liability to be received by liquidatee
-        let liab_amount_final: I80F48 = calc_amount(
-            calc_value(
-                asset_amount,
-                asset_price,
-                asset_bank.mint_decimals,
-                Some(final_discount),
-            )?,
-            liab_price,
-            liab_bank.mint_decimals,
-        )?;
-
-        // Insurance fund fee
-        let insurance_fund_fee: I80F48 = liab_amount_liquidator - liab_amount_final;
-
-        assert!(
-            insurance_fund_fee >= I80F48::ZERO,
-            "Insurance fund fee cannot be negative"
-        );
-
-        debug!(
-            "liab_quantity_liq: {}, liab_q_final: {}, asset_amount: {}, insurance_fund_fee: {}, liab_price: {}, asset_price: {}",
-            liab_amount_liquidator, liab_amount_final, asset_amount, insurance_fund_fee, liab_price, asset_price
-        );
-
-        // Liquidator pays off liability
-        let (liquidator_liability_pre_balance, liquidator_liability_post_balance) = {
-            let mut bank_account = BankAccountWrapper::find_or_create(
-                &ctx.accounts.liab_bank.key(),
-                &mut liab_bank,
-                &mut liquidator_marginfi_account.lending_account,
-            )?;
-
-            let pre_balance: I80F48 = bank_account
-                .bank
-                .get_liability_amount(bank_account.balance.liability_shares.into())?;
-
-            bank_account.decrease_balance_in_liquidation(liab_amount_liquidator)?;
-
-            let post_balance: I80F48 = bank_account
-                .bank
-                .get_liability_amount(bank_account.balance.liability_shares.into())?;
-
-            (pre_balance, post_balance)
-        };
-
-        // Liquidatee pays off `asset_quantity` amount of collateral
-        let (liquidatee_asset_pre_balance, liquidatee_asset_post_balance) = {
-            let mut bank_account = BankAccountWrapper::find(
-                &ctx.accounts.asset_bank.key(),
-                &mut asset_bank,
-                &mut liquidatee_marginfi_account.lending_account,
-            )?;
-
-            let pre_balance: I80F48 = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
-
-            bank_account
-                .withdraw(asset_amount)
-                .map_err(|_| MarginfiError::OverliquidationAttempt)?;
-
-            let post_balance: I80F48 = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
-
-            (pre_balance, post_balance)
-        };
-
-        // Liquidator receives `asset_quantity` amount of collateral
-        let (liquidator_asset_pre_balance, liquidator_asset_post_balance) = {
-            let mut bank_account = BankAccountWrapper::find_or_create(
+    let (asset_oracle_ais, liab_oracle_ais) = {
+        let remaining_ais = RemainingAccountsIndex::new(ctx.remaining_accounts);
+        (
+            remaining_ais.find_oracle_ais(
+                &*ctx.accounts.asset_bank.load()?,
                 &ctx.accounts.asset_bank.key(),
-                &mut asset_bank,
-                &mut liquidator_marginfi_account.lending_account,
-            )?;
-
-            let pre_balance: I80F48 = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
-
-            bank_account.increase_balance_in_liquidation(asset_amount)?;
-
-            let post_balance: I80F48 = bank_account
-                .bank
-                .get_asset_amount(bank_account.balance.asset_shares.into())?;
-
-            (pre_balance, post_balance)
-        };
-
-        let (insurance_fee_to_transfer, insurance_fee_dust) = (
-            insurance_fund_fee
-                .checked_to_num::<u64>()
-                .ok_or(MarginfiError::MathError)?,
-            insurance_fund_fee.frac(),
-        );
-
-        let (liquidatee_liability_pre_balance, liquidatee_liability_post_balance) = {
-            // Liquidatee receives liability payment
-            let liab_bank_liquidity_authority_bump = liab_bank.liquidity_vault_authority_bump;
-
-            let mut liquidatee_liab_bank_account = BankAccountWrapper::find(
+            )?,
+            remaining_ais.find_oracle_ais(
+                &*ctx.accounts.liab_bank.load()?,
                 &ctx.accounts.liab_bank.key(),
-                &mut liab_bank,
-                &mut liquidatee_marginfi_account.lending_account,
-            )?;
-
-            let liquidatee_liability_pre_balance: I80F48 =
-                liquidatee_liab_bank_account.bank.get_liability_amount(
-                    liquidatee_liab_bank_account.balance.liability_shares.into(),
-                )?;
-
-            liquidatee_liab_bank_account.increase_balance(liab_amount_final)?;
-
-            let liquidatee_liability_post_balance: I80F48 =
-                liquidatee_liab_bank_account.bank.get_liability_amount(
-                    liquidatee_liab_bank_account.balance.liability_shares.into(),
-                )?;
-
-            // ## SPL transfer ##
-            // Insurance fund receives fee
-            liquidatee_liab_bank_account.withdraw_spl_transfer(
-                insurance_fee_to_transfer,
-                ctx.accounts.bank_liquidity_vault.to_account_info(),
-                ctx.accounts.bank_insurance_vault.to_account_info(),
-                ctx.accounts
-                    .bank_liquidity_vault_authority
-                    .to_account_info(),
-                maybe_liab_bank_mint.as_ref(),
-                ctx.accounts.token_program.to_account_info(),
-                bank_signer!(
-                    BankVaultType::Liquidity,
-                    ctx.accounts.liab_bank.key(),
-                    liab_bank_liquidity_authority_bump
-                ),
-                ctx.remaining_accounts,
-            )?;
-
-            (
-                liquidatee_liability_pre_balance,
-                liquidatee_liability_post_balance,
-            )
-        };
-
-        liab_bank.collected_insurance_fees_outstanding =
-            I80F48::from(liab_bank.collected_insurance_fees_outstanding)
-                .checked_add(insurance_fee_dust)
-                .ok_or(MarginfiError::MathError)?
-                .into();
-
-        asset_bank.update_bank_cache(group)?;
-
-        liab_bank.update_bank_cache(group)?;
-
-        (
-            LiquidationBalances {
-                liquidatee_asset_balance: liquidatee_asset_pre_balance.to_num::<f64>(),
-                liquidatee_liability_balance: liquidatee_liability_pre_balance.to_num::<f64>(),
-                liquidator_asset_balance: liquidator_asset_pre_balance.to_num::<f64>(),
-                liquidator_liability_balance: liquidator_liability_pre_balance.to_num::<f64>(),
-            },
-            LiquidationBalances {
-                liquidatee_asset_balance: liquidatee_asset_post_balance.to_num::<f64>(),
-                liquidatee_liability_balance: liquidatee_liability_post_balance.to_num::<f64>(),
-                liquidator_asset_balance: liquidator_asset_post_balance.to_num::<f64>(),
-                liquidator_liability_balance: liquidator_liability_post_balance.to_num::<f64>(),
-            },
+            )?,
         )
     };
 
-    // ## Risk checks ##
+    let liab_vaults = LiquidationLiabilityVaults {
+        liquidity_vault_authority: ctx
+            .accounts
+            .bank_liquidity_vault_authority
+            .to_account_info(),
+        liquidity_vault: ctx.accounts.bank_liquidity_vault.to_account_info(),
+        insurance_vault: ctx.accounts.bank_insurance_vault.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+        maybe_mint: maybe_liab_bank_mint,
+    };
 
-    let liquidator_remaining_acc_len = liquidator_marginfi_account.get_remaining_accounts_len()?;
-    let liquidator_accounts_starting_pos =
-        liquidatee_accounts_starting_pos - liquidator_remaining_acc_len;
+    let LiquidationLegResult {
+        pre_balances,
+        post_balances,
+        liquidator_fee,
+        insurance_fee,
+        ..
+    } = liquidate_leg(
+        group,
+        LiquidationLegBanks {
+            asset_bank: &ctx.accounts.asset_bank,
+            asset_oracle_ais: &asset_oracle_ais,
+            liab_bank: &ctx.accounts.liab_bank,
+            liab_oracle_ais: &liab_oracle_ais,
+        },
+        &liab_vaults,
+        &mut liquidator_marginfi_account,
+        &mut liquidatee_marginfi_account,
+        asset_amount,
+        pre_liquidation_unhealthiness,
+        I80F48::ZERO,
+        &clock,
+        ctx.remaining_accounts,
+    )?;
 
-    let liquidator_remaining_accounts =
-        &ctx.remaining_accounts[liquidator_accounts_starting_pos..liquidatee_accounts_starting_pos];
+    // ## Risk checks ##
 
     // TODO why call RiskEngine::new here again instead of reusing the one we made in line ~151? Is
     // it because we mutated the liab bank and corresponding balance? Is reloading the entire engine
     // more CU intensive than mutating the old engine with the updated balance + bank
 
     // Verify liquidatee liquidation post health
-    let post_liquidation_health =
-        RiskEngine::new(&liquidatee_marginfi_account, liquidatee_remaining_accounts)?
-            .check_post_liquidation_condition_and_get_account_health(
-                &ctx.accounts.liab_bank.key(),
-                pre_liquidation_health,
-            )?;
+    let post_liquidation_health = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_post_liquidation_condition_and_get_account_health(
+        &ctx.accounts.liab_bank.key(),
+        pre_liquidation_health,
+    )?;
 
     // TODO consider if health cache update here is worth blowing the extra CU
 
-    liquidator_marginfi_account.lending_account.sort_balances();
+    liquidator_marginfi_account.sort_balances();
 
     // Verify liquidator account health
     let (risk_result, _engine) = RiskEngine::check_account_init_health(
         &liquidator_marginfi_account,
-        liquidator_remaining_accounts,
+        liquidator_marginfi_account.balances(),
+        ctx.remaining_accounts,
         &mut None,
     );
     risk_result?;
@@ -376,11 +204,415 @@ pub fn lending_account_liquidate<'info>(
         liquidatee_post_health: post_liquidation_health.to_num::<f64>(),
         pre_balances,
         post_balances,
+        liquidator_fee: liquidator_fee.to_num::<f64>(),
+        insurance_fee: insurance_fee.to_num::<f64>(),
     });
 
     Ok(())
 }
 
+/// Liability bank accounts used to pay the insurance fee during a liquidation
+pub(crate) struct LiquidationLiabilityVaults<'info> {
+    pub liquidity_vault_authority: AccountInfo<'info>,
+    pub liquidity_vault: AccountInfo<'info>,
+    pub insurance_vault: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    /// Required for Token-2022 liability banks only
+    pub maybe_mint: Option<InterfaceAccount<'info, Mint>>,
+}
+
+/// The asset and liability bank of a liquidation, with the oracles used to price them
+pub(crate) struct LiquidationLegBanks<'a, 'info> {
+    pub asset_bank: &'a AccountLoader<'info, Bank>,
+    pub asset_oracle_ais: &'a [AccountInfo<'info>],
+    pub liab_bank: &'a AccountLoader<'info, Bank>,
+    pub liab_oracle_ais: &'a [AccountInfo<'info>],
+}
+
+pub(crate) struct LiquidationLegResult {
+    pub pre_balances: LiquidationBalances,
+    pub post_balances: LiquidationBalances,
+    pub liquidator_fee: I80F48,
+    pub insurance_fee: I80F48,
+    /// Liability repaid on the liquidatee's behalf, in native liability tokens
+    pub liability_repaid: I80F48,
+}
+
+/// Moves `asset_amount` of the liquidatee's collateral to the liquidator, who repays the
+/// liquidatee's liability at a discount, and pays the insurance fee out of the liability bank.
+///
+/// * `unhealthiness` - pre-liquidation unhealthiness of the liquidatee, which sets the liquidator
+///   fee and whether the close factor applies
+/// * `prior_repaid` - liability in `liab_bank` already repaid earlier in the same instruction,
+///   which counts against the close factor
+///
+/// Does not check the health of either account, the caller must do so once all legs are done.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn liquidate_leg<'info>(
+    group: &MarginfiGroup,
+    banks: LiquidationLegBanks<'_, 'info>,
+    liab_vaults: &LiquidationLiabilityVaults<'info>,
+    liquidator_marginfi_account: &mut MarginfiAccountRefMut,
+    liquidatee_marginfi_account: &mut MarginfiAccountRefMut,
+    asset_amount: u64,
+    unhealthiness: I80F48,
+    prior_repaid: I80F48,
+    clock: &Clock,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> MarginfiResult<LiquidationLegResult> {
+    let asset_amount: I80F48 = I80F48::from_num(asset_amount);
+    let asset_bank_pk = banks.asset_bank.key();
+    let liab_bank_pk = banks.liab_bank.key();
+
+    let mut asset_bank = banks.asset_bank.load_mut()?;
+    let mut liab_bank = banks.liab_bank.load_mut()?;
+
+    let LiquidationAmounts {
+        liquidator_fee,
+        insurance_fee,
+        liab_amount_liquidator,
+        liab_amount_final,
+        insurance_fund_fee,
+    } = calc_liquidation_amounts(
+        &asset_bank,
+        banks.asset_oracle_ais,
+        &liab_bank,
+        banks.liab_oracle_ais,
+        asset_amount,
+        unhealthiness,
+        clock,
+    )?;
+
+    // Liquidator pays off liability
+    let (liquidator_liability_pre_balance, liquidator_liability_post_balance) = {
+        let mut bank_account = BankAccountWrapper::find_or_create(
+            &liab_bank_pk,
+            &mut liab_bank,
+            liquidator_marginfi_account.balances_mut(),
+        )?;
+
+        let pre_balance: I80F48 = bank_account
+            .bank
+            .get_liability_amount(bank_account.balance.liability_shares.into())?;
+
+        bank_account.decrease_balance_in_liquidation(liab_amount_liquidator)?;
+
+        let post_balance: I80F48 = bank_account
+            .bank
+            .get_liability_amount(bank_account.balance.liability_shares.into())?;
+
+        (pre_balance, post_balance)
+    };
+
+    // Liquidatee pays off `asset_quantity` amount of collateral
+    let (liquidatee_asset_pre_balance, liquidatee_asset_post_balance) = {
+        let mut bank_account = BankAccountWrapper::find(
+            &asset_bank_pk,
+            &mut asset_bank,
+            liquidatee_marginfi_account.balances_mut(),
+        )?;
+
+        let pre_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        bank_account
+            .withdraw(asset_amount)
+            .map_err(|_| MarginfiError::OverliquidationAttempt)?;
+
+        let post_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        (pre_balance, post_balance)
+    };
+
+    // Liquidator receives `asset_quantity` amount of collateral
+    let (liquidator_asset_pre_balance, liquidator_asset_post_balance) = {
+        let mut bank_account = BankAccountWrapper::find_or_create(
+            &asset_bank_pk,
+            &mut asset_bank,
+            liquidator_marginfi_account.balances_mut(),
+        )?;
+
+        let pre_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        bank_account.increase_balance_in_liquidation(asset_amount)?;
+
+        let post_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        (pre_balance, post_balance)
+    };
+
+    let (insurance_fee_to_transfer, insurance_fee_dust) = (
+        insurance_fund_fee
+            .checked_to_num::<u64>()
+            .ok_or(MarginfiError::MathError)?,
+        insurance_fund_fee.frac(),
+    );
+
+    let (liquidatee_liability_pre_balance, liquidatee_liability_post_balance) = {
+        // Liquidatee receives liability payment
+        let liab_bank_liquidity_authority_bump = liab_bank.liquidity_vault_authority_bump;
+
+        let mut liquidatee_liab_bank_account = BankAccountWrapper::find_liability(
+            &liab_bank_pk,
+            &mut liab_bank,
+            liquidatee_marginfi_account.balances_mut(),
+        )?;
+
+        let liquidatee_liability_pre_balance: I80F48 =
+            liquidatee_liab_bank_account.get_liability_amount_owed()?;
+
+        check_liquidation_close_factor(
+            group,
+            &liquidatee_liab_bank_account.bank.config,
+            unhealthiness,
+            liquidatee_liability_pre_balance,
+            prior_repaid,
+            liab_amount_final,
+        )?;
+
+        liquidatee_liab_bank_account.repay_in_liquidation(liab_amount_final)?;
+
+        let liquidatee_liability_post_balance: I80F48 =
+            liquidatee_liab_bank_account.get_liability_amount_owed()?;
+
+        // ## SPL transfer ##
+        // Insurance fund receives fee
+        liquidatee_liab_bank_account.withdraw_spl_transfer(
+            insurance_fee_to_transfer,
+            liab_vaults.liquidity_vault.clone(),
+            liab_vaults.insurance_vault.clone(),
+            liab_vaults.liquidity_vault_authority.clone(),
+            liab_vaults.maybe_mint.as_ref(),
+            liab_vaults.token_program.clone(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                liab_bank_pk,
+                liab_bank_liquidity_authority_bump
+            ),
+            remaining_accounts,
+        )?;
+
+        (
+            liquidatee_liability_pre_balance,
+            liquidatee_liability_post_balance,
+        )
+    };
+
+    liab_bank.collected_insurance_fees_outstanding =
+        I80F48::from(liab_bank.collected_insurance_fees_outstanding)
+            .checked_add(insurance_fee_dust)
+            .ok_or(MarginfiError::MathError)?
+            .into();
+
+    asset_bank.update_bank_cache(group)?;
+
+    liab_bank.update_bank_cache(group)?;
+
+    Ok(LiquidationLegResult {
+        pre_balances: LiquidationBalances {
+            liquidatee_asset_balance: liquidatee_asset_pre_balance.to_num::<f64>(),
+            liquidatee_liability_balance: liquidatee_liability_pre_balance.to_num::<f64>(),
+            liquidator_asset_balance: liquidator_asset_pre_balance.to_num::<f64>(),
+            liquidator_liability_balance: liquidator_liability_pre_balance.to_num::<f64>(),
+        },
+        post_balances: LiquidationBalances {
+            liquidatee_asset_balance: liquidatee_asset_post_balance.to_num::<f64>(),
+            liquidatee_liability_balance: liquidatee_liability_post_balance.to_num::<f64>(),
+            liquidator_asset_balance: liquidator_asset_post_balance.to_num::<f64>(),
+            liquidator_liability_balance: liquidator_liability_post_balance.to_num::<f64>(),
+        },
+        liquidator_fee,
+        insurance_fee,
+        liability_repaid: liab_amount_final,
+    })
+}
+
+pub(crate) struct LiquidationAmounts {
+    pub liquidator_fee: I80F48,
+    pub insurance_fee: I80F48,
+    /// Liability paid by the liquidator, in native liability tokens
+    pub liab_amount_liquidator: I80F48,
+    /// Liability repaid on the liquidatee's behalf, in native liability tokens
+    pub liab_amount_final: I80F48,
+    /// The difference, paid to the liability bank's insurance fund
+    pub insurance_fund_fee: I80F48,
+}
+
+/// Prices `asset_amount` of seized collateral in liability tokens, after fees.
+pub(crate) fn calc_liquidation_amounts<'info>(
+    asset_bank: &Bank,
+    asset_oracle_ais: &[AccountInfo<'info>],
+    liab_bank: &Bank,
+    liab_oracle_ais: &[AccountInfo<'info>],
+    asset_amount: I80F48,
+    unhealthiness: I80F48,
+    clock: &Clock,
+) -> MarginfiResult<LiquidationAmounts> {
+    // Fees are set by the bank whose collateral is being seized. The liquidator fee grows with
+    // how far underwater the liquidatee is, if the bank enables it.
+    let liquidator_fee = asset_bank
+        .config
+        .get_liquidation_liquidator_fee_for_unhealthiness(unhealthiness)?;
+    let insurance_fee = asset_bank.config.get_liquidation_insurance_fee();
+
+    calc_liquidation_amounts_with_fees(
+        asset_bank,
+        asset_oracle_ais,
+        liab_bank,
+        liab_oracle_ais,
+        asset_amount,
+        liquidator_fee,
+        insurance_fee,
+        clock,
+    )
+}
+
+/// Same as `calc_liquidation_amounts`, with fees chosen by the caller instead of the asset bank.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_liquidation_amounts_with_fees<'info>(
+    asset_bank: &Bank,
+    asset_oracle_ais: &[AccountInfo<'info>],
+    liab_bank: &Bank,
+    liab_oracle_ais: &[AccountInfo<'info>],
+    asset_amount: I80F48,
+    liquidator_fee: I80F48,
+    insurance_fee: I80F48,
+    clock: &Clock,
+) -> MarginfiResult<LiquidationAmounts> {
+    // Stale prices (within the relaxed window only) get a haircut against the liquidatee, same as
+    // in the liquidatee's health, see `RiskEngine::new_for_liquidation`
+    let asset_price: I80F48 = {
+        let (asset_pf, stale) = OraclePriceFeedAdapter::try_from_bank_in_window(
+            asset_bank,
+            asset_oracle_ais,
+            clock,
+            OracleAgeWindow::Relaxed,
+        )?;
+        let price = asset_pf.get_price_of_type(
+            OraclePriceType::RealTime,
+            Some(PriceBias::Low),
+            asset_bank.config.oracle_max_confidence,
+        )?;
+        if stale {
+            asset_bank.apply_oracle_stale_haircut(price, PriceBias::Low)?
+        } else {
+            price
+        }
+    };
+    check!(asset_price > I80F48::ZERO, MarginfiError::ZeroAssetPrice);
+
+    let liab_price: I80F48 = {
+        let (liab_pf, stale) = OraclePriceFeedAdapter::try_from_bank_in_window(
+            liab_bank,
+            liab_oracle_ais,
+            clock,
+            OracleAgeWindow::Relaxed,
+        )?;
+        let price = liab_pf.get_price_of_type(
+            OraclePriceType::RealTime,
+            Some(PriceBias::High),
+            liab_bank.config.oracle_max_confidence,
+        )?;
+        if stale {
+            liab_bank.apply_oracle_stale_haircut(price, PriceBias::High)?
+        } else {
+            price
+        }
+    };
+    check!(liab_price > I80F48::ZERO, MarginfiError::ZeroLiabilityPrice);
+
+    let final_discount: I80F48 = I80F48::ONE - (insurance_fee + liquidator_fee);
+    let liquidator_discount: I80F48 = I80F48::ONE - liquidator_fee;
+
+    // Quantity of liability to be paid off by liquidator
+    let liab_amount_liquidator: I80F48 = calc_amount(
+        calc_value(
+            asset_amount,
+            asset_price,
+            asset_bank.mint_decimals,
+            Some(liquidator_discount),
+        )?,
+        liab_price,
+        liab_bank.mint_decimals,
+    )?;
+
+    // Quantity of liability to be received by liquidatee
+    let liab_amount_final: I80F48 = calc_amount(
+        calc_value(
+            asset_amount,
+            asset_price,
+            asset_bank.mint_decimals,
+            Some(final_discount),
+        )?,
+        liab_price,
+        liab_bank.mint_decimals,
+    )?;
+
+    // Insurance fund fee
+    let insurance_fund_fee: I80F48 = liab_amount_liquidator - liab_amount_final;
+
+    assert!(
+        insurance_fund_fee >= I80F48::ZERO,
+        "Insurance fund fee cannot be negative"
+    );
+
+    debug!(
+        "liab_quantity_liq: {}, liab_q_final: {}, asset_amount: {}, insurance_fund_fee: {}, liab_price: {}, asset_price: {}",
+        liab_amount_liquidator, liab_amount_final, asset_amount, insurance_fund_fee, liab_price, asset_price
+    );
+
+    Ok(LiquidationAmounts {
+        liquidator_fee,
+        insurance_fee,
+        liab_amount_liquidator,
+        liab_amount_final,
+        insurance_fund_fee,
+    })
+}
+
+/// Errors if repaying `repayment` of a liability would exceed the close factor.
+///
+/// * `pre_balance` - the liquidatee's liability before `repayment`
+/// * `prior_repaid` - liability already repaid earlier in the same instruction
+pub(crate) fn check_liquidation_close_factor(
+    group: &MarginfiGroup,
+    liab_bank_config: &BankConfig,
+    unhealthiness: I80F48,
+    pre_balance: I80F48,
+    prior_repaid: I80F48,
+    repayment: I80F48,
+) -> MarginfiResult {
+    if let Some(close_factor) = group.get_liquidation_close_factor(liab_bank_config, unhealthiness)
+    {
+        // Measured against the liability before any leg of this liquidation repaid it
+        let max_repayment = pre_balance
+            .checked_add(prior_repaid)
+            .ok_or_else(math_error!())?
+            .checked_mul(close_factor)
+            .ok_or_else(math_error!())?;
+        let total_repayment = prior_repaid
+            .checked_add(repayment)
+            .ok_or_else(math_error!())?;
+        if total_repayment > max_repayment {
+            msg!(
+                "repayment {} exceeds close factor limit {}",
+                total_repayment,
+                max_repayment
+            );
+            return err!(MarginfiError::LiquidationCloseFactorExceeded);
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct LendingAccountLiquidate<'info> {
     pub group: AccountLoader<'info, MarginfiGroup>,
@@ -0,0 +1,370 @@
+use crate::constants::{
+    INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED,
+};
+use crate::events::{
+    AccountEventHeader, LendingAccountLiquidateBatchEvent, LendingAccountLiquidateLegEvent,
+};
+use crate::state::health_cache::ExtendedHealthCache;
+use crate::state::marginfi_account::{RemainingAccountsIndex, RiskEngine};
+use crate::state::marginfi_group::{calc_liquidation_unhealthiness, Bank, MarginfiGroup};
+use crate::utils::{validate_asset_tags, validate_bank_asset_tags};
+use crate::{
+    check, check_eq,
+    errors::MarginfiError,
+    instructions::marginfi_account::{
+        liquidate_leg, LiquidationLegBanks, LiquidationLegResult, LiquidationLiabilityVaults,
+    },
+    math_error,
+    prelude::*,
+    state::marginfi_account::{Balance, MarginfiAccount, MarginfiAccountLoader},
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::sysvar::Sysvar;
+use anchor_spl::token_interface::TokenInterface;
+use fixed::types::I80F48;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidationLeg {
+    /// Bank the liquidatee's collateral is seized from
+    pub asset_bank: Pubkey,
+    /// Bank the liquidatee's debt is repaid to
+    pub liab_bank: Pubkey,
+    /// Amount of collateral to seize, in native `asset_bank` tokens
+    pub asset_amount: u64,
+}
+
+/// Liquidate several (asset bank, liability bank) pairs of an unhealthy account at once. The
+/// liquidatee's health is checked once before and once after all legs are executed, instead of
+/// once per pair as with `lending_account_liquidate`.
+///
+/// Remaining accounts:
+/// * For each leg, in order: the liability bank's liquidity vault authority, liquidity vault,
+///   insurance vault, and token program, followed by its mint if it is a Token-2022 bank
+/// * The liquidator's (post-liquidation) and the liquidatee's risk accounts, in any order. Every
+///   asset and liability bank in `legs` is read from the liquidatee's, so these banks must be
+///   writable.
+pub fn lending_account_liquidate_batch<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateBatch<'info>>,
+    legs: Vec<LiquidationLeg>,
+) -> MarginfiResult {
+    check!(!legs.is_empty(), MarginfiError::EmptyLiquidationBatch);
+    for leg in legs.iter() {
+        check!(leg.asset_amount > 0, MarginfiError::ZeroLiquidationAmount);
+        check!(
+            leg.asset_bank != leg.liab_bank,
+            MarginfiError::SameAssetAndLiabilityBanks
+        );
+    }
+
+    let LendingAccountLiquidateBatch {
+        liquidator_marginfi_account: liquidator_marginfi_account_loader,
+        liquidatee_marginfi_account: liquidatee_marginfi_account_loader,
+        group: marginfi_group_loader,
+        ..
+    } = ctx.accounts;
+
+    let mut liquidator_marginfi_account = liquidator_marginfi_account_loader.load_data_mut()?;
+    let mut liquidatee_marginfi_account = liquidatee_marginfi_account_loader.load_data_mut()?;
+    let group = &*marginfi_group_loader.load()?;
+    let clock = Clock::get()?;
+
+    liquidatee_marginfi_account.sort_balances();
+
+    let liquidatee_banks = load_liquidatee_banks(
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+        &marginfi_group_loader.key(),
+    )?;
+
+    let mut bank_pks: Vec<Pubkey> = Vec::new();
+    let mut liab_bank_pks: Vec<Pubkey> = Vec::new();
+    for leg in legs.iter() {
+        for bank_pk in [leg.asset_bank, leg.liab_bank] {
+            if !bank_pks.contains(&bank_pk) {
+                bank_pks.push(bank_pk);
+            }
+        }
+        if !liab_bank_pks.contains(&leg.liab_bank) {
+            liab_bank_pks.push(leg.liab_bank);
+        }
+    }
+
+    for bank_pk in bank_pks.iter() {
+        find_liquidatee_bank(&liquidatee_banks, bank_pk)?
+            .bank
+            .load_mut()?
+            .accrue_interest(
+                clock.unix_timestamp,
+                group,
+                #[cfg(not(feature = "client"))]
+                *bank_pk,
+            )?;
+    }
+
+    // Only used to read back the maintenance-weighted liabilities, never persisted
+    let mut pre_liquidation_cache = ExtendedHealthCache::zeroed();
+    let pre_liquidation_health: I80F48 = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_pre_liquidation_condition_for_banks_and_get_account_health(
+        &liab_bank_pks,
+        &mut Some(&mut pre_liquidation_cache),
+    )?;
+
+    let pre_liquidation_unhealthiness = calc_liquidation_unhealthiness(
+        pre_liquidation_health,
+        pre_liquidation_cache.liability_value_maint.into(),
+    )?;
+
+    // ##Accounting changes##
+
+    let header = AccountEventHeader {
+        signer: Some(ctx.accounts.authority.key()),
+        marginfi_account: liquidator_marginfi_account_loader.key(),
+        marginfi_account_authority: liquidator_marginfi_account.authority,
+        marginfi_group: marginfi_group_loader.key(),
+    };
+
+    let mut leg_ais: &'info [AccountInfo<'info>] = ctx.remaining_accounts;
+    // Liability repaid per liability bank so far, counted against the close factor
+    let mut repaid: Vec<(Pubkey, I80F48)> = Vec::with_capacity(liab_bank_pks.len());
+
+    for leg in legs.iter() {
+        let asset = find_liquidatee_bank(&liquidatee_banks, &leg.asset_bank)?;
+        let liab = find_liquidatee_bank(&liquidatee_banks, &leg.liab_bank)?;
+
+        let (asset_mint, liab_mint, liab_vaults) = {
+            let asset_bank = asset.bank.load()?;
+            let liab_bank = liab.bank.load()?;
+
+            // Same asset tag rules as `lending_account_liquidate`, checked against the balances
+            // left by any earlier legs
+            validate_bank_asset_tags(&asset_bank, &liab_bank)?;
+            validate_asset_tags(&liab_bank, liquidatee_marginfi_account.balances())?;
+            validate_asset_tags(&liab_bank, liquidator_marginfi_account.balances())?;
+            validate_asset_tags(&asset_bank, liquidator_marginfi_account.balances())?;
+
+            (
+                asset_bank.mint,
+                liab_bank.mint,
+                take_liability_vaults(&mut leg_ais, &leg.liab_bank, &liab_bank)?,
+            )
+        };
+
+        let prior_repaid = repaid
+            .iter()
+            .find(|(bank_pk, _)| *bank_pk == leg.liab_bank)
+            .map(|(_, amount)| *amount)
+            .unwrap_or(I80F48::ZERO);
+
+        let LiquidationLegResult {
+            pre_balances,
+            post_balances,
+            liquidator_fee,
+            insurance_fee,
+            liability_repaid,
+        } = liquidate_leg(
+            group,
+            LiquidationLegBanks {
+                asset_bank: &asset.bank,
+                asset_oracle_ais: &asset.oracle_ais,
+                liab_bank: &liab.bank,
+                liab_oracle_ais: &liab.oracle_ais,
+            },
+            &liab_vaults,
+            &mut liquidator_marginfi_account,
+            &mut liquidatee_marginfi_account,
+            leg.asset_amount,
+            pre_liquidation_unhealthiness,
+            prior_repaid,
+            &clock,
+            ctx.remaining_accounts,
+        )?;
+
+        let total_repaid = prior_repaid
+            .checked_add(liability_repaid)
+            .ok_or_else(math_error!())?;
+        match repaid
+            .iter_mut()
+            .find(|(bank_pk, _)| *bank_pk == leg.liab_bank)
+        {
+            Some((_, amount)) => *amount = total_repaid,
+            None => repaid.push((leg.liab_bank, total_repaid)),
+        }
+
+        emit!(LendingAccountLiquidateLegEvent {
+            header: header.clone(),
+            liquidatee_marginfi_account: liquidatee_marginfi_account_loader.key(),
+            asset_bank: leg.asset_bank,
+            asset_mint,
+            liability_bank: leg.liab_bank,
+            liability_mint: liab_mint,
+            pre_balances,
+            post_balances,
+            liquidator_fee: liquidator_fee.to_num::<f64>(),
+            insurance_fee: insurance_fee.to_num::<f64>(),
+        });
+    }
+
+    // ## Risk checks ##
+
+    // Verify liquidatee liquidation post health
+    let post_liquidation_health = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_post_liquidation_condition_for_banks_and_get_account_health(
+        &liab_bank_pks,
+        pre_liquidation_health,
+    )?;
+
+    liquidator_marginfi_account.sort_balances();
+
+    // Verify liquidator account health
+    let (risk_result, _engine) = RiskEngine::check_account_init_health(
+        &liquidator_marginfi_account,
+        liquidator_marginfi_account.balances(),
+        ctx.remaining_accounts,
+        &mut None,
+    );
+    risk_result?;
+
+    emit!(LendingAccountLiquidateBatchEvent {
+        header,
+        liquidatee_marginfi_account: liquidatee_marginfi_account_loader.key(),
+        liquidatee_marginfi_account_authority: liquidatee_marginfi_account.authority,
+        liquidatee_pre_health: pre_liquidation_health.to_num::<f64>(),
+        liquidatee_post_health: post_liquidation_health.to_num::<f64>(),
+        legs: legs.len() as u8,
+    });
+
+    Ok(())
+}
+
+/// A bank the liquidatee has a balance in, and the oracles that price it
+struct LiquidateeBank<'info> {
+    bank: AccountLoader<'info, Bank>,
+    oracle_ais: Vec<AccountInfo<'info>>,
+}
+
+/// Finds every bank the liquidatee has a balance in (and its oracles) among the remaining
+/// accounts, by key.
+fn load_liquidatee_banks<'info>(
+    liquidatee_balances: &[Balance],
+    remaining_ais: &'info [AccountInfo<'info>],
+    group_pk: &Pubkey,
+) -> MarginfiResult<Vec<LiquidateeBank<'info>>> {
+    let remaining_ais = RemainingAccountsIndex::new(remaining_ais);
+    let mut liquidatee_banks: Vec<LiquidateeBank<'info>> = Vec::new();
+
+    for balance in liquidatee_balances
+        .iter()
+        .filter(|balance| balance.is_active())
+    {
+        // A fixed-term and a variable balance can share a bank
+        if liquidatee_banks
+            .iter()
+            .any(|b| b.bank.key() == balance.bank_pk)
+        {
+            continue;
+        }
+
+        let bank_ai = remaining_ais.get(&balance.bank_pk).ok_or_else(|| {
+            msg!("Bank {:?} not in remaining accounts", balance.bank_pk);
+            error!(MarginfiError::InvalidBankAccount)
+        })?;
+        let bank = AccountLoader::<Bank>::try_from(bank_ai)?;
+
+        let oracle_ais = {
+            let bank = bank.load()?;
+            check_eq!(bank.group, *group_pk, MarginfiError::InvalidBankAccount);
+            remaining_ais.find_oracle_ais(&bank, &balance.bank_pk)?
+        };
+
+        liquidatee_banks.push(LiquidateeBank { bank, oracle_ais });
+    }
+
+    Ok(liquidatee_banks)
+}
+
+fn find_liquidatee_bank<'a, 'info>(
+    liquidatee_banks: &'a [LiquidateeBank<'info>],
+    bank_pk: &Pubkey,
+) -> MarginfiResult<&'a LiquidateeBank<'info>> {
+    liquidatee_banks
+        .iter()
+        .find(|b| b.bank.key() == *bank_pk)
+        .ok_or_else(|| error!(MarginfiError::LendingAccountBalanceNotFound))
+}
+
+/// Takes the liability bank's vault authority, liquidity vault, insurance vault, token program,
+/// and (Token-2022 only) mint from the front of `remaining_ais`, validating each against the
+/// bank's seeds.
+fn take_liability_vaults<'info>(
+    remaining_ais: &mut &'info [AccountInfo<'info>],
+    liab_bank_pk: &Pubkey,
+    liab_bank: &Bank,
+) -> MarginfiResult<LiquidationLiabilityVaults<'info>> {
+    check!(
+        remaining_ais.len() >= 4,
+        MarginfiError::MissingPythOrBankAccount
+    );
+    let (vault_ais, rest) = remaining_ais.split_at(4);
+    *remaining_ais = rest;
+
+    let expected_keys = [
+        (
+            LIQUIDITY_VAULT_AUTHORITY_SEED,
+            liab_bank.liquidity_vault_authority_bump,
+        ),
+        (LIQUIDITY_VAULT_SEED, liab_bank.liquidity_vault_bump),
+        (INSURANCE_VAULT_SEED, liab_bank.insurance_vault_bump),
+    ];
+    for ((seed, bump), ai) in expected_keys.iter().zip(vault_ais.iter()) {
+        let expected_key = Pubkey::create_program_address(
+            &[seed.as_bytes(), liab_bank_pk.as_ref(), &[*bump]],
+            &crate::id(),
+        )
+        .map_err(|_| error!(ErrorCode::ConstraintSeeds))?;
+        if expected_key != *ai.key {
+            msg!("expected vault {:?}, got {:?}", expected_key, ai.key);
+            return err!(ErrorCode::ConstraintSeeds);
+        }
+    }
+
+    let token_program = Interface::<TokenInterface>::try_from(&vault_ais[3])?;
+    let maybe_mint = utils::maybe_take_bank_mint(remaining_ais, liab_bank, token_program.key)?;
+
+    Ok(LiquidationLiabilityVaults {
+        liquidity_vault_authority: vault_ais[0].clone(),
+        liquidity_vault: vault_ais[1].clone(),
+        insurance_vault: vault_ais[2].clone(),
+        token_program: vault_ais[3].clone(),
+        maybe_mint,
+    })
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountLiquidateBatch<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group,
+        has_one = authority
+    )]
+    pub liquidator_marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub liquidatee_marginfi_account: AccountLoader<'info, MarginfiAccount>,
+}
@@ -0,0 +1,409 @@
+use crate::constants::{
+    INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED,
+};
+use crate::events::{AccountEventHeader, LendingAccountLiquidateDirectEvent};
+use crate::state::health_cache::ExtendedHealthCache;
+use crate::state::marginfi_account::{RemainingAccountsIndex, RiskEngine};
+use crate::state::marginfi_group::{
+    calc_liquidation_unhealthiness, Bank, BankVaultType, MarginfiGroup,
+};
+use crate::utils::{validate_asset_tags, validate_bank_asset_tags};
+use crate::{
+    bank_signer, check,
+    errors::MarginfiError,
+    instructions::marginfi_account::{
+        calc_liquidation_amounts, check_liquidation_close_factor, LiquidationAmounts,
+    },
+    prelude::*,
+    state::marginfi_account::{BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader},
+    utils,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::sysvar::Sysvar;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use fixed::types::I80F48;
+
+/// Liquidate an unhealthy account without a liquidator marginfi account. The liquidator pays the
+/// liability straight into the liability bank's vault from a token account, and receives the
+/// seized collateral straight out of the asset bank's vault into a token account. Prices, fees,
+/// and liquidatee checks are the same as `lending_account_liquidate`.
+///
+/// Remaining accounts: liability mint (Token-2022 only), asset mint (Token-2022 only), then the
+/// liquidatee's risk accounts in any order, which include both banks' oracles.
+pub fn lending_account_liquidate_direct<'info>(
+    mut ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateDirect<'info>>,
+    asset_amount: u64,
+) -> MarginfiResult {
+    check!(asset_amount > 0, MarginfiError::ZeroLiquidationAmount);
+
+    check!(
+        ctx.accounts.asset_bank.key() != ctx.accounts.liab_bank.key(),
+        MarginfiError::SameAssetAndLiabilityBanks
+    );
+
+    {
+        let asset_bank = ctx.accounts.asset_bank.load()?;
+        let liab_bank = ctx.accounts.liab_bank.load()?;
+        validate_bank_asset_tags(&asset_bank, &liab_bank)?;
+
+        // * Note: user will be repaid in liab_bank
+        let user_acc = ctx.accounts.liquidatee_marginfi_account.load_data()?;
+        validate_asset_tags(&liab_bank, user_acc.balances())?;
+    } // release immutable borrow of asset_bank/liab_bank + liquidatee account
+
+    let clock = Clock::get()?;
+    let maybe_liab_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.liab_bank.load()?,
+        ctx.accounts.liab_token_program.key,
+    )?;
+    let maybe_asset_bank_mint = utils::maybe_take_bank_mint(
+        &mut ctx.remaining_accounts,
+        &*ctx.accounts.asset_bank.load()?,
+        ctx.accounts.asset_token_program.key,
+    )?;
+
+    let LendingAccountLiquidateDirect {
+        liquidatee_marginfi_account: liquidatee_marginfi_account_loader,
+        group: marginfi_group_loader,
+        asset_bank: asset_bank_loader,
+        liab_bank: liab_bank_loader,
+        ..
+    } = ctx.accounts;
+
+    let mut liquidatee_marginfi_account = liquidatee_marginfi_account_loader.load_data_mut()?;
+    let group = &*marginfi_group_loader.load()?;
+    {
+        asset_bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            asset_bank_loader.key(),
+        )?;
+        liab_bank_loader.load_mut()?.accrue_interest(
+            clock.unix_timestamp,
+            group,
+            #[cfg(not(feature = "client"))]
+            liab_bank_loader.key(),
+        )?;
+    }
+
+    liquidatee_marginfi_account.sort_balances();
+
+    // Only used to read back the maintenance-weighted liabilities, never persisted
+    let mut pre_liquidation_cache = ExtendedHealthCache::zeroed();
+    let pre_liquidation_health: I80F48 = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_pre_liquidation_condition_and_get_account_health(
+        Some(&liab_bank_loader.key()),
+        &mut Some(&mut pre_liquidation_cache),
+    )?;
+
+    let pre_liquidation_unhealthiness = calc_liquidation_unhealthiness(
+        pre_liquidation_health,
+        pre_liquidation_cache.liability_value_maint.into(),
+    )?;
+
+    // ##Accounting changes##
+
+    let mut asset_bank = asset_bank_loader.load_mut()?;
+    let mut liab_bank = liab_bank_loader.load_mut()?;
+
+    let (asset_oracle_ais, liab_oracle_ais) = {
+        let remaining_ais = RemainingAccountsIndex::new(ctx.remaining_accounts);
+        (
+            remaining_ais.find_oracle_ais(&asset_bank, &asset_bank_loader.key())?,
+            remaining_ais.find_oracle_ais(&liab_bank, &liab_bank_loader.key())?,
+        )
+    };
+
+    let LiquidationAmounts {
+        liquidator_fee,
+        insurance_fee,
+        liab_amount_liquidator,
+        liab_amount_final,
+        insurance_fund_fee,
+    } = calc_liquidation_amounts(
+        &asset_bank,
+        &asset_oracle_ais,
+        &liab_bank,
+        &liab_oracle_ais,
+        I80F48::from_num(asset_amount),
+        pre_liquidation_unhealthiness,
+        &clock,
+    )?;
+
+    // The liquidator pays whole tokens, rounding in the bank's favor
+    let liability_amount: u64 = liab_amount_liquidator
+        .checked_ceil()
+        .and_then(|amount| amount.checked_to_num::<u64>())
+        .ok_or(MarginfiError::MathError)?;
+    check!(liability_amount > 0, MarginfiError::ZeroLiquidationAmount);
+
+    let (insurance_fee_to_transfer, insurance_fee_dust) = (
+        insurance_fund_fee
+            .checked_to_num::<u64>()
+            .ok_or(MarginfiError::MathError)?,
+        insurance_fund_fee.frac(),
+    );
+
+    // Liquidatee pays off `asset_amount` of collateral, straight to the liquidator
+    let (liquidatee_asset_pre_balance, liquidatee_asset_post_balance) = {
+        let asset_bank_liquidity_authority_bump = asset_bank.liquidity_vault_authority_bump;
+
+        let mut bank_account = BankAccountWrapper::find(
+            &asset_bank_loader.key(),
+            &mut asset_bank,
+            liquidatee_marginfi_account.balances_mut(),
+        )?;
+
+        let pre_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        bank_account
+            .withdraw(I80F48::from_num(asset_amount))
+            .map_err(|_| MarginfiError::OverliquidationAttempt)?;
+
+        let post_balance: I80F48 = bank_account
+            .bank
+            .get_asset_amount(bank_account.balance.asset_shares.into())?;
+
+        bank_account.withdraw_spl_transfer(
+            asset_amount,
+            ctx.accounts.asset_bank_liquidity_vault.to_account_info(),
+            ctx.accounts
+                .liquidator_asset_token_account
+                .to_account_info(),
+            ctx.accounts
+                .asset_bank_liquidity_vault_authority
+                .to_account_info(),
+            maybe_asset_bank_mint.as_ref(),
+            ctx.accounts.asset_token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                asset_bank_loader.key(),
+                asset_bank_liquidity_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+
+        (pre_balance, post_balance)
+    };
+
+    let (liquidatee_liability_pre_balance, liquidatee_liability_post_balance) = {
+        let liab_bank_liquidity_authority_bump = liab_bank.liquidity_vault_authority_bump;
+
+        // Liquidator pays the liability into the vault
+        let liability_amount_pre_fee = maybe_liab_bank_mint
+            .as_ref()
+            .map(|mint| {
+                utils::calculate_pre_fee_spl_deposit_amount(
+                    mint.to_account_info(),
+                    liability_amount,
+                    clock.epoch,
+                )
+            })
+            .transpose()?
+            .unwrap_or(liability_amount);
+
+        liab_bank.deposit_spl_transfer(
+            liability_amount_pre_fee,
+            ctx.accounts.liquidator_liab_token_account.to_account_info(),
+            ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            maybe_liab_bank_mint.as_ref(),
+            ctx.accounts.liab_token_program.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
+        // Liquidatee receives liability payment
+        let mut bank_account = BankAccountWrapper::find_liability(
+            &liab_bank_loader.key(),
+            &mut liab_bank,
+            liquidatee_marginfi_account.balances_mut(),
+        )?;
+
+        let pre_balance: I80F48 = bank_account.get_liability_amount_owed()?;
+
+        check_liquidation_close_factor(
+            group,
+            &bank_account.bank.config,
+            pre_liquidation_unhealthiness,
+            pre_balance,
+            I80F48::ZERO,
+            liab_amount_final,
+        )?;
+
+        bank_account.repay_in_liquidation(liab_amount_final)?;
+
+        let post_balance: I80F48 = bank_account.get_liability_amount_owed()?;
+
+        // ## SPL transfer ##
+        // Insurance fund receives fee
+        bank_account.withdraw_spl_transfer(
+            insurance_fee_to_transfer,
+            ctx.accounts.liab_bank_liquidity_vault.to_account_info(),
+            ctx.accounts.liab_bank_insurance_vault.to_account_info(),
+            ctx.accounts
+                .liab_bank_liquidity_vault_authority
+                .to_account_info(),
+            maybe_liab_bank_mint.as_ref(),
+            ctx.accounts.liab_token_program.to_account_info(),
+            bank_signer!(
+                BankVaultType::Liquidity,
+                liab_bank_loader.key(),
+                liab_bank_liquidity_authority_bump
+            ),
+            ctx.remaining_accounts,
+        )?;
+
+        (pre_balance, post_balance)
+    };
+
+    liab_bank.collected_insurance_fees_outstanding =
+        I80F48::from(liab_bank.collected_insurance_fees_outstanding)
+            .checked_add(insurance_fee_dust)
+            .ok_or(MarginfiError::MathError)?
+            .into();
+
+    asset_bank.update_bank_cache(group)?;
+    liab_bank.update_bank_cache(group)?;
+
+    let asset_mint = asset_bank.mint;
+    let liability_mint = liab_bank.mint;
+    drop(asset_bank);
+    drop(liab_bank);
+
+    // ## Risk checks ##
+
+    // Verify liquidatee liquidation post health. There is no liquidator account to check.
+    let post_liquidation_health = RiskEngine::new_for_liquidation(
+        &liquidatee_marginfi_account,
+        liquidatee_marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_post_liquidation_condition_and_get_account_health(
+        &liab_bank_loader.key(),
+        pre_liquidation_health,
+    )?;
+
+    emit!(LendingAccountLiquidateDirectEvent {
+        header: AccountEventHeader {
+            signer: Some(ctx.accounts.authority.key()),
+            marginfi_account: liquidatee_marginfi_account_loader.key(),
+            marginfi_account_authority: liquidatee_marginfi_account.authority,
+            marginfi_group: marginfi_group_loader.key(),
+        },
+        asset_bank: asset_bank_loader.key(),
+        asset_mint,
+        liability_bank: liab_bank_loader.key(),
+        liability_mint,
+        liquidatee_pre_health: pre_liquidation_health.to_num::<f64>(),
+        liquidatee_post_health: post_liquidation_health.to_num::<f64>(),
+        asset_amount,
+        liability_amount,
+        liquidatee_asset_pre_balance: liquidatee_asset_pre_balance.to_num::<f64>(),
+        liquidatee_asset_post_balance: liquidatee_asset_post_balance.to_num::<f64>(),
+        liquidatee_liability_pre_balance: liquidatee_liability_pre_balance.to_num::<f64>(),
+        liquidatee_liability_post_balance: liquidatee_liability_post_balance.to_num::<f64>(),
+        liquidator_fee: liquidator_fee.to_num::<f64>(),
+        insurance_fee: insurance_fee.to_num::<f64>(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountLiquidateDirect<'info> {
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub asset_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub liab_bank: AccountLoader<'info, Bank>,
+
+    #[account(
+        mut,
+        has_one = group
+    )]
+    pub liquidatee_marginfi_account: AccountLoader<'info, MarginfiAccount>,
+
+    /// The liquidator, owner of `liquidator_liab_token_account`
+    pub authority: Signer<'info>,
+
+    /// CHECK: Token mint/authority are checked at transfer
+    #[account(mut)]
+    pub liquidator_liab_token_account: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub liquidator_asset_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            asset_bank.key().as_ref(),
+        ],
+        bump = asset_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub asset_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_SEED.as_bytes(),
+            asset_bank.key().as_ref(),
+        ],
+        bump = asset_bank.load()?.liquidity_vault_bump
+    )]
+    pub asset_bank_liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        seeds = [
+            LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.liquidity_vault_authority_bump
+    )]
+    pub liab_bank_liquidity_vault_authority: AccountInfo<'info>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_VAULT_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.liquidity_vault_bump
+    )]
+    pub liab_bank_liquidity_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Seed constraint
+    #[account(
+        mut,
+        seeds = [
+            INSURANCE_VAULT_SEED.as_bytes(),
+            liab_bank.key().as_ref(),
+        ],
+        bump = liab_bank.load()?.insurance_vault_bump
+    )]
+    pub liab_bank_insurance_vault: AccountInfo<'info>,
+
+    pub asset_token_program: Interface<'info, TokenInterface>,
+
+    pub liab_token_program: Interface<'info, TokenInterface>,
+}
@@ -1,13 +1,19 @@
 mod borrow;
 mod close;
 mod close_balance;
+mod deleverage;
 mod deposit;
 mod emissions;
+mod extend;
+mod fixed_term;
 mod flashloan;
 mod initialize;
 mod liquidate;
+mod liquidate_batch;
+mod liquidate_direct;
 mod pulse_health;
 mod repay;
+mod simulate_health;
 mod sort_balances;
 mod transfer_account;
 mod withdraw;
@@ -15,13 +21,19 @@ mod withdraw;
 pub use borrow::*;
 pub use close::*;
 pub use close_balance::*;
+pub use deleverage::*;
 pub use deposit::*;
 pub use emissions::*;
+pub use extend::*;
+pub use fixed_term::*;
 pub use flashloan::*;
 pub use initialize::*;
 pub use liquidate::*;
+pub use liquidate_batch::*;
+pub use liquidate_direct::*;
 pub use pulse_health::*;
 pub use repay::*;
+pub use simulate_health::*;
 pub use sort_balances::*;
 pub use transfer_account::*;
 pub use withdraw::*;
@@ -1,6 +1,5 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{clock::Clock, sysvar::Sysvar};
-use bytemuck::Zeroable;
 use fixed::types::I80F48;
 
 use crate::{
@@ -8,8 +7,8 @@ use crate::{
     errors::MarginfiError,
     events::HealthPulseEvent,
     state::{
-        health_cache::HealthCache,
-        marginfi_account::{MarginfiAccount, RiskEngine},
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{MarginfiAccount, MarginfiAccountLoader, RiskEngine},
     },
     MarginfiResult,
 };
@@ -18,14 +17,15 @@ pub fn lending_account_pulse_health<'info>(
     ctx: Context<'_, '_, 'info, 'info, PulseHealth<'info>>,
 ) -> MarginfiResult {
     let clock = Clock::get()?;
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
 
-    let mut health_cache = HealthCache::zeroed();
+    let mut health_cache = ExtendedHealthCache::zeroed();
     health_cache.timestamp = clock.unix_timestamp;
     health_cache.program_version = PROGRAM_VERSION;
 
     let (engine_result, engine) = RiskEngine::check_account_init_health(
         &marginfi_account,
+        marginfi_account.balances(),
         ctx.remaining_accounts,
         &mut Some(&mut health_cache),
     );
@@ -94,11 +94,19 @@ pub fn lending_account_pulse_health<'info>(
         }
     }
 
-    marginfi_account.health_cache = health_cache;
+    marginfi_account.set_health_cache(&health_cache);
+
+    let active_balances = marginfi_account
+        .balances()
+        .iter()
+        .filter(|balance| balance.is_active())
+        .count();
+    health_cache.balances.truncate(active_balances);
 
     emit!(HealthPulseEvent {
         account: ctx.accounts.marginfi_account.key(),
-        health_cache
+        health_cache: health_cache.cache,
+        balances: health_cache.balances,
     });
 
     Ok(())
@@ -5,7 +5,9 @@ use crate::{
     prelude::MarginfiResult,
     state::marginfi_group::MarginfiGroup,
     state::{
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, ACCOUNT_DISABLED},
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, ACCOUNT_DISABLED,
+        },
         marginfi_group::Bank,
     },
     utils,
@@ -44,7 +46,7 @@ pub fn lending_account_repay<'info>(
 
     let repay_all = repay_all.unwrap_or(false);
     let mut bank = bank_loader.load_mut()?;
-    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
@@ -59,9 +61,11 @@ pub fn lending_account_repay<'info>(
         bank_loader.key(),
     )?;
 
-    let lending_account = &mut marginfi_account.lending_account;
-    let mut bank_account =
-        BankAccountWrapper::find(&bank_loader.key(), &mut bank, lending_account)?;
+    let mut bank_account = BankAccountWrapper::find(
+        &bank_loader.key(),
+        &mut bank,
+        marginfi_account.balances_mut(),
+    )?;
 
     let repay_amount_post_fee = if repay_all {
         bank_account.repay_all()?
@@ -108,7 +112,7 @@ pub fn lending_account_repay<'info>(
         close_balance: repay_all,
     });
 
-    marginfi_account.lending_account.sort_balances();
+    marginfi_account.sort_balances();
 
     Ok(())
 }
@@ -0,0 +1,153 @@
+use anchor_lang::prelude::*;
+use bytemuck::Zeroable;
+use fixed::types::I80F48;
+
+use crate::{
+    check, check_eq,
+    errors::MarginfiError,
+    state::{
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{
+            BankAccountWrapper, LendingAccountExtension, MarginfiAccount, MarginfiAccountData,
+            MarginfiAccountLoader, RiskEngine, RiskRequirementType, ACCOUNT_IN_FLASHLOAN,
+        },
+        marginfi_group::{Bank, WrappedI80F48},
+    },
+    utils::validate_asset_tags,
+    MarginfiResult,
+};
+
+/// A hypothetical change to one of the account's balances
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimulatedBalanceDelta {
+    pub bank: Pubkey,
+    /// In native `bank` tokens. Positive amounts repay any liability, then deposit (like
+    /// `lending_account_deposit`), negative amounts withdraw any asset, then borrow.
+    pub amount: i64,
+}
+
+/// The account's health after the deltas of `lending_account_simulate_health` were applied.
+/// Values are in dollars, with the same weights and price adjustments as `HealthCache`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct SimulatedHealth {
+    pub asset_value_init: WrappedI80F48,
+    pub liability_value_init: WrappedI80F48,
+    pub asset_value_maint: WrappedI80F48,
+    pub liability_value_maint: WrappedI80F48,
+    pub asset_value_equity: WrappedI80F48,
+    pub liability_value_equity: WrappedI80F48,
+    /// The error a borrow or withdraw would fail the initial health check with, e.g.
+    /// `RiskEngineInitRejected`, 0 if the check passes.
+    pub init_err: u32,
+}
+
+/// Applies `deltas` to an in-memory copy of the account and returns its health for the initial,
+/// maintenance and equity requirements (through return data, so other programs can read it after
+/// a CPI). Nothing is written to the account or any bank, even if passed writable: deltas apply to
+/// copies, and the risk engine only reads the banks it prices (oracle prices are recorded by
+/// `lending_pool_accrue_bank_interest` alone).
+///
+/// Deltas go through the same balance logic as a real deposit or borrow, so e.g. a borrow from a
+/// paused bank or above its borrow limit errors here too. Interest is not accrued first.
+///
+/// Remaining accounts: the banks and oracles of the account's balances after the deltas are
+/// applied, in any order.
+pub fn lending_account_simulate_health<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingAccountSimulateHealth<'info>>,
+    deltas: Vec<SimulatedBalanceDelta>,
+) -> MarginfiResult<SimulatedHealth> {
+    let mut simulated_account = {
+        let marginfi_account = ctx.accounts.marginfi_account.load_data()?;
+        let mut account = Box::new(MarginfiAccount::zeroed());
+        *account = *marginfi_account;
+        let extension = marginfi_account.extension().map(|extension| {
+            let mut copy = Box::new(LendingAccountExtension::zeroed());
+            *copy = *extension;
+            copy
+        });
+        SimulatedAccount::new(account, extension)?
+    };
+
+    check!(
+        !simulated_account.get_flag(ACCOUNT_IN_FLASHLOAN),
+        MarginfiError::AccountInFlashloan
+    );
+
+    for delta in deltas.iter() {
+        apply_delta(&mut simulated_account, ctx.remaining_accounts, delta)?;
+    }
+    simulated_account.sort_balances();
+
+    let mut health_cache = ExtendedHealthCache::zeroed();
+    let (init_result, engine) = RiskEngine::check_account_init_health(
+        &simulated_account,
+        simulated_account.balances(),
+        ctx.remaining_accounts,
+        &mut Some(&mut health_cache),
+    );
+    let engine = match engine {
+        Some(engine) => engine,
+        // The engine failed to load, e.g. a missing bank or oracle account
+        None => return Err(init_result.unwrap_err()),
+    };
+    let init_err = match init_result {
+        Ok(()) => 0,
+        Err(Error::AnchorError(err)) => err.error_code_number,
+        Err(err) => return Err(err),
+    };
+
+    let (asset_value_maint, liability_value_maint) =
+        engine.get_account_health_components(RiskRequirementType::Maintenance, &mut None)?;
+    let (asset_value_equity, liability_value_equity) =
+        engine.get_account_health_components(RiskRequirementType::Equity, &mut None)?;
+
+    Ok(SimulatedHealth {
+        asset_value_init: health_cache.asset_value,
+        liability_value_init: health_cache.liability_value,
+        asset_value_maint: asset_value_maint.into(),
+        liability_value_maint: liability_value_maint.into(),
+        asset_value_equity: asset_value_equity.into(),
+        liability_value_equity: liability_value_equity.into(),
+        init_err,
+    })
+}
+
+/// Boxed, as the extension alone doesn't fit in a stack frame
+type SimulatedAccount = MarginfiAccountData<Box<MarginfiAccount>, Box<LendingAccountExtension>>;
+
+fn apply_delta<'info>(
+    simulated_account: &mut SimulatedAccount,
+    remaining_ais: &'info [AccountInfo<'info>],
+    delta: &SimulatedBalanceDelta,
+) -> MarginfiResult {
+    let bank_ai = remaining_ais
+        .iter()
+        .find(|ai| *ai.key == delta.bank)
+        .ok_or_else(|| error!(MarginfiError::InvalidBankAccount))?;
+
+    let mut bank = Box::new(Bank::zeroed());
+    *bank = *AccountLoader::<Bank>::try_from(bank_ai)?.load()?;
+    check_eq!(
+        bank.group,
+        simulated_account.group,
+        MarginfiError::InvalidBankAccount
+    );
+    validate_asset_tags(&bank, simulated_account.balances())?;
+
+    let mut bank_account = BankAccountWrapper::find_or_create(
+        &delta.bank,
+        &mut bank,
+        simulated_account.balances_mut(),
+    )?;
+    let amount = I80F48::from_num(delta.amount.unsigned_abs());
+    if delta.amount >= 0 {
+        bank_account.increase_balance(amount)
+    } else {
+        bank_account.decrease_balance(amount)
+    }
+}
+
+#[derive(Accounts)]
+pub struct LendingAccountSimulateHealth<'info> {
+    pub marginfi_account: AccountLoader<'info, MarginfiAccount>,
+}
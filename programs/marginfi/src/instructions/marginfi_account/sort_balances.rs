@@ -1,11 +1,14 @@
-use crate::{state::marginfi_account::MarginfiAccount, MarginfiResult};
+use crate::{
+    state::marginfi_account::{MarginfiAccount, MarginfiAccountLoader},
+    MarginfiResult,
+};
 use anchor_lang::prelude::*;
 
 pub fn lending_account_sort_balances<'info>(
     ctx: Context<'_, '_, 'info, 'info, SortBalances<'info>>,
 ) -> MarginfiResult {
-    let mut marginfi_account = ctx.accounts.marginfi_account.load_mut()?;
-    marginfi_account.lending_account.sort_balances();
+    let mut marginfi_account = ctx.accounts.marginfi_account.load_data_mut()?;
+    marginfi_account.sort_balances();
     Ok(())
 }
 
@@ -5,13 +5,16 @@ use crate::{
     events::{AccountEventHeader, MarginfiAccountTransferToNewAccount},
     prelude::*,
     state::marginfi_account::{
-        LendingAccount, MarginfiAccount, ACCOUNT_DISABLED, ACCOUNT_IN_FLASHLOAN,
+        split_extended_account_data, Balance, LendingAccount, MarginfiAccount,
+        MarginfiAccountLoader, ACCOUNT_DISABLED, ACCOUNT_IN_FLASHLOAN,
     },
     state::marginfi_group::MarginfiGroup,
 };
 use anchor_lang::prelude::*;
 use bytemuck::Zeroable;
 
+use super::extend::realloc_to_extended;
+
 pub fn transfer_to_new_account(ctx: Context<TransferToNewAccount>) -> MarginfiResult {
     // Validate the global fee wallet and claim a nominal fee
     let group = ctx.accounts.group.load()?;
@@ -22,7 +25,7 @@ pub fn transfer_to_new_account(ctx: Context<TransferToNewAccount>) -> MarginfiRe
     );
     anchor_lang::system_program::transfer(ctx.accounts.transfer_fee(), ACCOUNT_TRANSFER_FEE)?;
 
-    let mut old_account = ctx.accounts.old_marginfi_account.load_mut()?;
+    let mut old_account = ctx.accounts.old_marginfi_account.load_data_mut()?;
 
     check!(
         !old_account.get_flag(ACCOUNT_IN_FLASHLOAN),
@@ -36,6 +39,19 @@ pub fn transfer_to_new_account(ctx: Context<TransferToNewAccount>) -> MarginfiRe
         MarginfiError::AccountAlreadyMigrated
     );
 
+    // An extended account's balances live in its extension, so the new account needs one too
+    let new_account_ai = ctx.accounts.new_marginfi_account.to_account_info();
+    if let Some(old_extension) = old_account.extension() {
+        realloc_to_extended(
+            &new_account_ai,
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+        )?;
+        let mut data = new_account_ai.try_borrow_mut_data()?;
+        let (_, new_extension) = split_extended_account_data(&mut data);
+        *new_extension = *old_extension;
+    }
+
     let mut new_account = ctx.accounts.new_marginfi_account.load_init()?;
     new_account.initialize(old_account.group, ctx.accounts.new_authority.key());
     new_account.lending_account = old_account.lending_account;
@@ -46,6 +62,7 @@ pub fn transfer_to_new_account(ctx: Context<TransferToNewAccount>) -> MarginfiRe
     old_account.migrated_to = ctx.accounts.new_marginfi_account.key();
 
     old_account.lending_account = LendingAccount::zeroed();
+    old_account.balances_mut().fill(Balance::zeroed());
     old_account.set_flag(ACCOUNT_DISABLED);
 
     emit!(MarginfiAccountTransferToNewAccount {
@@ -5,8 +5,11 @@ use crate::{
     events::{AccountEventHeader, LendingAccountWithdrawEvent},
     prelude::*,
     state::{
-        health_cache::HealthCache,
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, ACCOUNT_DISABLED},
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, RiskEngine,
+            ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, BankVaultType, MarginfiGroup},
     },
     utils,
@@ -14,7 +17,6 @@ use crate::{
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{clock::Clock, sysvar::Sysvar};
 use anchor_spl::token_interface::{TokenAccount, TokenInterface};
-use bytemuck::Zeroable;
 use fixed::types::I80F48;
 
 /// 1. Accrue interest
@@ -42,7 +44,7 @@ pub fn lending_account_withdraw<'info>(
     let clock = Clock::get()?;
 
     let withdraw_all = withdraw_all.unwrap_or(false);
-    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
 
     check!(
         !marginfi_account.get_flag(ACCOUNT_DISABLED),
@@ -67,9 +69,11 @@ pub fn lending_account_withdraw<'info>(
 
         let liquidity_vault_authority_bump = bank.liquidity_vault_authority_bump;
 
-        let lending_account = &mut marginfi_account.lending_account;
-        let mut bank_account =
-            BankAccountWrapper::find(&bank_loader.key(), &mut bank, lending_account)?;
+        let mut bank_account = BankAccountWrapper::find(
+            &bank_loader.key(),
+            &mut bank,
+            marginfi_account.balances_mut(),
+        )?;
 
         let amount_pre_fee = if withdraw_all {
             bank_account.withdraw_all()?
@@ -121,20 +125,21 @@ pub fn lending_account_withdraw<'info>(
             close_balance: withdraw_all,
         });
     }
-    let mut health_cache = HealthCache::zeroed();
+    let mut health_cache = ExtendedHealthCache::zeroed();
     health_cache.timestamp = clock.unix_timestamp;
 
-    marginfi_account.lending_account.sort_balances();
+    marginfi_account.sort_balances();
 
     let (risk_result, _engine) = RiskEngine::check_account_init_health(
         &marginfi_account,
+        marginfi_account.balances(),
         ctx.remaining_accounts,
         &mut Some(&mut health_cache),
     );
     risk_result?;
     health_cache.program_version = PROGRAM_VERSION;
     health_cache.set_engine_ok(true);
-    marginfi_account.health_cache = health_cache;
+    marginfi_account.set_health_cache(&health_cache);
 
     Ok(())
 }
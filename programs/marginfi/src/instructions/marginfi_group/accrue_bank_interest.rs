@@ -1,11 +1,18 @@
 use crate::{
-    state::marginfi_group::{Bank, MarginfiGroup},
+    state::{
+        marginfi_group::{Bank, MarginfiGroup},
+        price::{OracleAgeWindow, OraclePriceFeedAdapter},
+    },
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
 
-pub fn lending_pool_accrue_bank_interest(
-    ctx: Context<LendingPoolAccrueBankInterest>,
+/// Optionally, pass the bank's oracle account(s) in remaining accounts to also update the bank's
+/// on-chain oracle EMA (Switchboard pull banks only, see `Bank::oracle_ema_price`) or exchange
+/// rate (see `Bank::exchange_rate`), or set a depegged `OracleSetup::Fixed` bank to `ReduceOnly`.
+/// The oracle need only be within the bank's relaxed `oracle_max_age_relaxed` window.
+pub fn lending_pool_accrue_bank_interest<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueBankInterest<'info>>,
 ) -> MarginfiResult {
     let clock = Clock::get()?;
     let mut bank = ctx.accounts.bank.load_mut()?;
@@ -20,6 +27,16 @@ pub fn lending_pool_accrue_bank_interest(
 
     bank.update_bank_cache(group)?;
 
+    if !ctx.remaining_accounts.is_empty() {
+        let (price_feed, _) = OraclePriceFeedAdapter::try_from_bank_in_window(
+            &bank,
+            ctx.remaining_accounts,
+            &clock,
+            OracleAgeWindow::Relaxed,
+        )?;
+        bank.record_oracle_price(&price_feed, clock.unix_timestamp)?;
+    }
+
     Ok(())
 }
 
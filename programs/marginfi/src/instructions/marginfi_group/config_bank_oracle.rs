@@ -1,14 +1,27 @@
 use crate::{
-    constants::{FREEZE_SETTINGS, PYTH_PUSH_MIGRATED},
-    events::{GroupEventHeader, LendingPoolBankConfigureOracleEvent},
+    check,
+    constants::{FREEZE_SETTINGS, MAX_MEDIAN_ORACLE_SOURCES, MAX_ORACLE_KEYS, PYTH_PUSH_MIGRATED},
+    errors::MarginfiError,
+    events::{
+        GroupEventHeader, LendingPoolBankConfigureOracleEvent,
+        LendingPoolBankConfigureOracleFallbackEvent,
+    },
     state::{
+        exchange_rate::ExchangeRateConfig,
         marginfi_group::{Bank, MarginfiGroup},
         price::OracleSetup,
     },
     MarginfiResult,
 };
 use anchor_lang::prelude::*;
+use fixed::types::I80F48;
 
+/// * `oracle` - for `OracleSetup::Median`, the first source. All sources, `oracle` included, are
+///   taken from the oracle accounts passed in remaining accounts.
+/// * `oracle` - for `OracleSetup::Fixed`, the live feed guarding the peg. Set the fixed price
+///   (`BankConfigOpt::oracle_fixed_price`) first.
+/// * `oracle` - for `OracleSetup::CrossRate`, the token/SOL feed. Both it and the SOL/USD feed are
+///   taken from the oracle accounts passed in remaining accounts, in that order.
 pub fn lending_pool_configure_bank_oracle(
     ctx: Context<LendingPoolConfigureBankOracle>,
     setup: u8,
@@ -22,9 +35,40 @@ pub fn lending_pool_configure_bank_oracle(
     } else {
         let setup_type =
             OracleSetup::from_u8(setup).unwrap_or_else(|| panic!("unsupported oracle type"));
+        // Needs its exchange rate settings too, see `lending_pool_configure_bank_oracle_exchange_rate`
+        check!(
+            setup_type != OracleSetup::ExchangeRate,
+            MarginfiError::InvalidOracleSetup
+        );
 
         bank.config.oracle_setup = setup_type;
-        bank.config.oracle_keys[0] = oracle;
+        if matches!(setup_type, OracleSetup::Median | OracleSetup::CrossRate) {
+            check!(
+                ctx.remaining_accounts.len() <= MAX_MEDIAN_ORACLE_SOURCES,
+                MarginfiError::WrongNumberOfOracleAccounts
+            );
+            bank.config.oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+            for (key, ai) in bank
+                .config
+                .oracle_keys
+                .iter_mut()
+                .zip(ctx.remaining_accounts.iter())
+            {
+                *key = ai.key();
+            }
+            require_keys_eq!(
+                bank.config.oracle_keys[0],
+                oracle,
+                MarginfiError::WrongOracleAccountKeys
+            );
+        } else {
+            bank.config.oracle_keys[0] = oracle;
+            // A new primary feed drops any fallback (see
+            // `lending_pool_configure_bank_oracle_fallback`), as well as leftover median sources
+            if setup_type != OracleSetup::StakedWithPythPush {
+                bank.config.oracle_keys[1..].fill(Pubkey::default());
+            }
+        }
 
         if setup_type == OracleSetup::PythPushOracle {
             bank.config.update_config_flag(true, PYTH_PUSH_MIGRATED);
@@ -68,3 +112,135 @@ pub struct LendingPoolConfigureBankOracle<'info> {
     )]
     pub bank: AccountLoader<'info, Bank>,
 }
+
+/// Sets (or, with `Pubkey::default()`, removes) a fallback feed for a Pyth push or Switchboard pull
+/// bank, used automatically when the primary feed is stale or exceeds `oracle_max_confidence`. The
+/// fallback may be either a Pyth push or a Switchboard pull feed.
+///
+/// Remaining accounts: the primary oracle, followed by the fallback if one is being set.
+pub fn lending_pool_configure_bank_oracle_fallback(
+    ctx: Context<LendingPoolConfigureBankOracleFallback>,
+    fallback: Pubkey,
+) -> MarginfiResult {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+
+    if bank.get_flag(FREEZE_SETTINGS) {
+        panic!("cannot change oracle settings on frozen banks")
+    }
+
+    check!(
+        matches!(
+            bank.config.oracle_setup,
+            OracleSetup::PythPushOracle | OracleSetup::SwitchboardPull
+        ),
+        MarginfiError::InvalidOracleSetup
+    );
+
+    bank.config.oracle_keys[1] = fallback;
+
+    msg!("setting fallback oracle to: {:?}", fallback);
+
+    bank.config
+        .validate_oracle_setup(ctx.remaining_accounts, None, None, None)?;
+
+    emit!(LendingPoolBankConfigureOracleFallbackEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        bank: ctx.accounts.bank.key(),
+        fallback
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolConfigureBankOracleFallback<'info> {
+    #[account(
+        has_one = admin
+    )]
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
+
+/// Switches a bank to `OracleSetup::ExchangeRate`: priced as the base asset's feed times the
+/// exchange rate read from `config`'s source, e.g. an SPL stake pool. Records the current rate, which
+/// later rates may then only drift from by `max_change_per_epoch` per epoch.
+///
+/// Remaining accounts: the base asset's Pyth push or Switchboard pull feed, followed by the
+/// exchange rate account.
+pub fn lending_pool_configure_bank_oracle_exchange_rate(
+    ctx: Context<LendingPoolConfigureBankOracleExchangeRate>,
+    config: ExchangeRateConfig,
+) -> MarginfiResult {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+
+    if bank.get_flag(FREEZE_SETTINGS) {
+        panic!("cannot change oracle settings on frozen banks")
+    }
+
+    check!(
+        ctx.remaining_accounts.len() == 2,
+        MarginfiError::WrongNumberOfOracleAccounts
+    );
+    let oracle = ctx.remaining_accounts[0].key();
+
+    bank.config.oracle_setup = OracleSetup::ExchangeRate;
+    bank.config.oracle_keys = [Pubkey::default(); MAX_ORACLE_KEYS];
+    bank.config.oracle_keys[0] = oracle;
+    bank.config.oracle_keys[1] = ctx.remaining_accounts[1].key();
+    bank.config
+        .validate_oracle_setup(ctx.remaining_accounts, None, None, None)?;
+
+    let clock = Clock::get()?;
+    bank.exchange_rate.configure(&config)?;
+    let (rate, rate_epoch) = bank
+        .exchange_rate
+        .read_rate(&ctx.remaining_accounts[1], &bank.mint)?;
+    bank.exchange_rate.check_rate_age(rate_epoch, clock.epoch)?;
+    check!(rate > I80F48::ZERO, MarginfiError::InvalidOracleSetup);
+    bank.exchange_rate.record_rate(rate, clock.epoch);
+
+    msg!(
+        "setting oracle to exchange rate: {:?} x {:?} (rate {:?})",
+        bank.config.oracle_keys[0],
+        bank.config.oracle_keys[1],
+        rate.to_num::<f64>()
+    );
+
+    emit!(LendingPoolBankConfigureOracleEvent {
+        header: GroupEventHeader {
+            marginfi_group: ctx.accounts.group.key(),
+            signer: Some(*ctx.accounts.admin.key)
+        },
+        bank: ctx.accounts.bank.key(),
+        oracle_setup: OracleSetup::ExchangeRate as u8,
+        oracle
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LendingPoolConfigureBankOracleExchangeRate<'info> {
+    #[account(
+        has_one = admin
+    )]
+    pub group: AccountLoader<'info, MarginfiGroup>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
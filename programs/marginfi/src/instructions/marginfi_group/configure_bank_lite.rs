@@ -1,6 +1,6 @@
 use crate::constants::FREEZE_SETTINGS;
 use crate::set_if_some;
-use crate::state::marginfi_group::InterestRateConfigOpt;
+use crate::state::marginfi_group::{InterestRateConfigOpt, OraclePriceBounds};
 use crate::{
     state::marginfi_group::{Bank, MarginfiGroup},
     MarginfiResult,
@@ -19,6 +19,7 @@ pub fn lending_pool_configure_bank_interest_only(
         bank.config
             .interest_rate_config
             .update(&interest_rate_config);
+        bank.config.interest_rate_config.validate()?;
         msg!("Bank configured!");
     }
 
@@ -47,6 +48,7 @@ pub fn lending_pool_configure_bank_limits_only(
     deposit_limit: Option<u64>,
     borrow_limit: Option<u64>,
     total_asset_value_init_limit: Option<u64>,
+    oracle_price_bounds: Option<OraclePriceBounds>,
 ) -> MarginfiResult {
     let mut bank = ctx.accounts.bank.load_mut()?;
 
@@ -61,6 +63,11 @@ pub fn lending_pool_configure_bank_limits_only(
             bank.config.total_asset_value_init_limit,
             total_asset_value_init_limit
         );
+        if let Some(bounds) = oracle_price_bounds {
+            msg!("setting oracle price bounds: {:?}", bounds);
+            bank.config.set_oracle_price_bounds(&bounds);
+            bank.config.validate_oracle_price_bounds()?;
+        }
         msg!("Bank configured!");
     }
 
@@ -0,0 +1,28 @@
+use crate::{state::marginfi_group::MarginfiGroup, MarginfiResult};
+use anchor_lang::prelude::*;
+
+/// Configure the fee charged when owners deleverage their own accounts, see
+/// `MarginfiGroup::deleverage_insurance_fee`.
+///
+/// Admin only
+pub fn configure_deleverage(
+    ctx: Context<MarginfiGroupConfigureDeleverage>,
+    deleverage_insurance_fee: u32,
+) -> MarginfiResult {
+    let mut marginfi_group = ctx.accounts.marginfi_group.load_mut()?;
+
+    marginfi_group.update_deleverage_insurance_fee(deleverage_insurance_fee)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupConfigureDeleverage<'info> {
+    #[account(
+        mut,
+        has_one = admin
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    pub admin: Signer<'info>,
+}
@@ -0,0 +1,30 @@
+use crate::{state::marginfi_group::MarginfiGroup, MarginfiResult};
+use anchor_lang::prelude::*;
+
+/// Configure the group-wide liquidation close factor, see
+/// `MarginfiGroup::liquidation_close_factor`. Banks can override the close factor with
+/// `lending_pool_configure_bank`.
+///
+/// Admin only
+pub fn configure_liquidation(
+    ctx: Context<MarginfiGroupConfigureLiquidation>,
+    close_factor: u32,
+    full_close_threshold: u32,
+) -> MarginfiResult {
+    let mut marginfi_group = ctx.accounts.marginfi_group.load_mut()?;
+
+    marginfi_group.update_liquidation_close_factor(close_factor, full_close_threshold);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarginfiGroupConfigureLiquidation<'info> {
+    #[account(
+        mut,
+        has_one = admin
+    )]
+    pub marginfi_group: AccountLoader<'info, MarginfiGroup>,
+
+    pub admin: Signer<'info>,
+}
@@ -9,15 +9,17 @@ use crate::{
     events::{AccountEventHeader, LendingPoolBankHandleBankruptcyEvent},
     math_error,
     state::{
-        health_cache::HealthCache,
-        marginfi_account::{BankAccountWrapper, MarginfiAccount, RiskEngine, ACCOUNT_DISABLED},
+        health_cache::ExtendedHealthCache,
+        marginfi_account::{
+            BankAccountWrapper, MarginfiAccount, MarginfiAccountLoader, RiskEngine,
+            ACCOUNT_DISABLED,
+        },
         marginfi_group::{Bank, BankVaultType, MarginfiGroup},
     },
     utils, MarginfiResult,
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token_interface::{TokenAccount, TokenInterface};
-use bytemuck::Zeroable;
 use fixed::types::I80F48;
 use std::cmp::{max, min};
 
@@ -53,15 +55,19 @@ pub fn lending_pool_handle_bankruptcy<'info>(
 
     drop(bank);
 
-    let mut marginfi_account = marginfi_account_loader.load_mut()?;
+    let mut marginfi_account = marginfi_account_loader.load_data_mut()?;
 
-    let mut health_cache = HealthCache::zeroed();
+    let mut health_cache = ExtendedHealthCache::zeroed();
     health_cache.timestamp = clock.unix_timestamp;
     health_cache.program_version = PROGRAM_VERSION;
-    RiskEngine::new(&marginfi_account, ctx.remaining_accounts)?
-        .check_account_bankrupt(&mut Some(&mut health_cache))?;
+    RiskEngine::new(
+        &marginfi_account,
+        marginfi_account.balances(),
+        ctx.remaining_accounts,
+    )?
+    .check_account_bankrupt(&mut Some(&mut health_cache))?;
     health_cache.set_engine_ok(true);
-    marginfi_account.health_cache = health_cache;
+    marginfi_account.set_health_cache(&health_cache);
 
     let mut bank = bank_loader.load_mut()?;
     let group = &marginfi_group_loader.load()?;
@@ -73,20 +79,13 @@ pub fn lending_pool_handle_bankruptcy<'info>(
         bank_loader.key(),
     )?;
 
-    let lending_account_balance = marginfi_account
-        .lending_account
-        .balances
-        .iter_mut()
-        .find(|balance| balance.is_active() && balance.bank_pk == bank_loader.key());
-
-    check!(
-        lending_account_balance.is_some(),
-        MarginfiError::LendingAccountBalanceNotFound
-    );
-
-    let lending_account_balance = lending_account_balance.unwrap();
-
-    let bad_debt = bank.get_liability_amount(lending_account_balance.liability_shares.into())?;
+    let bad_debt = BankAccountWrapper::find_liability(
+        &bank_loader.key(),
+        &mut bank,
+        marginfi_account.balances_mut(),
+    )
+    .map_err(|_| MarginfiError::LendingAccountBalanceNotFound)?
+    .get_liability_amount_owed()?;
 
     check!(
         bad_debt > ZERO_AMOUNT_THRESHOLD,
@@ -156,12 +155,16 @@ pub fn lending_pool_handle_bankruptcy<'info>(
 
     // Settle bad debt.
     // The liabilities of this account and global total liabilities are reduced by `bad_debt`
-    BankAccountWrapper::find(
+    let mut bank_account = BankAccountWrapper::find_liability(
         &bank_loader.key(),
         &mut bank,
-        &mut marginfi_account.lending_account,
-    )?
-    .repay(bad_debt)?;
+        marginfi_account.balances_mut(),
+    )?;
+    if bank_account.balance.is_fixed_term() {
+        bank_account.repay_fixed_term(bad_debt)?;
+    } else {
+        bank_account.repay(bad_debt)?;
+    }
 
     bank.update_bank_cache(group)?;
 
@@ -11,6 +11,8 @@ mod config_group_fee;
 mod configure;
 mod configure_bank;
 mod configure_bank_lite;
+mod configure_deleverage;
+mod configure_liquidation;
 mod edit_global_fee;
 mod edit_stake_settings;
 mod handle_bankruptcy;
@@ -34,6 +36,8 @@ pub use config_group_fee::*;
 pub use configure::*;
 pub use configure_bank::*;
 pub use configure_bank_lite::*;
+pub use configure_deleverage::*;
+pub use configure_liquidation::*;
 pub use edit_global_fee::*;
 pub use edit_stake_settings::*;
 pub use handle_bankruptcy::*;
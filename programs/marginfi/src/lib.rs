@@ -3,8 +3,11 @@ use instructions::*;
 use prelude::*;
 
 use state::emode::{EmodeEntry, MAX_EMODE_ENTRIES};
+use state::exchange_rate::ExchangeRateConfig;
 use state::marginfi_group::WrappedI80F48;
-use state::marginfi_group::{BankConfigCompact, BankConfigOpt, InterestRateConfigOpt};
+use state::marginfi_group::{
+    BankConfigCompact, BankConfigOpt, InterestRateConfigOpt, OraclePriceBounds,
+};
 
 pub mod constants;
 pub mod errors;
@@ -52,6 +55,24 @@ pub mod marginfi {
         )
     }
 
+    /// (admin only) Sets the group-wide liquidation close factor and the unhealthiness beyond
+    /// which it no longer applies
+    pub fn marginfi_group_configure_liquidation(
+        ctx: Context<MarginfiGroupConfigureLiquidation>,
+        close_factor: u32,
+        full_close_threshold: u32,
+    ) -> MarginfiResult {
+        marginfi_group::configure_liquidation(ctx, close_factor, full_close_threshold)
+    }
+
+    /// (admin only) Sets the fee charged when owners deleverage their own accounts
+    pub fn marginfi_group_configure_deleverage(
+        ctx: Context<MarginfiGroupConfigureDeleverage>,
+        deleverage_insurance_fee: u32,
+    ) -> MarginfiResult {
+        marginfi_group::configure_deleverage(ctx, deleverage_insurance_fee)
+    }
+
     /// (Runs once per program) Configures the fee state account, where the global admin sets fees
     /// that are assessed to the protocol
     pub fn init_global_fee_state(
@@ -138,18 +159,20 @@ pub mod marginfi {
         deposit_limit: Option<u64>,
         borrow_limit: Option<u64>,
         total_asset_value_init_limit: Option<u64>,
+        oracle_price_bounds: Option<OraclePriceBounds>,
     ) -> MarginfiResult {
         marginfi_group::lending_pool_configure_bank_limits_only(
             ctx,
             deposit_limit,
             borrow_limit,
             total_asset_value_init_limit,
+            oracle_price_bounds,
         )
     }
 
     // Operational instructions
-    pub fn lending_pool_accrue_bank_interest(
-        ctx: Context<LendingPoolAccrueBankInterest>,
+    pub fn lending_pool_accrue_bank_interest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingPoolAccrueBankInterest<'info>>,
     ) -> MarginfiResult {
         marginfi_group::lending_pool_accrue_bank_interest(ctx)
     }
@@ -172,6 +195,22 @@ pub mod marginfi {
         marginfi_group::lending_pool_configure_bank_oracle(ctx, setup, oracle)
     }
 
+    /// (admin only)
+    pub fn lending_pool_configure_bank_oracle_fallback(
+        ctx: Context<LendingPoolConfigureBankOracleFallback>,
+        fallback: Pubkey,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_configure_bank_oracle_fallback(ctx, fallback)
+    }
+
+    /// (admin only)
+    pub fn lending_pool_configure_bank_oracle_exchange_rate(
+        ctx: Context<LendingPoolConfigureBankOracleExchangeRate>,
+        config: ExchangeRateConfig,
+    ) -> MarginfiResult {
+        marginfi_group::lending_pool_configure_bank_oracle_exchange_rate(ctx, config)
+    }
+
     pub fn lending_pool_close_bank(ctx: Context<LendingPoolCloseBank>) -> MarginfiResult {
         marginfi_group::lending_pool_close_bank(ctx)
     }
@@ -314,6 +353,60 @@ pub mod marginfi {
         marginfi_account::lending_account_liquidate(ctx, asset_amount)
     }
 
+    /// Liquidate several (asset bank, liability bank) pairs of an unhealthy marginfi account in one
+    /// go, checking health once before and once after
+    pub fn lending_account_liquidate_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateBatch<'info>>,
+        legs: Vec<LiquidationLeg>,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_liquidate_batch(ctx, legs)
+    }
+
+    /// Liquidate a lending account balance of an unhealthy marginfi account, paying and receiving
+    /// tokens directly instead of through a liquidator marginfi account
+    pub fn lending_account_liquidate_direct<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountLiquidateDirect<'info>>,
+        asset_amount: u64,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_liquidate_direct(ctx, asset_amount)
+    }
+
+    /// (account authority only) Repay a liability and withdraw collateral worth the same at
+    /// oracle price, improving account health without paying a liquidation fee
+    pub fn lending_account_deleverage<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountDeleverage<'info>>,
+        asset_amount: u64,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_deleverage(ctx, asset_amount)
+    }
+
+    /// (account authority only) Borrow at a fixed rate until a fixed maturity, `duration`
+    /// seconds from now
+    pub fn lending_account_borrow_fixed_term<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountBorrowFixedTerm<'info>>,
+        amount: u64,
+        duration: u32,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_borrow_fixed_term(ctx, amount, duration)
+    }
+
+    pub fn lending_account_repay_fixed_term<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountRepayFixedTerm<'info>>,
+        amount: u64,
+        repay_all: Option<bool>,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_repay_fixed_term(ctx, amount, repay_all)
+    }
+
+    /// (account authority only) Extend a matured fixed-term borrow for another `duration`
+    /// seconds at the current rate
+    pub fn lending_account_roll_fixed_term<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountRollFixedTerm<'info>>,
+        duration: u32,
+    ) -> MarginfiResult {
+        marginfi_account::lending_account_roll_fixed_term(ctx, duration)
+    }
+
     pub fn lending_account_withdraw_emissions<'info>(
         ctx: Context<'_, '_, 'info, 'info, LendingAccountWithdrawEmissions<'info>>,
     ) -> MarginfiResult {
@@ -342,18 +435,35 @@ pub mod marginfi {
         marginfi_account::transfer_to_new_account(ctx)
     }
 
+    /// Grow a marginfi account from 16 to 32 balances. One-way; `fee_payer` covers the rent.
+    pub fn marginfi_account_extend(ctx: Context<MarginfiAccountExtend>) -> MarginfiResult {
+        marginfi_account::marginfi_account_extend(ctx)
+    }
+
     pub fn lending_account_sort_balances<'info>(
         ctx: Context<'_, '_, 'info, 'info, SortBalances<'info>>,
     ) -> MarginfiResult {
         marginfi_account::lending_account_sort_balances(ctx)
     }
 
+    /// Refresh the account's `HealthCache` and emit it with a per-balance breakdown in
+    /// `HealthPulseEvent`. Only extended accounts also store that breakdown on-chain, for standard
+    /// accounts the event is the only source of it.
     pub fn lending_account_pulse_health<'info>(
         ctx: Context<'_, '_, 'info, 'info, PulseHealth<'info>>,
     ) -> MarginfiResult {
         marginfi_account::lending_account_pulse_health(ctx)
     }
 
+    /// Health of a marginfi account after hypothetical deposits/borrows, without changing any
+    /// state. The result is returned through return data.
+    pub fn lending_account_simulate_health<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LendingAccountSimulateHealth<'info>>,
+        deltas: Vec<SimulatedBalanceDelta>,
+    ) -> MarginfiResult<SimulatedHealth> {
+        marginfi_account::lending_account_simulate_health(ctx, deltas)
+    }
+
     pub fn lending_account_close_balance(
         ctx: Context<LendingAccountCloseBalance>,
     ) -> MarginfiResult {
@@ -18,8 +18,12 @@ pub struct BankCache {
     pub borrowing_rate: u32,
     pub interest_accumulated_for: u32,
     pub accumulated_since_last_update: WrappedI80F48,
+    /// The plateau rate currently in effect if adaptive interest rates are enabled, otherwise 0.
+    /// u32::MAX = 1000%
+    pub adaptive_plateau_rate: u32,
+    _pad0: [u8; 4],
     // Space reserved for future fields
-    _reserved0: [u8; 128],
+    _reserved0: [[u8; 8]; 15],
 }
 
 impl Default for BankCache {
@@ -34,6 +38,10 @@ impl BankCache {
         self.lending_rate = apr_to_u32(interest_rates.lending_rate_apr);
         self.borrowing_rate = apr_to_u32(interest_rates.borrowing_rate_apr);
     }
+
+    pub fn update_adaptive_plateau_rate(&mut self, plateau_rate: Option<I80F48>) {
+        self.adaptive_plateau_rate = plateau_rate.map(apr_to_u32).unwrap_or(0);
+    }
 }
 
 /// Useful when converting an I80F48 apr into a BankCache u32 from 0-1000. Clamps to 1000% if
@@ -45,6 +53,15 @@ pub fn apr_to_u32(value: I80F48) -> u32 {
     (ratio * I80F48::from_num(u32::MAX)).to_num::<u32>()
 }
 
+/// Inverse of `apr_to_u32`
+pub fn u32_to_apr(value: u32) -> I80F48 {
+    I80F48::from_num(value)
+        .checked_div(I80F48::from_num(u32::MAX))
+        .unwrap()
+        .checked_mul(I80F48::from_num(10))
+        .unwrap()
+}
+
 #[derive(Debug, Clone)]
 pub struct ComputedInterestRates {
     pub base_rate_apr: I80F48,
@@ -0,0 +1,305 @@
+use crate::constants::{SPL_STAKE_POOL_ID, U32_MAX};
+use crate::errors::MarginfiError;
+use crate::prelude::MarginfiResult;
+use crate::state::marginfi_group::WrappedI80F48;
+use crate::{assert_struct_align, assert_struct_size, check, math_error, msg};
+use anchor_lang::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use fixed::types::I80F48;
+use type_layout::TypeLayout;
+
+/// The rate is an SPL stake pool's (e.g. jitoSOL) total lamports over its pool token supply
+pub const EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL: u8 = 1;
+/// The rate is read from any account as `numerator / denominator`, two little-endian u64s at
+/// configured offsets, alongside the epoch the account was last updated (also a u64)
+pub const EXCHANGE_RATE_SOURCE_CUSTOM: u8 = 2;
+
+// SPL stake pool `StakePool` layout: account type (1), 3 pubkeys (96), withdraw bump (1), 5 pubkeys
+// (160, the third is the pool mint), then total lamports, pool token supply, and last update epoch
+const SPL_STAKE_POOL_ACCOUNT_TYPE: u8 = 1;
+const SPL_STAKE_POOL_POOL_MINT_OFFSET: usize = 162;
+const SPL_STAKE_POOL_TOTAL_LAMPORTS_OFFSET: u32 = 258;
+const SPL_STAKE_POOL_POOL_TOKEN_SUPPLY_OFFSET: u32 = 266;
+const SPL_STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET: u32 = 274;
+
+/// The admin-set part of `ExchangeRateSettings`, see there.
+/// * The offsets are ignored for `EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL`
+#[derive(AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExchangeRateConfig {
+    pub source: u8,
+    pub max_age_epochs: u8,
+    pub max_change_per_epoch: u32,
+    pub numerator_offset: u32,
+    pub denominator_offset: u32,
+    pub epoch_offset: u32,
+}
+
+assert_struct_size!(ExchangeRateSettings, 48);
+assert_struct_align!(ExchangeRateSettings, 8);
+/// How an `OracleSetup::ExchangeRate` bank reads the on-chain exchange rate between its token and
+/// the base asset priced by its feed, e.g. SOL per jitoSOL. Both amounts are taken in native
+/// units, so the token and the base asset are expected to share decimals.
+#[repr(C)]
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Debug, PartialEq, Eq, Pod, Zeroable, Copy, Clone, TypeLayout,
+)]
+pub struct ExchangeRateSettings {
+    /// `EXCHANGE_RATE_SOURCE_*`, 0 if the bank doesn't use an exchange rate
+    pub source: u8,
+    /// How many epochs the rate account may go without an update (e.g. a stake pool awaiting its
+    /// epoch crank) before the rate is considered stale
+    pub max_age_epochs: u8,
+    pub _pad0: [u8; 2],
+    /// Max change of the rate from `last_rate`, per epoch since `last_rate_epoch` (at least one),
+    /// as a fraction of `last_rate`. A rate that moves faster than this is rejected, not used.
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub max_change_per_epoch: u32,
+    /// Byte offsets of the numerator, denominator, and last update epoch in the rate account
+    pub numerator_offset: u32,
+    pub denominator_offset: u32,
+    pub epoch_offset: u32,
+    pub _pad1: [u8; 4],
+    /// The rate as of `last_rate_epoch`. Recorded when configured, then at most once an epoch
+    /// when interest is accrued with the bank's oracles passed (see `Bank::record_oracle_price`).
+    pub last_rate: WrappedI80F48,
+    pub last_rate_epoch: u64,
+}
+
+impl Default for ExchangeRateSettings {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
+
+impl ExchangeRateSettings {
+    /// Applies `config`, forgetting any previously recorded rate
+    pub fn configure(&mut self, config: &ExchangeRateConfig) -> MarginfiResult {
+        check!(
+            config.max_change_per_epoch != 0,
+            MarginfiError::InvalidOracleSetup
+        );
+
+        let (numerator_offset, denominator_offset, epoch_offset) = match config.source {
+            EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL => (
+                SPL_STAKE_POOL_TOTAL_LAMPORTS_OFFSET,
+                SPL_STAKE_POOL_POOL_TOKEN_SUPPLY_OFFSET,
+                SPL_STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET,
+            ),
+            EXCHANGE_RATE_SOURCE_CUSTOM => (
+                config.numerator_offset,
+                config.denominator_offset,
+                config.epoch_offset,
+            ),
+            _ => return err!(MarginfiError::InvalidOracleSetup),
+        };
+
+        *self = Self {
+            source: config.source,
+            max_age_epochs: config.max_age_epochs,
+            max_change_per_epoch: config.max_change_per_epoch,
+            numerator_offset,
+            denominator_offset,
+            epoch_offset,
+            ..Self::zeroed()
+        };
+
+        Ok(())
+    }
+
+    /// The current rate in `ai` and the epoch the account last updated it. A stake pool must be
+    /// the one that issues `bank_mint`.
+    pub fn read_rate(&self, ai: &AccountInfo, bank_mint: &Pubkey) -> MarginfiResult<(I80F48, u64)> {
+        let data = ai.try_borrow_data()?;
+
+        if self.source == EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL {
+            check!(
+                ai.owner == &SPL_STAKE_POOL_ID
+                    && data.first() == Some(&SPL_STAKE_POOL_ACCOUNT_TYPE),
+                MarginfiError::InvalidOracleSetup
+            );
+
+            let pool_mint = data
+                .get(SPL_STAKE_POOL_POOL_MINT_OFFSET..SPL_STAKE_POOL_POOL_MINT_OFFSET + 32)
+                .ok_or(MarginfiError::InvalidOracleSetup)?;
+            if pool_mint != bank_mint.as_ref() {
+                msg!(
+                    "stake pool mint {:?} is not the bank's mint {:?}",
+                    Pubkey::try_from(pool_mint).unwrap(),
+                    bank_mint
+                );
+                return err!(MarginfiError::InvalidOracleSetup);
+            }
+        }
+
+        let read_u64 = |offset: u32| -> MarginfiResult<u64> {
+            let start = offset as usize;
+            let bytes = data
+                .get(start..start + 8)
+                .ok_or(MarginfiError::InvalidOracleSetup)?;
+            Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let numerator = read_u64(self.numerator_offset)?;
+        let denominator = read_u64(self.denominator_offset)?;
+        let epoch = read_u64(self.epoch_offset)?;
+
+        let rate = I80F48::from_num(numerator)
+            .checked_div(I80F48::from_num(denominator))
+            .ok_or_else(math_error!())?;
+
+        Ok((rate, epoch))
+    }
+
+    /// The current rate in `ai`, if it is fresh and within `max_change_per_epoch` of `last_rate`
+    pub fn load_rate(
+        &self,
+        ai: &AccountInfo,
+        bank_mint: &Pubkey,
+        current_epoch: u64,
+    ) -> MarginfiResult<I80F48> {
+        let (rate, rate_epoch) = self.read_rate(ai, bank_mint)?;
+        self.check_rate_age(rate_epoch, current_epoch)?;
+        self.check_rate_change(rate, current_epoch)?;
+
+        Ok(rate)
+    }
+
+    pub fn check_rate_age(&self, rate_epoch: u64, current_epoch: u64) -> MarginfiResult {
+        if current_epoch.saturating_sub(rate_epoch) > self.max_age_epochs as u64 {
+            msg!(
+                "exchange rate last updated in epoch {:?}, now {:?}",
+                rate_epoch,
+                current_epoch
+            );
+            return err!(MarginfiError::ExchangeRateStale);
+        }
+
+        Ok(())
+    }
+
+    fn check_rate_change(&self, rate: I80F48, current_epoch: u64) -> MarginfiResult {
+        let last_rate: I80F48 = self.last_rate.into();
+        check!(last_rate > I80F48::ZERO, MarginfiError::InvalidOracleSetup);
+
+        let epochs = current_epoch.saturating_sub(self.last_rate_epoch).max(1);
+        let max_change = last_rate
+            .checked_mul(I80F48::from_num(self.max_change_per_epoch))
+            .ok_or_else(math_error!())?
+            .checked_div(U32_MAX)
+            .ok_or_else(math_error!())?
+            .checked_mul(I80F48::from_num(epochs))
+            .ok_or_else(math_error!())?;
+        let change = rate.checked_sub(last_rate).ok_or_else(math_error!())?.abs();
+
+        if change > max_change {
+            let rate: f64 = rate.to_num();
+            let last_rate: f64 = last_rate.to_num();
+            msg!(
+                "exchange rate {:?} moved too far from {:?}",
+                rate,
+                last_rate
+            );
+            return err!(MarginfiError::ExchangeRateChangeExceeded);
+        }
+
+        Ok(())
+    }
+
+    /// Records `rate` as of `current_epoch`, unless a rate was already recorded this epoch (which
+    /// would let the allowed change compound within one epoch).
+    pub fn record_rate(&mut self, rate: I80F48, current_epoch: u64) {
+        let last_rate: I80F48 = self.last_rate.into();
+        if last_rate != I80F48::ZERO && current_epoch <= self.last_rate_epoch {
+            return;
+        }
+
+        self.last_rate = rate.into();
+        self.last_rate_epoch = current_epoch;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_stake_pool_data(
+        pool_mint: &Pubkey,
+        total_lamports: u64,
+        pool_token_supply: u64,
+        epoch: u64,
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; 300];
+        data[0] = SPL_STAKE_POOL_ACCOUNT_TYPE;
+        data[162..194].copy_from_slice(pool_mint.as_ref());
+        data[258..266].copy_from_slice(&total_lamports.to_le_bytes());
+        data[266..274].copy_from_slice(&pool_token_supply.to_le_bytes());
+        data[274..282].copy_from_slice(&epoch.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn exchange_rate_spl_stake_pool() {
+        let mut settings = ExchangeRateSettings::default();
+        settings
+            .configure(&ExchangeRateConfig {
+                source: EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL,
+                max_age_epochs: 1,
+                max_change_per_epoch: u32::MAX / 100,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut lamports = 0u64;
+        // 1.1 SOL per pool token, last updated in epoch 10
+        let mut data = mock_stake_pool_data(&mint, 1_100, 1_000, 10);
+        let ai = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &SPL_STAKE_POOL_ID,
+            false,
+            0,
+        );
+
+        let (rate, epoch) = settings.read_rate(&ai, &mint).unwrap();
+        assert_eq!(rate, I80F48::from_num(11) / I80F48::from_num(10));
+        assert_eq!(epoch, 10);
+
+        // Nothing recorded yet
+        assert!(settings.load_rate(&ai, &mint, 10).is_err());
+        settings.record_rate(rate, 10);
+        assert_eq!(settings.load_rate(&ai, &mint, 11).unwrap(), rate);
+        let err = settings.load_rate(&ai, &mint, 12).unwrap_err();
+        assert_eq!(err, MarginfiError::ExchangeRateStale.into());
+
+        // 1.1 -> 1.12 is ~1.8%, more than one epoch's 1%, but fine over two epochs
+        ai.try_borrow_mut_data()
+            .unwrap()
+            .copy_from_slice(&mock_stake_pool_data(&mint, 1_120, 1_000, 11));
+        let err = settings.load_rate(&ai, &mint, 11).unwrap_err();
+        assert_eq!(err, MarginfiError::ExchangeRateChangeExceeded.into());
+        ai.try_borrow_mut_data()
+            .unwrap()
+            .copy_from_slice(&mock_stake_pool_data(&mint, 1_120, 1_000, 12));
+        assert!(settings.load_rate(&ai, &mint, 12).is_ok());
+
+        // Only one record per epoch
+        settings.record_rate(I80F48::from_num(1.2), 10);
+        assert_eq!(I80F48::from(settings.last_rate), rate);
+
+        // Another pool's rate can't price this bank's mint
+        let err = settings.read_rate(&ai, &Pubkey::new_unique()).unwrap_err();
+        assert_eq!(err, MarginfiError::InvalidOracleSetup.into());
+
+        // Wrong owner
+        let wrong_owner = Pubkey::new_unique();
+        let ai = AccountInfo {
+            owner: &wrong_owner,
+            ..ai
+        };
+        assert!(settings.read_rate(&ai, &mint).is_err());
+    }
+}
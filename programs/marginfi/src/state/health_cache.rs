@@ -1,14 +1,33 @@
 use crate::{
-    assert_struct_align, assert_struct_size, state::marginfi_account::MAX_LENDING_ACCOUNT_BALANCES,
+    assert_struct_align, assert_struct_size,
+    state::marginfi_account::{
+        RequirementType, WeightedValue, MAX_LENDING_ACCOUNT_BALANCES,
+        MAX_LENDING_ACCOUNT_BALANCES_EXTENDED,
+    },
     state::marginfi_group::WrappedI80F48,
+    state::price::OraclePriceType,
 };
 use anchor_lang::prelude::*;
 use bytemuck::{Pod, Zeroable};
+use std::ops::{Deref, DerefMut};
 use type_layout::TypeLayout;
 
 pub const HEALTHY: u32 = 1;
 pub const ENGINE_OK: u32 = 2;
 pub const ORACLE_OK: u32 = 4;
+pub const MAINT_CACHED: u32 = 8;
+
+/// `oracle_sources` entry for a balance priced by its bank's primary feed (or not priced at all)
+pub const ORACLE_SOURCE_PRIMARY: u8 = 0;
+/// `oracle_sources` entry for a balance priced by its bank's fallback feed
+pub const ORACLE_SOURCE_FALLBACK: u8 = 1;
+
+/// `HealthCacheBalance` price type of a balance that was not priced
+pub const PRICE_TYPE_NONE: u8 = 0;
+/// `HealthCacheBalance` price type of a balance valued at its oracle's time weighted price
+pub const PRICE_TYPE_TIME_WEIGHTED: u8 = 1;
+/// `HealthCacheBalance` price type of a balance valued at its oracle's real time price
+pub const PRICE_TYPE_REAL_TIME: u8 = 2;
 
 assert_struct_size!(HealthCache, 304);
 assert_struct_align!(HealthCache, 8);
@@ -70,7 +89,10 @@ pub struct HealthCache {
     ///   oracle cranks ran recently enough. Check `internal_err` and `err_index` for more details
     ///   in some circumstances. Invalid if generated after borrow/withdraw (these instructions will
     ///   ignore oracle issues if health is still satisfactory with some balance zeroed out).
-    /// * 8, 16, 32, 64, 128, etc - reserved for future use
+    /// * MAINT CACHED = 8 - If set, the maintenance values (the `_maint` fields here and in each
+    ///   `HealthCacheBalance`) were computed for this cache. If 0, they are zero because only the
+    ///   initial requirement was checked, e.g. in borrow/withdraw.
+    /// * 16, 32, 64, 128, etc - reserved for future use
     pub flags: u32,
     /// If the engine errored, look here for the error code. If the engine returns ok, you may also
     /// check here to see if the risk engine rejected this tx (3009).
@@ -78,6 +100,9 @@ pub struct HealthCache {
     /// Each price corresponds to that index of Balances in the LendingAccount. Useful for debugging
     /// or liquidator consumption, to determine how a user's position is priced internally.
     /// * An f64 stored as bytes
+    /// * The full per-balance breakdown (`HealthCacheBalance`) isn't stored here for lack of space:
+    ///   standard accounts only get it from `HealthPulseEvent`, extended accounts also store it in
+    ///   their `LendingAccountExtension`
     pub prices: [[u8; 8]; MAX_LENDING_ACCOUNT_BALANCES],
     /// Errors in asset oracles are ignored (with prices treated as zero). If you see a zero price
     /// and the `ORACLE_OK` flag is not set, check here to see what error was ignored internally.
@@ -89,9 +114,14 @@ pub struct HealthCache {
     pub pad0: [u8; 2],
     pub internal_liq_err: u32,
     pub internal_bankruptcy_err: u32,
+    /// Which feed priced each balance, indexed like `prices`
+    /// * ORACLE_SOURCE_PRIMARY = 0 - the bank's primary oracle, or the balance was not priced
+    /// * ORACLE_SOURCE_FALLBACK = 1 - the bank's fallback oracle, because the primary was stale or
+    ///   exceeded `oracle_max_confidence`
+    pub oracle_sources: [u8; MAX_LENDING_ACCOUNT_BALANCES],
     // Note: the largest on-chain deployed cache was 304 bytes so all future caches must be at least
     // this big to avoid data corruption in the empty space.
-    pub reserved0: [u8; 32],
+    pub reserved0: [u8; 16],
     pub reserved1: [u8; 16],
 }
 
@@ -127,4 +157,157 @@ impl HealthCache {
     pub fn is_engine_ok(&self) -> bool {
         self.flags & ENGINE_OK != 0
     }
+
+    pub fn set_maint_cached(&mut self, cached: bool) {
+        if cached {
+            self.flags |= MAINT_CACHED;
+        } else {
+            self.flags &= !MAINT_CACHED;
+        }
+    }
+
+    pub fn is_maint_cached(&self) -> bool {
+        self.flags & MAINT_CACHED != 0
+    }
+}
+
+const EXTENSION_BALANCES: usize =
+    MAX_LENDING_ACCOUNT_BALANCES_EXTENDED - MAX_LENDING_ACCOUNT_BALANCES;
+
+assert_struct_size!(HealthCacheExtension, 160);
+assert_struct_align!(HealthCacheExtension, 1);
+#[repr(C)]
+#[derive(
+    AnchorDeserialize, AnchorSerialize, Copy, Clone, Zeroable, Pod, PartialEq, Eq, TypeLayout, Debug,
+)]
+/// The `HealthCache` entries of an extended account's balances past the first
+/// `MAX_LENDING_ACCOUNT_BALANCES`, stored in its `LendingAccountExtension`
+pub struct HealthCacheExtension {
+    /// `HealthCache::prices`, continued
+    pub prices: [[u8; 8]; EXTENSION_BALANCES],
+    /// `HealthCache::oracle_sources`, continued
+    pub oracle_sources: [u8; EXTENSION_BALANCES],
+    pub reserved0: [u8; 16],
+}
+
+assert_struct_size!(HealthCacheBalance, 64);
+assert_struct_align!(HealthCacheBalance, 1);
+#[repr(C)]
+#[derive(
+    AnchorDeserialize, AnchorSerialize, Copy, Clone, Zeroable, Pod, PartialEq, Eq, TypeLayout, Debug,
+)]
+/// How the risk engine valued one balance, indexed like `HealthCache::prices`. `HealthCache` only
+/// has room for the totals, so these are stored in the `LendingAccountExtension` of extended
+/// accounts and emitted with `HealthPulseEvent` for all accounts.
+/// * Standard (16 balance) accounts don't store these anywhere on-chain. Their liquidators must
+///   read the `HealthPulseEvent` of a `lending_account_pulse_health` (e.g. by simulating it), or
+///   use only `HealthCache::prices` and `oracle_sources`
+/// * Values are in dollars, with all confidence adjustments and weights applied, and zero on the
+///   side the balance isn't on
+/// * Maintenance fields are zero unless `MAINT_CACHED` is set
+/// * All f64s stored as bytes
+pub struct HealthCacheBalance {
+    /// Contribution to `HealthCache::asset_value`
+    pub asset_value: [u8; 8],
+    /// Contribution to `HealthCache::liability_value`
+    pub liability_value: [u8; 8],
+    /// Contribution to `HealthCache::asset_value_maint`
+    pub asset_value_maint: [u8; 8],
+    /// Contribution to `HealthCache::liability_value_maint`
+    pub liability_value_maint: [u8; 8],
+    /// Weight applied for the initial requirement: the bank's, or the emode entry's if higher,
+    /// times the `asset_weight_init` discount above the bank's total asset value limit
+    pub weight_init: [u8; 8],
+    /// Weight applied for the maintenance requirement: the bank's, or the emode entry's if higher
+    pub weight_maint: [u8; 8],
+    /// Price used for the maintenance requirement (`HealthCache::prices` has the initial one)
+    pub price_maint: [u8; 8],
+    /// Oracle price type behind `HealthCache::prices`, see `PRICE_TYPE_TIME_WEIGHTED` etc. Usually
+    /// time weighted, unless the bank takes the worse price when its oracle deviates.
+    pub price_type_init: u8,
+    /// Oracle price type behind `price_maint`
+    pub price_type_maint: u8,
+    pub pad0: [u8; 6],
+}
+
+impl HealthCacheBalance {
+    /// Records the balance's value for `requirement_type`. Equity values aren't kept per balance.
+    pub(crate) fn set_value(&mut self, requirement_type: RequirementType, value: &WeightedValue) {
+        let asset_value = value.asset_value.to_num::<f64>().to_le_bytes();
+        let liability_value = value.liability_value.to_num::<f64>().to_le_bytes();
+        let weight = value.weight.to_num::<f64>().to_le_bytes();
+        let price_type = match value.price_type {
+            None => PRICE_TYPE_NONE,
+            Some(OraclePriceType::TimeWeighted) => PRICE_TYPE_TIME_WEIGHTED,
+            Some(OraclePriceType::RealTime) => PRICE_TYPE_REAL_TIME,
+        };
+
+        match requirement_type {
+            RequirementType::Initial => {
+                self.asset_value = asset_value;
+                self.liability_value = liability_value;
+                self.weight_init = weight;
+                self.price_type_init = price_type;
+            }
+            RequirementType::Maintenance => {
+                self.asset_value_maint = asset_value;
+                self.liability_value_maint = liability_value;
+                self.weight_maint = weight;
+                self.price_maint = value.price.to_num::<f64>().to_le_bytes();
+                self.price_type_maint = price_type;
+            }
+            RequirementType::Equity => {}
+        }
+    }
+}
+
+/// A `HealthCache` with room for every balance an account can have, plus the per-balance
+/// breakdown. Only extended accounts store the `extension` and `balances`, see
+/// `MarginfiAccountData::set_health_cache`.
+#[derive(Clone)]
+pub struct ExtendedHealthCache {
+    pub cache: HealthCache,
+    pub extension: HealthCacheExtension,
+    /// On the heap, it's too big for the stack of the instructions using it
+    pub balances: Vec<HealthCacheBalance>,
+}
+
+impl ExtendedHealthCache {
+    pub fn zeroed() -> Self {
+        Self {
+            cache: HealthCache::zeroed(),
+            extension: HealthCacheExtension::zeroed(),
+            balances: vec![HealthCacheBalance::zeroed(); MAX_LENDING_ACCOUNT_BALANCES_EXTENDED],
+        }
+    }
+
+    /// Records the price of the balance at `index`, see `HealthCache::prices`
+    pub fn set_price(&mut self, index: usize, price: [u8; 8]) {
+        match index.checked_sub(MAX_LENDING_ACCOUNT_BALANCES) {
+            None => self.cache.prices[index] = price,
+            Some(index) => self.extension.prices[index] = price,
+        }
+    }
+
+    /// Records the oracle source of the balance at `index`, see `HealthCache::oracle_sources`
+    pub fn set_oracle_source(&mut self, index: usize, source: u8) {
+        match index.checked_sub(MAX_LENDING_ACCOUNT_BALANCES) {
+            None => self.cache.oracle_sources[index] = source,
+            Some(index) => self.extension.oracle_sources[index] = source,
+        }
+    }
+}
+
+impl Deref for ExtendedHealthCache {
+    type Target = HealthCache;
+
+    fn deref(&self) -> &HealthCache {
+        &self.cache
+    }
+}
+
+impl DerefMut for ExtendedHealthCache {
+    fn deref_mut(&mut self) -> &mut HealthCache {
+        &mut self.cache
+    }
 }
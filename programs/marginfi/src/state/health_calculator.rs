@@ -0,0 +1,582 @@
+use crate::constants::EXP_10_I80F48;
+use crate::errors::MarginfiError;
+use crate::prelude::MarginfiResult;
+use crate::state::emode::{reconcile_emode_configs, EmodeConfig};
+use crate::state::marginfi_account::{
+    Balance, BalanceSide, BalanceValuation, RequirementType, RiskRequirementType, WeightedValue,
+};
+use crate::state::marginfi_group::{Bank, RiskTier};
+use crate::state::price::{OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceSnapshot};
+use crate::{check, math_error};
+use anchor_lang::prelude::*;
+use bytemuck::Zeroable;
+use fixed::types::I80F48;
+use std::cmp::{max, min};
+
+/// A bank of the account together with its price, as passed to `HealthCalculator::new`
+#[derive(Clone, Copy)]
+pub struct HealthCalculatorBank {
+    pub key: Pubkey,
+    pub bank: Bank,
+    pub price: PriceSnapshot,
+}
+
+struct PricedBank<'a> {
+    key: Pubkey,
+    bank: &'a Bank,
+    price_feed: MarginfiResult<OraclePriceFeedAdapter>,
+}
+
+/// Health of a marginfi account computed from already deserialized accounts and off-chain prices,
+/// e.g. for bots and frontends. Balances are valued exactly like the on-chain `RiskEngine` does
+/// (see `BalanceValuation`), including emode, price bias, confidence caps and weight discounts.
+///
+/// Interest is not accrued, pass banks that were (see `Bank::accrue_interest`) if that matters.
+/// Amounts are in native tokens, values in dollars.
+pub struct HealthCalculator<'a> {
+    balances: &'a [Balance],
+    banks: Vec<PricedBank<'a>>,
+    current_timestamp: i64,
+}
+
+impl<'a> HealthCalculator<'a> {
+    /// `balances` are all of the account's balances (see `MarginfiAccountData::balances`).
+    /// `banks` must contain every bank the account has a balance in, plus any bank that will be
+    /// queried. A price that doesn't load (e.g. out of the bank's price bounds) is treated like an
+    /// oracle that failed to load on-chain.
+    pub fn new(
+        balances: &'a [Balance],
+        banks: &'a [HealthCalculatorBank],
+        current_timestamp: i64,
+    ) -> Self {
+        let banks = banks
+            .iter()
+            .map(|b| PricedBank {
+                key: b.key,
+                bank: &b.bank,
+                price_feed: OraclePriceFeedAdapter::try_from_snapshot(&b.bank, b.price),
+            })
+            .collect();
+
+        Self {
+            balances,
+            banks,
+            current_timestamp,
+        }
+    }
+
+    /// The weighted (assets, liabilities) of the account, like
+    /// `RiskEngine::get_account_health_components`
+    pub fn get_health_components(
+        &self,
+        requirement_type: RiskRequirementType,
+    ) -> MarginfiResult<(I80F48, I80F48)> {
+        let emode_config = self.get_emode_config(None)?;
+        self.calc_health_components(requirement_type.to_weight_type(), &emode_config)
+    }
+
+    /// The spot price of `bank_pk` at which the account would become liquidatable, assuming all
+    /// other prices stay put and the bank's confidence interval moves along with its price. None if
+    /// no price of this bank could make it liquidatable, e.g. the account has no exposure to it.
+    pub fn get_liquidation_price(&self, bank_pk: &Pubkey) -> MarginfiResult<Option<I80F48>> {
+        let emode_config = self.get_emode_config(None)?;
+        let (assets, liabilities) =
+            self.calc_health_components(RequirementType::Maintenance, &emode_config)?;
+        let health = assets.checked_sub(liabilities).ok_or_else(math_error!())?;
+
+        // Values are linear in the price, so the account's health moves by `exposure` for every
+        // 100% the price moves
+        let mut exposure = I80F48::ZERO;
+        for balance in self.active_balances().filter(|b| b.bank_pk == *bank_pk) {
+            let value =
+                self.calc_weighted_value(balance, RequirementType::Maintenance, &emode_config)?;
+            exposure = exposure
+                .checked_add(value.asset_value)
+                .ok_or_else(math_error!())?
+                .checked_sub(value.liability_value)
+                .ok_or_else(math_error!())?;
+        }
+        if exposure == I80F48::ZERO {
+            return Ok(None);
+        }
+
+        let factor = I80F48::ONE
+            .checked_sub(health.checked_div(exposure).ok_or_else(math_error!())?)
+            .ok_or_else(math_error!())?;
+        if factor <= I80F48::ZERO {
+            return Ok(None);
+        }
+
+        let priced_bank = self.get_bank(bank_pk)?;
+        let price_feed = priced_bank
+            .price_feed
+            .as_ref()
+            .map_err(|_| error!(MarginfiError::InternalLogicError))?;
+        let price = price_feed.get_price_of_type(
+            OraclePriceType::RealTime,
+            None,
+            priced_bank.bank.config.oracle_max_confidence,
+        )?;
+
+        Ok(Some(price.checked_mul(factor).ok_or_else(math_error!())?))
+    }
+
+    /// The most the account can withdraw from its deposit in `bank_pk` and still pass the initial
+    /// health check. Doesn't consider the bank's vault balance.
+    pub fn get_max_withdrawable(&self, bank_pk: &Pubkey) -> MarginfiResult<I80F48> {
+        let priced_bank = self.get_bank(bank_pk)?;
+        if priced_bank
+            .bank
+            .assert_operational_mode(Some(false))
+            .is_err()
+            || self.has_matured_fixed_term()
+        {
+            return Ok(I80F48::ZERO);
+        }
+
+        let emode_config = self.get_emode_config(None)?;
+        let (assets, liabilities) =
+            self.calc_health_components(RequirementType::Initial, &emode_config)?;
+        let health = assets.checked_sub(liabilities).ok_or_else(math_error!())?;
+        if health < I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        let (amount, value) = self.get_deposit(priced_bank, &emode_config)?;
+
+        partial_amount(amount, value, health)
+    }
+
+    /// The most the account can borrow from `bank_pk` and still pass the initial health check.
+    /// Like `lending_account_borrow`, any deposit in the bank is withdrawn first and counts toward
+    /// the amount. Includes the bank's origination fee and its borrow limit, as well as its
+    /// remaining liquidity (ignoring tokens in its vault that aren't accounted as deposits).
+    pub fn get_max_borrowable(&self, bank_pk: &Pubkey) -> MarginfiResult<I80F48> {
+        let priced_bank = self.get_bank(bank_pk)?;
+        let bank = priced_bank.bank;
+        if bank.assert_operational_mode(Some(false)).is_err() || self.has_matured_fixed_term() {
+            return Ok(I80F48::ZERO);
+        }
+
+        // Once the account borrows, the bank's emode config applies as well
+        let emode_config = self.get_emode_config(Some(bank_pk))?;
+        let (assets, liabilities) =
+            self.calc_health_components(RequirementType::Initial, &emode_config)?;
+        let health = assets.checked_sub(liabilities).ok_or_else(math_error!())?;
+        if health <= I80F48::ZERO {
+            return Ok(I80F48::ZERO);
+        }
+
+        let (deposit_amount, deposit_value) = self.get_deposit(priced_bank, &emode_config)?;
+        if deposit_value >= health {
+            return partial_amount(deposit_amount, deposit_value, health);
+        }
+        if bank.assert_operational_mode(Some(true)).is_err() || self.violates_risk_tiers(bank_pk)? {
+            return Ok(deposit_amount);
+        }
+        let health = health
+            .checked_sub(deposit_value)
+            .ok_or_else(math_error!())?;
+
+        // Every token received adds (1 + fee) tokens of liability
+        let fee_factor = I80F48::ONE
+            .checked_add(
+                bank.config
+                    .interest_rate_config
+                    .protocol_origination_fee
+                    .into(),
+            )
+            .ok_or_else(math_error!())?;
+
+        let total_liabilities = bank.get_total_liability_amount()?;
+        let mut max_liability = bank
+            .get_asset_amount(bank.total_asset_shares.into())?
+            .checked_sub(deposit_amount)
+            .ok_or_else(math_error!())?
+            .checked_sub(total_liabilities)
+            .ok_or_else(math_error!())?;
+        if bank.config.is_borrow_limit_active() {
+            let remaining_limit = I80F48::from_num(bank.config.borrow_limit)
+                .checked_sub(total_liabilities)
+                .ok_or_else(math_error!())?;
+            max_liability = min(max_liability, remaining_limit);
+        }
+
+        let liability_value_per_token = self.calc_liability_value_per_token(priced_bank)?;
+        if liability_value_per_token > I80F48::ZERO {
+            let healthy_liability = health
+                .checked_div(liability_value_per_token)
+                .ok_or_else(math_error!())?;
+            max_liability = min(max_liability, healthy_liability);
+        }
+
+        let borrow_amount = max(max_liability, I80F48::ZERO)
+            .checked_div(fee_factor)
+            .ok_or_else(math_error!())?;
+
+        Ok(deposit_amount
+            .checked_add(borrow_amount)
+            .ok_or_else(math_error!())?)
+    }
+
+    fn active_balances(&self) -> impl Iterator<Item = &'a Balance> {
+        self.balances.iter().filter(|b| b.is_active())
+    }
+
+    fn get_bank(&self, bank_pk: &Pubkey) -> MarginfiResult<&PricedBank<'a>> {
+        self.banks
+            .iter()
+            .find(|b| b.key == *bank_pk)
+            .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))
+    }
+
+    /// Reconciles the emode configs of all banks the account borrows from, and `borrow_bank_pk`
+    fn get_emode_config(&self, borrow_bank_pk: Option<&Pubkey>) -> MarginfiResult<EmodeConfig> {
+        let mut liability_banks: Vec<&Pubkey> = self
+            .active_balances()
+            .filter(|b| !b.is_empty(BalanceSide::Liabilities))
+            .map(|b| &b.bank_pk)
+            .collect();
+        if let Some(bank_pk) = borrow_bank_pk {
+            liability_banks.push(bank_pk);
+        }
+        liability_banks.sort();
+        liability_banks.dedup();
+
+        let configs = liability_banks
+            .into_iter()
+            .map(|bank_pk| Ok(self.get_bank(bank_pk)?.bank.emode.emode_config))
+            .collect::<MarginfiResult<Vec<_>>>()?;
+
+        Ok(reconcile_emode_configs(configs))
+    }
+
+    fn calc_weighted_value(
+        &self,
+        balance: &Balance,
+        requirement_type: RequirementType,
+        emode_config: &EmodeConfig,
+    ) -> MarginfiResult<WeightedValue> {
+        let priced_bank = self.get_bank(&balance.bank_pk)?;
+
+        BalanceValuation {
+            bank: priced_bank.bank,
+            price_feed: &priced_bank.price_feed,
+            price_stale: false,
+            balance,
+            current_timestamp: self.current_timestamp,
+        }
+        .calc_weighted_value(requirement_type, emode_config)
+    }
+
+    fn calc_health_components(
+        &self,
+        requirement_type: RequirementType,
+        emode_config: &EmodeConfig,
+    ) -> MarginfiResult<(I80F48, I80F48)> {
+        let mut total_assets = I80F48::ZERO;
+        let mut total_liabilities = I80F48::ZERO;
+
+        for balance in self.active_balances() {
+            let value = self.calc_weighted_value(balance, requirement_type, emode_config)?;
+            total_assets = total_assets
+                .checked_add(value.asset_value)
+                .ok_or_else(math_error!())?;
+            total_liabilities = total_liabilities
+                .checked_add(value.liability_value)
+                .ok_or_else(math_error!())?;
+        }
+
+        Ok((total_assets, total_liabilities))
+    }
+
+    /// The amount and initial value of the account's (variable rate) deposit in the bank
+    fn get_deposit(
+        &self,
+        priced_bank: &PricedBank,
+        emode_config: &EmodeConfig,
+    ) -> MarginfiResult<(I80F48, I80F48)> {
+        let balance = self.active_balances().find(|b| {
+            b.bank_pk == priced_bank.key && !b.is_fixed_term() && !b.is_empty(BalanceSide::Assets)
+        });
+
+        match balance {
+            Some(balance) => {
+                let amount = priced_bank
+                    .bank
+                    .get_asset_amount(balance.asset_shares.into())?;
+                let value =
+                    self.calc_weighted_value(balance, RequirementType::Initial, emode_config)?;
+                Ok((amount, value.asset_value))
+            }
+            None => Ok((I80F48::ZERO, I80F48::ZERO)),
+        }
+    }
+
+    /// The initial value of a liability of one native token in the bank
+    fn calc_liability_value_per_token(&self, priced_bank: &PricedBank) -> MarginfiResult<I80F48> {
+        let one_token = EXP_10_I80F48[priced_bank.bank.mint_decimals as usize];
+        let balance = Balance {
+            active: 1,
+            bank_pk: priced_bank.key,
+            liability_shares: priced_bank.bank.get_liability_shares(one_token)?.into(),
+            ..Balance::empty_deactivated()
+        };
+
+        let value = BalanceValuation {
+            bank: priced_bank.bank,
+            price_feed: &priced_bank.price_feed,
+            price_stale: false,
+            balance: &balance,
+            current_timestamp: self.current_timestamp,
+        }
+        .calc_weighted_value(RequirementType::Initial, &EmodeConfig::zeroed())?;
+
+        Ok(value
+            .liability_value
+            .checked_div(one_token)
+            .ok_or_else(math_error!())?)
+    }
+
+    fn has_matured_fixed_term(&self) -> bool {
+        self.active_balances()
+            .any(|b| b.is_fixed_term_matured(self.current_timestamp))
+    }
+
+    /// Whether borrowing from the bank would leave an isolated liability next to another one, see
+    /// `RiskEngine::check_account_risk_tiers`
+    fn violates_risk_tiers(&self, borrow_bank_pk: &Pubkey) -> MarginfiResult<bool> {
+        let mut liability_banks: Vec<&Pubkey> = self
+            .active_balances()
+            .filter(|b| !b.is_empty(BalanceSide::Liabilities))
+            .map(|b| &b.bank_pk)
+            .chain(std::iter::once(borrow_bank_pk))
+            .collect();
+        liability_banks.sort();
+        liability_banks.dedup();
+
+        if liability_banks.len() == 1 {
+            return Ok(false);
+        }
+        for bank_pk in liability_banks {
+            if self.get_bank(bank_pk)?.bank.config.risk_tier == RiskTier::Isolated {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// The part of `amount` (worth `value`) that can go while keeping `health` non-negative
+fn partial_amount(amount: I80F48, value: I80F48, health: I80F48) -> MarginfiResult<I80F48> {
+    check!(health >= I80F48::ZERO, MarginfiError::InternalLogicError);
+    if value <= health {
+        return Ok(amount);
+    }
+
+    Ok(amount
+        .checked_mul(health)
+        .ok_or_else(math_error!())?
+        .checked_div(value)
+        .ok_or_else(math_error!())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_eq_with_tolerance;
+    use crate::constants::ORACLE_DEVIATION_WORSE_PRICE;
+    use crate::state::health_cache::{
+        HealthCacheBalance, PRICE_TYPE_REAL_TIME, PRICE_TYPE_TIME_WEIGHTED,
+    };
+    use crate::state::marginfi_group::{BankConfig, BankOperationalState};
+    use fixed_macro::types::I80F48;
+
+    const SOL_BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+    const USDC_BANK: Pubkey = Pubkey::new_from_array([2; 32]);
+
+    fn bank(
+        key: Pubkey,
+        mint_decimals: u8,
+        price: I80F48,
+        config: BankConfig,
+    ) -> HealthCalculatorBank {
+        HealthCalculatorBank {
+            key,
+            bank: Bank {
+                mint_decimals,
+                asset_share_value: I80F48::ONE.into(),
+                liability_share_value: I80F48::ONE.into(),
+                config: BankConfig {
+                    operational_state: BankOperationalState::Operational,
+                    risk_tier: RiskTier::Collateral,
+                    borrow_limit: u64::MAX,
+                    ..config
+                },
+                ..Default::default()
+            },
+            price: PriceSnapshot {
+                price,
+                conf_interval: I80F48::ZERO,
+                ema_price: price,
+                ema_conf_interval: I80F48::ZERO,
+            },
+        }
+    }
+
+    /// 10 SOL at $100 (weights 0.5/0.75) against 300 USDC borrowed from a bank with 1000 USDC
+    fn setup() -> ([Balance; 2], Vec<HealthCalculatorBank>) {
+        let sol_bank = bank(
+            SOL_BANK,
+            9,
+            I80F48!(100),
+            BankConfig {
+                asset_weight_init: I80F48!(0.5).into(),
+                asset_weight_maint: I80F48!(0.75).into(),
+                ..Default::default()
+            },
+        );
+        let mut usdc_bank = bank(
+            USDC_BANK,
+            6,
+            I80F48::ONE,
+            BankConfig {
+                asset_weight_init: I80F48::ONE.into(),
+                asset_weight_maint: I80F48::ONE.into(),
+                ..Default::default()
+            },
+        );
+        usdc_bank.bank.total_asset_shares = I80F48!(1_000_000_000).into();
+        usdc_bank.bank.total_liability_shares = I80F48!(300_000_000).into();
+
+        let balances = [
+            Balance {
+                active: 1,
+                bank_pk: SOL_BANK,
+                asset_shares: I80F48!(10_000_000_000).into(),
+                ..Balance::empty_deactivated()
+            },
+            Balance {
+                active: 1,
+                bank_pk: USDC_BANK,
+                liability_shares: I80F48!(300_000_000).into(),
+                ..Balance::empty_deactivated()
+            },
+        ];
+
+        (balances, vec![sol_bank, usdc_bank])
+    }
+
+    #[test]
+    fn health_calculator() -> anyhow::Result<()> {
+        let (balances, banks) = setup();
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        let tolerance = I80F48!(0.0001);
+
+        let (assets, liabilities) =
+            calculator.get_health_components(RiskRequirementType::Initial)?;
+        assert_eq_with_tolerance!(assets, I80F48!(500), tolerance);
+        assert_eq_with_tolerance!(liabilities, I80F48!(300), tolerance);
+        let (assets, liabilities) =
+            calculator.get_health_components(RiskRequirementType::Maintenance)?;
+        assert_eq_with_tolerance!(assets, I80F48!(750), tolerance);
+        assert_eq_with_tolerance!(liabilities, I80F48!(300), tolerance);
+
+        // 10 SOL * $40 * 0.75 = $300, 300 USDC * $2.5 = $750
+        let sol_price = calculator.get_liquidation_price(&SOL_BANK)?.unwrap();
+        assert_eq_with_tolerance!(sol_price, I80F48!(40), tolerance);
+        let usdc_price = calculator.get_liquidation_price(&USDC_BANK)?.unwrap();
+        assert_eq_with_tolerance!(usdc_price, I80F48!(2.5), tolerance);
+
+        // $200 of initial health left: 4 SOL, or 200 USDC
+        let sol_withdrawable = calculator.get_max_withdrawable(&SOL_BANK)?;
+        assert_eq_with_tolerance!(sol_withdrawable, I80F48!(4_000_000_000), I80F48::ONE);
+        assert_eq!(calculator.get_max_withdrawable(&USDC_BANK)?, I80F48::ZERO);
+        let usdc_borrowable = calculator.get_max_borrowable(&USDC_BANK)?;
+        assert_eq_with_tolerance!(usdc_borrowable, I80F48!(200_000_000), I80F48::ONE);
+        // Borrowing SOL withdraws the deposit first, which already uses up all health
+        let sol_borrowable = calculator.get_max_borrowable(&SOL_BANK)?;
+        assert_eq_with_tolerance!(sol_borrowable, I80F48!(4_000_000_000), I80F48::ONE);
+
+        let unknown_bank = Pubkey::new_unique();
+        assert!(calculator.get_max_borrowable(&unknown_bank).is_err());
+        assert_eq!(calculator.get_liquidation_price(&unknown_bank)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn health_calculator_borrow_caps() -> anyhow::Result<()> {
+        let (balances, mut banks) = setup();
+
+        // Only 100 USDC left under the borrow limit
+        banks[1].bank.config.borrow_limit = 400_000_000;
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        let usdc_borrowable = calculator.get_max_borrowable(&USDC_BANK)?;
+        assert_eq_with_tolerance!(usdc_borrowable, I80F48!(100_000_000), I80F48::ONE);
+
+        // A 1% origination fee: borrowing x adds 1.01x to the liability
+        banks[1].bank.config.borrow_limit = u64::MAX;
+        banks[1]
+            .bank
+            .config
+            .interest_rate_config
+            .protocol_origination_fee = I80F48!(0.01).into();
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        let usdc_borrowable = calculator.get_max_borrowable(&USDC_BANK)?;
+        assert_eq_with_tolerance!(
+            usdc_borrowable,
+            I80F48!(200_000_000) / I80F48!(1.01),
+            I80F48::ONE
+        );
+
+        // Reduce-only banks can't be borrowed from
+        banks[1].bank.config.operational_state = BankOperationalState::ReduceOnly;
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        assert_eq!(calculator.get_max_borrowable(&USDC_BANK)?, I80F48::ZERO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn health_cache_balance_breakdown() -> anyhow::Result<()> {
+        let (balances, mut banks) = setup();
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        let emode_config = EmodeConfig::zeroed();
+        let to_f64 = |bytes: [u8; 8]| f64::from_le_bytes(bytes);
+
+        let mut sol = HealthCacheBalance::zeroed();
+        for requirement_type in [RequirementType::Initial, RequirementType::Maintenance] {
+            let value =
+                calculator.calc_weighted_value(&balances[0], requirement_type, &emode_config)?;
+            sol.set_value(requirement_type, &value);
+        }
+        assert_eq!(to_f64(sol.asset_value), 500.);
+        assert_eq!(to_f64(sol.asset_value_maint), 750.);
+        assert_eq!(to_f64(sol.liability_value), 0.);
+        assert_eq!(to_f64(sol.weight_init), 0.5);
+        assert_eq!(to_f64(sol.weight_maint), 0.75);
+        assert_eq!(to_f64(sol.price_maint), 100.);
+        assert_eq!(sol.price_type_init, PRICE_TYPE_TIME_WEIGHTED);
+        assert_eq!(sol.price_type_maint, PRICE_TYPE_REAL_TIME);
+
+        // USDC spot trades 50% above its EMA, and the bank takes the worse (higher) price for debt
+        banks[1].price.price = I80F48!(1.5);
+        banks[1].bank.config.oracle_max_deviation = u32::MAX / 10;
+        banks[1].bank.config.config_flags |= ORACLE_DEVIATION_WORSE_PRICE;
+        let calculator = HealthCalculator::new(&balances, &banks, 0);
+        let value = calculator.calc_weighted_value(
+            &balances[1],
+            RequirementType::Initial,
+            &emode_config,
+        )?;
+        let mut usdc = HealthCacheBalance::zeroed();
+        usdc.set_value(RequirementType::Initial, &value);
+        assert_eq!(to_f64(usdc.liability_value), 450.);
+        assert_eq!(to_f64(usdc.weight_init), 1.);
+        assert_eq!(usdc.price_type_init, PRICE_TYPE_REAL_TIME);
+
+        Ok(())
+    }
+}
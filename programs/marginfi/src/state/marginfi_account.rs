@@ -1,4 +1,4 @@
-use super::price::OraclePriceFeedAdapter;
+use super::price::{OracleAgeWindow, OraclePriceFeedAdapter, OracleSetup};
 use crate::constants::{
     ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED, BANKRUPT_THRESHOLD,
     EMISSIONS_FLAG_BORROW_ACTIVE, EMISSIONS_FLAG_LENDING_ACTIVE, EMPTY_BALANCE_THRESHOLD,
@@ -7,7 +7,10 @@ use crate::constants::{
 use crate::errors::MarginfiError;
 use crate::prelude::MarginfiResult;
 use crate::state::emode::{reconcile_emode_configs, EmodeConfig};
-use crate::state::health_cache::HealthCache;
+use crate::state::health_cache::{
+    ExtendedHealthCache, HealthCache, HealthCacheBalance, HealthCacheExtension,
+    ORACLE_SOURCE_FALLBACK, ORACLE_SOURCE_PRIMARY,
+};
 use crate::state::marginfi_group::{Bank, RiskTier, WrappedI80F48};
 use crate::state::price::PriceAdapter;
 use crate::state::price::{OraclePriceType, PriceBias};
@@ -18,23 +21,35 @@ use anchor_lang::prelude::*;
 use anchor_spl::token_interface::Mint;
 use bytemuck::{Pod, Zeroable};
 use fixed::types::I80F48;
+use std::cell::{Ref, RefMut};
 use std::cmp::{max, min};
+use std::ops::{Deref, DerefMut};
 use type_layout::TypeLayout;
 
 pub const ACCOUNT_IN_FLASHLOAN: u64 = 1 << 1;
 pub const ACCOUNT_DISABLED: u64 = 1 << 0;
 pub const ACCOUNT_TRANSFER_AUTHORITY_DEPRECATED: u64 = 1 << 3;
+/// The account was extended with `marginfi_account_extend`, see `LendingAccountExtension`
+pub const ACCOUNT_EXTENDED: u64 = 1 << 4;
 
-/// 4 for `ASSET_TAG_STAKED` (bank, oracle, lst mint, lst pool), 2 for all others (bank, oracle)
+/// 4 for `ASSET_TAG_STAKED` (bank, oracle, lst mint, lst pool), 1 + the number of sources for
+/// `OracleSetup::Median` (bank, oracles), 3 for `OracleSetup::CrossRate` (bank, token/SOL oracle,
+/// SOL/USD oracle) and `OracleSetup::ExchangeRate` (bank, oracle, exchange rate account), 3 with a
+/// fallback oracle (bank, oracle, fallback), 2 for all others (bank, oracle)
 pub fn get_remaining_accounts_per_bank(bank: &Bank) -> MarginfiResult<usize> {
+    if bank.config.oracle_setup == OracleSetup::Median {
+        return Ok(1 + bank.config.get_median_oracle_sources_len());
+    }
+    if matches!(
+        bank.config.oracle_setup,
+        OracleSetup::CrossRate | OracleSetup::ExchangeRate
+    ) || bank.config.has_oracle_fallback()
+    {
+        return Ok(3);
+    }
     get_remaining_accounts_per_asset_tag(bank.config.asset_tag)
 }
 
-/// 4 for `ASSET_TAG_STAKED` (bank, oracle, lst mint, lst pool), 2 for all others (bank, oracle)
-fn get_remaining_accounts_per_balance(balance: &Balance) -> MarginfiResult<usize> {
-    get_remaining_accounts_per_asset_tag(balance.bank_asset_tag)
-}
-
 /// 4 for `ASSET_TAG_STAKED` (bank, oracle, lst mint, lst pool), 2 for all others (bank, oracle)
 fn get_remaining_accounts_per_asset_tag(asset_tag: u8) -> MarginfiResult<usize> {
     match asset_tag {
@@ -79,66 +94,223 @@ impl RiskRequirementType {
 pub struct BankAccountWithPriceFeed<'a, 'info> {
     bank: AccountLoader<'info, Bank>,
     price_feed: Box<MarginfiResult<OraclePriceFeedAdapter>>,
+    /// The price is older than the bank's strict `oracle_max_age` (only possible when loaded in
+    /// `OracleAgeWindow::Relaxed`), see `Bank::oracle_stale_liquidation_haircut`
+    price_stale: bool,
     balance: &'a Balance,
+    current_timestamp: i64,
 }
 
 impl<'info> BankAccountWithPriceFeed<'_, 'info> {
     pub fn load<'a>(
-        lending_account: &'a LendingAccount,
+        balances: &'a [Balance],
         remaining_ais: &'info [AccountInfo<'info>],
+        window: OracleAgeWindow,
     ) -> MarginfiResult<Vec<BankAccountWithPriceFeed<'a, 'info>>> {
         let clock = Clock::get()?;
-        let mut account_index = 0;
+        let remaining_ais = RemainingAccountsIndex::new(remaining_ais);
 
-        lending_account
-            .balances
+        balances
             .iter()
             .filter(|balance| balance.is_active())
             .map(|balance| {
-                let bank_ai: Option<&AccountInfo<'info>> = remaining_ais.get(account_index);
-                if bank_ai.is_none() {
-                    msg!("Ran out of remaining accounts at {:?}", account_index);
-                    return err!(MarginfiError::InvalidBankAccount);
-                }
-                let bank_ai = bank_ai.unwrap();
+                let bank_index = match remaining_ais.position(&balance.bank_pk) {
+                    Some(bank_index) => bank_index,
+                    None => {
+                        msg!("Bank {:?} not in remaining accounts", balance.bank_pk);
+                        return err!(MarginfiError::InvalidBankAccount);
+                    }
+                };
+                let bank_ai = &remaining_ais.ais[bank_index];
                 let bank_al = AccountLoader::<Bank>::try_from(bank_ai)?;
-
-                let num_accounts = get_remaining_accounts_per_balance(balance)?;
-                check_eq!(
-                    balance.bank_pk,
-                    *bank_ai.key,
-                    MarginfiError::InvalidBankAccount
-                );
                 let bank = bank_al.load()?;
-
-                let oracle_ai_idx = account_index + 1;
-                let oracle_ais = &remaining_ais[oracle_ai_idx..oracle_ai_idx + num_accounts - 1];
-
-                let price_adapter = Box::new(OraclePriceFeedAdapter::try_from_bank_config(
-                    &bank.config,
-                    oracle_ais,
-                    &clock,
-                ));
-
-                account_index += num_accounts;
+                let oracle_ais = remaining_ais.get_oracle_ais(&bank, bank_index)?;
+
+                let (price_adapter, price_stale) =
+                    match OraclePriceFeedAdapter::try_from_bank_in_window(
+                        &bank,
+                        &oracle_ais,
+                        &clock,
+                        window,
+                    ) {
+                        Ok((price_feed, price_stale)) => (Box::new(Ok(price_feed)), price_stale),
+                        Err(e) => (Box::new(Err(e)), false),
+                    };
+                drop(bank);
 
                 Ok(BankAccountWithPriceFeed {
                     bank: bank_al.clone(),
                     price_feed: price_adapter,
+                    price_stale,
                     balance,
+                    current_timestamp: clock.unix_timestamp,
                 })
             })
             .collect::<Result<Vec<_>>>()
     }
 
+    /// See `BalanceValuation::calc_weighted_value`
+    fn calc_weighted_value(
+        &self,
+        requirement_type: RequirementType,
+        emode_config: &EmodeConfig,
+    ) -> MarginfiResult<WeightedValue> {
+        let bank = self.bank.load()?;
+
+        BalanceValuation {
+            bank: &bank,
+            price_feed: self.price_feed.as_ref(),
+            price_stale: self.price_stale,
+            balance: self.balance,
+            current_timestamp: self.current_timestamp,
+        }
+        .calc_weighted_value(requirement_type, emode_config)
+    }
+
+    /// `ORACLE_SOURCE_FALLBACK` if the bank's fallback feed is in use, `ORACLE_SOURCE_PRIMARY`
+    /// otherwise (including when no feed could be loaded)
+    fn get_oracle_source(&self) -> u8 {
+        match self.price_feed.as_ref() {
+            Ok(price_feed) if price_feed.is_fallback() => ORACLE_SOURCE_FALLBACK,
+            _ => ORACLE_SOURCE_PRIMARY,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self, side: BalanceSide) -> bool {
+        self.balance.is_empty(side)
+    }
+}
+
+/// Remaining accounts sorted by key, so the risk engine can find each balance's bank and oracles
+/// wherever the client put them, among any number of unrelated accounts. Sorting is `O(n log n)`
+/// once, each lookup a binary search.
+pub(crate) struct RemainingAccountsIndex<'info> {
+    ais: &'info [AccountInfo<'info>],
+    /// Indices into `ais`, sorted by key
+    by_key: Vec<usize>,
+}
+
+impl<'info> RemainingAccountsIndex<'info> {
+    pub(crate) fn new(ais: &'info [AccountInfo<'info>]) -> Self {
+        let mut by_key: Vec<usize> = (0..ais.len()).collect();
+        by_key.sort_unstable_by_key(|&index| ais[index].key);
+
+        Self { ais, by_key }
+    }
+
+    /// Index into `ais` of an account with this key
+    fn position(&self, key: &Pubkey) -> Option<usize> {
+        self.by_key
+            .binary_search_by_key(&key, |&index| self.ais[index].key)
+            .ok()
+            .map(|sorted_index| self.by_key[sorted_index])
+    }
+
+    /// The account with this key, if passed
+    pub(crate) fn get(&self, key: &Pubkey) -> Option<&'info AccountInfo<'info>> {
+        self.position(key).map(|index| &self.ais[index])
+    }
+
+    /// The bank's oracle accounts, by their keys in `oracle_keys`. If any of them isn't passed,
+    /// e.g. for a Pyth bank that still stores a feed id there instead of the account key, falls
+    /// back to the accounts directly after the bank, like clients have always passed them.
+    fn get_oracle_ais(
+        &self,
+        bank: &Bank,
+        bank_index: usize,
+    ) -> MarginfiResult<Vec<AccountInfo<'info>>> {
+        let num_oracle_ais = get_remaining_accounts_per_bank(bank)? - 1;
+        if let Some(oracle_ais) = self.get_oracle_ais_by_key(bank, num_oracle_ais) {
+            return Ok(oracle_ais);
+        }
+
+        self.get_oracle_ais_after_bank(bank_index, num_oracle_ais)
+    }
+
+    /// Same as `get_oracle_ais`, for a bank passed to the instruction by name rather than among
+    /// the remaining accounts, e.g. a liquidation's asset or liability bank. The positional
+    /// fallback applies only if the bank is among the remaining accounts too.
+    pub(crate) fn find_oracle_ais(
+        &self,
+        bank: &Bank,
+        bank_pk: &Pubkey,
+    ) -> MarginfiResult<Vec<AccountInfo<'info>>> {
+        let num_oracle_ais = get_remaining_accounts_per_bank(bank)? - 1;
+        if let Some(oracle_ais) = self.get_oracle_ais_by_key(bank, num_oracle_ais) {
+            return Ok(oracle_ais);
+        }
+
+        match self.position(bank_pk) {
+            Some(bank_index) => self.get_oracle_ais_after_bank(bank_index, num_oracle_ais),
+            None => {
+                msg!("Oracles of bank {:?} not in remaining accounts", bank_pk);
+                err!(MarginfiError::WrongNumberOfOracleAccounts)
+            }
+        }
+    }
+
+    fn get_oracle_ais_by_key(
+        &self,
+        bank: &Bank,
+        num_oracle_ais: usize,
+    ) -> Option<Vec<AccountInfo<'info>>> {
+        bank.config.oracle_keys[..num_oracle_ais]
+            .iter()
+            .map(|key| self.get(key).cloned())
+            .collect()
+    }
+
+    fn get_oracle_ais_after_bank(
+        &self,
+        bank_index: usize,
+        num_oracle_ais: usize,
+    ) -> MarginfiResult<Vec<AccountInfo<'info>>> {
+        let start = bank_index + 1;
+        let oracle_ais = self
+            .ais
+            .get(start..start + num_oracle_ais)
+            .ok_or_else(|| error!(MarginfiError::WrongNumberOfOracleAccounts))?;
+
+        Ok(oracle_ais.to_vec())
+    }
+}
+
+/// A balance's value for one requirement, see `BalanceValuation::calc_weighted_value`
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct WeightedValue {
+    pub asset_value: I80F48,
+    pub liability_value: I80F48,
+    /// The oracle price the value is based on, after bias and other adjustments
+    pub price: I80F48,
+    /// The weight applied to the balance, including emode and the initial discount
+    pub weight: I80F48,
+    /// Which of the oracle's prices `price` is, `None` if the balance wasn't priced
+    pub price_type: Option<OraclePriceType>,
+    /// Internal oracle error code, if an asset's oracle did not load
+    pub err_code: u32,
+}
+
+/// A balance together with its bank and oracle price, i.e. everything needed to value it. Unlike
+/// `BankAccountWithPriceFeed` it doesn't care how the bank was loaded, which lets the off-chain
+/// `HealthCalculator` value balances exactly like the risk engine does.
+pub(crate) struct BalanceValuation<'a> {
+    pub bank: &'a Bank,
+    pub price_feed: &'a MarginfiResult<OraclePriceFeedAdapter>,
+    /// See `BankAccountWithPriceFeed::price_stale`
+    pub price_stale: bool,
+    pub balance: &'a Balance,
+    pub current_timestamp: i64,
+}
+
+impl BalanceValuation<'_> {
     #[inline(always)]
-    /// Calculate the value of the balance, which is either an asset or a liability. If it is an
-    /// asset, returns (asset_value, 0, price, 0), and if it is a liability, returns (0, liabilty
-    /// value, price, 0), where price is the actual oracle price used to determine the value after
-    /// bias adjustments, etc.
+    /// Calculate the value of the balance, which is either an asset or a liability, along with the
+    /// price and weight used to determine it. The price is the actual oracle price after bias
+    /// adjustments, etc, and the weight includes emode and the initial discount.
     ///
     /// err_code is an internal oracle error code in the event the oracle did not load. This applies
-    /// only to assets and the return type will always be (0, 0, 0, err_code).
+    /// only to assets, whose value, price and weight will then all be zero.
     ///
     /// Nuances:
     /// 1. Maintenance requirement is calculated using the real time price feed.
@@ -147,43 +319,41 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
     ///    is exceeded.
     /// 4. Assets are only calculated for collateral risk tier.
     /// 5. Oracle errors are ignored for deposits in isolated risk tier.
-    fn calc_weighted_value(
+    /// 6. Assets whose oracle spot price deviates from its EMA are worth nothing for the initial
+    ///    requirement, see `BankConfig::oracle_max_deviation`.
+    pub fn calc_weighted_value(
         &self,
         requirement_type: RequirementType,
         emode_config: &EmodeConfig,
-    ) -> MarginfiResult<(I80F48, I80F48, I80F48, u32)> {
+    ) -> MarginfiResult<WeightedValue> {
         match self.balance.get_side() {
             Some(side) => {
-                let bank = &self.bank.load()?;
+                let bank = self.bank;
 
                 match side {
                     BalanceSide::Assets => {
-                        let (value, price, err_code) =
-                            self.calc_weighted_asset_value(requirement_type, bank, emode_config)?;
-                        Ok((value, I80F48::ZERO, price, err_code))
+                        self.calc_weighted_asset_value(requirement_type, bank, emode_config)
                     }
                     BalanceSide::Liabilities => {
-                        let (value, price) =
-                            self.calc_weighted_liab_value(requirement_type, bank)?;
-                        Ok((I80F48::ZERO, value, price, 0))
+                        self.calc_weighted_liab_value(requirement_type, bank)
                     }
                 }
             }
-            None => Ok((I80F48::ZERO, I80F48::ZERO, I80F48::ZERO, 0)),
+            None => Ok(WeightedValue::default()),
         }
     }
 
-    /// Returns value, the net asset value in $, and the price used to determine that value. In most
-    /// cases, returns (value, price, 0). If there was an error loading the price feed, treats the
-    /// price as zero, and passes the u32 argument that contains the error code, i.e. the return
-    /// type is (0, 0, err_code). Other types of errors (e.g. math) will still throw.
+    /// Returns the net asset value in $, and the price and weight used to determine that value. If
+    /// there was an error loading the price feed, treats the price as zero, and passes the u32 that
+    /// contains the error code, i.e. only `err_code` is set. Other types of errors (e.g. math) will
+    /// still throw.
     #[inline(always)]
     fn calc_weighted_asset_value(
         &self,
         requirement_type: RequirementType,
         bank: &Bank,
         emode_config: &EmodeConfig,
-    ) -> MarginfiResult<(I80F48, I80F48, u32)> {
+    ) -> MarginfiResult<WeightedValue> {
         match bank.config.risk_tier {
             RiskTier::Collateral => {
                 let (price_feed, err_code) = self.try_get_price_feed();
@@ -193,11 +363,23 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
                     (&Err(_), RequirementType::Initial)
                 ) {
                     debug!("Skipping stale oracle");
-                    return Ok((I80F48::ZERO, I80F48::ZERO, err_code));
+                    return Ok(WeightedValue {
+                        err_code,
+                        ..Default::default()
+                    });
                 }
 
                 let price_feed = price_feed?;
 
+                let deviation_exceeded = bank.config.is_oracle_deviation_exceeded(price_feed)?;
+                if deviation_exceeded && matches!(requirement_type, RequirementType::Initial) {
+                    debug!("Skipping oracle deviating from ema");
+                    return Ok(WeightedValue {
+                        err_code: MarginfiError::OraclePriceDeviationExceeded.into(),
+                        ..Default::default()
+                    });
+                }
+
                 // If an emode entry exists for this bank's emode tag in the reconciled config of
                 // all borrowing banks, use its weight, otherwise use the weight designated on the
                 // collateral bank itself. If the bank's weight is higher, always use that weight.
@@ -221,10 +403,13 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
                             .get_weight(requirement_type, BalanceSide::Assets)
                     };
 
-                let lower_price = price_feed.get_price_of_type(
-                    requirement_type.get_oracle_price_type(),
-                    Some(PriceBias::Low),
-                    bank.config.oracle_max_confidence,
+                let (lower_price, price_type) = get_risk_price(
+                    price_feed,
+                    bank,
+                    requirement_type,
+                    PriceBias::Low,
+                    deviation_exceeded,
+                    self.price_stale,
                 )?;
 
                 if matches!(requirement_type, RequirementType::Initial) {
@@ -244,9 +429,15 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
                     Some(asset_weight),
                 )?;
 
-                Ok((value, lower_price, 0))
+                Ok(WeightedValue {
+                    asset_value: value,
+                    price: lower_price,
+                    weight: asset_weight,
+                    price_type: Some(price_type),
+                    ..Default::default()
+                })
             }
-            RiskTier::Isolated => Ok((I80F48::ZERO, I80F48::ZERO, 0)),
+            RiskTier::Isolated => Ok(WeightedValue::default()),
         }
     }
 
@@ -255,27 +446,43 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
         &self,
         requirement_type: RequirementType,
         bank: &Bank,
-    ) -> MarginfiResult<(I80F48, I80F48)> {
+    ) -> MarginfiResult<WeightedValue> {
         let (price_feed, _) = self.try_get_price_feed();
         let price_feed = price_feed?;
         let liability_weight = bank
             .config
             .get_weight(requirement_type, BalanceSide::Liabilities);
 
-        let higher_price = price_feed.get_price_of_type(
-            requirement_type.get_oracle_price_type(),
-            Some(PriceBias::High),
-            bank.config.oracle_max_confidence,
+        let deviation_exceeded = bank.config.is_oracle_deviation_exceeded(price_feed)?;
+        let (higher_price, price_type) = get_risk_price(
+            price_feed,
+            bank,
+            requirement_type,
+            PriceBias::High,
+            deviation_exceeded,
+            self.price_stale,
         )?;
 
+        let liability_amount = if self.balance.is_fixed_term() {
+            self.balance.get_fixed_term_owed(self.current_timestamp)?
+        } else {
+            bank.get_liability_amount(self.balance.liability_shares.into())?
+        };
+
         let value = calc_value(
-            bank.get_liability_amount(self.balance.liability_shares.into())?,
+            liability_amount,
             higher_price,
             bank.mint_decimals,
             Some(liability_weight),
         )?;
 
-        Ok((value, higher_price))
+        Ok(WeightedValue {
+            liability_value: value,
+            price: higher_price,
+            weight: liability_weight,
+            price_type: Some(price_type),
+            ..Default::default()
+        })
     }
 
     fn try_get_price_feed(&self) -> (MarginfiResult<&OraclePriceFeedAdapter>, u32) {
@@ -303,11 +510,6 @@ impl<'info> BankAccountWithPriceFeed<'_, 'info> {
             },
         }
     }
-
-    #[inline]
-    pub fn is_empty(&self, side: BalanceSide) -> bool {
-        self.balance.is_empty(side)
-    }
 }
 
 assert_struct_size!(MarginfiAccount, 2304);
@@ -337,20 +539,6 @@ impl MarginfiAccount {
         self.migrated_to = Pubkey::default();
     }
 
-    pub fn get_remaining_accounts_len(&self) -> MarginfiResult<usize> {
-        let mut total = 0usize;
-        for balance in self
-            .lending_account
-            .balances
-            .iter()
-            .filter(|b| b.is_active())
-        {
-            let num_accounts = get_remaining_accounts_per_balance(balance)?;
-            total += num_accounts;
-        }
-        Ok(total)
-    }
-
     pub fn get_flag(&self, flag: u64) -> bool {
         self.account_flags & flag != 0
     }
@@ -392,15 +580,13 @@ impl MarginfiAccount {
         Ok(())
     }
 
-    pub fn can_be_closed(&self) -> bool {
-        let is_disabled = self.get_flag(ACCOUNT_DISABLED);
-        let only_has_empty_balances = self
-            .lending_account
-            .balances
-            .iter()
-            .all(|balance| balance.get_side().is_none());
-
-        !is_disabled && only_has_empty_balances
+    /// Moves the balances into the zeroed `extension`, keeping their order, and flags the account
+    /// as extended.
+    pub fn extend(&mut self, extension: &mut LendingAccountExtension) {
+        extension.balances[..MAX_LENDING_ACCOUNT_BALANCES]
+            .copy_from_slice(&self.lending_account.balances);
+        self.lending_account.balances = [Balance::zeroed(); MAX_LENDING_ACCOUNT_BALANCES];
+        self.set_flag(ACCOUNT_EXTENDED);
     }
 }
 
@@ -417,29 +603,227 @@ pub struct LendingAccount {
     pub _padding: [u64; 8],
 }
 
+#[cfg(any(feature = "test", feature = "client"))]
 impl LendingAccount {
-    pub fn get_first_empty_balance(&self) -> Option<usize> {
-        self.balances
+    pub fn get_balance(&self, bank_pk: &Pubkey) -> Option<&Balance> {
+        self.balances.iter().find(|balance| {
+            balance.is_active() && !balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+        })
+    }
+
+    pub fn get_active_balances_iter(&self) -> impl Iterator<Item = &Balance> {
+        self.balances.iter().filter(|b| b.is_active())
+    }
+
+    pub fn get_fixed_term_balance(&self, bank_pk: &Pubkey) -> Option<&Balance> {
+        self.balances.iter().find(|balance| {
+            balance.is_active() && balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+        })
+    }
+}
+
+/// Number of balances an account extended with `marginfi_account_extend` can hold
+pub const MAX_LENDING_ACCOUNT_BALANCES_EXTENDED: usize = 32;
+
+/// Data length of an extended account: its `MarginfiAccount`, directly followed by its
+/// `LendingAccountExtension`
+pub const MARGINFI_ACCOUNT_EXTENDED_LEN: usize =
+    8 + std::mem::size_of::<MarginfiAccount>() + std::mem::size_of::<LendingAccountExtension>();
+
+assert_struct_size!(LendingAccountExtension, 5792);
+assert_struct_align!(LendingAccountExtension, 8);
+/// Trailing data of an extended account (see `ACCOUNT_EXTENDED`). Such an account keeps all of
+/// its balances here, so they stay contiguous, and its `LendingAccount::balances` stay empty.
+#[repr(C)]
+#[derive(
+    AnchorDeserialize, AnchorSerialize, Copy, Clone, Zeroable, Pod, PartialEq, Eq, TypeLayout,
+)]
+pub struct LendingAccountExtension {
+    pub balances: [Balance; MAX_LENDING_ACCOUNT_BALANCES_EXTENDED],
+    pub health_cache: HealthCacheExtension,
+    /// See `HealthCacheBalance`
+    pub health_cache_balances: [HealthCacheBalance; MAX_LENDING_ACCOUNT_BALANCES_EXTENDED],
+    pub _padding: [u64; 32],
+}
+
+/// A marginfi account along with its `LendingAccountExtension`, if it was extended. Code that
+/// works with balances should go through `balances`, which covers both layouts, rather than
+/// `lending_account`.
+pub struct MarginfiAccountData<A, E> {
+    account: A,
+    extension: Option<E>,
+}
+
+pub type MarginfiAccountRef<'a> =
+    MarginfiAccountData<Ref<'a, MarginfiAccount>, Ref<'a, LendingAccountExtension>>;
+pub type MarginfiAccountRefMut<'a> =
+    MarginfiAccountData<RefMut<'a, MarginfiAccount>, RefMut<'a, LendingAccountExtension>>;
+
+impl<A, E> MarginfiAccountData<A, E>
+where
+    A: Deref<Target = MarginfiAccount>,
+    E: Deref<Target = LendingAccountExtension>,
+{
+    /// Errors unless `extension` is given exactly if the account is extended
+    pub fn new(account: A, extension: Option<E>) -> MarginfiResult<Self> {
+        check_eq!(
+            account.get_flag(ACCOUNT_EXTENDED),
+            extension.is_some(),
+            MarginfiError::InvalidAccountExtension
+        );
+
+        Ok(Self { account, extension })
+    }
+
+    pub fn extension(&self) -> Option<&LendingAccountExtension> {
+        self.extension.as_deref()
+    }
+
+    /// All balance slots of the account, `MAX_LENDING_ACCOUNT_BALANCES` or
+    /// `MAX_LENDING_ACCOUNT_BALANCES_EXTENDED` of them
+    pub fn balances(&self) -> &[Balance] {
+        match self.extension.as_deref() {
+            Some(extension) => &extension.balances,
+            None => &self.account.lending_account.balances,
+        }
+    }
+
+    pub fn get_fixed_term_balance(&self, bank_pk: &Pubkey) -> Option<&Balance> {
+        self.balances().iter().find(|balance| {
+            balance.is_active() && balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+        })
+    }
+
+    pub fn can_be_closed(&self) -> bool {
+        let is_disabled = self.get_flag(ACCOUNT_DISABLED);
+        let only_has_empty_balances = self
+            .balances()
             .iter()
-            .position(|balance| !balance.is_active())
+            .all(|balance| balance.get_side().is_none());
+
+        !is_disabled && only_has_empty_balances
+    }
+}
+
+impl<A, E> MarginfiAccountData<A, E>
+where
+    A: DerefMut<Target = MarginfiAccount>,
+    E: DerefMut<Target = LendingAccountExtension>,
+{
+    pub fn balances_mut(&mut self) -> &mut [Balance] {
+        match self.extension.as_deref_mut() {
+            Some(extension) => &mut extension.balances,
+            None => &mut self.account.lending_account.balances,
+        }
     }
 
     pub fn sort_balances(&mut self) {
         // Sort all balances in descending order by bank_pk
-        self.balances.sort_by(|a, b| b.bank_pk.cmp(&a.bank_pk));
+        self.balances_mut()
+            .sort_by(|a, b| b.bank_pk.cmp(&a.bank_pk));
+    }
+
+    /// Stores the cache, including the `HealthCacheExtension` and per-balance breakdown if the
+    /// account is extended
+    pub fn set_health_cache(&mut self, health_cache: &ExtendedHealthCache) {
+        self.account.health_cache = health_cache.cache;
+        if let Some(extension) = self.extension.as_deref_mut() {
+            extension.health_cache = health_cache.extension;
+            extension
+                .health_cache_balances
+                .copy_from_slice(&health_cache.balances);
+        }
     }
 }
 
-#[cfg(any(feature = "test", feature = "client"))]
-impl LendingAccount {
-    pub fn get_balance(&self, bank_pk: &Pubkey) -> Option<&Balance> {
-        self.balances
-            .iter()
-            .find(|balance| balance.is_active() && balance.bank_pk.eq(bank_pk))
+impl<A: Deref<Target = MarginfiAccount>, E> Deref for MarginfiAccountData<A, E> {
+    type Target = MarginfiAccount;
+
+    fn deref(&self) -> &MarginfiAccount {
+        &self.account
     }
+}
 
-    pub fn get_active_balances_iter(&self) -> impl Iterator<Item = &Balance> {
-        self.balances.iter().filter(|b| b.is_active())
+impl<A: DerefMut<Target = MarginfiAccount>, E> DerefMut for MarginfiAccountData<A, E> {
+    fn deref_mut(&mut self) -> &mut MarginfiAccount {
+        &mut self.account
+    }
+}
+
+/// Splits the data of an account that is at least `MARGINFI_ACCOUNT_EXTENDED_LEN` long, e.g.
+/// one that was just grown to it. Unlike `MarginfiAccountLoader`, this doesn't check the
+/// discriminator or flags.
+pub fn split_extended_account_data(
+    data: &mut [u8],
+) -> (&mut MarginfiAccount, &mut LendingAccountExtension) {
+    let (account, extension) = data
+        [MarginfiAccount::DISCRIMINATOR.len()..MARGINFI_ACCOUNT_EXTENDED_LEN]
+        .split_at_mut(std::mem::size_of::<MarginfiAccount>());
+
+    (
+        bytemuck::from_bytes_mut(account),
+        bytemuck::from_bytes_mut(extension),
+    )
+}
+
+/// Loads a `MarginfiAccount` together with its `LendingAccountExtension`, see
+/// `MarginfiAccountData`. Prefer these over `load` and `load_mut`.
+pub trait MarginfiAccountLoader {
+    fn load_data(&self) -> MarginfiResult<MarginfiAccountRef<'_>>;
+    fn load_data_mut(&self) -> MarginfiResult<MarginfiAccountRefMut<'_>>;
+}
+
+impl MarginfiAccountLoader for AccountLoader<'_, MarginfiAccount> {
+    fn load_data(&self) -> MarginfiResult<MarginfiAccountRef<'_>> {
+        // Checks the owner and discriminator
+        drop(self.load()?);
+
+        let data = self.as_ref().try_borrow_data()?;
+        let (account, extension) = Ref::map_split(data, |data| {
+            data[MarginfiAccount::DISCRIMINATOR.len()..]
+                .split_at(std::mem::size_of::<MarginfiAccount>())
+        });
+        let account = Ref::map(account, bytemuck::from_bytes::<MarginfiAccount>);
+        let extension = if account.get_flag(ACCOUNT_EXTENDED) {
+            check!(
+                extension.len() >= std::mem::size_of::<LendingAccountExtension>(),
+                MarginfiError::InvalidAccountExtension
+            );
+            Some(Ref::map(extension, |data| {
+                bytemuck::from_bytes(&data[..std::mem::size_of::<LendingAccountExtension>()])
+            }))
+        } else {
+            None
+        };
+
+        MarginfiAccountData::new(account, extension)
+    }
+
+    fn load_data_mut(&self) -> MarginfiResult<MarginfiAccountRefMut<'_>> {
+        // Checks the owner, discriminator and that the account is writable
+        drop(self.load_mut()?);
+
+        let data = self.as_ref().try_borrow_mut_data()?;
+        let (account, extension) = RefMut::map_split(data, |data| {
+            data[MarginfiAccount::DISCRIMINATOR.len()..]
+                .split_at_mut(std::mem::size_of::<MarginfiAccount>())
+        });
+        let account = RefMut::map(account, bytemuck::from_bytes_mut::<MarginfiAccount>);
+        let extension = if account.get_flag(ACCOUNT_EXTENDED) {
+            check!(
+                extension.len() >= std::mem::size_of::<LendingAccountExtension>(),
+                MarginfiError::InvalidAccountExtension
+            );
+            Some(RefMut::map(extension, |data| {
+                bytemuck::from_bytes_mut(
+                    &mut data[..std::mem::size_of::<LendingAccountExtension>()],
+                )
+            }))
+        } else {
+            None
+        };
+
+        MarginfiAccountData::new(account, extension)
     }
 }
 
@@ -453,12 +837,20 @@ pub struct Balance {
     pub active: u8,
     pub bank_pk: Pubkey,
     pub bank_asset_tag: u8,
-    pub _pad0: [u8; 6],
+    /// APR locked in when a fixed-term borrow was opened or last rolled, in basis points (10_000 =
+    /// 100%). Zero for variable-rate balances.
+    pub fixed_term_rate_bps: u16,
+    /// Unix timestamp at which a fixed-term borrow is due. Zero for variable-rate balances, which
+    /// is what tells the two apart.
+    pub fixed_term_maturity: u32,
     pub asset_shares: WrappedI80F48,
+    /// Always zero for fixed-term balances, see `fixed_term_principal`
     pub liability_shares: WrappedI80F48,
     pub emissions_outstanding: WrappedI80F48,
     pub last_update: u64,
-    pub _padding: [u64; 1],
+    /// For fixed-term balances, the amount owed in native tokens as of `last_update`, rounded up.
+    /// Fixed-term borrows accrue at their own locked rate, not the bank's share value.
+    pub fixed_term_principal: u64,
 }
 
 impl Balance {
@@ -466,7 +858,41 @@ impl Balance {
         self.active != 0
     }
 
+    pub fn is_fixed_term(&self) -> bool {
+        self.fixed_term_maturity != 0
+    }
+
+    pub fn get_fixed_term_rate(&self) -> I80F48 {
+        I80F48::from_num(self.fixed_term_rate_bps) / I80F48::from_num(10_000)
+    }
+
+    pub fn is_fixed_term_matured(&self, current_timestamp: i64) -> bool {
+        self.is_fixed_term() && current_timestamp >= self.fixed_term_maturity as i64
+    }
+
+    /// Amount owed on a fixed-term borrow at `current_timestamp`, in native tokens. Interest
+    /// accrues at the locked rate since the last settlement, including past maturity.
+    pub fn get_fixed_term_owed(&self, current_timestamp: i64) -> MarginfiResult<I80F48> {
+        let owed = I80F48::from_num(self.fixed_term_principal);
+        let elapsed = current_timestamp
+            .saturating_sub(self.last_update as i64)
+            .max(0);
+        let interest = owed
+            .checked_mul(self.get_fixed_term_rate())
+            .ok_or_else(math_error!())?
+            .checked_mul(I80F48::from_num(elapsed))
+            .ok_or_else(math_error!())?
+            .checked_div(SECONDS_PER_YEAR)
+            .ok_or_else(math_error!())?;
+
+        Ok(owed.checked_add(interest).ok_or_else(math_error!())?)
+    }
+
     pub fn get_side(&self) -> Option<BalanceSide> {
+        if self.is_fixed_term() {
+            return (self.fixed_term_principal != 0).then_some(BalanceSide::Liabilities);
+        }
+
         let asset_shares = I80F48::from(self.asset_shares);
         let liability_shares = I80F48::from(self.liability_shares);
 
@@ -514,6 +940,10 @@ impl Balance {
 
     #[inline]
     pub fn is_empty(&self, side: BalanceSide) -> bool {
+        if self.is_fixed_term() && matches!(side, BalanceSide::Liabilities) {
+            return self.fixed_term_principal == 0;
+        }
+
         let shares: I80F48 = match side {
             BalanceSide::Assets => self.asset_shares,
             BalanceSide::Liabilities => self.liability_shares,
@@ -528,16 +958,69 @@ impl Balance {
             active: 0,
             bank_pk: Pubkey::default(),
             bank_asset_tag: ASSET_TAG_DEFAULT,
-            _pad0: [0; 6],
+            fixed_term_rate_bps: 0,
+            fixed_term_maturity: 0,
             asset_shares: WrappedI80F48::from(I80F48::ZERO),
             liability_shares: WrappedI80F48::from(I80F48::ZERO),
             emissions_outstanding: WrappedI80F48::from(I80F48::ZERO),
             last_update: 0,
-            _padding: [0; 1],
+            fixed_term_principal: 0,
         }
     }
 }
 
+/// The confidence-adjusted oracle price the risk engine values a balance at. If the oracle's spot
+/// price deviates from its EMA (see `BankConfig::oracle_max_deviation`) and the bank opted into
+/// `ORACLE_DEVIATION_WORSE_PRICE`, this is the worse of that price and the other of spot/EMA, so
+/// the type of the price used is returned along with it. A `stale` price gets the bank's
+/// `oracle_stale_liquidation_haircut` on top.
+#[inline(always)]
+pub(crate) fn get_risk_price(
+    price_feed: &OraclePriceFeedAdapter,
+    bank: &Bank,
+    requirement_type: RequirementType,
+    bias: PriceBias,
+    deviation_exceeded: bool,
+    stale: bool,
+) -> MarginfiResult<(I80F48, OraclePriceType)> {
+    let price_type = requirement_type.get_oracle_price_type();
+    let price =
+        price_feed.get_price_of_type(price_type, Some(bias), bank.config.oracle_max_confidence)?;
+
+    let (price, price_type) =
+        if deviation_exceeded && bank.config.uses_worse_price_on_oracle_deviation() {
+            let other_price_type = match price_type {
+                OraclePriceType::TimeWeighted => OraclePriceType::RealTime,
+                OraclePriceType::RealTime => OraclePriceType::TimeWeighted,
+            };
+            let other_price = price_feed.get_price_of_type(
+                other_price_type,
+                None,
+                bank.config.oracle_max_confidence,
+            )?;
+
+            let other_is_worse = match bias {
+                PriceBias::Low => other_price < price,
+                PriceBias::High => other_price > price,
+            };
+            if other_is_worse {
+                (other_price, other_price_type)
+            } else {
+                (price, price_type)
+            }
+        } else {
+            (price, price_type)
+        };
+
+    let price = if stale {
+        bank.apply_oracle_stale_haircut(price, bias)?
+    } else {
+        price
+    };
+
+    Ok((price, price_type))
+}
+
 // Convert a token quantity to USD value with 10⁻⁸ precision (I80F48 fixed-point format) at the current price
 #[inline]
 pub fn calc_value(
@@ -607,8 +1090,10 @@ pub struct RiskEngine<'a, 'info> {
 }
 
 impl<'info> RiskEngine<'_, 'info> {
+    /// `balances` are all of the account's balances, see `MarginfiAccountData::balances`
     pub fn new<'a>(
         marginfi_account: &'a MarginfiAccount,
+        balances: &'a [Balance],
         remaining_ais: &'info [AccountInfo<'info>],
     ) -> MarginfiResult<RiskEngine<'a, 'info>> {
         check!(
@@ -616,15 +1101,42 @@ impl<'info> RiskEngine<'_, 'info> {
             MarginfiError::AccountInFlashloan
         );
 
-        Self::new_no_flashloan_check(marginfi_account, remaining_ais)
+        Self::new_no_flashloan_check(
+            marginfi_account,
+            balances,
+            remaining_ais,
+            OracleAgeWindow::Strict,
+        )
+    }
+
+    /// Like `new`, but oracles only need to be within each bank's `oracle_max_age_relaxed`. Prices
+    /// older than the strict `oracle_max_age` get the bank's `oracle_stale_liquidation_haircut`.
+    pub fn new_for_liquidation<'a>(
+        marginfi_account: &'a MarginfiAccount,
+        balances: &'a [Balance],
+        remaining_ais: &'info [AccountInfo<'info>],
+    ) -> MarginfiResult<RiskEngine<'a, 'info>> {
+        check!(
+            !marginfi_account.get_flag(ACCOUNT_IN_FLASHLOAN),
+            MarginfiError::AccountInFlashloan
+        );
+
+        Self::new_no_flashloan_check(
+            marginfi_account,
+            balances,
+            remaining_ais,
+            OracleAgeWindow::Relaxed,
+        )
     }
 
     fn new_no_flashloan_check<'a>(
         marginfi_account: &'a MarginfiAccount,
+        balances: &'a [Balance],
         remaining_ais: &'info [AccountInfo<'info>],
+        window: OracleAgeWindow,
     ) -> MarginfiResult<RiskEngine<'a, 'info>> {
         let bank_accounts_with_price =
-            BankAccountWithPriceFeed::load(&marginfi_account.lending_account, remaining_ais)?;
+            BankAccountWithPriceFeed::load(balances, remaining_ais, window)?;
 
         let reconciled_emode_config = reconcile_emode_configs(
             bank_accounts_with_price
@@ -642,7 +1154,7 @@ impl<'info> RiskEngine<'_, 'info> {
 
     pub fn check_account_bankrupt(
         &self,
-        health_cache: &mut Option<&mut HealthCache>,
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
     ) -> MarginfiResult {
         let (total_assets, total_liabilities) =
             self.get_account_health_components(RiskRequirementType::Equity, health_cache)?;
@@ -674,10 +1186,18 @@ impl<'info> RiskEngine<'_, 'info> {
         let mut isolated_risk_count = 0;
         let mut total_liability_balances = 0;
 
-        for account in self.bank_accounts_with_price.iter() {
+        for (i, account) in self.bank_accounts_with_price.iter().enumerate() {
             if account.balance.is_empty(BalanceSide::Liabilities) {
                 continue;
             }
+            // A variable and a fixed-term borrow in the same bank count as one liability
+            let counted = self.bank_accounts_with_price[..i].iter().any(|a| {
+                a.balance.bank_pk == account.balance.bank_pk
+                    && !a.balance.is_empty(BalanceSide::Liabilities)
+            });
+            if counted {
+                continue;
+            }
             total_liability_balances += 1;
 
             let bank = account.bank.load()?;
@@ -697,11 +1217,59 @@ impl<'info> RiskEngine<'_, 'info> {
         Ok(())
     }
 
+    /// A fixed-term borrow that is past maturity must be repaid or rolled before the account can
+    /// take on any more risk. Until then it can also be liquidated, see
+    /// `has_matured_fixed_term_debt`.
+    fn check_no_matured_fixed_term(&self) -> MarginfiResult {
+        for account in self.bank_accounts_with_price.iter() {
+            if account
+                .balance
+                .is_fixed_term_matured(account.current_timestamp)
+            {
+                msg!(
+                    "fixed-term borrow in {} matured at {}",
+                    account.balance.bank_pk,
+                    account.balance.fixed_term_maturity
+                );
+                return err!(MarginfiError::FixedTermPositionMatured);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The account's only debt in this bank is a fixed-term borrow past maturity. Liquidators can
+    /// repay such a borrow even if the account is healthy, see
+    /// `check_pre_liquidation_condition_for_banks_and_get_account_health`.
+    fn has_matured_fixed_term_debt(&self, bank_pk: &Pubkey) -> bool {
+        let mut balances = self
+            .bank_accounts_with_price
+            .iter()
+            .filter(|a| a.balance.bank_pk == *bank_pk);
+
+        let matured = balances.clone().any(|a| {
+            a.balance.is_fixed_term_matured(a.current_timestamp)
+                && !a.is_empty(BalanceSide::Liabilities)
+        });
+        let has_variable_debt =
+            balances.any(|a| !a.balance.is_fixed_term() && !a.is_empty(BalanceSide::Liabilities));
+
+        matured && !has_variable_debt
+    }
+
+    /// Liquidating only matured fixed-term debt, see `has_matured_fixed_term_debt`
+    fn is_matured_fixed_term_liquidation(&self, bank_pks: &[Pubkey]) -> bool {
+        !bank_pks.is_empty()
+            && bank_pks
+                .iter()
+                .all(|bank_pk| self.has_matured_fixed_term_debt(bank_pk))
+    }
+
     /// Returns the total assets and liabilities of the account in the form of (assets, liabilities)
     pub fn get_account_health_components(
         &self,
         requirement_type: RiskRequirementType,
-        health_cache: &mut Option<&mut HealthCache>,
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
     ) -> MarginfiResult<(I80F48, I80F48)> {
         let mut total_assets: I80F48 = I80F48::ZERO;
         let mut total_liabilities: I80F48 = I80F48::ZERO;
@@ -710,8 +1278,14 @@ impl<'info> RiskEngine<'_, 'info> {
 
         for (i, bank_account) in self.bank_accounts_with_price.iter().enumerate() {
             let requirement_type = requirement_type.to_weight_type();
-            let (asset_val, liab_val, price, err_code) =
-                bank_account.calc_weighted_value(requirement_type, &self.emode_config)?;
+            let value = bank_account.calc_weighted_value(requirement_type, &self.emode_config)?;
+            let WeightedValue {
+                asset_value: asset_val,
+                liability_value: liab_val,
+                price,
+                err_code,
+                ..
+            } = value;
             if err_code != 0 && first_err_index == NO_INDEX_FOUND {
                 first_err_index = i;
                 if let Some(cache) = health_cache {
@@ -724,8 +1298,10 @@ impl<'info> RiskEngine<'_, 'info> {
                 // Note: We only record the Initial weighted price in cache, at some point we may
                 // record others.
                 if let RequirementType::Initial = requirement_type {
-                    health_cache.prices[i] = price.to_num::<f64>().to_le_bytes();
+                    health_cache.set_price(i, price.to_num::<f64>().to_le_bytes());
                 }
+                health_cache.set_oracle_source(i, bank_account.get_oracle_source());
+                health_cache.balances[i].set_value(requirement_type, &value);
             }
 
             debug!(
@@ -750,6 +1326,7 @@ impl<'info> RiskEngine<'_, 'info> {
                 RiskRequirementType::Maintenance => {
                     health_cache.asset_value_maint = total_assets.into();
                     health_cache.liability_value_maint = total_liabilities.into();
+                    health_cache.set_maint_cached(true);
                 }
                 RiskRequirementType::Equity => {
                     health_cache.asset_value_equity = total_assets.into();
@@ -763,15 +1340,21 @@ impl<'info> RiskEngine<'_, 'info> {
 
     pub fn check_account_init_health<'a>(
         marginfi_account: &'a MarginfiAccount,
+        balances: &'a [Balance],
         remaining_ais: &'info [AccountInfo<'info>],
-        health_cache: &mut Option<&mut HealthCache>,
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
     ) -> (MarginfiResult, Option<RiskEngine<'a, 'info>>) {
         if marginfi_account.get_flag(ACCOUNT_IN_FLASHLOAN) {
             // Note: All risk, including the health cache, is not applicable during flashloans
             return (Ok(()), None);
         }
 
-        let risk_engine = match Self::new_no_flashloan_check(marginfi_account, remaining_ais) {
+        let risk_engine = match Self::new_no_flashloan_check(
+            marginfi_account,
+            balances,
+            remaining_ais,
+            OracleAgeWindow::Strict,
+        ) {
             Ok(engine) => engine,
             Err(e) => return (Err(e), None),
         };
@@ -785,8 +1368,9 @@ impl<'info> RiskEngine<'_, 'info> {
     fn check_account_health(
         &self,
         requirement_type: RiskRequirementType,
-        health_cache: &mut Option<&mut HealthCache>,
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
     ) -> MarginfiResult {
+        let is_initial = matches!(requirement_type, RiskRequirementType::Initial);
         let (total_weighted_assets, total_weighted_liabilities) =
             self.get_account_health_components(requirement_type, health_cache)?;
 
@@ -811,42 +1395,72 @@ impl<'info> RiskEngine<'_, 'info> {
             return err!(MarginfiError::RiskEngineInitRejected);
         }
 
+        if is_initial {
+            self.check_no_matured_fixed_term()?;
+        }
+
         self.check_account_risk_tiers()?;
 
         Ok(())
     }
 
     /// Checks
-    /// 1. Account is liquidatable
+    /// 1. Account is liquidatable: below the maintenance requirement, or the liability bank's debt
+    ///    is a fixed-term borrow past maturity
     /// 2. Account has an outstanding liability for the provided liability bank. This check is
     ///    ignored if passing None.
     /// * returns - account health (assets - liabs)
     pub fn check_pre_liquidation_condition_and_get_account_health(
         &self,
         bank_pk: Option<&Pubkey>,
-        health_cache: &mut Option<&mut HealthCache>,
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
+    ) -> MarginfiResult<I80F48> {
+        let bank_pks = match bank_pk {
+            Some(bank_pk) => std::slice::from_ref(bank_pk),
+            None => &[],
+        };
+        self.check_pre_liquidation_condition_for_banks_and_get_account_health(
+            bank_pks,
+            health_cache,
+        )
+    }
+
+    /// Same as `check_pre_liquidation_condition_and_get_account_health`, but checks condition 2
+    /// for every liability bank in `bank_pks`, e.g. for a liquidation that repays several banks.
+    pub fn check_pre_liquidation_condition_for_banks_and_get_account_health(
+        &self,
+        bank_pks: &[Pubkey],
+        health_cache: &mut Option<&mut ExtendedHealthCache>,
     ) -> MarginfiResult<I80F48> {
         check!(
             !self.marginfi_account.get_flag(ACCOUNT_IN_FLASHLOAN),
             MarginfiError::AccountInFlashloan
         );
 
-        if bank_pk.is_some() {
-            let liability_bank_balance = self
+        for bank_pk in bank_pks {
+            // The bank may hold both a variable-rate and a fixed-term balance
+            let mut liability_bank_balances = self
                 .bank_accounts_with_price
                 .iter()
-                .find(|a| a.balance.bank_pk == *bank_pk.unwrap())
-                .ok_or(MarginfiError::LendingAccountBalanceNotFound)?;
+                .filter(|a| a.balance.bank_pk == *bank_pk)
+                .peekable();
 
             check!(
-                !liability_bank_balance.is_empty(BalanceSide::Liabilities),
-                MarginfiError::NoLiabilitiesInLiabilityBank
+                liability_bank_balances.peek().is_some(),
+                MarginfiError::LendingAccountBalanceNotFound
             );
 
-            check!(
-                liability_bank_balance.is_empty(BalanceSide::Assets),
-                MarginfiError::AssetsInLiabilityBank
-            );
+            let (has_liabilities, has_assets) =
+                liability_bank_balances.fold((false, false), |(liabs, assets), a| {
+                    (
+                        liabs || !a.is_empty(BalanceSide::Liabilities),
+                        assets || !a.is_empty(BalanceSide::Assets),
+                    )
+                });
+
+            check!(has_liabilities, MarginfiError::NoLiabilitiesInLiabilityBank);
+
+            check!(!has_assets, MarginfiError::AssetsInLiabilityBank);
         }
 
         let (assets, liabs) =
@@ -858,7 +1472,7 @@ impl<'info> RiskEngine<'_, 'info> {
             cache.set_healthy(account_health > I80F48::ZERO);
         }
 
-        if account_health > I80F48::ZERO {
+        if account_health > I80F48::ZERO && !self.is_matured_fixed_term_liquidation(bank_pks) {
             msg!(
                 "pre_liquidation_health: {} ({} - {})",
                 account_health,
@@ -881,31 +1495,47 @@ impl<'info> RiskEngine<'_, 'info> {
     ///
     /// 1. We check that the paid off liability is not zero. Assuming the liquidation always pays off some liability, this ensures that the liquidation was not too large.
     /// 2. We check that the account is still at most at the maintenance requirement level. This ensures that the liquidation was not too large overall.
+    ///    Skipped when liquidating matured fixed-term debt, which is due regardless of health.
     pub fn check_post_liquidation_condition_and_get_account_health(
         &self,
         bank_pk: &Pubkey,
         pre_liquidation_health: I80F48,
+    ) -> MarginfiResult<I80F48> {
+        self.check_post_liquidation_condition_for_banks_and_get_account_health(
+            std::slice::from_ref(bank_pk),
+            pre_liquidation_health,
+        )
+    }
+
+    /// Same as `check_post_liquidation_condition_and_get_account_health`, but checks that none of
+    /// the liabilities in `bank_pks` were exhausted, e.g. for a liquidation that repays several
+    /// banks.
+    pub fn check_post_liquidation_condition_for_banks_and_get_account_health(
+        &self,
+        bank_pks: &[Pubkey],
+        pre_liquidation_health: I80F48,
     ) -> MarginfiResult<I80F48> {
         check!(
             !self.marginfi_account.get_flag(ACCOUNT_IN_FLASHLOAN),
             MarginfiError::AccountInFlashloan
         );
 
-        let liability_bank_balance = self
-            .bank_accounts_with_price
-            .iter()
-            .find(|a| a.balance.bank_pk == *bank_pk)
-            .unwrap();
+        for bank_pk in bank_pks {
+            let (has_liabilities, has_assets) = self
+                .bank_accounts_with_price
+                .iter()
+                .filter(|a| a.balance.bank_pk == *bank_pk)
+                .fold((false, false), |(liabs, assets), a| {
+                    (
+                        liabs || !a.is_empty(BalanceSide::Liabilities),
+                        assets || !a.is_empty(BalanceSide::Assets),
+                    )
+                });
 
-        check!(
-            !liability_bank_balance.is_empty(BalanceSide::Liabilities),
-            MarginfiError::ExhaustedLiability
-        );
+            check!(has_liabilities, MarginfiError::ExhaustedLiability);
 
-        check!(
-            liability_bank_balance.is_empty(BalanceSide::Assets),
-            MarginfiError::TooSeverePayoff
-        );
+            check!(!has_assets, MarginfiError::TooSeverePayoff);
+        }
 
         let (assets, liabs) =
             self.get_account_health_components(RiskRequirementType::Maintenance, &mut None)?;
@@ -913,7 +1543,7 @@ impl<'info> RiskEngine<'_, 'info> {
         let account_health = assets.checked_sub(liabs).ok_or_else(math_error!())?;
 
         check!(
-            account_health <= I80F48::ZERO,
+            account_health <= I80F48::ZERO || self.is_matured_fixed_term_liquidation(bank_pks),
             MarginfiError::TooSevereLiquidation
         );
 
@@ -942,57 +1572,141 @@ impl<'a> BankAccountWrapper<'a> {
     pub fn find(
         bank_pk: &Pubkey,
         bank: &'a mut Bank,
-        lending_account: &'a mut LendingAccount,
+        balances: &'a mut [Balance],
     ) -> MarginfiResult<BankAccountWrapper<'a>> {
-        let balance = lending_account
-            .balances
+        let balance = balances
+            .iter_mut()
+            .find(|balance| {
+                balance.is_active() && !balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+            })
+            .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))?;
+
+        Ok(Self { balance, bank })
+    }
+
+    // Find existing fixed-term borrow by bank address.
+    pub fn find_fixed_term(
+        bank_pk: &Pubkey,
+        bank: &'a mut Bank,
+        balances: &'a mut [Balance],
+    ) -> MarginfiResult<BankAccountWrapper<'a>> {
+        let balance = balances
             .iter_mut()
-            .find(|balance| balance.is_active() && balance.bank_pk.eq(bank_pk))
+            .find(|balance| {
+                balance.is_active() && balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+            })
             .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))?;
 
         Ok(Self { balance, bank })
     }
 
+    // Find the balance holding the user's debt in this bank: the variable-rate balance if it has
+    // any liabilities, otherwise the fixed-term borrow, if any. Used by liquidation and
+    // bankruptcy, which repay whichever kind of debt the user has.
+    pub fn find_liability(
+        bank_pk: &Pubkey,
+        bank: &'a mut Bank,
+        balances: &'a mut [Balance],
+    ) -> MarginfiResult<BankAccountWrapper<'a>> {
+        let has_variable_liability = balances.iter().any(|balance| {
+            balance.is_active()
+                && !balance.is_fixed_term()
+                && balance.bank_pk.eq(bank_pk)
+                && !balance.is_empty(BalanceSide::Liabilities)
+        });
+
+        let has_fixed_term = balances.iter().any(|balance| {
+            balance.is_active() && balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+        });
+
+        if !has_variable_liability && has_fixed_term {
+            Self::find_fixed_term(bank_pk, bank, balances)
+        } else {
+            Self::find(bank_pk, bank, balances)
+        }
+    }
+
+    // Open a new fixed-term borrow for this bank at the locked `rate_bps` until `maturity`, errors
+    // if one already exists. The returned balance is empty, see `borrow_fixed_term`.
+    pub fn create_fixed_term(
+        bank_pk: &Pubkey,
+        bank: &'a mut Bank,
+        balances: &'a mut [Balance],
+        rate_bps: u16,
+        maturity: u32,
+    ) -> MarginfiResult<BankAccountWrapper<'a>> {
+        check!(maturity != 0, MarginfiError::InvalidFixedTermDuration);
+        check!(
+            !balances.iter().any(|balance| {
+                balance.is_active() && balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+            }),
+            MarginfiError::FixedTermPositionExists
+        );
+
+        let empty_index = balances
+            .iter()
+            .position(|balance| !balance.is_active())
+            .ok_or_else(|| error!(MarginfiError::LendingAccountBalanceSlotsFull))?;
+
+        balances[empty_index] = Balance {
+            active: 1,
+            bank_pk: *bank_pk,
+            bank_asset_tag: bank.config.asset_tag,
+            fixed_term_rate_bps: rate_bps,
+            fixed_term_maturity: maturity,
+            asset_shares: I80F48::ZERO.into(),
+            liability_shares: I80F48::ZERO.into(),
+            emissions_outstanding: I80F48::ZERO.into(),
+            last_update: Clock::get()?.unix_timestamp as u64,
+            fixed_term_principal: 0,
+        };
+
+        Ok(Self {
+            balance: balances.get_mut(empty_index).unwrap(),
+            bank,
+        })
+    }
+
     // Find existing user lending account balance by bank address.
     // Create it if not found.
     pub fn find_or_create(
         bank_pk: &Pubkey,
         bank: &'a mut Bank,
-        lending_account: &'a mut LendingAccount,
+        balances: &'a mut [Balance],
     ) -> MarginfiResult<BankAccountWrapper<'a>> {
-        let balance_index = lending_account
-            .balances
-            .iter()
-            .position(|balance| balance.is_active() && balance.bank_pk.eq(bank_pk));
+        let balance_index = balances.iter().position(|balance| {
+            balance.is_active() && !balance.is_fixed_term() && balance.bank_pk.eq(bank_pk)
+        });
 
         match balance_index {
             Some(balance_index) => {
-                let balance = lending_account
-                    .balances
+                let balance = balances
                     .get_mut(balance_index)
                     .ok_or_else(|| error!(MarginfiError::BankAccountNotFound))?;
 
                 Ok(Self { balance, bank })
             }
             None => {
-                let empty_index = lending_account
-                    .get_first_empty_balance()
+                let empty_index = balances
+                    .iter()
+                    .position(|balance| !balance.is_active())
                     .ok_or_else(|| error!(MarginfiError::LendingAccountBalanceSlotsFull))?;
 
-                lending_account.balances[empty_index] = Balance {
+                balances[empty_index] = Balance {
                     active: 1,
                     bank_pk: *bank_pk,
                     bank_asset_tag: bank.config.asset_tag,
-                    _pad0: [0; 6],
+                    fixed_term_rate_bps: 0,
+                    fixed_term_maturity: 0,
                     asset_shares: I80F48::ZERO.into(),
                     liability_shares: I80F48::ZERO.into(),
                     emissions_outstanding: I80F48::ZERO.into(),
                     last_update: Clock::get()?.unix_timestamp as u64,
-                    _padding: [0; 1],
+                    fixed_term_principal: 0,
                 };
 
                 Ok(Self {
-                    balance: lending_account.balances.get_mut(empty_index).unwrap(),
+                    balance: balances.get_mut(empty_index).unwrap(),
                     bank,
                 })
             }
@@ -1144,6 +1858,191 @@ impl<'a> BankAccountWrapper<'a> {
             .ok_or_else(math_error!())?)
     }
 
+    // ------------ Fixed-term operations, only valid on fixed-term balances
+
+    /// Lend `amount` to a freshly created fixed-term balance (see `create_fixed_term`).
+    pub fn borrow_fixed_term(&mut self, amount: u64) -> MarginfiResult {
+        let balance = &mut self.balance;
+        let bank = &mut self.bank;
+
+        check!(
+            balance.is_fixed_term() && balance.is_empty(BalanceSide::Liabilities),
+            MarginfiError::IllegalBalanceState
+        );
+        bank.assert_operational_mode(Some(true))?;
+
+        balance.fixed_term_principal = amount;
+        balance.last_update = Clock::get()?.unix_timestamp as u64;
+
+        let amount = I80F48::from_num(amount);
+        let interest_per_year = amount
+            .checked_mul(balance.get_fixed_term_rate())
+            .ok_or_else(math_error!())?;
+        bank.change_fixed_term_liabilities(amount, interest_per_year, false)?;
+        bank.increment_borrowing_position_count();
+
+        bank.check_utilization_ratio()?;
+
+        Ok(())
+    }
+
+    /// Repay part of a fixed-term borrow, will error if `amount` exceeds the amount owed. Closes
+    /// the balance if nothing is left owing.
+    pub fn repay_fixed_term(&mut self, amount: I80F48) -> MarginfiResult {
+        self.bank.assert_operational_mode(Some(false))?;
+
+        let owed = self.settle_fixed_term(Clock::get()?.unix_timestamp)?;
+
+        check!(
+            amount
+                <= owed
+                    .checked_add(ZERO_AMOUNT_THRESHOLD)
+                    .ok_or_else(math_error!())?,
+            MarginfiError::OperationRepayOnly
+        );
+
+        let remaining = max(
+            owed.checked_sub(amount).ok_or_else(math_error!())?,
+            I80F48::ZERO,
+        );
+        if remaining.is_zero_with_tolerance(ZERO_AMOUNT_THRESHOLD) {
+            self.remove_fixed_term(owed)?;
+        } else {
+            self.set_fixed_term_principal(remaining, owed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Repay a fixed-term borrow in full and close the balance. Returns the amount to transfer.
+    pub fn repay_all_fixed_term(&mut self) -> MarginfiResult<u64> {
+        self.bank.assert_operational_mode(None)?;
+
+        let owed = self.settle_fixed_term(Clock::get()?.unix_timestamp)?;
+
+        check!(
+            owed.is_positive_with_tolerance(ZERO_AMOUNT_THRESHOLD),
+            MarginfiError::NoLiabilityFound
+        );
+
+        let spl_deposit_amount = self.balance.fixed_term_principal;
+        self.remove_fixed_term(owed)?;
+
+        Ok(spl_deposit_amount)
+    }
+
+    /// Extend a matured fixed-term borrow to `maturity` at a newly locked `rate_bps`. Interest
+    /// accrued so far becomes part of the principal.
+    pub fn roll_fixed_term(&mut self, rate_bps: u16, maturity: u32) -> MarginfiResult {
+        let current_timestamp = Clock::get()?.unix_timestamp;
+
+        check!(
+            self.balance.is_fixed_term_matured(current_timestamp),
+            MarginfiError::FixedTermPositionNotMatured
+        );
+        check!(maturity != 0, MarginfiError::InvalidFixedTermDuration);
+        self.bank.assert_operational_mode(Some(true))?;
+
+        let owed = self.settle_fixed_term(current_timestamp)?;
+        let old_rate = self.balance.get_fixed_term_rate();
+
+        self.balance.fixed_term_rate_bps = rate_bps;
+        self.balance.fixed_term_maturity = maturity;
+
+        let rate_delta = self
+            .balance
+            .get_fixed_term_rate()
+            .checked_sub(old_rate)
+            .ok_or_else(math_error!())?;
+        self.bank.change_fixed_term_liabilities(
+            I80F48::ZERO,
+            owed.checked_mul(rate_delta).ok_or_else(math_error!())?,
+            true,
+        )?;
+
+        Ok(())
+    }
+
+    /// Moves the interest a fixed-term borrow accrued since its last update into the principal,
+    /// which is returned. The bank must already have accrued interest up to `current_timestamp`.
+    fn settle_fixed_term(&mut self, current_timestamp: i64) -> MarginfiResult<I80F48> {
+        check!(
+            self.balance.is_fixed_term(),
+            MarginfiError::IllegalBalanceState
+        );
+
+        // `Bank::accrue_interest` already added the accrued interest to the bank's total
+        let owed = self.balance.get_fixed_term_owed(current_timestamp)?;
+        let principal = self.set_fixed_term_principal(owed, owed)?;
+        self.balance.last_update = current_timestamp as u64;
+
+        Ok(principal)
+    }
+
+    /// Stores `owed` as the principal, rounded up to whole tokens, and moves the bank's fixed-term
+    /// totals along. `booked` is what the bank's `fixed_term_liabilities` currently counts for
+    /// this borrow. The rounding goes to the insurance fund. Returns the new principal.
+    fn set_fixed_term_principal(&mut self, owed: I80F48, booked: I80F48) -> MarginfiResult<I80F48> {
+        let principal = owed.checked_ceil().ok_or_else(math_error!())?;
+        let principal_delta = principal
+            .checked_sub(I80F48::from_num(self.balance.fixed_term_principal))
+            .ok_or_else(math_error!())?;
+
+        self.bank.change_fixed_term_liabilities(
+            principal.checked_sub(booked).ok_or_else(math_error!())?,
+            principal_delta
+                .checked_mul(self.balance.get_fixed_term_rate())
+                .ok_or_else(math_error!())?,
+            true,
+        )?;
+        self.bank.collected_insurance_fees_outstanding = principal
+            .checked_sub(owed)
+            .ok_or_else(math_error!())?
+            .checked_add(self.bank.collected_insurance_fees_outstanding.into())
+            .ok_or_else(math_error!())?
+            .into();
+
+        self.balance.fixed_term_principal = principal.checked_to_num().ok_or_else(math_error!())?;
+
+        Ok(principal)
+    }
+
+    /// Removes a settled fixed-term borrow of `owed` from the bank's totals and closes it.
+    fn remove_fixed_term(&mut self, owed: I80F48) -> MarginfiResult {
+        let interest_per_year = owed
+            .checked_mul(self.balance.get_fixed_term_rate())
+            .ok_or_else(math_error!())?;
+        self.bank
+            .change_fixed_term_liabilities(-owed, -interest_per_year, true)?;
+        self.bank.decrement_borrowing_position_count();
+
+        self.balance.close()
+    }
+
+    // ------------ Liquidation and bankruptcy helpers, valid on either kind of debt
+
+    /// The debt this balance holds right now, in native tokens.
+    pub fn get_liability_amount_owed(&self) -> MarginfiResult<I80F48> {
+        if self.balance.is_fixed_term() {
+            self.balance
+                .get_fixed_term_owed(Clock::get()?.unix_timestamp)
+        } else {
+            self.bank
+                .get_liability_amount(self.balance.liability_shares.into())
+        }
+    }
+
+    /// Pay down this balance's debt as part of a liquidation. Variable-rate balances behave like
+    /// `increase_balance`, so overpaying leaves an asset (which the post-liquidation checks
+    /// reject), fixed-term borrows error instead.
+    pub fn repay_in_liquidation(&mut self, amount: I80F48) -> MarginfiResult {
+        if self.balance.is_fixed_term() {
+            self.repay_fixed_term(amount)
+        } else {
+            self.increase_balance(amount)
+        }
+    }
+
     // ------------ Hybrid operations for seamless repay + deposit / withdraw + borrow
 
     /// Repay liability and deposit/increase asset depending on
@@ -1541,6 +2440,37 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_fixed_term_owed() {
+        let balance = Balance {
+            active: 1,
+            fixed_term_rate_bps: 1_000,
+            fixed_term_maturity: 2_000,
+            last_update: 1_000,
+            fixed_term_principal: 1_000,
+            ..Balance::empty_deactivated()
+        };
+
+        assert!(balance.is_fixed_term());
+        assert!(matches!(balance.get_side(), Some(BalanceSide::Liabilities)));
+        assert!(!balance.is_empty(BalanceSide::Liabilities));
+        assert!(balance.is_empty(BalanceSide::Assets));
+        assert!(!Balance::empty_deactivated().is_fixed_term());
+        assert!(!balance.is_fixed_term_matured(1_999));
+        assert!(balance.is_fixed_term_matured(2_000));
+        assert!(!Balance::empty_deactivated().is_fixed_term_matured(2_000));
+
+        // Half a year at 10% APR, simple interest since the last update
+        let owed = balance.get_fixed_term_owed(1_000 + 31_536_000 / 2).unwrap();
+        assert!((owed - I80F48!(1_050)).abs() < I80F48!(0.001));
+
+        // Interest keeps accruing past maturity
+        let owed = balance.get_fixed_term_owed(1_000 + 31_536_000).unwrap();
+        assert!((owed - I80F48!(1_100)).abs() < I80F48!(0.001));
+
+        assert_eq!(balance.get_fixed_term_owed(1_000).unwrap(), I80F48!(1_000));
+    }
+
     #[test]
     fn test_account_authority_transfer() {
         let group: [u8; 32] = [0; 32];
@@ -1557,12 +2487,13 @@ mod test {
                     active: 1,
                     bank_pk: bank_pk.into(),
                     bank_asset_tag: ASSET_TAG_DEFAULT,
-                    _pad0: [0; 6],
+                    fixed_term_rate_bps: 0,
+                    fixed_term_maturity: 0,
                     asset_shares: WrappedI80F48::default(),
                     liability_shares: WrappedI80F48::default(),
                     emissions_outstanding: WrappedI80F48::default(),
                     last_update: 0,
-                    _padding: [0_u64],
+                    fixed_term_principal: 0,
                 }; 16],
                 _padding: [0; 8],
             },
@@ -1581,6 +2512,102 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_remaining_accounts_index() {
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).rev().collect();
+        let owner = Pubkey::default();
+        let mut lamports = vec![0u64; keys.len()];
+        let mut data = vec![vec![0u8; 0]; keys.len()];
+        let ais: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, false, lamports, data, &owner, false, 0)
+            })
+            .collect();
+
+        let index = RemainingAccountsIndex::new(&ais);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(index.position(key), Some(i));
+        }
+        assert_eq!(index.position(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn test_remaining_accounts_index_find_oracle_ais() {
+        // bank, its oracle, an unrelated account
+        let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let (bank_pk, oracle_pk) = (keys[0], keys[1]);
+        let owner = Pubkey::default();
+        let mut lamports = vec![0u64; keys.len()];
+        let mut data = vec![vec![0u8; 0]; keys.len()];
+        let ais: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, false, lamports, data, &owner, false, 0)
+            })
+            .collect();
+        let index = RemainingAccountsIndex::new(&ais);
+
+        let mut bank = Bank::zeroed();
+        bank.config.oracle_keys[0] = oracle_pk;
+        // By key, even if the bank itself isn't passed
+        let oracle_ais = index.find_oracle_ais(&bank, &Pubkey::new_unique()).unwrap();
+        assert_eq!(oracle_ais.len(), 1);
+        assert_eq!(*oracle_ais[0].key, oracle_pk);
+
+        // Oracle key not among the accounts (e.g. a feed id): the account after the bank
+        bank.config.oracle_keys[0] = Pubkey::new_unique();
+        let oracle_ais = index.find_oracle_ais(&bank, &bank_pk).unwrap();
+        assert_eq!(*oracle_ais[0].key, oracle_pk);
+
+        // Neither the oracle nor the bank passed
+        assert!(index.find_oracle_ais(&bank, &Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_extend_account() {
+        let mut account = MarginfiAccount::zeroed();
+        for (i, balance) in account.lending_account.balances.iter_mut().enumerate() {
+            balance.active = 1;
+            balance.bank_pk = [i as u8 + 1; 32].into();
+        }
+        let balances_before = account.lending_account.balances;
+        let mut extension = Box::new(LendingAccountExtension::zeroed());
+
+        // The flag and the extension go together
+        assert!(MarginfiAccountData::new(&account, Some(&*extension)).is_err());
+
+        account.extend(&mut extension);
+        assert!(account.get_flag(ACCOUNT_EXTENDED));
+        assert!(MarginfiAccountData::<_, &LendingAccountExtension>::new(&account, None).is_err());
+
+        let mut data = MarginfiAccountData::new(&mut account, Some(&mut *extension)).unwrap();
+        assert_eq!(data.balances().len(), MAX_LENDING_ACCOUNT_BALANCES_EXTENDED);
+        assert!(data.balances()[..MAX_LENDING_ACCOUNT_BALANCES] == balances_before[..]);
+        assert!(data
+            .lending_account
+            .balances
+            .iter()
+            .all(|balance| !balance.is_active()));
+
+        // Slots past the first 16 are usable, and sorting covers all of them
+        data.balances_mut()[MAX_LENDING_ACCOUNT_BALANCES].active = 1;
+        data.balances_mut()[MAX_LENDING_ACCOUNT_BALANCES].bank_pk = [100; 32].into();
+        data.sort_balances();
+        assert_eq!(data.balances()[0].bank_pk, [100; 32].into());
+        assert_eq!(
+            data.balances()
+                .iter()
+                .filter(|balance| balance.is_active())
+                .count(),
+            MAX_LENDING_ACCOUNT_BALANCES + 1
+        );
+    }
+
     #[test]
     fn test_calc_emissions() {
         let balance_amount: u64 = 106153222432271169;
@@ -1,10 +1,15 @@
 use crate::borsh::{BorshDeserialize, BorshSerialize};
 use crate::constants::{
-    ASSET_TAG_DEFAULT, CLOSE_ENABLED_FLAG, EMISSIONS_FLAGS, FEE_VAULT_AUTHORITY_SEED,
-    FEE_VAULT_SEED, FREEZE_SETTINGS, GROUP_FLAGS, INSURANCE_VAULT_AUTHORITY_SEED,
-    INSURANCE_VAULT_SEED, LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED, MAX_ORACLE_KEYS,
-    MAX_PYTH_ORACLE_AGE, ORACLE_MIN_AGE, PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG,
-    PYTH_PUSH_MIGRATED, SECONDS_PER_YEAR, TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
+    ASSET_TAG_DEFAULT, CLOSE_ENABLED_FLAG, COMPOUND_INTEREST_FLAG, CURVE_POINTS, EMISSIONS_FLAGS,
+    EXP_10_I80F48, FEE_VAULT_AUTHORITY_SEED, FEE_VAULT_SEED, FREEZE_SETTINGS, GROUP_FLAGS,
+    INSURANCE_FEE_CONFIGURED, INSURANCE_VAULT_AUTHORITY_SEED, INSURANCE_VAULT_SEED,
+    INTEREST_CURVE_LEGACY, INTEREST_CURVE_MULTI_POINT, LIQUIDATION_FEE_RAMP,
+    LIQUIDATION_INSURANCE_FEE, LIQUIDATION_LIQUIDATOR_FEE, LIQUIDATOR_FEE_CONFIGURED,
+    LIQUIDITY_VAULT_AUTHORITY_SEED, LIQUIDITY_VAULT_SEED, MAX_DELEVERAGE_INSURANCE_FEE,
+    MAX_EXP_10_I80F48, MAX_LIQUIDATION_FEES, MAX_MEDIAN_ORACLE_SOURCES, MAX_ORACLE_KEYS,
+    MAX_PYTH_ORACLE_AGE, ORACLE_DEVIATION_WORSE_PRICE, ORACLE_EMA_PERIOD, ORACLE_MIN_AGE,
+    ORACLE_STALE_LIQUIDATION_HAIRCUT, PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG, PYTH_PUSH_MIGRATED,
+    SECONDS_PER_YEAR, TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE, U32_MAX, U32_MAX_DIV_10,
 };
 use crate::errors::MarginfiError;
 use crate::events::{GroupEventHeader, LendingPoolBankAccrueInterestEvent};
@@ -12,8 +17,10 @@ use crate::prelude::MarginfiResult;
 use crate::set_if_some;
 use crate::state::bank_cache::{BankCache, ComputedInterestRates};
 use crate::state::emode::EmodeSettings;
+use crate::state::exchange_rate::ExchangeRateSettings;
 use crate::state::marginfi_account::{calc_value, BalanceSide, RequirementType};
-use crate::state::price::{OraclePriceFeedAdapter, OracleSetup};
+use crate::state::oracle_confidence::{OracleConfidenceConfig, OracleConfidenceSettings};
+use crate::state::price::{OraclePriceFeedAdapter, OracleSetup, PriceBias};
 use crate::{assert_struct_align, assert_struct_size, check};
 use crate::{debug, math_error};
 use anchor_lang::prelude::*;
@@ -21,6 +28,8 @@ use anchor_spl::token_interface::*;
 use bytemuck::{Pod, Zeroable};
 use fixed::types::I80F48;
 use pyth_solana_receiver_sdk::price_update::FeedId;
+#[cfg(feature = "client")]
+use std::fmt::Display;
 use std::fmt::{Debug, Formatter};
 use type_layout::TypeLayout;
 
@@ -59,8 +68,26 @@ pub struct MarginfiGroup {
     /// Can modify the emissions `flags`, `emissions_rate` and `emissions_mint`, but nothing else,
     /// for every bank under this group
     pub delegate_emissions_admin: Pubkey,
+    /// Max fraction of a liability that a single liquidation can repay, from 0-100%, unless the
+    /// account is at least `liquidation_full_close_threshold` underwater. Banks can override this
+    /// with `BankConfig::liquidation_close_factor`.
+    /// * 0 disables the close factor, a liquidation can repay any amount (subject to the
+    ///   post-liquidation health check)
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_close_factor: u32,
+    /// Unhealthiness (maintenance shortfall as a fraction of maintenance-weighted liabilities) at
+    /// which the close factor stops applying, and the account can be closed out in one liquidation
+    /// * 0 means the close factor always applies
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_full_close_threshold: u32,
+    /// Fee charged on the liability repaid when an account owner deleverages with
+    /// `lending_account_deleverage`, paid to the liability bank's insurance fund, from 0-5%
+    /// * 0 means deleveraging is free
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub deleverage_insurance_fee: u32,
+    pub pad1: [u8; 4],
 
-    pub _padding_0: [[u64; 2]; 18],
+    pub _padding_0: [[u64; 2]; 17],
     pub _padding_1: [[u64; 2]; 32],
     pub _padding_4: u64,
 }
@@ -132,6 +159,69 @@ impl MarginfiGroup {
         }
     }
 
+    pub fn update_liquidation_close_factor(
+        &mut self,
+        close_factor: u32,
+        full_close_threshold: u32,
+    ) {
+        msg!(
+            "Set close factor from {:?} to {:?}, full close threshold from {:?} to {:?}",
+            self.liquidation_close_factor,
+            close_factor,
+            self.liquidation_full_close_threshold,
+            full_close_threshold
+        );
+        self.liquidation_close_factor = close_factor;
+        self.liquidation_full_close_threshold = full_close_threshold;
+    }
+
+    pub fn update_deleverage_insurance_fee(&mut self, insurance_fee: u32) -> MarginfiResult {
+        check!(
+            I80F48::from_num(insurance_fee) / U32_MAX <= MAX_DELEVERAGE_INSURANCE_FEE,
+            MarginfiError::InvalidConfig
+        );
+        msg!(
+            "Set deleverage insurance fee from {:?} to {:?}",
+            self.deleverage_insurance_fee,
+            insurance_fee
+        );
+        self.deleverage_insurance_fee = insurance_fee;
+
+        Ok(())
+    }
+
+    pub fn get_deleverage_insurance_fee(&self) -> I80F48 {
+        I80F48::from_num(self.deleverage_insurance_fee) / U32_MAX
+    }
+
+    /// Max fraction of a liability in `liab_bank_config` that a single liquidation can repay, for
+    /// an account with the given `unhealthiness` (see `calc_liquidation_unhealthiness`). None if
+    /// there is no limit.
+    pub fn get_liquidation_close_factor(
+        &self,
+        liab_bank_config: &BankConfig,
+        unhealthiness: I80F48,
+    ) -> Option<I80F48> {
+        let close_factor = if liab_bank_config.liquidation_close_factor != 0 {
+            liab_bank_config.liquidation_close_factor
+        } else {
+            self.liquidation_close_factor
+        };
+        if close_factor == 0 {
+            return None;
+        }
+
+        if self.liquidation_full_close_threshold != 0 {
+            let full_close_threshold =
+                I80F48::from_num(self.liquidation_full_close_threshold) / U32_MAX;
+            if unhealthiness >= full_close_threshold {
+                return None;
+            }
+        }
+
+        Some(I80F48::from_num(close_factor) / U32_MAX)
+    }
+
     /// Set the group parameters when initializing a group.
     /// This should be called only when the group is first initialized.
     #[allow(clippy::too_many_arguments)]
@@ -327,6 +417,8 @@ pub struct Bank {
     /// - FREEZE_SETTINGS: 8 - banks with this flag enabled can only update deposit/borrow caps
     /// - CLOSE_ENABLED_FLAG - banks with this flag were created after 0.1.4 and can be closed.
     ///   Banks without this flag can never be closed.
+    /// - COMPOUND_INTEREST_FLAG: 32 - interest compounds continuously rather than accruing as
+    ///   simple interest between updates, so accrual doesn't depend on how often the bank is touched
     ///
     pub flags: u64,
     /// Emissions APR. Number of emitted tokens (emissions_mint) per 1e(bank.mint_decimal) tokens
@@ -360,8 +452,40 @@ pub struct Bank {
     /// * For banks created in 0.1.4 or later, this is the number of positions open in total, and
     ///   the bank may safely be closed if this is zero. Will never go negative.
     pub borrowing_position_count: i32,
-    pub _padding_0: [u8; 16],
-    pub _padding_1: [[u64; 2]; 19], // 8 * 2 * 19 = 304B
+    /// Discount on the price of this bank's assets (and premium on its liabilities) when
+    /// liquidating with an oracle price older than `oracle_max_age` but still within
+    /// `oracle_max_age_relaxed`, from 0-100%. Compensates liquidators for acting on a stale price.
+    /// * 0 falls back to using the default 5% instead, i.e., `ORACLE_STALE_LIQUIDATION_HAIRCUT`
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub oracle_stale_liquidation_haircut: u32,
+    pub _padding_0: [u8; 12],
+    /// Current plateau rate when adaptive interest rates are enabled (see
+    /// `InterestRateConfig::adaptive_speed`). Zero if adaptive rates are disabled or the bank has
+    /// not accrued interest since they were enabled, in which case `plateau_interest_rate` applies.
+    pub adaptive_plateau_rate: WrappedI80F48,
+    /// Total owed on fixed-term borrows (see `Balance::fixed_term_principal`), in native tokens,
+    /// including interest accrued up to `last_update`. Not part of `total_liability_shares`, but
+    /// counts toward utilization for the interest rate curve.
+    pub fixed_term_liabilities: WrappedI80F48,
+    /// Sum of (amount owed * locked APR) over all fixed-term borrows, i.e. the interest they
+    /// accrue per year. Fixed-term interest goes to depositors in full.
+    pub fixed_term_interest_per_year: WrappedI80F48,
+    /// Added to the current variable borrowing rate to get the locked rate of a new fixed-term
+    /// borrow. Encoded like the `BankCache` rates (u32::MAX = 1000%).
+    pub fixed_term_premium: u32,
+    /// Longest fixed term a user may borrow for, in seconds. Zero disables fixed-term borrowing.
+    pub fixed_term_max_duration: u32,
+    /// Time `oracle_ema_price` was last updated, 0 if never.
+    pub oracle_ema_last_update: i64,
+    /// Exponential moving average of the oracle price, maintained on-chain for Switchboard pull
+    /// banks (which only publish a spot price) and used as their time-weighted price. Updated when
+    /// interest is accrued with the bank's oracle passed, see `Bank::get_oracle_ema`.
+    pub oracle_ema_price: WrappedI80F48,
+    /// Where an `OracleSetup::ExchangeRate` bank reads its exchange rate, and the last rate seen
+    pub exchange_rate: ExchangeRateSettings,
+    /// How this bank treats its oracles' confidence. Defaults to the program-wide constants.
+    pub oracle_confidence: OracleConfidenceSettings,
+    pub _padding_1: [[u64; 2]; 8], // 8 * 2 * 8 = 128B
 }
 
 // Initialize a Bank instance
@@ -415,7 +539,8 @@ impl Bank {
             fees_destination_account: Pubkey::default(),
             lending_position_count: 0,
             borrowing_position_count: 0,
-            _padding_0: [0; 16],
+            oracle_stale_liquidation_haircut: 0,
+            _padding_0: [0; 12],
             ..Default::default()
         }
     }
@@ -427,6 +552,14 @@ impl Bank {
             .ok_or_else(math_error!())?)
     }
 
+    /// Variable-rate liabilities plus everything owed on fixed-term borrows, in native tokens.
+    pub fn get_total_liability_amount(&self) -> MarginfiResult<I80F48> {
+        Ok(self
+            .get_liability_amount(self.total_liability_shares.into())?
+            .checked_add(self.fixed_term_liabilities.into())
+            .ok_or_else(math_error!())?)
+    }
+
     // Convert the user's asset shares into the current actual withdrawable amount
     pub fn get_asset_amount(&self, shares: I80F48) -> MarginfiResult<I80F48> {
         Ok(shares
@@ -533,17 +666,94 @@ impl Bank {
             .ok_or_else(math_error!())?
             .into();
 
-        if !bypass_borrow_limit && shares.is_positive() && self.config.is_borrow_limit_active() {
-            let total_liability_amount =
-                self.get_liability_amount(self.total_liability_shares.into())?;
-            let borrow_limit = I80F48::from_num(self.config.borrow_limit);
+        if !bypass_borrow_limit && shares.is_positive() {
+            self.check_borrow_limit()?;
+        }
 
-            if total_liability_amount >= borrow_limit {
-                let liab_num: f64 = total_liability_amount.to_num();
-                let borrow_num: f64 = borrow_limit.to_num();
-                msg!("amt: {:?}, borrow lim: {:?}", liab_num, borrow_num);
-                return err!(MarginfiError::BankLiabilityCapacityExceeded);
-            }
+        Ok(())
+    }
+
+    /// Update the Bank's fixed-term totals when a fixed-term borrow is opened, repaid, or
+    /// settled, and check the borrow limit if the amount owed grows.
+    ///
+    /// * `amount` - change in the amount owed, in native tokens
+    /// * `interest_per_year` - change in (amount owed * locked APR)
+    pub fn change_fixed_term_liabilities(
+        &mut self,
+        amount: I80F48,
+        interest_per_year: I80F48,
+        bypass_borrow_limit: bool,
+    ) -> MarginfiResult {
+        // Per-position rounding can leave the totals slightly below the sum being removed
+        self.fixed_term_liabilities = I80F48::from(self.fixed_term_liabilities)
+            .checked_add(amount)
+            .ok_or_else(math_error!())?
+            .max(I80F48::ZERO)
+            .into();
+        self.fixed_term_interest_per_year = I80F48::from(self.fixed_term_interest_per_year)
+            .checked_add(interest_per_year)
+            .ok_or_else(math_error!())?
+            .max(I80F48::ZERO)
+            .into();
+
+        if !bypass_borrow_limit && amount.is_positive() {
+            self.check_borrow_limit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Split a borrow's `origination_fee` between program fees, at the group's
+    /// `program_fee_rate`, and group fees
+    pub fn collect_origination_fee(
+        &mut self,
+        origination_fee: I80F48,
+        program_fee_rate: I80F48,
+    ) -> MarginfiResult {
+        if origination_fee.is_zero() {
+            return Ok(());
+        }
+
+        let mut bank_fees_after: I80F48 = self.collected_group_fees_outstanding.into();
+
+        if !program_fee_rate.is_zero() {
+            // Some portion of the origination fee to goes to program fees
+            let program_fee_amount: I80F48 = origination_fee
+                .checked_mul(program_fee_rate)
+                .ok_or_else(math_error!())?;
+            // The remainder of the origination fee goes to group fees
+            bank_fees_after =
+                bank_fees_after.saturating_add(origination_fee.saturating_sub(program_fee_amount));
+
+            // Update the bank's program fees
+            let program_fees_before: I80F48 = self.collected_program_fees_outstanding.into();
+            self.collected_program_fees_outstanding = program_fees_before
+                .saturating_add(program_fee_amount)
+                .into();
+        } else {
+            // If program fee rate is zero, add the full origination fee to group fees
+            bank_fees_after = bank_fees_after.saturating_add(origination_fee);
+        }
+
+        // Update the bank's group fees
+        self.collected_group_fees_outstanding = bank_fees_after.into();
+
+        Ok(())
+    }
+
+    fn check_borrow_limit(&self) -> MarginfiResult {
+        if !self.config.is_borrow_limit_active() {
+            return Ok(());
+        }
+
+        let total_liability_amount = self.get_total_liability_amount()?;
+        let borrow_limit = I80F48::from_num(self.config.borrow_limit);
+
+        if total_liability_amount >= borrow_limit {
+            let liab_num: f64 = total_liability_amount.to_num();
+            let borrow_num: f64 = borrow_limit.to_num();
+            msg!("amt: {:?}, borrow lim: {:?}", liab_num, borrow_num);
+            return err!(MarginfiError::BankLiabilityCapacityExceeded);
         }
 
         Ok(())
@@ -552,7 +762,7 @@ impl Bank {
     // Check whether the bank's "total assets ≥ total liabilities" is true to prevent illegal capital utilization ratio
     pub fn check_utilization_ratio(&self) -> MarginfiResult {
         let total_assets = self.get_asset_amount(self.total_asset_shares.into())?;
-        let total_liabilities = self.get_liability_amount(self.total_liability_shares.into())?;
+        let total_liabilities = self.get_total_liability_amount()?;
 
         if total_assets < total_liabilities {
             let assets_num: f64 = total_assets.to_num();
@@ -581,6 +791,7 @@ impl Bank {
 
         if let Some(ir_config) = &config.interest_rate_config {
             self.config.interest_rate_config.update(ir_config);
+            self.clamp_adaptive_plateau_rate();
         }
 
         set_if_some!(self.config.risk_tier, config.risk_tier);
@@ -590,10 +801,61 @@ impl Bank {
             config.total_asset_value_init_limit
         );
         set_if_some!(self.config.oracle_max_age, config.oracle_max_age);
+        set_if_some!(
+            self.config.oracle_max_age_relaxed,
+            config.oracle_max_age_relaxed
+        );
+        set_if_some!(
+            self.oracle_stale_liquidation_haircut,
+            config.oracle_stale_liquidation_haircut
+        );
         set_if_some!(
             self.config.oracle_max_confidence,
             config.oracle_max_confidence
         );
+        if let Some(fee) = config.liquidation_liquidator_fee {
+            msg!("setting liquidator fee: {:?}", fee);
+            self.config.liquidation_liquidator_fee = fee;
+            self.config
+                .update_config_flag(true, LIQUIDATOR_FEE_CONFIGURED);
+        }
+        if let Some(fee) = config.liquidation_insurance_fee {
+            msg!("setting liquidation insurance fee: {:?}", fee);
+            self.config.liquidation_insurance_fee = fee;
+            self.config
+                .update_config_flag(true, INSURANCE_FEE_CONFIGURED);
+        }
+        set_if_some!(
+            self.config.liquidation_max_liquidator_fee,
+            config.liquidation_max_liquidator_fee
+        );
+        set_if_some!(
+            self.config.liquidation_fee_ramp,
+            config.liquidation_fee_ramp
+        );
+        set_if_some!(
+            self.config.liquidation_close_factor,
+            config.liquidation_close_factor
+        );
+        set_if_some!(
+            self.config.oracle_max_deviation,
+            config.oracle_max_deviation
+        );
+        if let Some(flag) = config.oracle_deviation_use_worse_price {
+            msg!("setting oracle deviation worse price: {:?}", flag);
+            self.config
+                .update_config_flag(flag, ORACLE_DEVIATION_WORSE_PRICE);
+        }
+        if let Some(fixed_price) = &config.oracle_fixed_price {
+            msg!("setting oracle fixed price: {:?}", fixed_price);
+            self.config.set_oracle_fixed_price(fixed_price);
+        }
+        if let Some(oracle_confidence) = &config.oracle_confidence {
+            msg!("setting oracle confidence: {:?}", oracle_confidence);
+            self.oracle_confidence.configure(oracle_confidence)?;
+        }
+        set_if_some!(self.fixed_term_premium, config.fixed_term_premium);
+        set_if_some!(self.fixed_term_max_duration, config.fixed_term_max_duration);
 
         if let Some(flag) = config.permissionless_bad_debt_settlement {
             msg!(
@@ -603,6 +865,11 @@ impl Bank {
             self.update_flag(flag, PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG);
         }
 
+        if let Some(flag) = config.compound_interest {
+            msg!("setting compound interest: {:?}", flag);
+            self.update_flag(flag, COMPOUND_INTEREST_FLAG);
+        }
+
         if let Some(flag) = config.freeze_settings {
             msg!(
                 "setting freeze settings: {:?}",
@@ -642,12 +909,33 @@ impl Bank {
             return Ok(());
         }
 
+        // Interest for this period is charged at the current rate, the adapted rate applies from
+        // the next accrual onwards.
+        let ir_calc = self.get_interest_rate_calculator(group)?;
+
+        // Fixed-term interest moves the asset share value too, so the interest accumulated for
+        // the cache is measured from before it
+        let asset_share_value_pre_accrual: I80F48 = self.asset_share_value.into();
+
+        // Only reported in the accrual event
+        let (_fixed_term_group_fees, _fixed_term_insurance_fees) =
+            self.accrue_fixed_term_interest(time_delta, &ir_calc)?;
+
         let total_assets = self.get_asset_amount(self.total_asset_shares.into())?;
         let total_liabilities = self.get_liability_amount(self.total_liability_shares.into())?;
+        let fixed_term_liabilities: I80F48 = self.fixed_term_liabilities.into();
 
         self.last_update = current_timestamp;
 
+        self.update_adaptive_plateau_rate(
+            total_assets,
+            self.get_total_liability_amount()?,
+            time_delta,
+        )?;
+
         if (total_assets == I80F48::ZERO) || (total_liabilities == I80F48::ZERO) {
+            self.update_accumulated_interest_cache(asset_share_value_pre_accrual, time_delta)?;
+
             #[cfg(not(feature = "client"))]
             emit!(LendingPoolBankAccrueInterestEvent {
                 header: GroupEventHeader {
@@ -657,16 +945,12 @@ impl Bank {
                 bank,
                 mint: self.mint,
                 delta: time_delta,
-                fees_collected: 0.,
-                insurance_collected: 0.,
+                fees_collected: _fixed_term_group_fees.to_num::<f64>(),
+                insurance_collected: _fixed_term_insurance_fees.to_num::<f64>(),
             });
 
             return Ok(());
         }
-        let ir_calc = self
-            .config
-            .interest_rate_config
-            .create_interest_rate_calculator(group);
 
         let InterestRateStateChanges {
             new_asset_share_value: asset_share_value,
@@ -678,27 +962,22 @@ impl Bank {
             time_delta,
             total_assets,
             total_liabilities,
+            fixed_term_liabilities,
             &ir_calc,
             self.asset_share_value.into(),
             self.liability_share_value.into(),
+            self.get_flag(COMPOUND_INTEREST_FLAG),
         )
         .ok_or_else(math_error!())?;
 
         debug!("deposit share value: {}\nliability share value: {}\nfees collected: {}\ninsurance collected: {}",
             asset_share_value, liability_share_value, group_fees_collected, insurance_fees_collected);
 
-        // Calc interest only
-        self.cache.accumulated_since_last_update = asset_share_value
-            .checked_sub(I80F48::from(self.asset_share_value))
-            .and_then(|v| v.checked_mul(I80F48::from(self.total_asset_shares)))
-            .ok_or_else(math_error!())?
-            .into();
-        // The time span for this interest calculation
-        self.cache.interest_accumulated_for = time_delta.min(u32::MAX as u64) as u32;
-
         self.asset_share_value = asset_share_value.into();
         self.liability_share_value = liability_share_value.into();
 
+        self.update_accumulated_interest_cache(asset_share_value_pre_accrual, time_delta)?;
+
         if group_fees_collected > I80F48::ZERO {
             self.collected_group_fees_outstanding = {
                 group_fees_collected
@@ -738,14 +1017,314 @@ impl Bank {
                 bank,
                 mint: self.mint,
                 delta: time_delta,
-                fees_collected: group_fees_collected.to_num::<f64>(),
-                insurance_collected: insurance_fees_collected.to_num::<f64>(),
+                fees_collected: (group_fees_collected + _fixed_term_group_fees).to_num::<f64>(),
+                insurance_collected: (insurance_fees_collected + _fixed_term_insurance_fees)
+                    .to_num::<f64>(),
             });
         }
 
         Ok(())
     }
 
+    /// Records the interest depositors earned (variable and fixed-term) since the asset share value
+    /// was `asset_share_value_pre_accrual`, over `time_delta` seconds
+    fn update_accumulated_interest_cache(
+        &mut self,
+        asset_share_value_pre_accrual: I80F48,
+        time_delta: u64,
+    ) -> MarginfiResult {
+        // Calc interest only
+        self.cache.accumulated_since_last_update = I80F48::from(self.asset_share_value)
+            .checked_sub(asset_share_value_pre_accrual)
+            .and_then(|v| v.checked_mul(I80F48::from(self.total_asset_shares)))
+            .ok_or_else(math_error!())?
+            .into();
+        // The time span for this interest calculation
+        self.cache.interest_accumulated_for = time_delta.min(u32::MAX as u64) as u32;
+
+        Ok(())
+    }
+
+    /// Grows `fixed_term_liabilities` by the interest fixed-term borrows accrued over `time_delta`
+    /// seconds. Insurance, group and protocol fees take the same share of it as they do of
+    /// variable-rate interest at the bank's current rates, depositors get the rest.
+    ///
+    /// Returns the group and insurance fees collected.
+    fn accrue_fixed_term_interest(
+        &mut self,
+        time_delta: u64,
+        ir_calc: &InterestRateCalc,
+    ) -> MarginfiResult<(I80F48, I80F48)> {
+        let interest_per_year: I80F48 = self.fixed_term_interest_per_year.into();
+        if interest_per_year == I80F48::ZERO {
+            return Ok((I80F48::ZERO, I80F48::ZERO));
+        }
+
+        let interest = interest_per_year
+            .checked_mul(I80F48::from_num(time_delta))
+            .ok_or_else(math_error!())?
+            .checked_div(SECONDS_PER_YEAR)
+            .ok_or_else(math_error!())?;
+
+        let total_assets = self.get_asset_amount(self.total_asset_shares.into())?;
+        let fixed_term_liabilities: I80F48 = self.fixed_term_liabilities.into();
+
+        self.fixed_term_liabilities = fixed_term_liabilities
+            .checked_add(interest)
+            .ok_or_else(math_error!())?
+            .into();
+
+        // Nobody to pay the interest to
+        if total_assets == I80F48::ZERO {
+            return Ok((I80F48::ZERO, I80F48::ZERO));
+        }
+
+        let ComputedInterestRates {
+            borrowing_rate_apr,
+            group_fee_apr,
+            insurance_fee_apr,
+            protocol_fee_apr,
+            ..
+        } = ir_calc
+            .calc_interest_rate_with_fixed_term(
+                total_assets,
+                self.get_liability_amount(self.total_liability_shares.into())?,
+                fixed_term_liabilities,
+            )
+            .ok_or_else(math_error!())?;
+
+        let fee_share = |fee_apr: I80F48| -> MarginfiResult<I80F48> {
+            if borrowing_rate_apr == I80F48::ZERO {
+                return Ok(I80F48::ZERO);
+            }
+            Ok(interest
+                .checked_mul(fee_apr)
+                .ok_or_else(math_error!())?
+                .checked_div(borrowing_rate_apr)
+                .ok_or_else(math_error!())?)
+        };
+        let group_fees = fee_share(group_fee_apr)?;
+        let insurance_fees = fee_share(insurance_fee_apr)?;
+        let protocol_fees = fee_share(protocol_fee_apr)?;
+
+        let depositor_interest = interest
+            .checked_sub(group_fees)
+            .and_then(|v| v.checked_sub(insurance_fees))
+            .and_then(|v| v.checked_sub(protocol_fees))
+            .ok_or_else(math_error!())?
+            .max(I80F48::ZERO);
+
+        self.asset_share_value = depositor_interest
+            .checked_div(self.total_asset_shares.into())
+            .ok_or_else(math_error!())?
+            .checked_add(self.asset_share_value.into())
+            .ok_or_else(math_error!())?
+            .into();
+        self.collected_group_fees_outstanding = group_fees
+            .checked_add(self.collected_group_fees_outstanding.into())
+            .ok_or_else(math_error!())?
+            .into();
+        self.collected_insurance_fees_outstanding = insurance_fees
+            .checked_add(self.collected_insurance_fees_outstanding.into())
+            .ok_or_else(math_error!())?
+            .into();
+        self.collected_program_fees_outstanding = protocol_fees
+            .checked_add(self.collected_program_fees_outstanding.into())
+            .ok_or_else(math_error!())?
+            .into();
+
+        debug!(
+            "fixed-term interest accrued: {}, fees: {} group, {} insurance, {} protocol",
+            interest, group_fees, insurance_fees, protocol_fees
+        );
+
+        Ok((group_fees, insurance_fees))
+    }
+
+    /// The plateau rate currently in effect, if adaptive interest rates are enabled.
+    pub fn get_adaptive_plateau_rate(&self) -> Option<I80F48> {
+        if !self.config.interest_rate_config.is_adaptive() {
+            return None;
+        }
+
+        let adapted: I80F48 = self.adaptive_plateau_rate.into();
+        if adapted == I80F48::ZERO {
+            Some(
+                self.config
+                    .interest_rate_config
+                    .plateau_interest_rate
+                    .into(),
+            )
+        } else {
+            Some(adapted)
+        }
+    }
+
+    /// Interest rate calculator for the bank's config, using the adapted plateau rate if adaptive
+    /// interest rates are enabled.
+    pub fn get_interest_rate_calculator(
+        &self,
+        group: &MarginfiGroup,
+    ) -> MarginfiResult<InterestRateCalc> {
+        let ir_calc = self
+            .config
+            .interest_rate_config
+            .create_interest_rate_calculator(group);
+
+        match self.get_adaptive_plateau_rate() {
+            Some(plateau_rate) => Ok(ir_calc
+                .with_adaptive_plateau_rate(plateau_rate)
+                .ok_or_else(math_error!())?),
+            None => Ok(ir_calc),
+        }
+    }
+
+    /// Pulls a previously adapted plateau rate into the adaptive min/max rates, in case an update
+    /// to the interest rate config narrowed them.
+    fn clamp_adaptive_plateau_rate(&mut self) {
+        let ir_config = &self.config.interest_rate_config;
+        let adapted: I80F48 = self.adaptive_plateau_rate.into();
+        if !ir_config.is_adaptive() || adapted == I80F48::ZERO {
+            return;
+        }
+
+        let min_ir = u32_to_apr(ir_config.adaptive_min_rate);
+        let max_ir = u32_to_apr(ir_config.adaptive_max_rate);
+        self.adaptive_plateau_rate = adapted.max(min_ir).min(max_ir).into();
+    }
+
+    /// Nudges the adaptive plateau rate toward the rate that would bring utilization to
+    /// `optimal_utilization_rate`, based on the utilization over the last `time_delta` seconds.
+    ///
+    /// Does nothing (other than clearing any stale rate) if adaptive interest rates are disabled.
+    fn update_adaptive_plateau_rate(
+        &mut self,
+        total_assets: I80F48,
+        total_liabilities: I80F48,
+        time_delta: u64,
+    ) -> MarginfiResult {
+        let current_rate = match self.get_adaptive_plateau_rate() {
+            Some(rate) => rate,
+            None => {
+                self.adaptive_plateau_rate = I80F48::ZERO.into();
+                return Ok(());
+            }
+        };
+
+        // Utilization is undefined for an empty bank, keep the current rate
+        if total_assets == I80F48::ZERO {
+            return Ok(());
+        }
+
+        let utilization_ratio = total_liabilities
+            .checked_div(total_assets)
+            .ok_or_else(math_error!())?;
+        let new_rate = self
+            .config
+            .interest_rate_config
+            .calc_adapted_plateau_rate(current_rate, utilization_ratio, time_delta)
+            .ok_or_else(math_error!())?;
+
+        debug!("adaptive plateau rate: {} -> {}", current_rate, new_rate);
+        self.adaptive_plateau_rate = new_rate.into();
+
+        Ok(())
+    }
+
+    /// The oracle EMA as of `current_timestamp`, with `price` taken as the price since the last
+    /// update. Weighs `price` by `dt / (dt + ORACLE_EMA_PERIOD)`, so the EMA moves with time
+    /// elapsed rather than with how often it is read. `price` as-is if there is no EMA yet.
+    pub fn get_oracle_ema(&self, price: I80F48, current_timestamp: i64) -> MarginfiResult<I80F48> {
+        if self.oracle_ema_last_update == 0 {
+            return Ok(price);
+        }
+
+        let ema: I80F48 = self.oracle_ema_price.into();
+        let time_delta = current_timestamp.saturating_sub(self.oracle_ema_last_update);
+        if time_delta <= 0 {
+            return Ok(ema);
+        }
+
+        let weight = I80F48::from_num(time_delta)
+            .checked_div(I80F48::from_num(time_delta + ORACLE_EMA_PERIOD))
+            .ok_or_else(math_error!())?;
+        let ema = price
+            .checked_sub(ema)
+            .and_then(|delta| delta.checked_mul(weight))
+            .and_then(|delta| ema.checked_add(delta))
+            .ok_or_else(math_error!())?;
+
+        Ok(ema)
+    }
+
+    /// Records `get_oracle_ema` as of `current_timestamp`.
+    pub fn update_oracle_ema(&mut self, price: I80F48, current_timestamp: i64) -> MarginfiResult {
+        if current_timestamp <= self.oracle_ema_last_update {
+            return Ok(());
+        }
+
+        self.oracle_ema_price = self.get_oracle_ema(price, current_timestamp)?.into();
+        self.oracle_ema_last_update = current_timestamp;
+
+        Ok(())
+    }
+
+    /// Records what a freshly loaded `price_feed` says about the bank: updates the oracle EMA (see
+    /// `update_oracle_ema`) or the last exchange rate (see `ExchangeRateSettings::record_rate`),
+    /// and sets a depegged fixed-price bank to `ReduceOnly`. The admin must set it back to
+    /// `Operational` once the peg is restored.
+    ///
+    /// Only `lending_pool_accrue_bank_interest` records prices, the risk engine never writes to
+    /// the banks it reads.
+    pub fn record_oracle_price(
+        &mut self,
+        price_feed: &OraclePriceFeedAdapter,
+        current_timestamp: i64,
+    ) -> MarginfiResult {
+        if let Some(price) = price_feed.get_oracle_ema_source_price()? {
+            self.update_oracle_ema(price, current_timestamp)?;
+        }
+
+        if let Some((rate, epoch)) = price_feed.get_exchange_rate() {
+            self.exchange_rate.record_rate(rate, epoch);
+        }
+
+        if price_feed.is_depegged()
+            && self.config.operational_state == BankOperationalState::Operational
+        {
+            msg!("fixed price depegged, setting bank to reduce only");
+            self.config.operational_state = BankOperationalState::ReduceOnly;
+        }
+
+        Ok(())
+    }
+
+    /// See `oracle_stale_liquidation_haircut`
+    pub fn get_oracle_stale_liquidation_haircut(&self) -> I80F48 {
+        if self.oracle_stale_liquidation_haircut == 0 {
+            ORACLE_STALE_LIQUIDATION_HAIRCUT
+        } else {
+            I80F48::from_num(self.oracle_stale_liquidation_haircut) / U32_MAX
+        }
+    }
+
+    /// `price`, moved against the liquidatee by `oracle_stale_liquidation_haircut`: down for
+    /// `PriceBias::Low` (assets) and up for `PriceBias::High` (liabilities)
+    pub fn apply_oracle_stale_haircut(
+        &self,
+        price: I80F48,
+        bias: PriceBias,
+    ) -> MarginfiResult<I80F48> {
+        let haircut = self.get_oracle_stale_liquidation_haircut();
+        let factor = match bias {
+            PriceBias::Low => I80F48::ONE.checked_sub(haircut),
+            PriceBias::High => I80F48::ONE.checked_add(haircut),
+        }
+        .ok_or_else(math_error!())?;
+
+        Ok(price.checked_mul(factor).ok_or_else(math_error!())?)
+    }
+
     /// Updates bank cache with the actual values for interest/fee rates.
     ///
     /// Should be called in the end of each instruction calling `accrue_interest` to ensure the cache is up to date.
@@ -753,25 +1332,31 @@ impl Bank {
         let total_assets_amount = self.get_asset_amount(self.total_asset_shares.into())?;
         let total_liabilities_amount =
             self.get_liability_amount(self.total_liability_shares.into())?;
+        let fixed_term_liabilities_amount: I80F48 = self.fixed_term_liabilities.into();
 
-        if (total_assets_amount == I80F48::ZERO) || (total_liabilities_amount == I80F48::ZERO) {
+        if (total_assets_amount == I80F48::ZERO)
+            || (total_liabilities_amount == I80F48::ZERO
+                && fixed_term_liabilities_amount == I80F48::ZERO)
+        {
             self.cache = BankCache::default();
+            self.cache
+                .update_adaptive_plateau_rate(self.get_adaptive_plateau_rate());
             return Ok(());
         }
 
-        let ir_calc = self
-            .config
-            .interest_rate_config
-            .create_interest_rate_calculator(group);
+        let ir_calc = self.get_interest_rate_calculator(group)?;
 
-        let utilization_rate = total_liabilities_amount
-            .checked_div(total_assets_amount)
-            .ok_or_else(math_error!())?;
         let interest_rates = ir_calc
-            .calc_interest_rate(utilization_rate)
+            .calc_interest_rate_with_fixed_term(
+                total_assets_amount,
+                total_liabilities_amount,
+                fixed_term_liabilities_amount,
+            )
             .ok_or_else(math_error!())?;
 
         self.cache.update_interest_rates(&interest_rates);
+        self.cache
+            .update_adaptive_plateau_rate(self.get_adaptive_plateau_rate());
 
         Ok(())
     }
@@ -986,8 +1571,15 @@ pub struct BankConfig {
     pub oracle_setup: OracleSetup,
     pub oracle_keys: [Pubkey; MAX_ORACLE_KEYS],
 
+    /// Decimal exponent of `oracle_fixed_price`, e.g. -2 for cents
+    pub oracle_fixed_price_exponent: i8,
+
     // Note: Pubkey is aligned 1, so borrow_limit is the first aligned-8 value after deposit_limit
-    pub _pad0: [u8; 6], // Bank state (1) + Oracle Setup (1) + 6 = 8
+    pub _pad0: [u8; 1], // Bank state (1) + Oracle Setup (1) + exponent (1) + 1 + u32 (4) = 8
+
+    /// `OracleSetup::Fixed` only: the USD price (scaled by `oracle_fixed_price_exponent`) this bank
+    /// is valued at while its live feed stays within `oracle_fixed_price_max_deviation` of it.
+    pub oracle_fixed_price: u32,
 
     pub borrow_limit: u64,
 
@@ -1007,10 +1599,20 @@ pub struct BankConfig {
     /// * 1 - Always set if bank created in 0.1.4 or later, or if migrated to the new pyth
     ///   oracle setup from a prior version. Not set in 0.1.3 or earlier banks using pyth that have
     ///   not yet migrated. Does nothing for banks that use switchboard.
-    /// * 2, 4, 8, 16, etc - reserved for future use.
+    /// * 2 - `ORACLE_DEVIATION_WORSE_PRICE`, see `oracle_max_deviation`.
+    /// * 4 - `LIQUIDATOR_FEE_CONFIGURED`, see `liquidation_liquidator_fee`.
+    /// * 8 - `INSURANCE_FEE_CONFIGURED`, see `liquidation_insurance_fee`.
+    /// * 16, 32, 64, etc - reserved for future use.
     pub config_flags: u8,
 
-    pub _pad1: [u8; 5],
+    /// Decimal exponent of `oracle_price_min` and `oracle_price_max`, e.g. -2 for cents
+    pub oracle_price_bounds_exponent: i8,
+
+    /// `OracleSetup::Fixed` only: max deviation of the live feed's price from
+    /// `oracle_fixed_price`, as a fraction of the fixed price. Beyond this the bank has depegged:
+    /// it is valued at the live price instead, and is set to `ReduceOnly` when next written.
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub oracle_fixed_price_max_deviation: u32,
 
     /// USD denominated limit for calculating asset value for initialization margin requirements.
     /// Example, if total SOL deposits are equal to $1M and the limit it set to $500K,
@@ -1023,10 +1625,14 @@ pub struct BankConfig {
     pub total_asset_value_init_limit: u64,
 
     /// Time window in seconds for the oracle price feed to be considered live.
+    /// * This is the strict window, used for borrows, withdraws, and anything else that adds risk.
     pub oracle_max_age: u16,
 
-    // pad to next 4-byte alignment to meet u32's requirements.
-    pub _padding0: [u8; 2],
+    /// Looser time window in seconds, used where a slightly older price beats no price at all:
+    /// liquidations (see `Bank::oracle_stale_liquidation_haircut`) and recording oracle state
+    /// (e.g. the EMA) on interest accrual.
+    /// * 0 (or anything below `oracle_max_age`) falls back to `oracle_max_age`
+    pub oracle_max_age_relaxed: u16,
 
     /// From 0-100%, if the confidence exceeds this value, the oracle is considered invalid. Note:
     /// the confidence adjustment is capped at 5% regardless of this value.
@@ -1034,7 +1640,46 @@ pub struct BankConfig {
     /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
     pub oracle_max_confidence: u32,
 
-    pub _padding1: [u8; 32],
+    /// Discount on this bank's collateral paid to liquidators, from 0-100%
+    /// * 0 falls back to using the default 2.5% instead, i.e., `LIQUIDATION_LIQUIDATOR_FEE`,
+    ///   unless it was explicitly configured (`LIQUIDATOR_FEE_CONFIGURED`)
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_liquidator_fee: u32,
+    /// Discount on this bank's collateral paid to the liability bank's insurance fund, from 0-100%
+    /// * 0 falls back to using the default 2.5% instead, i.e., `LIQUIDATION_INSURANCE_FEE`,
+    ///   unless it was explicitly configured (`INSURANCE_FEE_CONFIGURED`)
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_insurance_fee: u32,
+    /// Cap on the liquidator fee, from 0-100%. The liquidator fee grows linearly with the
+    /// liquidatee's unhealthiness, from `liquidation_liquidator_fee` for a barely unhealthy
+    /// account up to this value for an account at least `liquidation_fee_ramp` underwater.
+    /// * 0 disables the ramp, the liquidator fee is always `liquidation_liquidator_fee`
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_max_liquidator_fee: u32,
+    /// Unhealthiness (maintenance shortfall as a fraction of maintenance-weighted liabilities) at
+    /// which the liquidator fee reaches `liquidation_max_liquidator_fee`, from 0-100%
+    /// * 0 falls back to using the default 10% instead, i.e., `LIQUIDATION_FEE_RAMP`
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_fee_ramp: u32,
+    /// Overrides the group's `liquidation_close_factor` for liabilities in this bank, from 0-100%
+    /// * 0 falls back to using the group's close factor instead
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub liquidation_close_factor: u32,
+    /// Max deviation of the oracle's spot price from its EMA, as a fraction of the EMA. While
+    /// exceeded, this bank's collateral counts for nothing towards initial health, i.e. no new
+    /// borrows or withdraws against it. Deposits, repays, and liquidations are unaffected. See
+    /// `ORACLE_DEVIATION_WORSE_PRICE` to also use the worse of the two prices while exceeded.
+    /// * 0 disables the check
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub oracle_max_deviation: u32,
+    /// Oracle prints below this USD price (scaled by `oracle_price_bounds_exponent`) are rejected
+    /// as broken with `OraclePriceOutOfBounds`, rather than used to value positions.
+    /// * 0 disables the lower bound
+    pub oracle_price_min: u32,
+    /// Oracle prints above this USD price (scaled by `oracle_price_bounds_exponent`) are rejected
+    /// as broken with `OraclePriceOutOfBounds`, rather than used to value positions.
+    /// * 0 disables the upper bound
+    pub oracle_price_max: u32,
 }
 
 // Used to provide a default initialization value
@@ -1051,16 +1696,26 @@ impl Default for BankConfig {
             operational_state: BankOperationalState::Paused,
             oracle_setup: OracleSetup::None,
             oracle_keys: [Pubkey::default(); MAX_ORACLE_KEYS],
-            _pad0: [0; 6],
+            oracle_fixed_price_exponent: 0,
+            _pad0: [0; 1],
+            oracle_fixed_price: 0,
             risk_tier: RiskTier::Isolated,
             asset_tag: ASSET_TAG_DEFAULT,
             config_flags: 0,
-            _pad1: [0; 5],
+            oracle_price_bounds_exponent: 0,
+            oracle_fixed_price_max_deviation: 0,
             total_asset_value_init_limit: TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
             oracle_max_age: 0,
-            _padding0: [0; 2],
+            oracle_max_age_relaxed: 0,
             oracle_max_confidence: 0,
-            _padding1: [0; 32],
+            liquidation_liquidator_fee: 0,
+            liquidation_insurance_fee: 0,
+            liquidation_max_liquidator_fee: 0,
+            liquidation_fee_ramp: 0,
+            liquidation_close_factor: 0,
+            oracle_max_deviation: 0,
+            oracle_price_min: 0,
+            oracle_price_max: 0,
         }
     }
 }
@@ -1128,11 +1783,63 @@ impl BankConfig {
 
         self.interest_rate_config.validate()?;
 
-        if self.risk_tier == RiskTier::Isolated {
-            check!(asset_init_w == I80F48::ZERO, MarginfiError::InvalidConfig);
+        let liquidation_fees = self
+            .get_liquidation_liquidator_fee()
+            .checked_add(self.get_liquidation_insurance_fee())
+            .ok_or_else(math_error!())?;
+        check!(
+            liquidation_fees <= MAX_LIQUIDATION_FEES,
+            MarginfiError::InvalidConfig
+        );
+
+        if self.liquidation_max_liquidator_fee != 0 {
+            let max_liquidator_fee = self.get_liquidation_max_liquidator_fee();
+            check!(
+                max_liquidator_fee >= self.get_liquidation_liquidator_fee(),
+                MarginfiError::InvalidConfig
+            );
+            let max_liquidation_fees = max_liquidator_fee
+                .checked_add(self.get_liquidation_insurance_fee())
+                .ok_or_else(math_error!())?;
+            check!(
+                max_liquidation_fees <= MAX_LIQUIDATION_FEES,
+                MarginfiError::InvalidConfig
+            );
+        }
+
+        // Seizing collateral worth V lowers maintenance assets by `asset_maint_w * V` and repays
+        // at least `(1 - fees) * V` of liabilities (weighted at least 1), so fees within the margin
+        // the maintenance weight leaves mean every liquidation step improves health. Collateral
+        // counted at full value leaves no margin on this side: there, liquidations only improve
+        // health through the liability bank's weight, which isn't known here.
+        if asset_maint_w < I80F48::ONE {
+            let max_liquidation_fees = self
+                .get_liquidation_max_liquidator_fee()
+                .checked_add(self.get_liquidation_insurance_fee())
+                .ok_or_else(math_error!())?;
+            check!(
+                max_liquidation_fees < I80F48::ONE - asset_maint_w,
+                MarginfiError::InvalidConfig
+            );
+        }
+
+        if self.risk_tier == RiskTier::Isolated {
+            check!(asset_init_w == I80F48::ZERO, MarginfiError::InvalidConfig);
             check!(asset_maint_w == I80F48::ZERO, MarginfiError::InvalidConfig);
         }
 
+        check!(
+            (self.oracle_fixed_price_exponent.unsigned_abs() as usize) < MAX_EXP_10_I80F48,
+            MarginfiError::InvalidConfig
+        );
+        // A fixed-price bank can't be left without a price or a depeg guard
+        if self.oracle_setup == OracleSetup::Fixed {
+            check!(
+                self.oracle_fixed_price != 0 && self.oracle_fixed_price_max_deviation != 0,
+                MarginfiError::InvalidConfig
+            );
+        }
+
         Ok(())
     }
 
@@ -1158,6 +1865,75 @@ impl BankConfig {
         Ok(())
     }
 
+    /// Fraction of seized collateral's value paid to the liquidator
+    pub fn get_liquidation_liquidator_fee(&self) -> I80F48 {
+        if self.liquidation_liquidator_fee == 0
+            && (self.config_flags & LIQUIDATOR_FEE_CONFIGURED) == 0
+        {
+            LIQUIDATION_LIQUIDATOR_FEE
+        } else {
+            I80F48::from_num(self.liquidation_liquidator_fee) / U32_MAX
+        }
+    }
+
+    /// Fraction of seized collateral's value paid to the liability bank's insurance fund
+    pub fn get_liquidation_insurance_fee(&self) -> I80F48 {
+        if self.liquidation_insurance_fee == 0
+            && (self.config_flags & INSURANCE_FEE_CONFIGURED) == 0
+        {
+            LIQUIDATION_INSURANCE_FEE
+        } else {
+            I80F48::from_num(self.liquidation_insurance_fee) / U32_MAX
+        }
+    }
+
+    /// Cap on the liquidator fee. If the ramp is disabled, this is just the flat liquidator fee.
+    pub fn get_liquidation_max_liquidator_fee(&self) -> I80F48 {
+        if self.liquidation_max_liquidator_fee == 0 {
+            self.get_liquidation_liquidator_fee()
+        } else {
+            I80F48::from_num(self.liquidation_max_liquidator_fee) / U32_MAX
+        }
+    }
+
+    /// Unhealthiness at which the liquidator fee reaches its cap
+    pub fn get_liquidation_fee_ramp(&self) -> I80F48 {
+        if self.liquidation_fee_ramp == 0 {
+            LIQUIDATION_FEE_RAMP
+        } else {
+            I80F48::from_num(self.liquidation_fee_ramp) / U32_MAX
+        }
+    }
+
+    /// Fraction of seized collateral's value paid to the liquidator, given the liquidatee's
+    /// `unhealthiness` (see `calc_liquidation_unhealthiness`). Scales linearly from the base
+    /// liquidator fee at zero unhealthiness up to the cap at `liquidation_fee_ramp`.
+    pub fn get_liquidation_liquidator_fee_for_unhealthiness(
+        &self,
+        unhealthiness: I80F48,
+    ) -> MarginfiResult<I80F48> {
+        let base_fee = self.get_liquidation_liquidator_fee();
+        if self.liquidation_max_liquidator_fee == 0 || unhealthiness <= I80F48::ZERO {
+            return Ok(base_fee);
+        }
+
+        let ramp_progress = unhealthiness
+            .checked_div(self.get_liquidation_fee_ramp())
+            .ok_or_else(math_error!())?
+            .min(I80F48::ONE);
+
+        let fee = self
+            .get_liquidation_max_liquidator_fee()
+            .checked_sub(base_fee)
+            .ok_or_else(math_error!())?
+            .checked_mul(ramp_progress)
+            .ok_or_else(math_error!())?
+            .checked_add(base_fee)
+            .ok_or_else(math_error!())?;
+
+        Ok(fee)
+    }
+
     pub fn usd_init_limit_active(&self) -> bool {
         self.total_asset_value_init_limit != TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE
     }
@@ -1176,6 +1952,73 @@ impl BankConfig {
         (self.config_flags & PYTH_PUSH_MIGRATED) != 0
     }
 
+    pub fn uses_worse_price_on_oracle_deviation(&self) -> bool {
+        (self.config_flags & ORACLE_DEVIATION_WORSE_PRICE) != 0
+    }
+
+    /// True if `oracle_max_deviation` is set and the spot price deviates from the EMA by more
+    /// than it.
+    pub fn is_oracle_deviation_exceeded(
+        &self,
+        price_feed: &OraclePriceFeedAdapter,
+    ) -> MarginfiResult<bool> {
+        if self.oracle_max_deviation == 0 {
+            return Ok(false);
+        }
+
+        price_feed.is_ema_deviation_exceeded(self.oracle_max_deviation)
+    }
+
+    pub fn set_oracle_price_bounds(&mut self, bounds: &OraclePriceBounds) {
+        self.oracle_price_min = bounds.min;
+        self.oracle_price_max = bounds.max;
+        self.oracle_price_bounds_exponent = bounds.exponent;
+    }
+
+    /// The (min, max) USD price an oracle print must fall within, None where disabled
+    pub fn get_oracle_price_bounds(&self) -> MarginfiResult<(Option<I80F48>, Option<I80F48>)> {
+        let to_price = |bound: u32| -> MarginfiResult<Option<I80F48>> {
+            if bound == 0 {
+                return Ok(None);
+            }
+            Ok(Some(scale_by_exp_10(
+                bound,
+                self.oracle_price_bounds_exponent,
+            )?))
+        };
+
+        Ok((
+            to_price(self.oracle_price_min)?,
+            to_price(self.oracle_price_max)?,
+        ))
+    }
+
+    pub fn set_oracle_fixed_price(&mut self, fixed_price: &OracleFixedPrice) {
+        self.oracle_fixed_price = fixed_price.price;
+        self.oracle_fixed_price_exponent = fixed_price.exponent;
+        self.oracle_fixed_price_max_deviation = fixed_price.max_deviation;
+    }
+
+    /// The USD price an `OracleSetup::Fixed` bank is valued at while pegged
+    pub fn get_oracle_fixed_price(&self) -> MarginfiResult<I80F48> {
+        scale_by_exp_10(self.oracle_fixed_price, self.oracle_fixed_price_exponent)
+    }
+
+    pub fn validate_oracle_price_bounds(&self) -> MarginfiResult {
+        check!(
+            (self.oracle_price_bounds_exponent.unsigned_abs() as usize) < MAX_EXP_10_I80F48,
+            MarginfiError::InvalidConfig
+        );
+        if self.oracle_price_min != 0 && self.oracle_price_max != 0 {
+            check!(
+                self.oracle_price_min < self.oracle_price_max,
+                MarginfiError::InvalidConfig
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn update_config_flag(&mut self, value: bool, flag: u8) {
         if value {
             self.config_flags |= flag;
@@ -1196,13 +2039,78 @@ impl BankConfig {
         }
     }
 
+    /// Pyth push and Switchboard pull banks may have a fallback feed in `oracle_keys[1]`, used when
+    /// the primary feed is stale or too uncertain
+    pub fn has_oracle_fallback(&self) -> bool {
+        matches!(
+            self.oracle_setup,
+            OracleSetup::PythPushOracle | OracleSetup::SwitchboardPull
+        ) && self.oracle_keys[1] != Pubkey::default()
+    }
+
+    /// Number of `OracleSetup::Median` sources, i.e. the leading non-default keys among the first
+    /// `MAX_MEDIAN_ORACLE_SOURCES` oracle keys
+    pub fn get_median_oracle_sources_len(&self) -> usize {
+        self.oracle_keys
+            .iter()
+            .take(MAX_MEDIAN_ORACLE_SOURCES)
+            .take_while(|key| **key != Pubkey::default())
+            .count()
+    }
+
     #[inline]
     pub fn get_oracle_max_age(&self) -> u64 {
         match (self.oracle_max_age, self.oracle_setup) {
-            (0, OracleSetup::PythPushOracle) => MAX_PYTH_ORACLE_AGE,
+            (
+                0,
+                OracleSetup::PythPushOracle
+                | OracleSetup::Median
+                | OracleSetup::Fixed
+                | OracleSetup::CrossRate
+                | OracleSetup::ExchangeRate,
+            ) => MAX_PYTH_ORACLE_AGE,
             (n, _) => n as u64,
         }
     }
+
+    /// See `oracle_max_age_relaxed`. Never shorter than `get_oracle_max_age`.
+    #[inline]
+    pub fn get_oracle_max_age_relaxed(&self) -> u64 {
+        (self.oracle_max_age_relaxed as u64).max(self.get_oracle_max_age())
+    }
+}
+
+/// `value * 10^exponent`
+fn scale_by_exp_10(value: u32, exponent: i8) -> MarginfiResult<I80F48> {
+    let scale = EXP_10_I80F48
+        .get(exponent.unsigned_abs() as usize)
+        .ok_or_else(math_error!())?;
+    let scaled = if exponent < 0 {
+        I80F48::from_num(value).checked_div(*scale)
+    } else {
+        I80F48::from_num(value).checked_mul(*scale)
+    };
+
+    Ok(scaled.ok_or_else(math_error!())?)
+}
+
+/// How far underwater an account is: its maintenance shortfall (-health) as a fraction of its
+/// maintenance-weighted liabilities. Zero for healthy accounts or accounts with no liabilities.
+pub fn calc_liquidation_unhealthiness(
+    health: I80F48,
+    liabilities: I80F48,
+) -> MarginfiResult<I80F48> {
+    if health >= I80F48::ZERO || liabilities <= I80F48::ZERO {
+        return Ok(I80F48::ZERO);
+    }
+
+    let unhealthiness = health
+        .checked_neg()
+        .ok_or_else(math_error!())?
+        .checked_div(liabilities)
+        .ok_or_else(math_error!())?;
+
+    Ok(unhealthiness)
 }
 
 #[derive(AnchorDeserialize, AnchorSerialize, Default, Clone, PartialEq, Eq, TypeLayout)]
@@ -1230,9 +2138,58 @@ pub struct BankConfigOpt {
 
     pub oracle_max_age: Option<u16>,
 
+    pub oracle_max_age_relaxed: Option<u16>,
+
+    pub oracle_stale_liquidation_haircut: Option<u32>,
+
     pub permissionless_bad_debt_settlement: Option<bool>,
 
     pub freeze_settings: Option<bool>,
+
+    pub compound_interest: Option<bool>,
+
+    pub liquidation_liquidator_fee: Option<u32>,
+
+    pub liquidation_insurance_fee: Option<u32>,
+
+    pub liquidation_max_liquidator_fee: Option<u32>,
+
+    pub liquidation_fee_ramp: Option<u32>,
+
+    pub liquidation_close_factor: Option<u32>,
+
+    pub oracle_max_deviation: Option<u32>,
+
+    pub oracle_deviation_use_worse_price: Option<bool>,
+
+    pub oracle_fixed_price: Option<OracleFixedPrice>,
+
+    pub fixed_term_premium: Option<u32>,
+
+    pub fixed_term_max_duration: Option<u32>,
+
+    pub oracle_confidence: Option<OracleConfidenceConfig>,
+}
+
+/// Hard bounds on a bank's oracle price, e.g. `{ min: 50, max: 200, exponent: -2 }` rejects USDC
+/// prints outside $0.50-$2. A zero `min` or `max` disables that side.
+#[derive(AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePriceBounds {
+    pub min: u32,
+    pub max: u32,
+    pub exponent: i8,
+}
+
+/// The constant price of an `OracleSetup::Fixed` bank, e.g. `{ price: 1, exponent: 0, .. }` for
+/// $1, and how far its live feed may stray from it before the bank counts as depegged.
+/// * `max_deviation` - A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+#[derive(
+    AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, Debug, PartialEq, Eq, TypeLayout,
+)]
+pub struct OracleFixedPrice {
+    pub price: u32,
+    pub exponent: i8,
+    pub max_deviation: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -1305,8 +2262,73 @@ pub struct InterestRateConfig {
     // A one-time fee (not annualized) when a loan is initiated, similar to a startup fee
     pub protocol_origination_fee: WrappedI80F48,
 
-    pub _padding0: [u8; 16],
-    pub _padding1: [[u8; 32]; 3],
+    /// If nonzero, the plateau rate adapts over time to push utilization toward
+    /// `optimal_utilization_rate` (the max rate scales with it so the curve keeps its shape). At
+    /// 0% or 100% utilization, the plateau rate changes by this fraction per year, e.g. 5 = 500%.
+    /// The change scales linearly with the distance from optimal utilization.
+    /// * Only valid with `INTEREST_CURVE_LEGACY`
+    /// * 0 (default) - adaptive rates disabled
+    pub adaptive_speed: WrappedI80F48,
+
+    /// Base APR at 0% utilization when using a multi-point curve. u32::MAX = 1000%
+    pub zero_util_rate: u32,
+    /// Base APR at 100% utilization when using a multi-point curve. u32::MAX = 1000%
+    pub hundred_util_rate: u32,
+    /// Kinks of the multi-point curve, sorted by ascending utilization. Unused points are zeroed
+    /// and must come after all used points.
+    pub points: [RatePoint; CURVE_POINTS],
+    /// * `INTEREST_CURVE_LEGACY` (0): optimal/plateau/max three-parameter curve
+    /// * `INTEREST_CURVE_MULTI_POINT` (1): piecewise linear curve through
+    ///   (0, `zero_util_rate`), `points`, (1, `hundred_util_rate`)
+    pub curve_type: u8,
+    pub _pad0: [u8; 7],
+    /// Adaptive rates only: lower bound of the adapted plateau rate. u32::MAX = 1000%
+    pub adaptive_min_rate: u32,
+    /// Adaptive rates only: upper bound of the adapted plateau rate. u32::MAX = 1000%
+    pub adaptive_max_rate: u32,
+    pub _padding1: [[u8; 8]; 4],
+}
+
+assert_struct_size!(RatePoint, 8);
+#[repr(C)]
+#[derive(
+    Default,
+    Debug,
+    Copy,
+    Clone,
+    AnchorDeserialize,
+    AnchorSerialize,
+    Zeroable,
+    Pod,
+    PartialEq,
+    Eq,
+    TypeLayout,
+)]
+pub struct RatePoint {
+    /// Utilization ratio at this kink. u32::MAX = 100%
+    pub util: u32,
+    /// Base APR at this kink. u32::MAX = 1000%
+    pub rate: u32,
+}
+
+impl RatePoint {
+    pub fn new(util: u32, rate: u32) -> Self {
+        Self { util, rate }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.util == 0 && self.rate == 0
+    }
+}
+
+/// Converts a u32 utilization (u32::MAX = 100%) into a ratio from 0-1
+pub fn u32_to_util(value: u32) -> I80F48 {
+    I80F48::from_num(value) / U32_MAX
+}
+
+/// Converts a u32 rate (u32::MAX = 1000%) into an APR from 0-10
+pub fn u32_to_apr(value: u32) -> I80F48 {
+    I80F48::from_num(value) / U32_MAX_DIV_10
 }
 
 impl InterestRateConfig {
@@ -1325,6 +2347,10 @@ impl InterestRateConfig {
             insurance_rate_fee: self.insurance_ir_fee.into(),
             protocol_fixed_fee: self.protocol_fixed_fee_apr.into(),
             protocol_rate_fee: self.protocol_ir_fee.into(),
+            curve_type: self.curve_type,
+            zero_util_rate: self.zero_util_rate,
+            hundred_util_rate: self.hundred_util_rate,
+            points: self.points,
             add_program_fees: group_bank_config.program_fees,
             program_fee_fixed: group.fee_state_cache.program_fee_fixed.into(),
             program_fee_rate: group.fee_state_cache.program_fee_rate.into(),
@@ -1332,6 +2358,81 @@ impl InterestRateConfig {
     }
 
     pub fn validate(&self) -> MarginfiResult {
+        match self.curve_type {
+            INTEREST_CURVE_LEGACY => self.validate_legacy_curve()?,
+            INTEREST_CURVE_MULTI_POINT => self.validate_multi_point_curve()?,
+            _ => return err!(MarginfiError::InvalidConfig),
+        }
+
+        self.validate_adaptive()
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        I80F48::from(self.adaptive_speed) != I80F48::ZERO
+    }
+
+    /// * Adaptive rates only apply to the legacy curve, which has a plateau to adapt
+    /// * 0 < min <= plateau <= max
+    fn validate_adaptive(&self) -> MarginfiResult {
+        if !self.is_adaptive() {
+            return Ok(());
+        }
+
+        let speed: I80F48 = self.adaptive_speed.into();
+        let plateau_ir: I80F48 = self.plateau_interest_rate.into();
+        let min_ir = u32_to_apr(self.adaptive_min_rate);
+        let max_ir = u32_to_apr(self.adaptive_max_rate);
+
+        check!(speed > I80F48::ZERO, MarginfiError::InvalidConfig);
+        check!(
+            self.curve_type == INTEREST_CURVE_LEGACY,
+            MarginfiError::InvalidConfig
+        );
+        check!(min_ir > I80F48::ZERO, MarginfiError::InvalidConfig);
+        check!(
+            min_ir <= plateau_ir && plateau_ir <= max_ir,
+            MarginfiError::InvalidConfig
+        );
+
+        Ok(())
+    }
+
+    /// Adaptive rates only. The plateau rate after `time_delta` seconds spent at
+    /// `utilization_ratio`, starting from `current_rate`, bounded by the adaptive min/max rates.
+    ///
+    /// The error term is the distance from optimal utilization, normalized to [-1, 1] on either
+    /// side of `optimal_utilization_rate`.
+    pub fn calc_adapted_plateau_rate(
+        &self,
+        current_rate: I80F48,
+        utilization_ratio: I80F48,
+        time_delta: u64,
+    ) -> Option<I80F48> {
+        let optimal_ur: I80F48 = self.optimal_utilization_rate.into();
+        let speed: I80F48 = self.adaptive_speed.into();
+        let ur = utilization_ratio.min(I80F48::ONE).max(I80F48::ZERO);
+
+        let error = if ur >= optimal_ur {
+            ur.checked_sub(optimal_ur)?
+                .checked_div(I80F48::ONE.checked_sub(optimal_ur)?)?
+        } else {
+            ur.checked_sub(optimal_ur)?.checked_div(optimal_ur)?
+        };
+
+        let change = speed
+            .checked_mul(error)?
+            .checked_mul(I80F48::from_num(time_delta))?
+            .checked_div(SECONDS_PER_YEAR)?;
+        let new_rate = current_rate.checked_mul(I80F48::ONE.checked_add(change)?)?;
+
+        Some(
+            new_rate
+                .max(u32_to_apr(self.adaptive_min_rate))
+                .min(u32_to_apr(self.adaptive_max_rate)),
+        )
+    }
+
+    fn validate_legacy_curve(&self) -> MarginfiResult {
         let optimal_ur: I80F48 = self.optimal_utilization_rate.into();
         let plateau_ir: I80F48 = self.plateau_interest_rate.into();
         let max_ir: I80F48 = self.max_interest_rate.into();
@@ -1347,6 +2448,38 @@ impl InterestRateConfig {
         Ok(())
     }
 
+    /// * Used points have a strictly increasing utilization in (0%, 100%)
+    /// * Empty points only appear after all used points
+    /// * Rates never decrease as utilization increases, and the curve is not flat at zero
+    fn validate_multi_point_curve(&self) -> MarginfiResult {
+        let mut prev_util: u32 = 0;
+        let mut prev_rate: u32 = self.zero_util_rate;
+        let mut seen_empty = false;
+
+        for point in self.points.iter() {
+            if point.is_empty() {
+                seen_empty = true;
+                continue;
+            }
+            check!(!seen_empty, MarginfiError::InvalidConfig);
+            check!(
+                point.util > prev_util && point.util < u32::MAX,
+                MarginfiError::InvalidConfig
+            );
+            check!(point.rate >= prev_rate, MarginfiError::InvalidConfig);
+            prev_util = point.util;
+            prev_rate = point.rate;
+        }
+
+        check!(
+            self.hundred_util_rate >= prev_rate,
+            MarginfiError::InvalidConfig
+        );
+        check!(self.hundred_util_rate > 0, MarginfiError::InvalidConfig);
+
+        Ok(())
+    }
+
     pub fn update(&mut self, ir_config: &InterestRateConfigOpt) {
         set_if_some!(
             self.optimal_utilization_rate,
@@ -1368,6 +2501,13 @@ impl InterestRateConfig {
             self.protocol_origination_fee,
             ir_config.protocol_origination_fee
         );
+        set_if_some!(self.curve_type, ir_config.curve_type);
+        set_if_some!(self.zero_util_rate, ir_config.zero_util_rate);
+        set_if_some!(self.hundred_util_rate, ir_config.hundred_util_rate);
+        set_if_some!(self.points, ir_config.points);
+        set_if_some!(self.adaptive_speed, ir_config.adaptive_speed);
+        set_if_some!(self.adaptive_min_rate, ir_config.adaptive_min_rate);
+        set_if_some!(self.adaptive_max_rate, ir_config.adaptive_max_rate);
     }
 }
 
@@ -1419,6 +2559,15 @@ pub struct InterestRateConfigOpt {
     pub protocol_fixed_fee_apr: Option<WrappedI80F48>,
     pub protocol_ir_fee: Option<WrappedI80F48>,
     pub protocol_origination_fee: Option<WrappedI80F48>,
+
+    pub curve_type: Option<u8>,
+    pub zero_util_rate: Option<u32>,
+    pub hundred_util_rate: Option<u32>,
+    pub points: Option<[RatePoint; CURVE_POINTS]>,
+
+    pub adaptive_speed: Option<WrappedI80F48>,
+    pub adaptive_min_rate: Option<u32>,
+    pub adaptive_max_rate: Option<u32>,
 }
 
 #[repr(C)]
@@ -1489,17 +2638,27 @@ impl From<BankConfigCompact> for BankConfig {
             operational_state: config.operational_state,
             oracle_setup: OracleSetup::None,
             oracle_keys: keys,
-            _pad0: [0; 6],
+            oracle_fixed_price_exponent: 0,
+            _pad0: [0; 1],
+            oracle_fixed_price: 0,
             borrow_limit: config.borrow_limit,
             risk_tier: config.risk_tier,
             asset_tag: config.asset_tag,
             config_flags: config.config_flags,
-            _pad1: [0; 5],
+            oracle_price_bounds_exponent: 0,
+            oracle_fixed_price_max_deviation: 0,
             total_asset_value_init_limit: config.total_asset_value_init_limit,
             oracle_max_age: config.oracle_max_age,
-            _padding0: [0; 2],
+            oracle_max_age_relaxed: 0,
             oracle_max_confidence: config.oracle_max_confidence,
-            _padding1: [0; 32],
+            liquidation_liquidator_fee: 0,
+            liquidation_insurance_fee: 0,
+            liquidation_max_liquidator_fee: 0,
+            liquidation_fee_ramp: 0,
+            liquidation_close_factor: 0,
+            oracle_max_deviation: 0,
+            oracle_price_min: 0,
+            oracle_price_max: 0,
         }
     }
 }
@@ -1540,6 +2699,11 @@ pub struct InterestRateCalc {
     // AKA group rate fee
     protocol_rate_fee: I80F48,
 
+    curve_type: u8,
+    zero_util_rate: u32,
+    hundred_util_rate: u32,
+    points: [RatePoint; CURVE_POINTS],
+
     program_fee_fixed: I80F48,
     program_fee_rate: I80F48,
 
@@ -1597,6 +2761,29 @@ impl InterestRateCalc {
         })
     }
 
+    /// `calc_interest_rate` at the utilization of variable-rate and fixed-term debt combined.
+    /// Fixed-term interest reaches depositors separately (see `Bank::accrue_fixed_term_interest`),
+    /// so the lending rate only pays out what variable-rate borrowers are charged.
+    pub fn calc_interest_rate_with_fixed_term(
+        &self,
+        total_assets_amount: I80F48,
+        total_liabilities_amount: I80F48,
+        fixed_term_liabilities_amount: I80F48,
+    ) -> Option<ComputedInterestRates> {
+        let utilization_rate = total_liabilities_amount
+            .checked_add(fixed_term_liabilities_amount)?
+            .checked_div(total_assets_amount)?;
+        let mut interest_rates = self.calc_interest_rate(utilization_rate)?;
+
+        if fixed_term_liabilities_amount > I80F48::ZERO {
+            interest_rates.lending_rate_apr = interest_rates
+                .base_rate_apr
+                .checked_mul(total_liabilities_amount.checked_div(total_assets_amount)?)?;
+        }
+
+        Some(interest_rates)
+    }
+
     /// Piecewise linear interest rate function.
     /// The curves approaches the `plateau_interest_rate` as the utilization ratio approaches the `optimal_utilization_rate`,
     /// once the utilization ratio exceeds the `optimal_utilization_rate`, the curve approaches the `max_interest_rate`.
@@ -1604,6 +2791,10 @@ impl InterestRateCalc {
     /// To be clear we don't particularly appreciate the piecewise linear nature of this "curve", but it is what it is.
     #[inline]
     fn interest_rate_curve(&self, ur: I80F48) -> Option<I80F48> {
+        if self.curve_type == INTEREST_CURVE_MULTI_POINT {
+            return self.multi_point_interest_rate_curve(ur);
+        }
+
         let optimal_ur: I80F48 = self.optimal_utilization_rate;
         let plateau_ir: I80F48 = self.plateau_interest_rate;
         let max_ir: I80F48 = self.max_interest_rate;
@@ -1619,6 +2810,52 @@ impl InterestRateCalc {
         }
     }
 
+    /// Piecewise linear interest rate function through (0, `zero_util_rate`), each used point in
+    /// `points`, and finally (1, `hundred_util_rate`). Utilization above 100% is clamped.
+    fn multi_point_interest_rate_curve(&self, ur: I80F48) -> Option<I80F48> {
+        let ur = ur.min(I80F48::ONE).max(I80F48::ZERO);
+
+        let mut prev_util = I80F48::ZERO;
+        let mut prev_rate = u32_to_apr(self.zero_util_rate);
+
+        let kinks = self
+            .points
+            .iter()
+            .take_while(|p| !p.is_empty())
+            .map(|p| (u32_to_util(p.util), u32_to_apr(p.rate)))
+            .chain(std::iter::once((
+                I80F48::ONE,
+                u32_to_apr(self.hundred_util_rate),
+            )));
+
+        for (util, rate) in kinks {
+            if ur <= util {
+                let span = util.checked_sub(prev_util)?;
+                if span == I80F48::ZERO {
+                    return Some(rate);
+                }
+                return ur
+                    .checked_sub(prev_util)?
+                    .checked_div(span)?
+                    .checked_mul(rate.checked_sub(prev_rate)?)?
+                    .checked_add(prev_rate);
+            }
+            prev_util = util;
+            prev_rate = rate;
+        }
+
+        Some(prev_rate)
+    }
+
+    /// Replaces the plateau rate with `plateau_rate`, scaling the max rate by the same factor so
+    /// the shape of the curve is preserved. Only meaningful for the legacy curve.
+    pub fn with_adaptive_plateau_rate(mut self, plateau_rate: I80F48) -> Option<Self> {
+        let scale = plateau_rate.checked_div(self.plateau_interest_rate)?;
+        self.max_interest_rate = self.max_interest_rate.checked_mul(scale)?;
+        self.plateau_interest_rate = plateau_rate;
+        Some(self)
+    }
+
     pub fn get_fees(&self) -> Fees {
         let (protocol_fee_rate, protocol_fee_fixed) = if self.add_program_fees {
             (self.program_fee_rate, self.program_fee_fixed)
@@ -1645,8 +2882,9 @@ struct InterestRateStateChanges {
     protocol_fees_collected: I80F48,
 }
 
-/// We use a simple interest rate model that auto settles the accrued interest into the lending account balances.
-/// The plan is to move to a compound interest model in the future.
+/// Accrued interest is auto settled into the lending account balances. Banks use a simple interest
+/// model unless `COMPOUND_INTEREST_FLAG` is set (`compound`), in which case interest compounds
+/// continuously and the result doesn't depend on how often the bank is updated.
 ///
 /// Simple interest rate model:
 /// - `P` - principal
@@ -1672,21 +2910,33 @@ struct InterestRateStateChanges {
 ///
 /// `i_b = i * (1 + f_i) + f_f`
 ///
+/// Compound interest rate model:
+///
+/// `P_t = P_0 * e^(i_b * t)`
+///
+/// Borrowers are charged continuously compounded interest at `i_b`. The interest paid is split
+/// between fees and lenders in the same proportions as in the simple model, so that
+/// `assets + fees - liabilities` is preserved exactly.
+#[allow(clippy::too_many_arguments)]
 fn calc_interest_rate_accrual_state_changes(
     time_delta: u64,
     total_assets_amount: I80F48,
     total_liabilities_amount: I80F48,
+    fixed_term_liabilities_amount: I80F48,
     interest_rate_calc: &InterestRateCalc,
     asset_share_value: I80F48,
     liability_share_value: I80F48,
+    compound: bool,
 ) -> Option<InterestRateStateChanges> {
-    // If the cache is empty, we need to calculate the interest rates
-    let utilization_rate = total_liabilities_amount.checked_div(total_assets_amount)?;
     debug!(
-        "Utilization rate: {}, time delta {}s",
-        utilization_rate, time_delta
+        "Liabilities: {} variable, {} fixed-term, time delta {}s",
+        total_liabilities_amount, fixed_term_liabilities_amount, time_delta
     );
-    let interest_rates = interest_rate_calc.calc_interest_rate(utilization_rate)?;
+    let interest_rates = interest_rate_calc.calc_interest_rate_with_fixed_term(
+        total_assets_amount,
+        total_liabilities_amount,
+        fixed_term_liabilities_amount,
+    )?;
 
     debug!("{:#?}", interest_rates);
 
@@ -1699,6 +2949,17 @@ fn calc_interest_rate_accrual_state_changes(
         ..
     } = interest_rates;
 
+    if compound {
+        return calc_compound_interest_state_changes(
+            time_delta,
+            total_assets_amount,
+            total_liabilities_amount,
+            &interest_rates,
+            asset_share_value,
+            liability_share_value,
+        );
+    }
+
     Some(InterestRateStateChanges {
         new_asset_share_value: calc_accrued_interest_payment_per_period(
             lending_rate_apr,
@@ -1728,6 +2989,118 @@ fn calc_interest_rate_accrual_state_changes(
     })
 }
 
+fn calc_compound_interest_state_changes(
+    time_delta: u64,
+    total_assets_amount: I80F48,
+    total_liabilities_amount: I80F48,
+    interest_rates: &ComputedInterestRates,
+    asset_share_value: I80F48,
+    liability_share_value: I80F48,
+) -> Option<InterestRateStateChanges> {
+    let ComputedInterestRates {
+        borrowing_rate_apr,
+        group_fee_apr,
+        insurance_fee_apr,
+        protocol_fee_apr,
+        ..
+    } = *interest_rates;
+
+    if borrowing_rate_apr.is_zero() {
+        return Some(InterestRateStateChanges {
+            new_asset_share_value: asset_share_value,
+            new_liability_share_value: liability_share_value,
+            insurance_fees_collected: I80F48::ZERO,
+            group_fees_collected: I80F48::ZERO,
+            protocol_fees_collected: I80F48::ZERO,
+        });
+    }
+
+    let growth = calc_compound_growth_for_period(borrowing_rate_apr, time_delta)?;
+    let interest_paid = total_liabilities_amount.checked_mul(growth.checked_sub(I80F48::ONE)?)?;
+
+    // Each fee takes the share of the interest paid that it takes of the borrowing rate
+    let fee_share = |fee_apr: I80F48| -> Option<I80F48> {
+        interest_paid
+            .checked_mul(fee_apr)?
+            .checked_div(borrowing_rate_apr)
+    };
+    let insurance_fees_collected = fee_share(insurance_fee_apr)?;
+    let group_fees_collected = fee_share(group_fee_apr)?;
+    let protocol_fees_collected = fee_share(protocol_fee_apr)?;
+
+    let lender_interest = interest_paid
+        .checked_sub(insurance_fees_collected)?
+        .checked_sub(group_fees_collected)?
+        .checked_sub(protocol_fees_collected)?;
+    let asset_growth =
+        I80F48::ONE.checked_add(lender_interest.checked_div(total_assets_amount)?)?;
+
+    Some(InterestRateStateChanges {
+        new_asset_share_value: asset_share_value.checked_mul(asset_growth)?,
+        new_liability_share_value: liability_share_value.checked_mul(growth)?,
+        insurance_fees_collected,
+        group_fees_collected,
+        protocol_fees_collected,
+    })
+}
+
+/// Cap on the exponent of a single compound accrual, i.e. debt grows at most e^10 (~22,000x) per
+/// update, e.g. a year at 1000% APR. Much further and the growth (or the interest paid on a large
+/// bank's liabilities) overflows I80F48, after which the bank could never accrue interest again.
+/// Only a bank left at an extreme rate for a long time without any update reaches the cap.
+const MAX_COMPOUND_EXPONENT: I80F48 = I80F48::lit("10");
+
+/// Growth factor of a principal under continuous compounding at `apr` for `time_delta` seconds.
+/// Formula: e^(APR * time_delta / SECONDS_PER_YEAR), with the exponent capped at
+/// `MAX_COMPOUND_EXPONENT`
+fn calc_compound_growth_for_period(apr: I80F48, time_delta: u64) -> Option<I80F48> {
+    let exponent = apr
+        .checked_mul(time_delta.into())?
+        .checked_div(SECONDS_PER_YEAR)?;
+
+    exp_fixed(exponent.min(MAX_COMPOUND_EXPONENT))
+}
+
+/// More than enough for full I80F48 precision once the exponent is reduced below 1/2
+const EXP_TAYLOR_TERMS: u32 = 20;
+
+/// e^x for non-negative `x`.
+///
+/// `x` is halved until it is below 1/2, e^x is approximated there with a Taylor series, and the
+/// result is squared back up: e^x = (e^(x / 2^k))^(2^k).
+fn exp_fixed(x: I80F48) -> Option<I80F48> {
+    if x.is_negative() {
+        return None;
+    }
+
+    let half = I80F48::from_num(0.5);
+    let mut reduced = x;
+    let mut squarings: u32 = 0;
+    while reduced > half {
+        reduced /= 2;
+        squarings += 1;
+    }
+
+    // 1 + x + x^2 / 2! + ... + x^n / n!
+    let mut result = I80F48::ONE;
+    let mut term = I80F48::ONE;
+    for n in 1..=EXP_TAYLOR_TERMS {
+        term = term
+            .checked_mul(reduced)?
+            .checked_div(I80F48::from_num(n))?;
+        if term.is_zero() {
+            break;
+        }
+        result = result.checked_add(term)?;
+    }
+
+    for _ in 0..squarings {
+        result = result.checked_mul(result)?;
+    }
+
+    Some(result)
+}
+
 /// Calculates the fee rate for a given base rate and fees specified.
 /// The returned rate is only the fee rate without the base rate.
 ///
@@ -1809,8 +3182,15 @@ impl From<InterestRateConfigCompact> for InterestRateConfig {
             protocol_fixed_fee_apr: ir_config.protocol_fixed_fee_apr,
             protocol_ir_fee: ir_config.protocol_ir_fee,
             protocol_origination_fee: ir_config.protocol_origination_fee,
-            _padding0: [0; 16],
-            _padding1: [[0; 32]; 3],
+            adaptive_speed: I80F48::ZERO.into(),
+            zero_util_rate: 0,
+            hundred_util_rate: 0,
+            points: [RatePoint::default(); CURVE_POINTS],
+            curve_type: INTEREST_CURVE_LEGACY,
+            _pad0: [0; 7],
+            adaptive_min_rate: 0,
+            adaptive_max_rate: 0,
+            _padding1: [[0; 8]; 4],
         }
     }
 }
@@ -2033,8 +3413,305 @@ mod tests {
         assert_eq_with_tolerance!(insurance_apr, I80F48!(0.17), I80F48!(0.001));
     }
 
-    #[test]
-    fn ir_accrual_failing_fuzz_test_example() -> anyhow::Result<()> {
+    fn multi_point_config() -> InterestRateConfig {
+        // 0% -> 1%, 50% -> 5%, 80% -> 10%, 90% -> 50%, 100% -> 150%
+        let util = |u: f64| (u * u32::MAX as f64) as u32;
+        let rate = |r: f64| (r / 10.0 * u32::MAX as f64) as u32;
+        let mut points = [RatePoint::default(); CURVE_POINTS];
+        points[0] = RatePoint::new(util(0.5), rate(0.05));
+        points[1] = RatePoint::new(util(0.8), rate(0.1));
+        points[2] = RatePoint::new(util(0.9), rate(0.5));
+
+        InterestRateConfig {
+            curve_type: INTEREST_CURVE_MULTI_POINT,
+            zero_util_rate: rate(0.01),
+            hundred_util_rate: rate(1.5),
+            points,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn multi_point_curve_interpolates_between_kinks() {
+        let config = multi_point_config();
+        config.validate().unwrap();
+        let calc = config.create_interest_rate_calculator(&MarginfiGroup::default());
+
+        let base_rate = |ur: I80F48| calc.calc_interest_rate(ur).unwrap().base_rate_apr;
+
+        assert_eq_with_tolerance!(base_rate(I80F48!(0)), I80F48!(0.01), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.25)), I80F48!(0.03), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.5)), I80F48!(0.05), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.65)), I80F48!(0.075), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.85)), I80F48!(0.3), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.95)), I80F48!(1.0), I80F48!(0.0001));
+        assert_eq_with_tolerance!(base_rate(I80F48!(1)), I80F48!(1.5), I80F48!(0.0001));
+        // Clamped above 100%
+        assert_eq_with_tolerance!(base_rate(I80F48!(1.2)), I80F48!(1.5), I80F48!(0.0001));
+    }
+
+    #[test]
+    fn multi_point_curve_without_points_is_linear() {
+        let config = InterestRateConfig {
+            curve_type: INTEREST_CURVE_MULTI_POINT,
+            zero_util_rate: 0,
+            hundred_util_rate: u32::MAX / 10, // 100%
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        let calc = config.create_interest_rate_calculator(&MarginfiGroup::default());
+
+        assert_eq_with_tolerance!(
+            calc.calc_interest_rate(I80F48!(0.4)).unwrap().base_rate_apr,
+            I80F48!(0.4),
+            I80F48!(0.0001)
+        );
+    }
+
+    #[test]
+    fn multi_point_curve_validation() {
+        let mut config = multi_point_config();
+        config.validate().unwrap();
+
+        // Utilization must be strictly increasing
+        config.points[1].util = config.points[0].util;
+        assert!(config.validate().is_err());
+
+        // Rates must not decrease
+        let mut config = multi_point_config();
+        config.points[1].rate = config.points[0].rate - 1;
+        assert!(config.validate().is_err());
+
+        // Empty points must be trailing
+        let mut config = multi_point_config();
+        config.points[1] = RatePoint::default();
+        assert!(config.validate().is_err());
+
+        // A kink at 100% utilization is not allowed, use `hundred_util_rate` instead
+        let mut config = multi_point_config();
+        config.points[3] = RatePoint::new(u32::MAX, config.hundred_util_rate);
+        assert!(config.validate().is_err());
+
+        // The end of the curve must be at least the last kink
+        let mut config = multi_point_config();
+        config.hundred_util_rate = config.points[2].rate - 1;
+        assert!(config.validate().is_err());
+
+        // Unknown curve types are rejected
+        let mut config = multi_point_config();
+        config.curve_type = 2;
+        assert!(config.validate().is_err());
+    }
+
+    fn adaptive_config() -> InterestRateConfig {
+        InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.8).into(),
+            plateau_interest_rate: I80F48!(0.1).into(),
+            max_interest_rate: I80F48!(1).into(),
+            adaptive_speed: I80F48!(5).into(),
+            adaptive_min_rate: u32::MAX / 1000, // 1%
+            adaptive_max_rate: u32::MAX / 2,    // 500%
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn adaptive_rate_drifts_toward_optimal_utilization() {
+        let config = adaptive_config();
+        config.validate().unwrap();
+        let plateau = I80F48!(0.1);
+        let one_day = 24 * 60 * 60;
+
+        // At optimal utilization the rate is left alone
+        assert_eq!(
+            config
+                .calc_adapted_plateau_rate(plateau, I80F48!(0.8), one_day)
+                .unwrap(),
+            plateau
+        );
+
+        // Full utilization: +500%/yr * 1 day
+        let up = config
+            .calc_adapted_plateau_rate(plateau, I80F48!(1), one_day)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            up,
+            plateau * (I80F48::ONE + I80F48!(5) / I80F48!(365)),
+            I80F48!(0.000001)
+        );
+
+        // Half way below optimal: -250%/yr * 1 day
+        let down = config
+            .calc_adapted_plateau_rate(plateau, I80F48!(0.4), one_day)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            down,
+            plateau * (I80F48::ONE - I80F48!(2.5) / I80F48!(365)),
+            I80F48!(0.000001)
+        );
+    }
+
+    #[test]
+    fn adaptive_rate_is_bounded() {
+        let config = adaptive_config();
+        let one_year = 31_536_000;
+
+        let floor = config
+            .calc_adapted_plateau_rate(I80F48!(0.1), I80F48::ZERO, one_year)
+            .unwrap();
+        assert_eq_with_tolerance!(floor, I80F48!(0.01), I80F48!(0.000001));
+
+        let mut rate = I80F48!(0.1);
+        for _ in 0..10 {
+            rate = config
+                .calc_adapted_plateau_rate(rate, I80F48::ONE, one_year)
+                .unwrap();
+        }
+        assert_eq_with_tolerance!(rate, I80F48!(5), I80F48!(0.000001));
+    }
+
+    #[test]
+    fn adaptive_rate_scales_the_curve() {
+        let config = adaptive_config();
+        let calc = config
+            .create_interest_rate_calculator(&MarginfiGroup::default())
+            .with_adaptive_plateau_rate(I80F48!(0.2))
+            .unwrap();
+
+        let base_rate = |ur: I80F48| calc.calc_interest_rate(ur).unwrap().base_rate_apr;
+        assert_eq_with_tolerance!(base_rate(I80F48!(0.8)), I80F48!(0.2), I80F48!(0.0001));
+        // Max rate doubled along with the plateau
+        assert_eq_with_tolerance!(base_rate(I80F48!(1)), I80F48!(2), I80F48!(0.0001));
+    }
+
+    #[test]
+    fn bank_persists_adaptive_rate() {
+        let mut bank = Bank {
+            config: BankConfig {
+                interest_rate_config: adaptive_config(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let one_day = 24 * 60 * 60;
+
+        // Starts at the configured plateau
+        assert_eq!(bank.get_adaptive_plateau_rate(), Some(I80F48!(0.1)));
+
+        bank.update_adaptive_plateau_rate(I80F48!(100), I80F48!(100), one_day)
+            .unwrap();
+        let adapted = bank.get_adaptive_plateau_rate().unwrap();
+        assert!(adapted > I80F48!(0.1));
+        assert_eq!(I80F48::from(bank.adaptive_plateau_rate), adapted);
+
+        let calc = bank
+            .get_interest_rate_calculator(&MarginfiGroup::default())
+            .unwrap();
+        assert_eq_with_tolerance!(
+            calc.calc_interest_rate(I80F48!(0.8)).unwrap().base_rate_apr,
+            adapted,
+            I80F48!(0.0001)
+        );
+
+        bank.cache
+            .update_adaptive_plateau_rate(bank.get_adaptive_plateau_rate());
+        assert_eq!(
+            bank.cache.adaptive_plateau_rate,
+            crate::state::bank_cache::apr_to_u32(adapted)
+        );
+
+        // Disabling adaptive rates clears the stored rate on the next update
+        bank.config.interest_rate_config.adaptive_speed = I80F48::ZERO.into();
+        assert_eq!(bank.get_adaptive_plateau_rate(), None);
+        bank.update_adaptive_plateau_rate(I80F48!(100), I80F48!(100), one_day)
+            .unwrap();
+        assert_eq!(I80F48::from(bank.adaptive_plateau_rate), I80F48::ZERO);
+    }
+
+    #[test]
+    fn configure_clamps_adaptive_rate() {
+        let mut bank = Bank {
+            config: BankConfig {
+                interest_rate_config: adaptive_config(),
+                ..Default::default()
+            },
+            adaptive_plateau_rate: I80F48!(3).into(),
+            ..Default::default()
+        };
+
+        // Max rate lowered from 500% to 200%
+        bank.configure(&BankConfigOpt {
+            interest_rate_config: Some(InterestRateConfigOpt {
+                adaptive_max_rate: Some(u32::MAX / 5),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_adaptive_plateau_rate().unwrap(),
+            I80F48!(2),
+            I80F48!(0.0001)
+        );
+
+        // Min rate raised from 1% to 10%
+        bank.adaptive_plateau_rate = I80F48!(0.05).into();
+        bank.configure(&BankConfigOpt {
+            interest_rate_config: Some(InterestRateConfigOpt {
+                adaptive_min_rate: Some(u32::MAX / 100),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq_with_tolerance!(
+            bank.get_adaptive_plateau_rate().unwrap(),
+            I80F48!(0.1),
+            I80F48!(0.0001)
+        );
+    }
+
+    #[test]
+    fn adaptive_rate_validation() {
+        let mut config = adaptive_config();
+        config.validate().unwrap();
+
+        // Plateau must sit within the bounds
+        config.adaptive_min_rate = u32::MAX / 50; // 20%
+        assert!(config.validate().is_err());
+
+        // The floor can't be zero, or the rate could never recover
+        let mut config = adaptive_config();
+        config.adaptive_min_rate = 0;
+        assert!(config.validate().is_err());
+
+        // Negative speeds are rejected
+        let mut config = adaptive_config();
+        config.adaptive_speed = I80F48!(-1).into();
+        assert!(config.validate().is_err());
+
+        // Multi-point curves have no plateau to adapt
+        let mut config = multi_point_config();
+        config.adaptive_speed = I80F48!(5).into();
+        config.adaptive_min_rate = 1;
+        config.adaptive_max_rate = u32::MAX;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn legacy_curve_still_validates() {
+        let config = InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.8).into(),
+            plateau_interest_rate: I80F48!(0.1).into(),
+            max_interest_rate: I80F48!(1).into(),
+            ..Default::default()
+        };
+        assert_eq!(config.curve_type, INTEREST_CURVE_LEGACY);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn ir_accrual_failing_fuzz_test_example() -> anyhow::Result<()> {
         let ir_config = InterestRateConfig {
             optimal_utilization_rate: I80F48!(0.4).into(),
             plateau_interest_rate: I80F48!(0.4).into(),
@@ -2131,9 +3808,11 @@ mod tests {
             3600,
             total_asset_shares,
             total_liability_shares,
+            I80F48::ZERO,
             &ir_config.create_interest_rate_calculator(&group),
             asset_share_value,
             liab_share_value,
+            false,
         )
         .unwrap();
 
@@ -2171,4 +3850,752 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn liquidation_fees_default_and_override() {
+        let mut config = BankConfig::default();
+        assert_eq!(
+            config.get_liquidation_liquidator_fee(),
+            LIQUIDATION_LIQUIDATOR_FEE
+        );
+        assert_eq!(
+            config.get_liquidation_insurance_fee(),
+            LIQUIDATION_INSURANCE_FEE
+        );
+
+        config.liquidation_liquidator_fee = u32::MAX / 10;
+        config.liquidation_insurance_fee = u32::MAX / 100;
+        assert_eq_with_tolerance!(
+            config.get_liquidation_liquidator_fee(),
+            I80F48!(0.1),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            config.get_liquidation_insurance_fee(),
+            I80F48!(0.01),
+            I80F48!(0.000001)
+        );
+    }
+
+    #[test]
+    fn liquidation_fees_can_be_configured_to_zero() {
+        let mut bank = Bank {
+            config: BankConfig {
+                interest_rate_config: InterestRateConfig {
+                    optimal_utilization_rate: I80F48!(0.8).into(),
+                    plateau_interest_rate: I80F48!(0.1).into(),
+                    max_interest_rate: I80F48!(1).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        bank.configure(&BankConfigOpt {
+            liquidation_liquidator_fee: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(bank.config.get_liquidation_liquidator_fee(), I80F48::ZERO);
+        assert_eq!(
+            bank.config.get_liquidation_insurance_fee(),
+            LIQUIDATION_INSURANCE_FEE
+        );
+
+        bank.configure(&BankConfigOpt {
+            liquidation_insurance_fee: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(bank.config.get_liquidation_insurance_fee(), I80F48::ZERO);
+    }
+
+    #[test]
+    fn liquidation_fees_validation() {
+        let valid_config = BankConfig {
+            interest_rate_config: InterestRateConfig {
+                optimal_utilization_rate: I80F48!(0.8).into(),
+                plateau_interest_rate: I80F48!(0.1).into(),
+                max_interest_rate: I80F48!(1).into(),
+                ..Default::default()
+            },
+            risk_tier: RiskTier::Collateral,
+            ..Default::default()
+        };
+        valid_config.validate().unwrap();
+
+        let mut config = valid_config;
+        config.liquidation_liquidator_fee = u32::MAX / 4;
+        config.liquidation_insurance_fee = u32::MAX / 4;
+        config.validate().unwrap();
+
+        config.liquidation_insurance_fee = u32::MAX / 2;
+        assert!(config.validate().is_err());
+
+        // A 0.96 maintenance weight leaves a 4% margin, less than the default fees
+        let mut config = valid_config;
+        config.asset_weight_maint = I80F48!(0.96).into();
+        assert!(config.validate().is_err());
+        config.liquidation_liquidator_fee = u32::MAX / 50;
+        config.liquidation_insurance_fee = u32::MAX / 100;
+        config.validate().unwrap();
+        // ...including the ramped cap
+        config.liquidation_max_liquidator_fee = u32::MAX / 20;
+        assert!(config.validate().is_err());
+
+        // Collateral at full value leaves no margin to check
+        config.asset_weight_maint = I80F48::ONE.into();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn liquidator_fee_ramps_with_unhealthiness() {
+        let mut config = BankConfig::default();
+        let liabs = I80F48!(1000);
+
+        // Ramp disabled: flat fee no matter how unhealthy
+        assert_eq!(
+            config
+                .get_liquidation_liquidator_fee_for_unhealthiness(
+                    calc_liquidation_unhealthiness(I80F48!(-500), liabs).unwrap()
+                )
+                .unwrap(),
+            LIQUIDATION_LIQUIDATOR_FEE
+        );
+
+        // 2.5% at zero health, up to 10% once 20% underwater
+        config.liquidation_max_liquidator_fee = u32::MAX / 10;
+        config.liquidation_fee_ramp = u32::MAX / 5;
+        let fee_at = |config: &BankConfig, health: I80F48| {
+            config
+                .get_liquidation_liquidator_fee_for_unhealthiness(
+                    calc_liquidation_unhealthiness(health, liabs).unwrap(),
+                )
+                .unwrap()
+        };
+        assert_eq!(fee_at(&config, I80F48::ZERO), LIQUIDATION_LIQUIDATOR_FEE);
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-10)),
+            I80F48!(0.02875),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-100)),
+            I80F48!(0.0625),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-200)),
+            I80F48!(0.1),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-900)),
+            I80F48!(0.1),
+            I80F48!(0.000001)
+        );
+
+        // Default ramp is 10% underwater
+        config.liquidation_fee_ramp = 0;
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-50)),
+            I80F48!(0.0625),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            fee_at(&config, I80F48!(-100)),
+            I80F48!(0.1),
+            I80F48!(0.000001)
+        );
+    }
+
+    #[test]
+    fn liquidation_unhealthiness() {
+        let liabs = I80F48!(1000);
+        assert_eq!(
+            calc_liquidation_unhealthiness(I80F48!(10), liabs).unwrap(),
+            I80F48::ZERO
+        );
+        assert_eq!(
+            calc_liquidation_unhealthiness(I80F48!(-10), I80F48::ZERO).unwrap(),
+            I80F48::ZERO
+        );
+        assert_eq_with_tolerance!(
+            calc_liquidation_unhealthiness(I80F48!(-250), liabs).unwrap(),
+            I80F48!(0.25),
+            I80F48!(0.000001)
+        );
+    }
+
+    #[test]
+    fn liquidation_close_factor() {
+        let mut group = MarginfiGroup::zeroed();
+        let mut bank_config = BankConfig::default();
+
+        // Disabled by default
+        assert_eq!(
+            group.get_liquidation_close_factor(&bank_config, I80F48!(0.01)),
+            None
+        );
+
+        // Group-wide 50%, always applies
+        group.update_liquidation_close_factor(u32::MAX / 2, 0);
+        assert_eq_with_tolerance!(
+            group
+                .get_liquidation_close_factor(&bank_config, I80F48!(0.9))
+                .unwrap(),
+            I80F48!(0.5),
+            I80F48!(0.000001)
+        );
+
+        // Bank override
+        bank_config.liquidation_close_factor = u32::MAX / 4;
+        assert_eq_with_tolerance!(
+            group
+                .get_liquidation_close_factor(&bank_config, I80F48!(0.01))
+                .unwrap(),
+            I80F48!(0.25),
+            I80F48!(0.000001)
+        );
+
+        // Lifted once the account is 20% or more underwater
+        group.update_liquidation_close_factor(u32::MAX / 2, u32::MAX / 5);
+        assert!(group
+            .get_liquidation_close_factor(&bank_config, I80F48!(0.19))
+            .is_some());
+        assert_eq!(
+            group.get_liquidation_close_factor(&bank_config, I80F48!(0.2)),
+            None
+        );
+    }
+
+    #[test]
+    fn deleverage_insurance_fee() {
+        let mut group = MarginfiGroup::zeroed();
+        assert_eq!(group.get_deleverage_insurance_fee(), I80F48::ZERO);
+
+        group
+            .update_deleverage_insurance_fee(u32::MAX / 100)
+            .unwrap();
+        assert_eq_with_tolerance!(
+            group.get_deleverage_insurance_fee(),
+            I80F48!(0.01),
+            I80F48!(0.000001)
+        );
+
+        // Capped at 5%
+        assert!(group
+            .update_deleverage_insurance_fee(u32::MAX / 10)
+            .is_err());
+        assert_eq!(group.deleverage_insurance_fee, u32::MAX / 100);
+    }
+
+    #[test]
+    fn ramped_liquidator_fee_validation() {
+        let valid_config = BankConfig {
+            interest_rate_config: InterestRateConfig {
+                optimal_utilization_rate: I80F48!(0.8).into(),
+                plateau_interest_rate: I80F48!(0.1).into(),
+                max_interest_rate: I80F48!(1).into(),
+                ..Default::default()
+            },
+            risk_tier: RiskTier::Collateral,
+            liquidation_max_liquidator_fee: u32::MAX / 5,
+            ..Default::default()
+        };
+        valid_config.validate().unwrap();
+
+        // Cap below the base fee
+        let mut config = valid_config;
+        config.liquidation_liquidator_fee = u32::MAX / 4;
+        assert!(config.validate().is_err());
+
+        // Cap plus insurance fee above the max
+        let mut config = valid_config;
+        config.liquidation_max_liquidator_fee = u32::MAX / 2;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn exp_fixed_matches_float() {
+        for x in [0.0, 1e-9, 0.0001, 0.12, 0.5, 1.0, 2.5, 10.0] {
+            let expected = I80F48::from_num(f64::exp(x));
+            assert_eq_with_tolerance!(
+                exp_fixed(I80F48::from_num(x)).unwrap(),
+                expected,
+                expected * I80F48!(0.000000001)
+            );
+        }
+        assert!(exp_fixed(I80F48!(-1)).is_none());
+    }
+
+    /// A flat 10% base rate (plus fees), so that borrowing rates don't drift with utilization
+    fn compound_test_calc() -> InterestRateCalc {
+        let ir_config = InterestRateConfig {
+            curve_type: INTEREST_CURVE_MULTI_POINT,
+            zero_util_rate: u32::MAX / 100,
+            hundred_util_rate: u32::MAX / 100,
+            insurance_ir_fee: I80F48!(0.1).into(),
+            protocol_fixed_fee_apr: I80F48!(0.01).into(),
+            ..Default::default()
+        };
+        let group = MarginfiGroup {
+            group_flags: 1,
+            fee_state_cache: FeeStateCache {
+                program_fee_fixed: PROTOCOL_FEE_FIXED_DEFAULT.into(),
+                program_fee_rate: PROTOCOL_FEE_RATE_DEFAULT.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        ir_config.create_interest_rate_calculator(&group)
+    }
+
+    /// Accrues over `total_time` in `steps` equal updates, returning the final share values and
+    /// total fees collected. Balances are updated between steps, as they would be on-chain.
+    fn accrue_in_steps(
+        calc: &InterestRateCalc,
+        total_time: u64,
+        steps: u64,
+        compound: bool,
+    ) -> (I80F48, I80F48, I80F48) {
+        let asset_shares = I80F48!(1_000_000);
+        let liability_shares = I80F48!(500_000);
+        let mut asset_share_value = I80F48::ONE;
+        let mut liability_share_value = I80F48::ONE;
+        let mut fees = I80F48::ZERO;
+
+        for _ in 0..steps {
+            let changes = calc_interest_rate_accrual_state_changes(
+                total_time / steps,
+                asset_shares * asset_share_value,
+                liability_shares * liability_share_value,
+                I80F48::ZERO,
+                calc,
+                asset_share_value,
+                liability_share_value,
+                compound,
+            )
+            .unwrap();
+            asset_share_value = changes.new_asset_share_value;
+            liability_share_value = changes.new_liability_share_value;
+            fees += changes.insurance_fees_collected
+                + changes.group_fees_collected
+                + changes.protocol_fees_collected;
+        }
+
+        (asset_share_value, liability_share_value, fees)
+    }
+
+    #[test]
+    fn compound_interest_is_crank_frequency_independent() {
+        let calc = compound_test_calc();
+        let one_year = 31_536_000;
+
+        let (asset_sv_once, liab_sv_once, fees_once) = accrue_in_steps(&calc, one_year, 1, true);
+        let (asset_sv_daily, liab_sv_daily, fees_daily) =
+            accrue_in_steps(&calc, one_year, 365, true);
+        let (asset_sv_hourly, liab_sv_hourly, fees_hourly) =
+            accrue_in_steps(&calc, one_year, 8760, true);
+
+        for (asset_sv, liab_sv, fees) in [
+            (asset_sv_once, liab_sv_once, fees_once),
+            (asset_sv_daily, liab_sv_daily, fees_daily),
+        ] {
+            assert_eq_with_tolerance!(asset_sv, asset_sv_hourly, I80F48!(0.000000001));
+            assert_eq_with_tolerance!(liab_sv, liab_sv_hourly, I80F48!(0.000000001));
+            assert_eq_with_tolerance!(fees, fees_hourly, I80F48!(0.0001));
+        }
+
+        // Simple interest under-accrues when cranked rarely
+        let (_, simple_liab_sv_once, _) = accrue_in_steps(&calc, one_year, 1, false);
+        let (_, simple_liab_sv_hourly, _) = accrue_in_steps(&calc, one_year, 8760, false);
+        assert!(simple_liab_sv_hourly - simple_liab_sv_once > I80F48!(0.005));
+    }
+
+    #[test]
+    fn compound_interest_at_fixed_rate_is_exact() {
+        let calc = compound_test_calc();
+        let rates = calc.calc_interest_rate(I80F48!(0.7)).unwrap();
+        let one_year = 31_536_000;
+
+        let once = calc_compound_growth_for_period(rates.borrowing_rate_apr, one_year).unwrap();
+        let mut hourly = I80F48::ONE;
+        for _ in 0..8760 {
+            hourly *= calc_compound_growth_for_period(rates.borrowing_rate_apr, 3600).unwrap();
+        }
+
+        assert_eq_with_tolerance!(once, hourly, I80F48!(0.000000001));
+        assert_eq_with_tolerance!(
+            once,
+            I80F48::from_num(f64::exp(rates.borrowing_rate_apr.to_num::<f64>())),
+            I80F48!(0.000000001)
+        );
+    }
+
+    #[test]
+    fn compound_interest_is_capped() {
+        let max_growth = exp_fixed(MAX_COMPOUND_EXPONENT).unwrap();
+        let one_year: u64 = 31_536_000;
+
+        // 1000% APR for a year is right at the cap
+        let at_cap = calc_compound_growth_for_period(I80F48!(10), one_year).unwrap();
+        assert_eq_with_tolerance!(at_cap, max_growth, I80F48!(0.0001));
+
+        // ...and 5.5 years (e^55) would overflow without it
+        assert!(exp_fixed(I80F48!(55)).is_none());
+        let idle = calc_compound_growth_for_period(I80F48!(10), one_year * 11 / 2).unwrap();
+        assert_eq!(idle, max_growth);
+
+        let calc = InterestRateCalc {
+            curve_type: INTEREST_CURVE_MULTI_POINT,
+            zero_util_rate: u32::MAX,
+            hundred_util_rate: u32::MAX,
+            ..compound_test_calc()
+        };
+        let changes = calc_interest_rate_accrual_state_changes(
+            one_year * 11 / 2,
+            I80F48!(1_000_000_000_000),
+            I80F48!(500_000_000_000),
+            I80F48::ZERO,
+            &calc,
+            I80F48::ONE,
+            I80F48::ONE,
+            true,
+        )
+        .unwrap();
+        assert_eq!(changes.new_liability_share_value, max_growth);
+    }
+
+    #[test]
+    fn compound_interest_preserves_net_assets() {
+        let calc = compound_test_calc();
+        let asset_shares = I80F48!(1_000_000);
+        let liability_shares = I80F48!(500_000);
+        let (asset_sv, liab_sv, fees) = accrue_in_steps(&calc, 31_536_000, 52, true);
+
+        assert!(fees > I80F48::ZERO);
+        assert_eq_with_tolerance!(
+            asset_shares * asset_sv + fees - liability_shares * liab_sv,
+            asset_shares - liability_shares,
+            I80F48!(0.001)
+        );
+    }
+
+    #[test]
+    fn bank_compound_interest_flag() -> anyhow::Result<()> {
+        let ir_config = InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.8).into(),
+            plateau_interest_rate: I80F48!(0.1).into(),
+            max_interest_rate: I80F48!(1).into(),
+            ..Default::default()
+        };
+        let make_bank = || Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            total_liability_shares: I80F48!(500_000).into(),
+            config: BankConfig {
+                interest_rate_config: ir_config,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let one_year = 31_536_000;
+
+        let mut simple_bank = make_bank();
+        let mut compound_bank = make_bank();
+        compound_bank.update_flag(true, COMPOUND_INTEREST_FLAG);
+
+        for bank in [&mut simple_bank, &mut compound_bank] {
+            bank.accrue_interest(
+                one_year,
+                &MarginfiGroup::default(),
+                #[cfg(not(feature = "client"))]
+                Pubkey::default(),
+            )?;
+        }
+
+        // 50% utilization on a 0.8/0.1 curve: 6.25% APR
+        assert_eq_with_tolerance!(
+            I80F48::from(simple_bank.liability_share_value),
+            I80F48!(1.0625),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(compound_bank.liability_share_value),
+            I80F48::from_num(f64::exp(0.0625)),
+            I80F48!(0.000001)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bank_fixed_term_interest_accrual() -> anyhow::Result<()> {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            fixed_term_liabilities: I80F48!(100_000).into(),
+            // 100_000 owed at 10% APR
+            fixed_term_interest_per_year: I80F48!(10_000).into(),
+            ..Default::default()
+        };
+
+        bank.accrue_interest(
+            31_536_000,
+            &MarginfiGroup::default(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )?;
+
+        // No fees configured, all fixed-term interest goes to depositors
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.fixed_term_liabilities),
+            I80F48!(110_000),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.asset_share_value),
+            I80F48!(1.01),
+            I80F48!(0.000001)
+        );
+        assert_eq!(bank.get_total_liability_amount()?, I80F48!(110_000));
+
+        // The cache reports the fixed-term interest depositors earned
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.cache.accumulated_since_last_update),
+            I80F48!(10_000),
+            I80F48!(0.001)
+        );
+        assert_eq!(bank.cache.interest_accumulated_for, 31_536_000);
+
+        // Fixed-term debt counts toward utilization
+        bank.total_asset_shares = I80F48!(100_000).into();
+        assert!(bank.check_utilization_ratio().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bank_fixed_term_interest_pays_fees() -> anyhow::Result<()> {
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            fixed_term_liabilities: I80F48!(500_000).into(),
+            // 500_000 owed at 10% APR
+            fixed_term_interest_per_year: I80F48!(50_000).into(),
+            config: BankConfig {
+                interest_rate_config: InterestRateConfig {
+                    optimal_utilization_rate: I80F48!(0.8).into(),
+                    plateau_interest_rate: I80F48!(0.1).into(),
+                    max_interest_rate: I80F48!(1).into(),
+                    insurance_ir_fee: I80F48!(0.1).into(),
+                    protocol_ir_fee: I80F48!(0.1).into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        bank.accrue_interest(
+            31_536_000,
+            &MarginfiGroup::default(),
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )?;
+
+        // 50% utilization: 6.25% base rate, 7.5% borrowing rate of which each fee is 0.625%, so
+        // each fee takes 1/12 of the 50_000 interest
+        assert_eq!(I80F48::from(bank.fixed_term_liabilities), I80F48!(550_000));
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.collected_insurance_fees_outstanding),
+            I80F48!(4166.666666),
+            I80F48!(0.001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.collected_group_fees_outstanding),
+            I80F48!(4166.666666),
+            I80F48!(0.001)
+        );
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.asset_share_value),
+            I80F48!(1.041666666),
+            I80F48!(0.000001)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bank_fixed_term_liabilities_count_toward_rates() -> anyhow::Result<()> {
+        let ir_config = InterestRateConfig {
+            optimal_utilization_rate: I80F48!(0.8).into(),
+            plateau_interest_rate: I80F48!(0.1).into(),
+            max_interest_rate: I80F48!(1).into(),
+            ..Default::default()
+        };
+        let mut bank = Bank {
+            asset_share_value: I80F48::ONE.into(),
+            liability_share_value: I80F48::ONE.into(),
+            total_asset_shares: I80F48!(1_000_000).into(),
+            fixed_term_liabilities: I80F48!(500_000).into(),
+            config: BankConfig {
+                interest_rate_config: ir_config,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let group = MarginfiGroup::default();
+
+        // Only fixed-term debt: the bank is still 50% utilized, 6.25% APR on a 0.8/0.1 curve
+        bank.update_bank_cache(&group)?;
+        assert_eq_with_tolerance!(
+            u32_to_apr(bank.cache.borrowing_rate),
+            I80F48!(0.0625),
+            I80F48!(0.000001)
+        );
+        assert_eq!(bank.cache.lending_rate, 0);
+
+        // Variable borrowers are charged at the combined utilization, depositors only earn their
+        // share here (fixed-term interest is credited separately)
+        bank.total_liability_shares = I80F48!(250_000).into();
+        bank.fixed_term_liabilities = I80F48!(250_000).into();
+        bank.update_bank_cache(&group)?;
+        assert_eq_with_tolerance!(
+            u32_to_apr(bank.cache.borrowing_rate),
+            I80F48!(0.0625),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            u32_to_apr(bank.cache.lending_rate),
+            I80F48!(0.015625),
+            I80F48!(0.000001)
+        );
+
+        bank.accrue_interest(
+            31_536_000,
+            &group,
+            #[cfg(not(feature = "client"))]
+            Pubkey::default(),
+        )?;
+        assert_eq_with_tolerance!(
+            I80F48::from(bank.liability_share_value),
+            I80F48!(1.0625),
+            I80F48!(0.000001)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn oracle_price_bounds() -> anyhow::Result<()> {
+        let mut config = BankConfig::default();
+        assert_eq!(config.get_oracle_price_bounds()?, (None, None));
+
+        // $0.50 - $2
+        config.set_oracle_price_bounds(&OraclePriceBounds {
+            min: 50,
+            max: 200,
+            exponent: -2,
+        });
+        config.validate_oracle_price_bounds()?;
+        assert_eq!(
+            config.get_oracle_price_bounds()?,
+            (Some(I80F48!(0.5)), Some(I80F48!(2)))
+        );
+
+        // Max only, $100k
+        config.set_oracle_price_bounds(&OraclePriceBounds {
+            min: 0,
+            max: 100,
+            exponent: 3,
+        });
+        config.validate_oracle_price_bounds()?;
+        assert_eq!(
+            config.get_oracle_price_bounds()?,
+            (None, Some(I80F48!(100_000)))
+        );
+
+        config.set_oracle_price_bounds(&OraclePriceBounds {
+            min: 200,
+            max: 50,
+            exponent: -2,
+        });
+        assert!(config.validate_oracle_price_bounds().is_err());
+
+        config.set_oracle_price_bounds(&OraclePriceBounds {
+            min: 50,
+            max: 200,
+            exponent: -24,
+        });
+        assert!(config.validate_oracle_price_bounds().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn oracle_ema() -> anyhow::Result<()> {
+        let mut bank = Bank::zeroed();
+
+        // No EMA yet, starts at the first price seen
+        assert_eq!(bank.get_oracle_ema(I80F48!(100), 1_000)?, I80F48!(100));
+        bank.update_oracle_ema(I80F48!(100), 1_000)?;
+        assert_eq!(bank.oracle_ema_last_update, 1_000);
+
+        // Repeated reads at the same time don't move it
+        bank.update_oracle_ema(I80F48!(200), 1_000)?;
+        assert_eq!(I80F48::from(bank.oracle_ema_price), I80F48!(100));
+
+        // A price held for one period moves it halfway
+        let ema = bank.get_oracle_ema(I80F48!(200), 1_000 + ORACLE_EMA_PERIOD)?;
+        assert_eq!(ema, I80F48!(150));
+
+        // The same hour in many small steps moves it about as far
+        for i in 1..=60 {
+            bank.update_oracle_ema(I80F48!(200), 1_000 + i * ORACLE_EMA_PERIOD / 60)?;
+        }
+        let ema: f64 = I80F48::from(bank.oracle_ema_price).to_num();
+        assert!(ema > 150. && ema < 165.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn oracle_max_age_relaxed_and_stale_haircut() -> anyhow::Result<()> {
+        let mut bank = Bank::zeroed();
+        bank.config.oracle_setup = OracleSetup::SwitchboardPull;
+        bank.config.oracle_max_age = 60;
+
+        // Unset or shorter than the strict window falls back to it
+        assert_eq!(bank.config.get_oracle_max_age_relaxed(), 60);
+        bank.config.oracle_max_age_relaxed = 30;
+        assert_eq!(bank.config.get_oracle_max_age_relaxed(), 60);
+        bank.config.oracle_max_age_relaxed = 300;
+        assert_eq!(bank.config.get_oracle_max_age_relaxed(), 300);
+
+        // Defaults to 5%, moving prices against the liquidatee
+        assert_eq_with_tolerance!(
+            bank.apply_oracle_stale_haircut(I80F48!(100), PriceBias::Low)?,
+            I80F48!(95),
+            I80F48!(0.000001)
+        );
+        assert_eq_with_tolerance!(
+            bank.apply_oracle_stale_haircut(I80F48!(100), PriceBias::High)?,
+            I80F48!(105),
+            I80F48!(0.000001)
+        );
+
+        bank.oracle_stale_liquidation_haircut = u32::MAX / 10;
+        assert_eq_with_tolerance!(
+            bank.apply_oracle_stale_haircut(I80F48!(100), PriceBias::Low)?,
+            I80F48!(90),
+            I80F48!(0.000001)
+        );
+
+        Ok(())
+    }
 }
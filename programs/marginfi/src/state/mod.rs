@@ -1,8 +1,12 @@
 pub mod bank_cache;
 pub mod emode;
+pub mod exchange_rate;
 pub mod fee_state;
 pub mod health_cache;
+#[cfg(feature = "client")]
+pub mod health_calculator;
 pub mod marginfi_account;
 pub mod marginfi_group;
+pub mod oracle_confidence;
 pub mod price;
 pub mod staked_settings;
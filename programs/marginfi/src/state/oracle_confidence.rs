@@ -0,0 +1,199 @@
+use crate::constants::{
+    CONF_INTERVAL_MULTIPLE, MAX_CONF_INTERVAL, MAX_CONF_INTERVAL_LIMIT, MAX_CONF_MULTIPLE,
+    MIN_CONF_MULTIPLE, MIN_PYTH_PUSH_PARTIAL_SIGNATURES, MIN_PYTH_PUSH_VERIFICATION_LEVEL,
+    STD_DEV_MULTIPLE, U32_MAX,
+};
+use crate::errors::MarginfiError;
+use crate::prelude::MarginfiResult;
+use crate::state::marginfi_group::WrappedI80F48;
+use crate::{assert_struct_align, assert_struct_size, check};
+use anchor_lang::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use fixed::types::I80F48;
+use pyth_solana_receiver_sdk::price_update::VerificationLevel;
+use type_layout::TypeLayout;
+
+/// The admin-set part of `OracleConfidenceSettings`, see there. Zeroes restore the defaults.
+#[derive(
+    AnchorDeserialize, AnchorSerialize, Default, Clone, Copy, Debug, PartialEq, Eq, TypeLayout,
+)]
+pub struct OracleConfidenceConfig {
+    pub pyth_min_signatures: u8,
+    pub max_conf_interval: u32,
+    pub pyth_conf_multiple: WrappedI80F48,
+    pub swb_std_dev_multiple: WrappedI80F48,
+}
+
+assert_struct_size!(OracleConfidenceSettings, 48);
+assert_struct_align!(OracleConfidenceSettings, 8);
+/// How a bank treats the confidence reported by its oracles. Each field left at zero uses the
+/// program-wide default, so banks that never configure these behave as before.
+#[repr(C)]
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Debug, PartialEq, Eq, Pod, Zeroable, Copy, Clone, TypeLayout,
+)]
+pub struct OracleConfidenceSettings {
+    /// Pyth price updates signed by fewer Wormhole guardians than this are rejected. Lets feeds
+    /// that only reach partial verification be used.
+    /// * 0 falls back to requiring full verification, i.e., `MIN_PYTH_PUSH_VERIFICATION_LEVEL`
+    /// * Otherwise at least `MIN_PYTH_PUSH_PARTIAL_SIGNATURES`
+    pub pyth_min_signatures: u8,
+    pub _pad0: [u8; 3],
+    /// Cap on the confidence interval applied to a biased price, as a fraction of the price. Note
+    /// that prices with a wider interval than `BankConfig::oracle_max_confidence` still fail.
+    /// * 0 falls back to using the default 5% instead, i.e., `MAX_CONF_INTERVAL`
+    /// * At most `MAX_CONF_INTERVAL_LIMIT` (25%)
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub max_conf_interval: u32,
+    pub _pad1: [u8; 8],
+    /// Multiple of a Pyth feed's reported confidence taken as its confidence interval
+    /// * 0 falls back to `CONF_INTERVAL_MULTIPLE` (2.12)
+    pub pyth_conf_multiple: WrappedI80F48,
+    /// Multiple of a Switchboard feed's std deviation taken as its confidence interval
+    /// * 0 falls back to `STD_DEV_MULTIPLE` (1.96)
+    pub swb_std_dev_multiple: WrappedI80F48,
+}
+
+impl Default for OracleConfidenceSettings {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
+
+impl OracleConfidenceSettings {
+    /// Applies `config`. Multiples must be within `MIN_CONF_MULTIPLE..=MAX_CONF_MULTIPLE`, a
+    /// signature requirement at least `MIN_PYTH_PUSH_PARTIAL_SIGNATURES`, and the interval cap at
+    /// most `MAX_CONF_INTERVAL_LIMIT`.
+    pub fn configure(&mut self, config: &OracleConfidenceConfig) -> MarginfiResult {
+        check!(
+            config.pyth_min_signatures == 0
+                || config.pyth_min_signatures >= MIN_PYTH_PUSH_PARTIAL_SIGNATURES,
+            MarginfiError::InvalidConfig
+        );
+        check!(
+            I80F48::from_num(config.max_conf_interval) / U32_MAX <= MAX_CONF_INTERVAL_LIMIT,
+            MarginfiError::InvalidConfig
+        );
+
+        for multiple in [config.pyth_conf_multiple, config.swb_std_dev_multiple] {
+            let multiple: I80F48 = multiple.into();
+            check!(
+                multiple == I80F48::ZERO
+                    || (MIN_CONF_MULTIPLE..=MAX_CONF_MULTIPLE).contains(&multiple),
+                MarginfiError::InvalidConfig
+            );
+        }
+
+        *self = Self {
+            pyth_min_signatures: config.pyth_min_signatures,
+            max_conf_interval: config.max_conf_interval,
+            pyth_conf_multiple: config.pyth_conf_multiple,
+            swb_std_dev_multiple: config.swb_std_dev_multiple,
+            ..Self::zeroed()
+        };
+
+        Ok(())
+    }
+
+    pub fn get_pyth_verification_level(&self) -> VerificationLevel {
+        match self.pyth_min_signatures {
+            0 => MIN_PYTH_PUSH_VERIFICATION_LEVEL,
+            num_signatures => VerificationLevel::Partial { num_signatures },
+        }
+    }
+
+    pub fn get_max_conf_interval(&self) -> I80F48 {
+        if self.max_conf_interval == 0 {
+            MAX_CONF_INTERVAL
+        } else {
+            I80F48::from_num(self.max_conf_interval) / U32_MAX
+        }
+    }
+
+    pub fn get_pyth_conf_multiple(&self) -> I80F48 {
+        Self::multiple_or(self.pyth_conf_multiple, CONF_INTERVAL_MULTIPLE)
+    }
+
+    pub fn get_swb_std_dev_multiple(&self) -> I80F48 {
+        Self::multiple_or(self.swb_std_dev_multiple, STD_DEV_MULTIPLE)
+    }
+
+    fn multiple_or(multiple: WrappedI80F48, default: I80F48) -> I80F48 {
+        let multiple: I80F48 = multiple.into();
+        if multiple == I80F48::ZERO {
+            default
+        } else {
+            multiple
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed_macro::types::I80F48;
+
+    #[test]
+    fn oracle_confidence_settings() {
+        let mut settings = OracleConfidenceSettings::default();
+        assert_eq!(
+            settings.get_pyth_verification_level(),
+            VerificationLevel::Full
+        );
+        assert_eq!(settings.get_max_conf_interval(), MAX_CONF_INTERVAL);
+        assert_eq!(settings.get_pyth_conf_multiple(), CONF_INTERVAL_MULTIPLE);
+        assert_eq!(settings.get_swb_std_dev_multiple(), STD_DEV_MULTIPLE);
+
+        settings
+            .configure(&OracleConfidenceConfig {
+                pyth_min_signatures: 5,
+                max_conf_interval: u32::MAX / 10,
+                pyth_conf_multiple: I80F48!(3).into(),
+                swb_std_dev_multiple: I80F48::ZERO.into(),
+            })
+            .unwrap();
+        assert_eq!(
+            settings.get_pyth_verification_level(),
+            VerificationLevel::Partial { num_signatures: 5 }
+        );
+        assert!(settings.get_max_conf_interval() > I80F48!(0.0999));
+        assert_eq!(settings.get_pyth_conf_multiple(), I80F48!(3));
+        assert_eq!(settings.get_swb_std_dev_multiple(), STD_DEV_MULTIPLE);
+
+        // Multiples below 1 would shrink the reported confidence
+        let res = settings.configure(&OracleConfidenceConfig {
+            swb_std_dev_multiple: I80F48!(0.5).into(),
+            ..Default::default()
+        });
+        assert!(res.is_err());
+        assert_eq!(settings.get_pyth_conf_multiple(), I80F48!(3));
+
+        // A single guardian's signature isn't enough
+        for pyth_min_signatures in [1, MIN_PYTH_PUSH_PARTIAL_SIGNATURES - 1] {
+            let res = settings.configure(&OracleConfidenceConfig {
+                pyth_min_signatures,
+                ..Default::default()
+            });
+            assert_eq!(res.unwrap_err(), MarginfiError::InvalidConfig.into());
+        }
+
+        // Nor is a cap that lets the interval swallow most of the price
+        let res = settings.configure(&OracleConfidenceConfig {
+            max_conf_interval: u32::MAX / 2,
+            ..Default::default()
+        });
+        assert_eq!(res.unwrap_err(), MarginfiError::InvalidConfig.into());
+        assert_eq!(
+            settings.get_pyth_verification_level(),
+            VerificationLevel::Partial { num_signatures: 5 }
+        );
+
+        settings
+            .configure(&OracleConfidenceConfig {
+                pyth_min_signatures: MIN_PYTH_PUSH_PARTIAL_SIGNATURES,
+                max_conf_interval: u32::MAX / 4,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+}
@@ -1,12 +1,12 @@
 use crate::check_eq;
 use crate::constants::{
-    CONF_INTERVAL_MULTIPLE, EXP_10_I80F48, MAX_CONF_INTERVAL, MIN_PYTH_PUSH_VERIFICATION_LEVEL,
-    NATIVE_STAKE_ID, PYTH_ID, SPL_SINGLE_POOL_ID, STD_DEV_MULTIPLE, SWITCHBOARD_PULL_ID, U32_MAX,
-    U32_MAX_DIV_10,
+    ASSET_TAG_STAKED, EXP_10_I80F48, MAX_MEDIAN_ORACLE_SOURCES, MIN_MEDIAN_ORACLE_SOURCES,
+    NATIVE_STAKE_ID, PYTH_ID, SPL_SINGLE_POOL_ID, SWITCHBOARD_PULL_ID, U32_MAX, U32_MAX_DIV_10,
 };
 use crate::errors::MarginfiError;
 use crate::prelude::MarginfiResult;
-use crate::state::marginfi_group::BankConfig;
+use crate::state::marginfi_group::{Bank, BankConfig};
+use crate::state::oracle_confidence::OracleConfidenceSettings;
 use crate::{check, debug, live, math_error, msg, require_keys_eq};
 use anchor_lang::prelude::borsh;
 use anchor_lang::prelude::*;
@@ -31,6 +31,18 @@ pub enum OracleSetup {
     PythPushOracle,
     SwitchboardPull,
     StakedWithPythPush,
+    /// Median of 2-3 Pyth push and/or Switchboard pull feeds in `oracle_keys[0..3]`, see
+    /// `MedianPriceFeed`
+    Median,
+    /// Valued at the admin-set `oracle_fixed_price`, with the Pyth push or Switchboard pull feed
+    /// in `oracle_keys[0]` only guarding against a depeg, see `FixedPriceFeed`
+    Fixed,
+    /// A token/SOL feed in `oracle_keys[0]` times a SOL/USD feed in `oracle_keys[1]`, each a Pyth
+    /// push or Switchboard pull feed, see `CrossRatePriceFeed`
+    CrossRate,
+    /// A Pyth push or Switchboard pull feed for a base asset in `oracle_keys[0]` times an on-chain
+    /// exchange rate read from the account in `oracle_keys[1]`, see `Bank::exchange_rate`
+    ExchangeRate,
 }
 unsafe impl Zeroable for OracleSetup {}
 unsafe impl Pod for OracleSetup {}
@@ -44,11 +56,25 @@ impl OracleSetup {
             3 => Some(Self::PythPushOracle),
             4 => Some(Self::SwitchboardPull),
             5 => Some(Self::StakedWithPythPush),
+            6 => Some(Self::Median),
+            7 => Some(Self::Fixed),
+            8 => Some(Self::CrossRate),
+            9 => Some(Self::ExchangeRate),
             _ => None,
         }
     }
 }
 
+/// Which of a bank's oracle age windows a price must fall within
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OracleAgeWindow {
+    /// `BankConfig::oracle_max_age`, for borrows, withdraws, and anything else that adds risk
+    Strict,
+    /// `BankConfig::oracle_max_age_relaxed`, for liquidations and recording oracle state. Prices
+    /// older than the strict window are flagged as stale, see `try_from_bank_in_window`.
+    Relaxed,
+}
+
 #[enum_dispatch]
 pub trait PriceAdapter {
     fn get_price_of_type(
@@ -64,25 +90,227 @@ pub trait PriceAdapter {
 pub enum OraclePriceFeedAdapter {
     PythPushOracle(PythPushOraclePriceFeed),
     SwitchboardPull(SwitchboardPullPriceFeed),
+    Median(MedianPriceFeed),
+    Fallback(FallbackPriceFeed),
+    Fixed(FixedPriceFeed),
+    CrossRate(CrossRatePriceFeed),
+    ExchangeRate(ExchangeRatePriceFeed),
+    #[cfg(feature = "client")]
+    Snapshot(SnapshotPriceFeed),
 }
 
 impl OraclePriceFeedAdapter {
-    pub fn try_from_bank_config<'info>(
-        bank_config: &BankConfig,
-        ais: &'info [AccountInfo<'info>],
+    /// Like `try_from_bank_config_with_max_age`, but a Switchboard pull feed also gets the bank's
+    /// on-chain EMA as its time-weighted price, and `OracleSetup::ExchangeRate` (which needs
+    /// `Bank::exchange_rate`) is supported.
+    pub fn try_from_bank<'info>(
+        bank: &Bank,
+        ais: &[AccountInfo<'info>],
         clock: &Clock,
     ) -> MarginfiResult<Self> {
-        Self::try_from_bank_config_with_max_age(
-            bank_config,
+        Self::try_from_bank_with_max_age(bank, ais, clock, bank.config.get_oracle_max_age())
+    }
+
+    /// Like `try_from_bank`, in the given age `window`. Also returns whether the price is stale,
+    /// i.e. only fresh enough for the relaxed window, not the strict one.
+    pub fn try_from_bank_in_window<'info>(
+        bank: &Bank,
+        ais: &[AccountInfo<'info>],
+        clock: &Clock,
+        window: OracleAgeWindow,
+    ) -> MarginfiResult<(Self, bool)> {
+        let strict_result = Self::try_from_bank(bank, ais, clock);
+        let max_age_relaxed = bank.config.get_oracle_max_age_relaxed();
+        match (strict_result, window) {
+            (Ok(feed), _) => Ok((feed, false)),
+            // Only the max age differs, so if the relaxed window loads, the price was too old
+            (Err(_), OracleAgeWindow::Relaxed)
+                if max_age_relaxed > bank.config.get_oracle_max_age() =>
+            {
+                let feed = Self::try_from_bank_with_max_age(bank, ais, clock, max_age_relaxed)?;
+                msg!("oracle older than the strict window, using the relaxed one");
+
+                Ok((feed, true))
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    fn try_from_bank_with_max_age<'info>(
+        bank: &Bank,
+        ais: &[AccountInfo<'info>],
+        clock: &Clock,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        if bank.config.oracle_setup == OracleSetup::ExchangeRate {
+            let feed = ExchangeRatePriceFeed::load(bank, ais, clock, max_age)?;
+            feed.check_price_bounds(&bank.config)?;
+
+            return Ok(feed);
+        }
+
+        let mut feed = Self::try_from_bank_config_with_max_age(
+            &bank.config,
+            &bank.oracle_confidence,
             ais,
             clock,
-            bank_config.get_oracle_max_age(),
-        )
+            max_age,
+        )?;
+        if let OraclePriceFeedAdapter::SwitchboardPull(ref mut swb_feed) = feed {
+            let ema_price = bank.get_oracle_ema(swb_feed.get_price()?, clock.unix_timestamp)?;
+            swb_feed.ema_price = Some(ema_price);
+            feed.check_price_bounds(&bank.config)?;
+        }
+
+        Ok(feed)
+    }
+
+    /// A feed that reports `snapshot` the way the bank's own feed would (see `PriceSnapshot`),
+    /// erroring if it is out of the bank's price bounds.
+    #[cfg(feature = "client")]
+    pub fn try_from_snapshot(bank: &Bank, snapshot: PriceSnapshot) -> MarginfiResult<Self> {
+        let feed = OraclePriceFeedAdapter::Snapshot(SnapshotPriceFeed {
+            snapshot,
+            max_conf_interval: bank.oracle_confidence.get_max_conf_interval(),
+        });
+        feed.check_price_bounds(&bank.config)?;
+
+        Ok(feed)
+    }
+
+    /// The spot price the bank's on-chain EMA follows, if the bank maintains one, i.e. if this is
+    /// a Switchboard pull feed (and not a fallback).
+    pub fn get_oracle_ema_source_price(&self) -> MarginfiResult<Option<I80F48>> {
+        match self {
+            OraclePriceFeedAdapter::SwitchboardPull(swb_feed) => Ok(Some(swb_feed.get_price()?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The exchange rate and the epoch it was read in, for `OracleSetup::ExchangeRate` banks
+    pub fn get_exchange_rate(&self) -> Option<(I80F48, u64)> {
+        match self {
+            OraclePriceFeedAdapter::ExchangeRate(feed) => Some((feed.rate, feed.epoch)),
+            _ => None,
+        }
+    }
+
+    pub fn is_fallback(&self) -> bool {
+        matches!(self, OraclePriceFeedAdapter::Fallback(_))
+    }
+
+    /// True for a fixed-price bank whose live feed has moved too far from the fixed price
+    pub fn is_depegged(&self) -> bool {
+        matches!(self, OraclePriceFeedAdapter::Fixed(feed) if feed.depegged)
+    }
+
+    /// True if the spot price diverges from the time-weighted (EMA) price by more than
+    /// `max_deviation` of the time-weighted price. Confidence is not checked here.
+    /// * A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub fn is_ema_deviation_exceeded(&self, max_deviation: u32) -> MarginfiResult<bool> {
+        let spot = self.get_price_of_type(OraclePriceType::RealTime, None, 0)?;
+        let ema = self.get_price_of_type(OraclePriceType::TimeWeighted, None, 0)?;
+
+        let deviation = spot.checked_sub(ema).ok_or_else(math_error!())?.abs();
+        let max_deviation = ema
+            .checked_mul(I80F48::from_num(max_deviation))
+            .ok_or_else(math_error!())?
+            .checked_div(U32_MAX)
+            .ok_or_else(math_error!())?;
+
+        if deviation > max_deviation {
+            let spot: f64 = spot.to_num();
+            let ema: f64 = ema.to_num();
+            msg!("spot price {:?} deviates from ema {:?}", spot, ema);
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Errors if either the spot or time-weighted confidence exceeds `oracle_max_confidence`
+    fn check_confidence(&self, oracle_max_confidence: u32) -> MarginfiResult {
+        for price_type in [OraclePriceType::RealTime, OraclePriceType::TimeWeighted] {
+            self.get_price_of_type(price_type, Some(PriceBias::High), oracle_max_confidence)?;
+        }
+
+        Ok(())
+    }
+
+    /// Errors if either the spot or time-weighted price falls outside the bank's
+    /// `oracle_price_min`/`oracle_price_max`, i.e. the print is obviously broken.
+    fn check_price_bounds(&self, bank_config: &BankConfig) -> MarginfiResult {
+        let (min, max) = bank_config.get_oracle_price_bounds()?;
+        if min.is_none() && max.is_none() {
+            return Ok(());
+        }
+
+        for price_type in [OraclePriceType::RealTime, OraclePriceType::TimeWeighted] {
+            let price = self.get_price_of_type(price_type, None, 0)?;
+            let below_min = min.is_some_and(|min| price < min);
+            let above_max = max.is_some_and(|max| price > max);
+            if below_min || above_max {
+                let price: f64 = price.to_num();
+                msg!("{:?} price {:?} out of bounds", price_type, price);
+                return err!(MarginfiError::OraclePriceOutOfBounds);
+            }
+        }
+
+        Ok(())
     }
 
+    /// If the bank has a fallback feed (see `BankConfig::has_oracle_fallback`), `ais` is the
+    /// primary followed by the fallback, and the fallback is used when the primary is stale, its
+    /// confidence exceeds `oracle_max_confidence`, or its price is out of bounds.
     pub fn try_from_bank_config_with_max_age<'info>(
         bank_config: &BankConfig,
-        ais: &'info [AccountInfo<'info>],
+        confidence: &OracleConfidenceSettings,
+        ais: &[AccountInfo<'info>],
+        clock: &Clock,
+        max_age: u64,
+    ) -> MarginfiResult<Self> {
+        if !bank_config.has_oracle_fallback() {
+            let feed = Self::try_from_primary_oracle(bank_config, confidence, ais, clock, max_age)?;
+            feed.check_price_bounds(bank_config)?;
+
+            return Ok(feed);
+        }
+
+        check!(ais.len() == 2, MarginfiError::WrongNumberOfOracleAccounts);
+        require_keys_eq!(
+            *ais[1].key,
+            bank_config.oracle_keys[1],
+            MarginfiError::WrongOracleAccountKeys
+        );
+
+        let primary =
+            Self::try_from_primary_oracle(bank_config, confidence, &ais[..1], clock, max_age)
+                .and_then(|feed| {
+                    feed.check_confidence(bank_config.oracle_max_confidence)?;
+                    feed.check_price_bounds(bank_config)?;
+                    Ok(feed)
+                });
+        match primary {
+            Ok(feed) => Ok(feed),
+            // Anything else (e.g. the wrong account) must not be a way to pick the other price
+            Err(err) if allows_oracle_failover(&err) => {
+                msg!("Primary oracle unusable, using fallback: {:?}", err);
+                let feed = load_oracle_source(&ais[1], confidence, clock, max_age)?;
+                feed.check_confidence(bank_config.oracle_max_confidence)?;
+                feed.check_price_bounds(bank_config)?;
+
+                Ok(OraclePriceFeedAdapter::Fallback(FallbackPriceFeed {
+                    feed: Box::new(feed),
+                }))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn try_from_primary_oracle<'info>(
+        bank_config: &BankConfig,
+        confidence: &OracleConfidenceSettings,
+        ais: &[AccountInfo<'info>],
         clock: &Clock,
         max_age: u64,
     ) -> MarginfiResult<Self> {
@@ -121,7 +349,13 @@ impl OraclePriceFeedAdapter {
                     );
 
                     Ok(OraclePriceFeedAdapter::PythPushOracle(
-                        PythPushOraclePriceFeed::load_checked(account_info, None, clock, max_age)?,
+                        PythPushOraclePriceFeed::load_checked(
+                            account_info,
+                            None,
+                            confidence,
+                            clock,
+                            max_age,
+                        )?,
                     ))
                 } else {
                     // 0.1.3 or earlier, migrate with `migrate_pyth_push_oracle`
@@ -134,6 +368,7 @@ impl OraclePriceFeedAdapter {
                         PythPushOraclePriceFeed::load_checked(
                             account_info,
                             Some(price_feed_id),
+                            confidence,
                             clock,
                             max_age,
                         )?,
@@ -152,7 +387,12 @@ impl OraclePriceFeedAdapter {
                 }
 
                 Ok(OraclePriceFeedAdapter::SwitchboardPull(
-                    SwitchboardPullPriceFeed::load_checked(&ais[0], clock.unix_timestamp, max_age)?,
+                    SwitchboardPullPriceFeed::load_checked(
+                        &ais[0],
+                        confidence,
+                        clock.unix_timestamp,
+                        max_age,
+                    )?,
                 ))
             }
             OracleSetup::StakedWithPythPush => {
@@ -171,7 +411,7 @@ impl OraclePriceFeedAdapter {
                     return Err(error!(MarginfiError::WrongOracleAccountKeys));
                 }
 
-                let lst_mint = Account::<'info, Mint>::try_from(&ais[1]).unwrap();
+                let lst_mint = Mint::try_deserialize(&mut &ais[1].data.borrow()[..]).unwrap();
                 let lst_supply = lst_mint.supply;
                 let stake_state = try_from_slice_unchecked::<StakeStateV2>(&ais[2].data.borrow())?;
                 let (_, stake) = match stake_state {
@@ -219,8 +459,13 @@ impl OraclePriceFeedAdapter {
                         MarginfiError::WrongOracleAccountKeys
                     );
 
-                    feed =
-                        PythPushOraclePriceFeed::load_checked(account_info, None, clock, max_age)?;
+                    feed = PythPushOraclePriceFeed::load_checked(
+                        account_info,
+                        None,
+                        confidence,
+                        clock,
+                        max_age,
+                    )?;
                 } else {
                     // 0.1.3 or earlier, migrate with `propagate_staked_settings`
                     // TODO remove in 0.1.5
@@ -231,6 +476,7 @@ impl OraclePriceFeedAdapter {
                     feed = PythPushOraclePriceFeed::load_checked(
                         account_info,
                         Some(price_feed_id),
+                        confidence,
                         clock,
                         max_age,
                     )?;
@@ -253,18 +499,123 @@ impl OraclePriceFeedAdapter {
                 let price = OraclePriceFeedAdapter::PythPushOracle(feed);
                 Ok(price)
             }
+            OracleSetup::Median => {
+                let num_sources = bank_config.get_median_oracle_sources_len();
+                check!(
+                    ais.len() == num_sources,
+                    MarginfiError::WrongNumberOfOracleAccounts
+                );
+
+                let mut sources = Vec::with_capacity(num_sources);
+                for (ai, key) in ais.iter().zip(bank_config.oracle_keys.iter()) {
+                    require_keys_eq!(*ai.key, *key, MarginfiError::WrongOracleAccountKeys);
+
+                    // A stale or otherwise unusable source is dropped here rather than failing the
+                    // whole price, `MedianPriceFeed::new` decides if enough are left.
+                    match load_oracle_source(ai, confidence, clock, max_age) {
+                        Ok(source) => sources.push(source),
+                        Err(_err) => {
+                            debug!("Median oracle source {:?} skipped: {:?}", ai.key, _err);
+                        }
+                    }
+                }
+
+                Ok(OraclePriceFeedAdapter::Median(MedianPriceFeed::new(
+                    sources,
+                    num_sources,
+                    confidence.get_max_conf_interval(),
+                )?))
+            }
+            OracleSetup::Fixed => {
+                check!(ais.len() == 1, MarginfiError::WrongNumberOfOracleAccounts);
+                require_keys_eq!(
+                    *ais[0].key,
+                    bank_config.oracle_keys[0],
+                    MarginfiError::WrongOracleAccountKeys
+                );
+
+                let live_feed = load_oracle_source(&ais[0], confidence, clock, max_age)?;
+
+                Ok(OraclePriceFeedAdapter::Fixed(FixedPriceFeed::new(
+                    bank_config.get_oracle_fixed_price()?,
+                    bank_config.oracle_fixed_price_max_deviation,
+                    live_feed,
+                )?))
+            }
+            OracleSetup::ExchangeRate => {
+                msg!("Exchange rate banks can only be priced with the whole bank");
+                err!(MarginfiError::InvalidOracleSetup)
+            }
+            OracleSetup::CrossRate => {
+                check!(ais.len() == 2, MarginfiError::WrongNumberOfOracleAccounts);
+                for (ai, key) in ais.iter().zip(bank_config.oracle_keys.iter()) {
+                    require_keys_eq!(*ai.key, *key, MarginfiError::WrongOracleAccountKeys);
+                }
+
+                Ok(OraclePriceFeedAdapter::CrossRate(CrossRatePriceFeed {
+                    token_sol_feed: Box::new(load_oracle_source(
+                        &ais[0], confidence, clock, max_age,
+                    )?),
+                    sol_usd_feed: Box::new(load_oracle_source(
+                        &ais[1], confidence, clock, max_age,
+                    )?),
+                }))
+            }
         }
     }
 
     /// * lst_mint, stake_pool, sol_pool - required only if configuring
     ///   `OracleSetup::StakedWithPythPush` initially. (subsequent validations of staked banks can
     ///   omit these)
+    ///
+    /// * oracle_ais - the primary oracle account(s), followed by the fallback if the bank has one
     pub fn validate_bank_config(
         bank_config: &BankConfig,
         oracle_ais: &[AccountInfo],
         lst_mint: Option<Pubkey>,
         stake_pool: Option<Pubkey>,
         sol_pool: Option<Pubkey>,
+    ) -> MarginfiResult {
+        if !bank_config.has_oracle_fallback() {
+            return Self::validate_primary_oracle(
+                bank_config,
+                oracle_ais,
+                lst_mint,
+                stake_pool,
+                sol_pool,
+            );
+        }
+
+        check!(
+            oracle_ais.len() == 2,
+            MarginfiError::WrongNumberOfOracleAccounts
+        );
+        require_keys_eq!(
+            oracle_ais[1].key(),
+            bank_config.oracle_keys[1],
+            MarginfiError::WrongOracleAccountKeys
+        );
+        check!(
+            bank_config.oracle_keys[1] != bank_config.oracle_keys[0],
+            MarginfiError::InvalidOracleSetup
+        );
+        check_oracle_source(&oracle_ais[1])?;
+
+        Self::validate_primary_oracle(
+            bank_config,
+            &oracle_ais[..1],
+            lst_mint,
+            stake_pool,
+            sol_pool,
+        )
+    }
+
+    fn validate_primary_oracle(
+        bank_config: &BankConfig,
+        oracle_ais: &[AccountInfo],
+        lst_mint: Option<Pubkey>,
+        stake_pool: Option<Pubkey>,
+        sol_pool: Option<Pubkey>,
     ) -> MarginfiResult {
         match bank_config.oracle_setup {
             OracleSetup::None => Err(MarginfiError::OracleNotSetup.into()),
@@ -407,6 +758,572 @@ impl OraclePriceFeedAdapter {
                     Ok(())
                 }
             }
+            OracleSetup::Median => {
+                check!(
+                    bank_config.asset_tag != ASSET_TAG_STAKED,
+                    MarginfiError::InvalidOracleSetup
+                );
+
+                let num_sources = bank_config.get_median_oracle_sources_len();
+                check!(
+                    (MIN_MEDIAN_ORACLE_SOURCES..=MAX_MEDIAN_ORACLE_SOURCES).contains(&num_sources),
+                    MarginfiError::InvalidOracleSetup
+                );
+                // Sources must be packed at the front, with every other slot left empty
+                check!(
+                    bank_config.oracle_keys[num_sources..]
+                        .iter()
+                        .all(|key| *key == Pubkey::default()),
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    oracle_ais.len() == num_sources,
+                    MarginfiError::WrongNumberOfOracleAccounts
+                );
+
+                for (i, (ai, key)) in oracle_ais
+                    .iter()
+                    .zip(bank_config.oracle_keys.iter())
+                    .enumerate()
+                {
+                    require_keys_eq!(ai.key(), *key, MarginfiError::WrongOracleAccountKeys);
+                    // The same feed twice would let it outvote the others
+                    check!(
+                        !bank_config.oracle_keys[..i].contains(key),
+                        MarginfiError::InvalidOracleSetup
+                    );
+                    check_oracle_source(ai)?;
+                }
+
+                Ok(())
+            }
+            OracleSetup::Fixed => {
+                check!(
+                    bank_config.asset_tag != ASSET_TAG_STAKED,
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    bank_config.oracle_fixed_price != 0
+                        && bank_config.oracle_fixed_price_max_deviation != 0,
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    bank_config.oracle_keys[1..]
+                        .iter()
+                        .all(|key| *key == Pubkey::default()),
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    oracle_ais.len() == 1,
+                    MarginfiError::WrongNumberOfOracleAccounts
+                );
+                require_keys_eq!(
+                    oracle_ais[0].key(),
+                    bank_config.oracle_keys[0],
+                    MarginfiError::WrongOracleAccountKeys
+                );
+                check_oracle_source(&oracle_ais[0])?;
+
+                Ok(())
+            }
+            OracleSetup::CrossRate | OracleSetup::ExchangeRate => {
+                check!(
+                    bank_config.asset_tag != ASSET_TAG_STAKED,
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    bank_config.oracle_keys[0] != Pubkey::default()
+                        && bank_config.oracle_keys[1] != Pubkey::default()
+                        && bank_config.oracle_keys[0] != bank_config.oracle_keys[1],
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    bank_config.oracle_keys[2..]
+                        .iter()
+                        .all(|key| *key == Pubkey::default()),
+                    MarginfiError::InvalidOracleSetup
+                );
+                check!(
+                    oracle_ais.len() == 2,
+                    MarginfiError::WrongNumberOfOracleAccounts
+                );
+                for (ai, key) in oracle_ais.iter().zip(bank_config.oracle_keys.iter()) {
+                    require_keys_eq!(ai.key(), *key, MarginfiError::WrongOracleAccountKeys);
+                }
+                check_oracle_source(&oracle_ais[0])?;
+                // The exchange rate account is checked against `Bank::exchange_rate` when
+                // configured, see `lending_pool_configure_bank_oracle_exchange_rate`
+                if bank_config.oracle_setup == OracleSetup::CrossRate {
+                    check_oracle_source(&oracle_ais[1])?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Failure modes of a primary feed that switch a bank over to its fallback feed
+fn allows_oracle_failover(err: &Error) -> bool {
+    let error_code = match err {
+        Error::AnchorError(inner) => inner.error_code_number,
+        Error::ProgramError(inner) => match inner.program_error {
+            ProgramError::Custom(error_code) => error_code,
+            _ => return false,
+        },
+    };
+
+    matches!(
+        MarginfiError::from(error_code),
+        MarginfiError::PythPushStalePrice
+            | MarginfiError::SwitchboardStalePrice
+            | MarginfiError::OracleMaxConfidenceExceeded
+            | MarginfiError::OraclePriceOutOfBounds
+    )
+}
+
+/// A bank's fallback feed, in use because its primary feed was stale, too uncertain, or out of
+/// bounds. Prices
+/// exactly like the wrapped feed, the wrapper only records where the price came from.
+#[cfg_attr(feature = "client", derive(Clone))]
+pub struct FallbackPriceFeed {
+    feed: Box<OraclePriceFeedAdapter>,
+}
+
+impl PriceAdapter for FallbackPriceFeed {
+    fn get_price_of_type(
+        &self,
+        oracle_price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        self.feed
+            .get_price_of_type(oracle_price_type, bias, oracle_max_confidence)
+    }
+}
+
+/// An asset valued at a constant price, e.g. $1 for a fully backed stable, so it takes no
+/// confidence haircut. The live feed is only a depeg guard: once its spot price is more than
+/// `max_deviation` away from the fixed price, the asset is priced exactly like the live feed
+/// (confidence and all) instead.
+///
+/// A stale or otherwise unusable live feed fails the price like any other oracle, the peg can't
+/// be trusted without it.
+#[cfg_attr(feature = "client", derive(Clone))]
+pub struct FixedPriceFeed {
+    price: I80F48,
+    live_feed: Box<OraclePriceFeedAdapter>,
+    pub depegged: bool,
+}
+
+impl FixedPriceFeed {
+    /// * `max_deviation` - A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+    pub fn new(
+        price: I80F48,
+        max_deviation: u32,
+        live_feed: OraclePriceFeedAdapter,
+    ) -> MarginfiResult<Self> {
+        let live_price = live_feed.get_price_of_type(OraclePriceType::RealTime, None, 0)?;
+
+        let deviation = live_price
+            .checked_sub(price)
+            .ok_or_else(math_error!())?
+            .abs();
+        let max_deviation = price
+            .checked_mul(I80F48::from_num(max_deviation))
+            .ok_or_else(math_error!())?
+            .checked_div(U32_MAX)
+            .ok_or_else(math_error!())?;
+
+        let depegged = deviation > max_deviation;
+        if depegged {
+            let price: f64 = price.to_num();
+            let live_price: f64 = live_price.to_num();
+            msg!("live price {:?} depegged from {:?}", live_price, price);
+        }
+
+        Ok(Self {
+            price,
+            live_feed: Box::new(live_feed),
+            depegged,
+        })
+    }
+}
+
+impl PriceAdapter for FixedPriceFeed {
+    fn get_price_of_type(
+        &self,
+        oracle_price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        if self.depegged {
+            return self.live_feed.get_price_of_type(
+                oracle_price_type,
+                bias,
+                oracle_max_confidence,
+            );
+        }
+
+        Ok(self.price)
+    }
+}
+
+/// An asset only quoted in SOL, priced in USD through a SOL/USD feed. Prices (and their
+/// confidence) are multiplied per price type and bias: the low price is the low token/SOL price
+/// times the low SOL/USD price, and likewise for the high price. Each feed is held to
+/// `oracle_max_confidence` on its own.
+#[cfg_attr(feature = "client", derive(Clone))]
+pub struct CrossRatePriceFeed {
+    token_sol_feed: Box<OraclePriceFeedAdapter>,
+    sol_usd_feed: Box<OraclePriceFeedAdapter>,
+}
+
+impl PriceAdapter for CrossRatePriceFeed {
+    fn get_price_of_type(
+        &self,
+        oracle_price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        let token_sol_price = self.token_sol_feed.get_price_of_type(
+            oracle_price_type,
+            bias,
+            oracle_max_confidence,
+        )?;
+        let sol_usd_price =
+            self.sol_usd_feed
+                .get_price_of_type(oracle_price_type, bias, oracle_max_confidence)?;
+
+        Ok(token_sol_price
+            .checked_mul(sol_usd_price)
+            .ok_or_else(math_error!())?)
+    }
+}
+
+/// A token priced as a base asset's feed times the on-chain exchange rate between them, e.g. the
+/// SOL/USD price times SOL per jitoSOL from the stake pool. The rate has no confidence interval of
+/// its own, so the base feed's confidence carries over as-is (relative to the price).
+#[cfg_attr(feature = "client", derive(Clone))]
+pub struct ExchangeRatePriceFeed {
+    base_feed: Box<OraclePriceFeedAdapter>,
+    rate: I80F48,
+    /// The epoch `rate` was read in
+    epoch: u64,
+}
+
+impl ExchangeRatePriceFeed {
+    fn load<'info>(
+        bank: &Bank,
+        ais: &[AccountInfo<'info>],
+        clock: &Clock,
+        max_age: u64,
+    ) -> MarginfiResult<OraclePriceFeedAdapter> {
+        let bank_config = &bank.config;
+        check!(ais.len() == 2, MarginfiError::WrongNumberOfOracleAccounts);
+        for (ai, key) in ais.iter().zip(bank_config.oracle_keys.iter()) {
+            require_keys_eq!(*ai.key, *key, MarginfiError::WrongOracleAccountKeys);
+        }
+
+        let base_feed = load_oracle_source(&ais[0], &bank.oracle_confidence, clock, max_age)?;
+        let rate = bank
+            .exchange_rate
+            .load_rate(&ais[1], &bank.mint, clock.epoch)?;
+
+        Ok(OraclePriceFeedAdapter::ExchangeRate(Self {
+            base_feed: Box::new(base_feed),
+            rate,
+            epoch: clock.epoch,
+        }))
+    }
+}
+
+impl PriceAdapter for ExchangeRatePriceFeed {
+    fn get_price_of_type(
+        &self,
+        oracle_price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        let base_price =
+            self.base_feed
+                .get_price_of_type(oracle_price_type, bias, oracle_max_confidence)?;
+
+        Ok(base_price
+            .checked_mul(self.rate)
+            .ok_or_else(math_error!())?)
+    }
+}
+
+/// A bank's price as observed off-chain, e.g. by a bot that already reads the oracles, for use
+/// without any `AccountInfo`s. Prices are final, e.g. the fixed price of an `OracleSetup::Fixed`
+/// bank, or the bank's `oracle_ema_price` as the time-weighted price of a Switchboard pull bank.
+/// Confidence intervals are as the bank's feed would report them, i.e. already scaled by
+/// `OracleConfidenceSettings`, and are checked and capped the same way.
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceSnapshot {
+    pub price: I80F48,
+    pub conf_interval: I80F48,
+    pub ema_price: I80F48,
+    pub ema_conf_interval: I80F48,
+}
+
+#[cfg(feature = "client")]
+#[derive(Clone)]
+pub struct SnapshotPriceFeed {
+    snapshot: PriceSnapshot,
+    /// See `OracleConfidenceSettings::max_conf_interval`
+    max_conf_interval: I80F48,
+}
+
+#[cfg(feature = "client")]
+impl PriceAdapter for SnapshotPriceFeed {
+    fn get_price_of_type(
+        &self,
+        price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        let (price, conf_interval) = match price_type {
+            OraclePriceType::TimeWeighted => {
+                (self.snapshot.ema_price, self.snapshot.ema_conf_interval)
+            }
+            OraclePriceType::RealTime => (self.snapshot.price, self.snapshot.conf_interval),
+        };
+
+        match bias {
+            None => Ok(price),
+            Some(price_bias) => {
+                let conf_interval = limit_confidence_interval(
+                    price,
+                    conf_interval,
+                    oracle_max_confidence,
+                    self.max_conf_interval,
+                )?;
+
+                match price_bias {
+                    PriceBias::Low => {
+                        Ok(price.checked_sub(conf_interval).ok_or_else(math_error!())?)
+                    }
+                    PriceBias::High => {
+                        Ok(price.checked_add(conf_interval).ok_or_else(math_error!())?)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Median of several independent feeds for the same asset, e.g. a Pyth push feed and a
+/// Switchboard pull feed. The kind of each source is inferred from its account owner.
+///
+/// One source may be stale or otherwise unusable (when three are configured, the median is then
+/// taken over the remaining two), any more than that and the price is rejected.
+#[cfg_attr(feature = "client", derive(Clone))]
+pub struct MedianPriceFeed {
+    /// Sources that loaded successfully
+    sources: Vec<OraclePriceFeedAdapter>,
+    /// Sources configured on the bank, including any that failed to load
+    num_sources: usize,
+    /// See `OracleConfidenceSettings::max_conf_interval`
+    max_conf_interval: I80F48,
+}
+
+impl MedianPriceFeed {
+    pub fn new(
+        sources: Vec<OraclePriceFeedAdapter>,
+        num_sources: usize,
+        max_conf_interval: I80F48,
+    ) -> MarginfiResult<Self> {
+        check!(
+            sources.len() >= Self::min_valid_sources(num_sources),
+            MarginfiError::OracleMedianInsufficientSources
+        );
+
+        Ok(Self {
+            sources,
+            num_sources,
+            max_conf_interval,
+        })
+    }
+
+    fn min_valid_sources(num_sources: usize) -> usize {
+        num_sources.saturating_sub(1).max(1)
+    }
+}
+
+fn is_pyth_push_owner(ai: &AccountInfo) -> bool {
+    // On localnet, allow the mock program ID -OR- the real one
+    ai.owner.eq(&pyth_solana_receiver_sdk::id()) || (!live!() && ai.owner.eq(&PYTH_ID))
+}
+
+/// Loads a migrated Pyth push or a Switchboard pull feed, whichever `ai` is. Used for the extra
+/// feeds (median sources, fallbacks) where the feed kind isn't part of the bank's `OracleSetup`.
+fn load_oracle_source(
+    ai: &AccountInfo,
+    confidence: &OracleConfidenceSettings,
+    clock: &Clock,
+    max_age: u64,
+) -> MarginfiResult<OraclePriceFeedAdapter> {
+    if ai.owner.eq(&SWITCHBOARD_PULL_ID) {
+        Ok(OraclePriceFeedAdapter::SwitchboardPull(
+            SwitchboardPullPriceFeed::load_checked(ai, confidence, clock.unix_timestamp, max_age)?,
+        ))
+    } else if is_pyth_push_owner(ai) {
+        Ok(OraclePriceFeedAdapter::PythPushOracle(
+            PythPushOraclePriceFeed::load_checked(ai, None, confidence, clock, max_age)?,
+        ))
+    } else {
+        err!(MarginfiError::InvalidOracleSetup)
+    }
+}
+
+fn check_oracle_source(ai: &AccountInfo) -> MarginfiResult {
+    if ai.owner.eq(&SWITCHBOARD_PULL_ID) {
+        SwitchboardPullPriceFeed::check_ais(ai)
+    } else if is_pyth_push_owner(ai) {
+        load_price_update_v2_checked(ai)?;
+        Ok(())
+    } else {
+        msg!(
+            "Oracle {:?} is not a Pyth push or Switchboard pull feed",
+            ai.key
+        );
+        err!(MarginfiError::InvalidOracleSetup)
+    }
+}
+
+/// Errors if `conf_interval` is wider than `oracle_max_confidence` (10% if 0) of `price`,
+/// otherwise returns it capped to `max_conf_interval` of `price`.
+/// * `oracle_max_confidence` - A %, as u32, e.g. 100% = u32::MAX, 50% = u32::MAX/2, etc.
+fn limit_confidence_interval(
+    price: I80F48,
+    conf_interval: I80F48,
+    oracle_max_confidence: u32,
+    max_conf_interval: I80F48,
+) -> MarginfiResult<I80F48> {
+    let oracle_max_confidence = if oracle_max_confidence > 0 {
+        I80F48::from_num(oracle_max_confidence)
+    } else {
+        // The default max confidence is 10%
+        U32_MAX_DIV_10
+    };
+    let max_conf = price
+        .checked_mul(oracle_max_confidence)
+        .ok_or_else(math_error!())?
+        .checked_div(U32_MAX)
+        .ok_or_else(math_error!())?;
+    if conf_interval > max_conf {
+        let conf_interval = conf_interval.to_num::<f64>();
+        let max_conf = max_conf.to_num::<f64>();
+        msg!("conf was {:?}, but max is {:?}", conf_interval, max_conf);
+        return err!(MarginfiError::OracleMaxConfidenceExceeded);
+    }
+
+    // Cap confidence interval to 5% (by default) of price regardless
+    let capped_conf_interval = price
+        .checked_mul(max_conf_interval)
+        .ok_or_else(math_error!())?;
+
+    assert!(
+        capped_conf_interval >= I80F48::ZERO,
+        "Negative max confidence interval"
+    );
+
+    assert!(
+        conf_interval >= I80F48::ZERO,
+        "Negative confidence interval"
+    );
+
+    Ok(min(conf_interval, capped_conf_interval))
+}
+
+/// Median of `values` and half the gap between the two middle values (zero for an odd count).
+/// Sorts `values` in place.
+fn median_and_spread(values: &mut [I80F48]) -> MarginfiResult<(I80F48, I80F48)> {
+    check!(
+        !values.is_empty(),
+        MarginfiError::OracleMedianInsufficientSources
+    );
+    values.sort_unstable();
+
+    let lo = values[(values.len() - 1) / 2];
+    let hi = values[values.len() / 2];
+    let half_gap = hi
+        .checked_sub(lo)
+        .ok_or_else(math_error!())?
+        .checked_div(I80F48::from_num(2))
+        .ok_or_else(math_error!())?;
+    let median = lo.checked_add(half_gap).ok_or_else(math_error!())?;
+
+    Ok((median, half_gap))
+}
+
+impl PriceAdapter for MedianPriceFeed {
+    fn get_price_of_type(
+        &self,
+        price_type: OraclePriceType,
+        bias: Option<PriceBias>,
+        oracle_max_confidence: u32,
+    ) -> MarginfiResult<I80F48> {
+        let mut prices = Vec::with_capacity(self.sources.len());
+        let mut confidence_intervals = Vec::with_capacity(self.sources.len());
+        for source in self.sources.iter() {
+            // A source whose confidence is too wide counts against the tolerance like a stale one,
+            // regardless of whether a biased price was asked for.
+            let quote = source
+                .get_price_of_type(price_type, None, oracle_max_confidence)
+                .and_then(|price| {
+                    let high = source.get_price_of_type(
+                        price_type,
+                        Some(PriceBias::High),
+                        oracle_max_confidence,
+                    )?;
+                    Ok((price, high.checked_sub(price).ok_or_else(math_error!())?))
+                });
+
+            match quote {
+                Ok((price, confidence_interval)) => {
+                    prices.push(price);
+                    confidence_intervals.push(confidence_interval);
+                }
+                Err(_err) => {
+                    debug!("Median oracle source skipped: {:?}", _err);
+                }
+            }
+        }
+        check!(
+            prices.len() >= Self::min_valid_sources(self.num_sources),
+            MarginfiError::OracleMedianInsufficientSources
+        );
+
+        let (price, price_spread) = median_and_spread(&mut prices)?;
+
+        let price_bias = match bias {
+            None => return Ok(price),
+            Some(price_bias) => price_bias,
+        };
+
+        // The typical source confidence, widened to cover any disagreement between the two
+        // sources the median was taken from.
+        let (median_conf, _) = median_and_spread(&mut confidence_intervals)?;
+        let conf_interval = median_conf.max(price_spread);
+
+        let capped_conf_interval = limit_confidence_interval(
+            price,
+            conf_interval,
+            oracle_max_confidence,
+            self.max_conf_interval,
+        )?;
+
+        match price_bias {
+            PriceBias::Low => Ok(price
+                .checked_sub(capped_conf_interval)
+                .ok_or_else(math_error!())?),
+            PriceBias::High => Ok(price
+                .checked_add(capped_conf_interval)
+                .ok_or_else(math_error!())?),
         }
     }
 }
@@ -415,12 +1332,17 @@ impl OraclePriceFeedAdapter {
 pub struct PythPushOraclePriceFeed {
     ema_price: Box<pyth_solana_receiver_sdk::price_update::Price>,
     price: Box<pyth_solana_receiver_sdk::price_update::Price>,
+    /// Multiple of the reported confidence taken as the confidence interval
+    conf_multiple: I80F48,
+    /// See `OracleConfidenceSettings::max_conf_interval`
+    max_conf_interval: I80F48,
 }
 
 impl PythPushOraclePriceFeed {
     pub fn load_checked(
         ai: &AccountInfo,
         feed_id: Option<&FeedId>, // TODO remove in 0.1.5
+        confidence: &OracleConfidenceSettings,
         clock: &Clock,
         max_age: u64,
     ) -> MarginfiResult<Self> {
@@ -436,7 +1358,7 @@ impl PythPushOraclePriceFeed {
                 clock,
                 max_age,
                 feed_id,
-                MIN_PYTH_PUSH_VERIFICATION_LEVEL,
+                confidence.get_pyth_verification_level(),
             )
             .map_err(|e| {
                 debug!("Pyth push oracle error: {:?}", e);
@@ -464,6 +1386,8 @@ impl PythPushOraclePriceFeed {
         Ok(Self {
             price: Box::new(price),
             ema_price: Box::new(ema_price),
+            conf_multiple: confidence.get_pyth_conf_multiple(),
+            max_conf_interval: confidence.get_max_conf_interval(),
         })
     }
 
@@ -480,46 +1404,17 @@ impl PythPushOraclePriceFeed {
 
         let conf_interval =
             pyth_price_components_to_i80f48(I80F48::from_num(price.conf), price.exponent)?
-                .checked_mul(CONF_INTERVAL_MULTIPLE)
+                .checked_mul(self.conf_multiple)
                 .ok_or_else(math_error!())?;
 
         let price = pyth_price_components_to_i80f48(I80F48::from_num(price.price), price.exponent)?;
 
-        // Fail the price fetch if confidence > price * oracle_max_confidence
-        let oracle_max_confidence = if oracle_max_confidence > 0 {
-            I80F48::from_num(oracle_max_confidence)
-        } else {
-            // The default max confidence is 10%
-            U32_MAX_DIV_10
-        };
-        let max_conf = price
-            .checked_mul(oracle_max_confidence)
-            .ok_or_else(math_error!())?
-            .checked_div(U32_MAX)
-            .ok_or_else(math_error!())?;
-        if conf_interval > max_conf {
-            let conf_interval = conf_interval.to_num::<f64>();
-            let max_conf = max_conf.to_num::<f64>();
-            msg!("conf was {:?}, but max is {:?}", conf_interval, max_conf);
-            return err!(MarginfiError::OracleMaxConfidenceExceeded);
-        }
-
-        // Cap confidence interval to 5% of price regardless
-        let capped_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
-            .ok_or_else(math_error!())?;
-
-        assert!(
-            capped_conf_interval >= I80F48::ZERO,
-            "Negative max confidence interval"
-        );
-
-        assert!(
-            conf_interval >= I80F48::ZERO,
-            "Negative confidence interval"
-        );
-
-        Ok(min(conf_interval, capped_conf_interval))
+        limit_confidence_interval(
+            price,
+            conf_interval,
+            oracle_max_confidence,
+            self.max_conf_interval,
+        )
     }
 
     /// Find PDA address of a pyth push oracle given a shard_id and feed_id
@@ -595,11 +1490,14 @@ impl PriceAdapter for PythPushOraclePriceFeed {
 impl PriceAdapter for SwitchboardPullPriceFeed {
     fn get_price_of_type(
         &self,
-        _price_type: OraclePriceType,
+        price_type: OraclePriceType,
         bias: Option<PriceBias>,
         oracle_max_confidence: u32,
     ) -> MarginfiResult<I80F48> {
-        let price = self.get_price()?;
+        let price = match (price_type, self.ema_price) {
+            (OraclePriceType::TimeWeighted, Some(ema_price)) => ema_price,
+            _ => self.get_price()?,
+        };
 
         match bias {
             Some(price_bias) => {
@@ -622,11 +1520,19 @@ impl PriceAdapter for SwitchboardPullPriceFeed {
 #[cfg_attr(feature = "client", derive(Clone, Debug))]
 pub struct SwitchboardPullPriceFeed {
     pub feed: Box<LitePullFeedAccountData>,
+    /// The bank's on-chain EMA (see `Bank::get_oracle_ema`), returned as the time-weighted price.
+    /// If None, the time-weighted price is the spot price.
+    pub ema_price: Option<I80F48>,
+    /// Multiple of the reported std deviation taken as the confidence interval
+    pub std_dev_multiple: I80F48,
+    /// See `OracleConfidenceSettings::max_conf_interval`
+    pub max_conf_interval: I80F48,
 }
 
 impl SwitchboardPullPriceFeed {
     pub fn load_checked(
         ai: &AccountInfo,
+        confidence: &OracleConfidenceSettings,
         current_timestamp: i64,
         max_age: u64,
     ) -> MarginfiResult<Self> {
@@ -651,6 +1557,9 @@ impl SwitchboardPullPriceFeed {
 
         Ok(Self {
             feed: Box::new(lite_feed),
+            ema_price: None,
+            std_dev_multiple: confidence.get_swb_std_dev_multiple(),
+            max_conf_interval: confidence.get_max_conf_interval(),
         })
     }
 
@@ -681,44 +1590,17 @@ impl SwitchboardPullPriceFeed {
         let conf_interval: I80F48 = I80F48::from_num(self.feed.result.std_dev)
             .checked_div(EXP_10_I80F48[switchboard_on_demand::PRECISION as usize])
             .ok_or_else(math_error!())?
-            .checked_mul(STD_DEV_MULTIPLE)
+            .checked_mul(self.std_dev_multiple)
             .ok_or_else(math_error!())?;
 
         let price = self.get_price()?;
 
-        let oracle_max_confidence = if oracle_max_confidence > 0 {
-            I80F48::from_num(oracle_max_confidence)
-        } else {
-            U32_MAX_DIV_10
-        };
-        let max_conf = price
-            .checked_mul(oracle_max_confidence)
-            .ok_or_else(math_error!())?
-            .checked_div(U32_MAX)
-            .ok_or_else(math_error!())?;
-
-        if conf_interval > max_conf {
-            let conf_interval = conf_interval.to_num::<f64>();
-            let max_conf = max_conf.to_num::<f64>();
-            msg!("conf was {:?}, but max is {:?}", conf_interval, max_conf);
-            return err!(MarginfiError::OracleMaxConfidenceExceeded);
-        }
-
-        let max_conf_interval = price
-            .checked_mul(MAX_CONF_INTERVAL)
-            .ok_or_else(math_error!())?;
-
-        assert!(
-            max_conf_interval >= I80F48::ZERO,
-            "Negative max confidence interval"
-        );
-
-        assert!(
-            conf_interval >= I80F48::ZERO,
-            "Negative confidence interval"
-        );
-
-        Ok(min(conf_interval, max_conf_interval))
+        limit_confidence_interval(
+            price,
+            conf_interval,
+            oracle_max_confidence,
+            self.max_conf_interval,
+        )
     }
 }
 
@@ -746,7 +1628,7 @@ impl From<&PullFeedAccountData> for LitePullFeedAccountData {
 #[derive(Copy, Clone, Debug)]
 pub enum OraclePriceType {
     /// Time weighted price
-    /// EMA for PythEma
+    /// EMA for PythEma, the bank's on-chain EMA for Switchboard pull (see `Bank::oracle_ema_price`)
     TimeWeighted,
     /// Real time price
     RealTime,
@@ -849,6 +1731,7 @@ pub enum PriceBias {
 mod tests {
     use pretty_assertions::assert_eq;
 
+    use crate::constants::{CONF_INTERVAL_MULTIPLE, MAX_CONF_INTERVAL};
     use crate::utils::hex_to_bytes;
 
     use super::*;
@@ -883,8 +1766,13 @@ mod tests {
 
         let current_timestamp = 42;
         let max_age = 100;
-        let feed: SwitchboardPullPriceFeed =
-            SwitchboardPullPriceFeed::load_checked(&ai, current_timestamp, max_age).unwrap();
+        let feed: SwitchboardPullPriceFeed = SwitchboardPullPriceFeed::load_checked(
+            &ai,
+            &OracleConfidenceSettings::default(),
+            current_timestamp,
+            max_age,
+        )
+        .unwrap();
         let price: I80F48 = feed.get_price().unwrap();
         let conf: I80F48 = feed.get_confidence_interval(0).unwrap();
 
@@ -950,8 +1838,13 @@ mod tests {
 
         let current_timestamp = 42;
         let max_age = 100;
-        let feed: SwitchboardPullPriceFeed =
-            SwitchboardPullPriceFeed::load_checked(&ai, current_timestamp, max_age).unwrap();
+        let feed: SwitchboardPullPriceFeed = SwitchboardPullPriceFeed::load_checked(
+            &ai,
+            &OracleConfidenceSettings::default(),
+            current_timestamp,
+            max_age,
+        )
+        .unwrap();
         let price: I80F48 = feed.get_price().unwrap();
         let conf: I80F48 = feed.get_confidence_interval(0).unwrap();
 
@@ -990,4 +1883,238 @@ mod tests {
         let max_price: I80F48 = target_price_high.checked_add(price_tolerance).unwrap();
         assert!(price_bias_high >= min_price && price_bias_high <= max_price);
     }
+
+    fn mock_pyth_source(price: i64, conf: u64) -> OraclePriceFeedAdapter {
+        let price = pyth_solana_receiver_sdk::price_update::Price {
+            price,
+            conf,
+            exponent: -2,
+            publish_time: 0,
+        };
+        OraclePriceFeedAdapter::PythPushOracle(PythPushOraclePriceFeed {
+            ema_price: Box::new(price),
+            price: Box::new(price),
+            conf_multiple: CONF_INTERVAL_MULTIPLE,
+            max_conf_interval: MAX_CONF_INTERVAL,
+        })
+    }
+
+    #[test]
+    fn pyth_push_custom_confidence() {
+        // $100 +/- $0.50
+        let price = pyth_solana_receiver_sdk::price_update::Price {
+            price: 10_000,
+            conf: 50,
+            exponent: -2,
+            publish_time: 0,
+        };
+        let mut feed = PythPushOraclePriceFeed {
+            ema_price: Box::new(price),
+            price: Box::new(price),
+            conf_multiple: I80F48::from_num(3),
+            max_conf_interval: MAX_CONF_INTERVAL,
+        };
+        let price_low = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        assert!((price_low - I80F48::from_num(98.5)).abs() < I80F48::from_num(0.0001));
+
+        // Capped to 1% of the price
+        feed.max_conf_interval = I80F48::from_num(0.01);
+        let price_high = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::High), 0)
+            .unwrap();
+        assert!((price_high - I80F48::from_num(101)).abs() < I80F48::from_num(0.0001));
+    }
+
+    #[test]
+    fn median_price_feed() {
+        // $100, $101 and an outlier at $150, each +/- $0.50 (x2.12 for the pyth conf multiple)
+        let feed = MedianPriceFeed::new(
+            vec![
+                mock_pyth_source(15_000, 50),
+                mock_pyth_source(10_000, 50),
+                mock_pyth_source(10_100, 50),
+            ],
+            3,
+            MAX_CONF_INTERVAL,
+        )
+        .unwrap();
+        let price = feed
+            .get_price_of_type(OraclePriceType::RealTime, None, 0)
+            .unwrap();
+        assert_eq!(price, I80F48::from_num(101));
+        let price_low = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        assert!((price_low - I80F48::from_num(99.94)).abs() < I80F48::from_num(0.0001));
+
+        // One source dropped: the median is the midpoint, and the confidence widens to cover both
+        let feed = MedianPriceFeed::new(
+            vec![mock_pyth_source(10_000, 50), mock_pyth_source(10_600, 50)],
+            3,
+            MAX_CONF_INTERVAL,
+        )
+        .unwrap();
+        let price = feed
+            .get_price_of_type(OraclePriceType::TimeWeighted, None, 0)
+            .unwrap();
+        assert_eq!(price, I80F48::from_num(103));
+        let price_high = feed
+            .get_price_of_type(OraclePriceType::TimeWeighted, Some(PriceBias::High), 0)
+            .unwrap();
+        assert_eq!(price_high, I80F48::from_num(106));
+
+        // A source with too wide a confidence counts as dropped: $100 +/- $21.20 is more than 10%
+        let feed = MedianPriceFeed::new(
+            vec![
+                mock_pyth_source(10_000, 50),
+                mock_pyth_source(10_000, 1_000),
+                mock_pyth_source(10_200, 50),
+            ],
+            3,
+            MAX_CONF_INTERVAL,
+        )
+        .unwrap();
+        let price = feed
+            .get_price_of_type(OraclePriceType::RealTime, None, 0)
+            .unwrap();
+        assert_eq!(price, I80F48::from_num(101));
+
+        // Only one of three sources left
+        assert!(
+            MedianPriceFeed::new(vec![mock_pyth_source(10_000, 50)], 3, MAX_CONF_INTERVAL).is_err()
+        );
+        let feed = MedianPriceFeed::new(
+            vec![
+                mock_pyth_source(10_000, 50),
+                mock_pyth_source(10_000, 1_000),
+            ],
+            3,
+            MAX_CONF_INTERVAL,
+        )
+        .unwrap();
+        assert!(feed
+            .get_price_of_type(OraclePriceType::RealTime, None, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn oracle_failover_errors() {
+        assert!(allows_oracle_failover(&error!(
+            MarginfiError::PythPushStalePrice
+        )));
+        assert!(allows_oracle_failover(&error!(
+            MarginfiError::SwitchboardStalePrice
+        )));
+        assert!(allows_oracle_failover(
+            &MarginfiError::OracleMaxConfidenceExceeded.into()
+        ));
+        assert!(allows_oracle_failover(&error!(
+            MarginfiError::OraclePriceOutOfBounds
+        )));
+        assert!(!allows_oracle_failover(&error!(
+            MarginfiError::WrongOracleAccountKeys
+        )));
+        assert!(!allows_oracle_failover(&error!(
+            MarginfiError::PythPushWrongAccountOwner
+        )));
+    }
+
+    #[test]
+    fn ema_deviation() {
+        // Spot $105, EMA $100
+        let feed = OraclePriceFeedAdapter::PythPushOracle(PythPushOraclePriceFeed {
+            ema_price: Box::new(pyth_solana_receiver_sdk::price_update::Price {
+                price: 10_000,
+                conf: 0,
+                exponent: -2,
+                publish_time: 0,
+            }),
+            price: Box::new(pyth_solana_receiver_sdk::price_update::Price {
+                price: 10_500,
+                conf: 0,
+                exponent: -2,
+                publish_time: 0,
+            }),
+            conf_multiple: CONF_INTERVAL_MULTIPLE,
+            max_conf_interval: MAX_CONF_INTERVAL,
+        });
+        assert!(feed.is_ema_deviation_exceeded(u32::MAX / 25).unwrap());
+        assert!(!feed.is_ema_deviation_exceeded(u32::MAX / 10).unwrap());
+
+        // No deviation when spot and EMA agree
+        let feed = mock_pyth_source(10_000, 50);
+        assert!(!feed.is_ema_deviation_exceeded(1).unwrap());
+    }
+
+    #[test]
+    fn cross_rate_price_feed() {
+        // 0.5 SOL +/- 0.01 per token, $10 +/- $0.10 per SOL
+        let feed = CrossRatePriceFeed {
+            token_sol_feed: Box::new(mock_pyth_source(50, 1)),
+            sol_usd_feed: Box::new(mock_pyth_source(1_000, 10)),
+        };
+        let price = feed
+            .get_price_of_type(OraclePriceType::RealTime, None, 0)
+            .unwrap();
+        assert_eq!(price, I80F48::from_num(5));
+
+        // Each side is biased the same way, so both confidence intervals widen the product's
+        let token_sol_low = feed
+            .token_sol_feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        let sol_usd_low = feed
+            .sol_usd_feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        let low = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        assert_eq!(low, token_sol_low * sol_usd_low);
+        assert!(low < token_sol_low * I80F48::from_num(10));
+
+        let high = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::High), 0)
+            .unwrap();
+        assert!(high > price);
+
+        // A token/SOL feed too uncertain on its own fails the whole price
+        let feed = CrossRatePriceFeed {
+            token_sol_feed: Box::new(mock_pyth_source(50, 10)),
+            sol_usd_feed: Box::new(mock_pyth_source(1_000, 10)),
+        };
+        assert!(feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn fixed_price_feed() {
+        // Fixed at $1 with a 2% depeg threshold, live feed at $0.99 +/- $0.01
+        let feed =
+            FixedPriceFeed::new(I80F48::ONE, u32::MAX / 50, mock_pyth_source(99, 1)).unwrap();
+        assert!(!feed.depegged);
+        // No confidence haircut either way
+        for bias in [None, Some(PriceBias::Low), Some(PriceBias::High)] {
+            let price = feed
+                .get_price_of_type(OraclePriceType::RealTime, bias, 0)
+                .unwrap();
+            assert_eq!(price, I80F48::ONE);
+        }
+
+        // Live feed at $0.95: depegged, priced like the live feed
+        let feed =
+            FixedPriceFeed::new(I80F48::ONE, u32::MAX / 50, mock_pyth_source(95, 1)).unwrap();
+        assert!(feed.depegged);
+        let price = feed
+            .get_price_of_type(OraclePriceType::RealTime, None, 0)
+            .unwrap();
+        assert_eq!(price, I80F48::from_num(0.95));
+        let low = feed
+            .get_price_of_type(OraclePriceType::RealTime, Some(PriceBias::Low), 0)
+            .unwrap();
+        assert!(low < price);
+    }
 }
@@ -1,6 +1,6 @@
 use crate::constants::{ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED};
 use crate::errors::MarginfiError;
-use crate::state::marginfi_account::MarginfiAccount;
+use crate::state::marginfi_account::Balance;
 use crate::state::marginfi_group::{Bank, WrappedI80F48};
 use crate::MarginfiResult;
 use crate::{bank_authority_seed, bank_seed};
@@ -153,11 +153,11 @@ pub fn calculate_post_fee_spl_deposit_amount(
 
 /// Validate that after a deposit to Bank, the users's account contains either all Default/SOL
 /// balances, or all Staked/Sol balances. Default and Staked assets cannot mix.
-pub fn validate_asset_tags(bank: &Bank, marginfi_account: &MarginfiAccount) -> MarginfiResult {
+pub fn validate_asset_tags(bank: &Bank, balances: &[Balance]) -> MarginfiResult {
     let mut has_default_asset = false;
     let mut has_staked_asset = false;
 
-    for balance in marginfi_account.lending_account.balances.iter() {
+    for balance in balances.iter() {
         if balance.is_active() {
             match balance.bank_asset_tag {
                 ASSET_TAG_DEFAULT => has_default_asset = true,
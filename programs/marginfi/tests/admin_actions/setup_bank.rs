@@ -4,14 +4,20 @@ use fixed_macro::types::I80F48;
 use fixtures::{assert_anchor_error, assert_custom_error, prelude::*};
 use marginfi::{
     constants::{
-        CLOSE_ENABLED_FLAG, FREEZE_SETTINGS, INIT_BANK_ORIGINATION_FEE_DEFAULT,
+        CLOSE_ENABLED_FLAG, COMPOUND_INTEREST_FLAG, CURVE_POINTS, FREEZE_SETTINGS,
+        INIT_BANK_ORIGINATION_FEE_DEFAULT, INTEREST_CURVE_LEGACY, INTEREST_CURVE_MULTI_POINT,
         PERMISSIONLESS_BAD_DEBT_SETTLEMENT_FLAG,
     },
     prelude::{MarginfiError, MarginfiGroup},
     state::{
         bank_cache::BankCache,
         emode::{EmodeEntry, EMODE_ON},
-        marginfi_group::{Bank, BankConfig, BankConfigOpt, BankVaultType},
+        exchange_rate::ExchangeRateSettings,
+        marginfi_group::{
+            Bank, BankConfig, BankConfigOpt, BankVaultType, OracleFixedPrice, OraclePriceBounds,
+            RatePoint,
+        },
+        oracle_confidence::{OracleConfidenceConfig, OracleConfidenceSettings},
     },
 };
 use pretty_assertions::assert_eq;
@@ -113,7 +119,17 @@ async fn add_bank_success() -> anyhow::Result<()> {
             cache,
             lending_position_count,
             borrowing_position_count,
+            oracle_stale_liquidation_haircut,
             _padding_0,
+            adaptive_plateau_rate,
+            fixed_term_liabilities,
+            fixed_term_interest_per_year,
+            fixed_term_premium,
+            fixed_term_max_duration,
+            oracle_ema_last_update,
+            oracle_ema_price,
+            exchange_rate,
+            oracle_confidence,
             _padding_1,
             .. // ignore internal padding
         } = bank_f.load().await;
@@ -148,8 +164,18 @@ async fn add_bank_success() -> anyhow::Result<()> {
 
             assert_eq!(lending_position_count, 0);
             assert_eq!(borrowing_position_count, 0);
-            assert_eq!(_padding_0, <[u8; 16] as Default>::default());
-            assert_eq!(_padding_1, <[[u64; 2]; 19] as Default>::default());
+            assert_eq!(oracle_stale_liquidation_haircut, 0);
+            assert_eq!(_padding_0, <[u8; 12] as Default>::default());
+            assert_eq!(adaptive_plateau_rate, I80F48!(0.0).into());
+            assert_eq!(fixed_term_liabilities, I80F48!(0.0).into());
+            assert_eq!(fixed_term_interest_per_year, I80F48!(0.0).into());
+            assert_eq!(fixed_term_premium, 0);
+            assert_eq!(fixed_term_max_duration, 0);
+            assert_eq!(oracle_ema_last_update, 0);
+            assert_eq!(oracle_ema_price, I80F48!(0.0).into());
+            assert_eq!(exchange_rate, ExchangeRateSettings::default());
+            assert_eq!(oracle_confidence, OracleConfidenceSettings::default());
+            assert_eq!(_padding_1, <[[u64; 2]; 8] as Default>::default());
 
             // this is the only loosely checked field
             assert!(last_update >= 0 && last_update <= 5);
@@ -250,7 +276,17 @@ async fn add_bank_with_seed_success() -> anyhow::Result<()> {
             cache,
             lending_position_count,
             borrowing_position_count,
+            oracle_stale_liquidation_haircut,
             _padding_0,
+            adaptive_plateau_rate,
+            fixed_term_liabilities,
+            fixed_term_interest_per_year,
+            fixed_term_premium,
+            fixed_term_max_duration,
+            oracle_ema_last_update,
+            oracle_ema_price,
+            exchange_rate,
+            oracle_confidence,
             _padding_1,
             .. // ignore internal padding
         } = bank_f.load().await;
@@ -285,8 +321,18 @@ async fn add_bank_with_seed_success() -> anyhow::Result<()> {
 
             assert_eq!(lending_position_count, 0);
             assert_eq!(borrowing_position_count, 0);
-            assert_eq!(_padding_0, <[u8; 16] as Default>::default());
-            assert_eq!(_padding_1, <[[u64; 2]; 19] as Default>::default());
+            assert_eq!(oracle_stale_liquidation_haircut, 0);
+            assert_eq!(_padding_0, <[u8; 12] as Default>::default());
+            assert_eq!(adaptive_plateau_rate, I80F48!(0.0).into());
+            assert_eq!(fixed_term_liabilities, I80F48!(0.0).into());
+            assert_eq!(fixed_term_interest_per_year, I80F48!(0.0).into());
+            assert_eq!(fixed_term_premium, 0);
+            assert_eq!(fixed_term_max_duration, 0);
+            assert_eq!(oracle_ema_last_update, 0);
+            assert_eq!(oracle_ema_price, I80F48!(0.0).into());
+            assert_eq!(exchange_rate, ExchangeRateSettings::default());
+            assert_eq!(oracle_confidence, OracleConfidenceSettings::default());
+            assert_eq!(_padding_1, <[[u64; 2]; 8] as Default>::default());
 
             // this is the only loosely checked field
             assert!(last_update >= 0 && last_update <= 5);
@@ -357,6 +403,30 @@ async fn configure_bank_success(bank_mint: BankMint) -> anyhow::Result<()> {
             protocol_fixed_fee_apr: Some(I80F48::from_num(0.51).into()),
             protocol_ir_fee: Some(I80F48::from_num(0.011).into()),
             protocol_origination_fee: Some(I80F48::ZERO.into()),
+            ..Default::default()
+        }),
+        compound_interest: Some(true),
+        oracle_max_age_relaxed: Some(300),
+        oracle_stale_liquidation_haircut: Some(u32::MAX / 10),
+        liquidation_liquidator_fee: Some(u32::MAX / 20),
+        liquidation_insurance_fee: Some(u32::MAX / 100),
+        liquidation_max_liquidator_fee: Some(u32::MAX / 10),
+        liquidation_fee_ramp: Some(u32::MAX / 5),
+        liquidation_close_factor: Some(u32::MAX / 2),
+        oracle_max_deviation: Some(u32::MAX / 20),
+        oracle_deviation_use_worse_price: Some(true),
+        oracle_fixed_price: Some(OracleFixedPrice {
+            price: 100,
+            exponent: -2,
+            max_deviation: u32::MAX / 50,
+        }),
+        fixed_term_premium: Some(u32::MAX / 500),
+        fixed_term_max_duration: Some(90 * 24 * 60 * 60),
+        oracle_confidence: Some(OracleConfidenceConfig {
+            pyth_min_signatures: 5,
+            max_conf_interval: u32::MAX / 50,
+            pyth_conf_multiple: I80F48!(3).into(),
+            swb_std_dev_multiple: I80F48!(2.5).into(),
         }),
         ..BankConfigOpt::default()
     };
@@ -380,9 +450,23 @@ async fn configure_bank_success(bank_mint: BankMint) -> anyhow::Result<()> {
         asset_tag,
         total_asset_value_init_limit,
         oracle_max_age,
+        oracle_max_age_relaxed,
+        oracle_stale_liquidation_haircut,
         oracle_max_confidence,
         permissionless_bad_debt_settlement,
         freeze_settings,
+        compound_interest,
+        liquidation_liquidator_fee,
+        liquidation_insurance_fee,
+        liquidation_max_liquidator_fee,
+        liquidation_fee_ramp,
+        liquidation_close_factor,
+        oracle_max_deviation,
+        oracle_deviation_use_worse_price,
+        oracle_fixed_price,
+        fixed_term_premium,
+        fixed_term_max_duration,
+        oracle_confidence,
     } = &config_bank_opt;
     // Compare bank field to opt field if Some, otherwise compare to old bank field
     macro_rules! check_bank_field {
@@ -425,7 +509,62 @@ async fn configure_bank_success(bank_mint: BankMint) -> anyhow::Result<()> {
         check_bank_field!(asset_tag);
         check_bank_field!(total_asset_value_init_limit);
         check_bank_field!(oracle_max_age);
+        check_bank_field!(oracle_max_age_relaxed);
         check_bank_field!(oracle_max_confidence);
+        check_bank_field!(liquidation_liquidator_fee);
+        check_bank_field!(liquidation_insurance_fee);
+        check_bank_field!(liquidation_max_liquidator_fee);
+        check_bank_field!(liquidation_fee_ramp);
+        check_bank_field!(liquidation_close_factor);
+        check_bank_field!(oracle_max_deviation);
+
+        assert_eq!(
+            bank.config.uses_worse_price_on_oracle_deviation(),
+            oracle_deviation_use_worse_price
+                .unwrap_or(old_bank.config.uses_worse_price_on_oracle_deviation())
+        );
+
+        let fixed_price = oracle_fixed_price.unwrap();
+        assert_eq!(bank.config.oracle_fixed_price, fixed_price.price);
+        assert_eq!(
+            bank.config.oracle_fixed_price_exponent,
+            fixed_price.exponent
+        );
+        assert_eq!(
+            bank.config.oracle_fixed_price_max_deviation,
+            fixed_price.max_deviation
+        );
+
+        assert_eq!(
+            bank.oracle_stale_liquidation_haircut,
+            oracle_stale_liquidation_haircut.unwrap_or(old_bank.oracle_stale_liquidation_haircut)
+        );
+        assert_eq!(
+            bank.fixed_term_premium,
+            fixed_term_premium.unwrap_or(old_bank.fixed_term_premium)
+        );
+        assert_eq!(
+            bank.fixed_term_max_duration,
+            fixed_term_max_duration.unwrap_or(old_bank.fixed_term_max_duration)
+        );
+
+        let oracle_confidence = oracle_confidence.unwrap();
+        assert_eq!(
+            bank.oracle_confidence.pyth_min_signatures,
+            oracle_confidence.pyth_min_signatures
+        );
+        assert_eq!(
+            bank.oracle_confidence.max_conf_interval,
+            oracle_confidence.max_conf_interval
+        );
+        assert_eq!(
+            bank.oracle_confidence.pyth_conf_multiple,
+            oracle_confidence.pyth_conf_multiple
+        );
+        assert_eq!(
+            bank.oracle_confidence.swb_std_dev_multiple,
+            oracle_confidence.swb_std_dev_multiple
+        );
 
         assert!(permissionless_bad_debt_settlement
             // If Some(...) check flag set properly
@@ -442,6 +581,14 @@ async fn configure_bank_success(bank_mint: BankMint) -> anyhow::Result<()> {
             // If None check flag is unchanged
             .unwrap_or(bank.get_flag(FREEZE_SETTINGS) == old_bank.get_flag(FREEZE_SETTINGS)));
 
+        assert!(compound_interest
+            // If Some(...) check flag set properly
+            .map(|set| set == bank.get_flag(COMPOUND_INTEREST_FLAG))
+            // If None check flag is unchanged
+            .unwrap_or(
+                bank.get_flag(COMPOUND_INTEREST_FLAG) == old_bank.get_flag(COMPOUND_INTEREST_FLAG)
+            ));
+
         // Oracles no longer update in the standard config instruction
         assert_eq!(
             bank.config.oracle_keys, old_bank.config.oracle_keys,
@@ -768,6 +915,7 @@ async fn configure_bank_interest_only_success() -> anyhow::Result<()> {
         protocol_fixed_fee_apr: Some(I80F48!(0.03).into()),
         protocol_ir_fee: Some(I80F48!(0.04).into()),
         protocol_origination_fee: Some(I80F48!(0.05).into()),
+        ..Default::default()
     };
 
     test_f
@@ -835,6 +983,74 @@ async fn configure_bank_interest_only_success() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn configure_bank_interest_only_multi_point_curve() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+    let bank = test_f.get_bank(&BankMint::Usdc);
+
+    // 0% -> 1%, 50% -> 5%, 80% -> 10%, 100% -> 150% (rates: u32::MAX = 1000%)
+    let mut points = [RatePoint::default(); CURVE_POINTS];
+    points[0] = RatePoint::new(u32::MAX / 2, u32::MAX / 200);
+    points[1] = RatePoint::new(u32::MAX / 5 * 4, u32::MAX / 100);
+    let ir_config = marginfi::state::marginfi_group::InterestRateConfigOpt {
+        curve_type: Some(INTEREST_CURVE_MULTI_POINT),
+        zero_util_rate: Some(u32::MAX / 1000),
+        hundred_util_rate: Some(u32::MAX / 20 * 3),
+        points: Some(points),
+        ..Default::default()
+    };
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_interest_only(bank, ir_config.clone())
+        .await?;
+
+    let bank_after: Bank = test_f.load_and_deserialize(&bank.key).await;
+    let ir_after = bank_after.config.interest_rate_config;
+    assert_eq!(ir_after.curve_type, INTEREST_CURVE_MULTI_POINT);
+    assert_eq!(ir_after.zero_util_rate, ir_config.zero_util_rate.unwrap());
+    assert_eq!(
+        ir_after.hundred_util_rate,
+        ir_config.hundred_util_rate.unwrap()
+    );
+    assert_eq!(ir_after.points, points);
+
+    // Kinks out of order are rejected
+    let mut bad_points = points;
+    bad_points.swap(0, 1);
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_interest_only(
+            bank,
+            marginfi::state::marginfi_group::InterestRateConfigOpt {
+                points: Some(bad_points),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidConfig);
+
+    // Switching back to the legacy curve keeps the original three-parameter config working
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_interest_only(
+            bank,
+            marginfi::state::marginfi_group::InterestRateConfigOpt {
+                curve_type: Some(INTEREST_CURVE_LEGACY),
+                ..Default::default()
+            },
+        )
+        .await?;
+    let bank_after: Bank = test_f.load_and_deserialize(&bank.key).await;
+    assert_eq!(
+        bank_after.config.interest_rate_config.curve_type,
+        INTEREST_CURVE_LEGACY
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn configure_bank_interest_only_not_admin() -> anyhow::Result<()> {
     let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
@@ -900,6 +1116,52 @@ async fn configure_bank_limits_only_success() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn configure_bank_oracle_price_bounds() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+    let bank = test_f.get_bank(&BankMint::Usdc);
+    let old_bank = bank.load().await;
+
+    // $0.50 - $2
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_price_bounds(
+            bank,
+            OraclePriceBounds {
+                min: 50,
+                max: 200,
+                exponent: -2,
+            },
+        )
+        .await?;
+
+    let bank_after: Bank = test_f.load_and_deserialize(&bank.key).await;
+    assert_eq!(bank_after.config.oracle_price_min, 50);
+    assert_eq!(bank_after.config.oracle_price_max, 200);
+    assert_eq!(bank_after.config.oracle_price_bounds_exponent, -2);
+    assert_eq!(
+        bank_after.config.deposit_limit,
+        old_bank.config.deposit_limit
+    );
+
+    // Min must be below max
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_price_bounds(
+            bank,
+            OraclePriceBounds {
+                min: 200,
+                max: 50,
+                exponent: -2,
+            },
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidConfig);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn configure_bank_limits_only_not_admin() -> anyhow::Result<()> {
     let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
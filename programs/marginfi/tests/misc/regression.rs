@@ -10,9 +10,11 @@ use marginfi::{
     constants::ASSET_TAG_DEFAULT,
     state::{
         bank_cache::BankCache,
+        exchange_rate::ExchangeRateSettings,
         health_cache::HealthCache,
         marginfi_account::MarginfiAccount,
         marginfi_group::{Bank, BankOperationalState, RiskTier},
+        oracle_confidence::OracleConfidenceSettings,
         price::OracleSetup,
     },
 };
@@ -59,7 +61,8 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         pubkey!("2s37akK2eyBbp8DZgCm7RtsaEz8eJP3Nxd4urLHQv7yB")
     );
     assert_eq!(balance_1.bank_asset_tag, ASSET_TAG_DEFAULT);
-    assert_eq!(balance_1._pad0, [0; 6]);
+    assert_eq!(balance_1.fixed_term_rate_bps, 0);
+    assert_eq!(balance_1.fixed_term_maturity, 0);
     assert_eq!(
         I80F48::from(balance_1.asset_shares),
         I80F48::from_str("1650216221.466876226897366").unwrap()
@@ -76,7 +79,7 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         I80F48::from(balance_1.last_update),
         I80F48::from_str("1711158766").unwrap()
     );
-    assert_eq!(balance_1._padding, [0; 1]);
+    assert_eq!(balance_1.fixed_term_principal, 0);
 
     let balance_2 = account.lending_account.balances[1];
     assert!(balance_2.is_active());
@@ -85,7 +88,8 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         pubkey!("CCKtUs6Cgwo4aaQUmBPmyoApH2gUDErxNZCAntD6LYGh")
     );
     assert_eq!(balance_2.bank_asset_tag, ASSET_TAG_DEFAULT);
-    assert_eq!(balance_2._pad0, [0; 6]);
+    assert_eq!(balance_2.fixed_term_rate_bps, 0);
+    assert_eq!(balance_2.fixed_term_maturity, 0);
     assert_eq!(
         I80F48::from(balance_2.asset_shares),
         I80F48::from_str("0").unwrap()
@@ -102,7 +106,7 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         I80F48::from(balance_2.last_update),
         I80F48::from_str("1711158793").unwrap()
     );
-    assert_eq!(balance_2._padding, [0; 1]);
+    assert_eq!(balance_2.fixed_term_principal, 0);
 
     // Sample 2
 
@@ -136,7 +140,8 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         pubkey!("6hS9i46WyTq1KXcoa2Chas2Txh9TJAVr6n1t3tnrE23K")
     );
     assert_eq!(balance_1.bank_asset_tag, ASSET_TAG_DEFAULT);
-    assert_eq!(balance_1._pad0, [0; 6]);
+    assert_eq!(balance_1.fixed_term_rate_bps, 0);
+    assert_eq!(balance_1.fixed_term_maturity, 0);
     assert_eq!(
         I80F48::from(balance_1.asset_shares),
         I80F48::from_str("470.952530958931234").unwrap()
@@ -153,7 +158,7 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         I80F48::from(balance_1.last_update),
         I80F48::from_str("1705760628").unwrap()
     );
-    assert_eq!(balance_1._padding, [0; 1]);
+    assert_eq!(balance_1.fixed_term_principal, 0);
 
     let balance_2 = account.lending_account.balances[1];
     assert!(!balance_2.is_active());
@@ -162,7 +167,8 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         pubkey!("11111111111111111111111111111111")
     );
     assert_eq!(balance_2.bank_asset_tag, ASSET_TAG_DEFAULT);
-    assert_eq!(balance_2._pad0, [0; 6]);
+    assert_eq!(balance_2.fixed_term_rate_bps, 0);
+    assert_eq!(balance_2.fixed_term_maturity, 0);
     assert_eq!(
         I80F48::from(balance_2.asset_shares),
         I80F48::from_str("0").unwrap()
@@ -179,7 +185,7 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         I80F48::from(balance_2.last_update),
         I80F48::from_str("0").unwrap()
     );
-    assert_eq!(balance_2._padding, [0; 1]);
+    assert_eq!(balance_2.fixed_term_principal, 0);
 
     // Sample 3
 
@@ -214,7 +220,8 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         pubkey!("11111111111111111111111111111111")
     );
     assert_eq!(balance_1.bank_asset_tag, ASSET_TAG_DEFAULT);
-    assert_eq!(balance_1._pad0, [0; 6]);
+    assert_eq!(balance_1.fixed_term_rate_bps, 0);
+    assert_eq!(balance_1.fixed_term_maturity, 0);
     assert_eq!(
         I80F48::from(balance_1.asset_shares),
         I80F48::from_str("0").unwrap()
@@ -231,7 +238,7 @@ async fn account_field_values_reg() -> anyhow::Result<()> {
         I80F48::from(balance_1.last_update),
         I80F48::from_str("0").unwrap()
     );
-    assert_eq!(balance_1._padding, [0; 1]);
+    assert_eq!(balance_1.fixed_term_principal, 0);
 
     Ok(())
 }
@@ -633,19 +640,29 @@ async fn bank_field_values_reg() -> anyhow::Result<()> {
             Pubkey::default(),
         ]
     );
-    assert_eq!(bank.config._pad0, [0; 6]);
+    assert_eq!(bank.config.oracle_fixed_price_exponent, 0);
+    assert_eq!(bank.config._pad0, [0; 1]);
+    assert_eq!(bank.config.oracle_fixed_price, 0);
     assert_eq!(bank.config.borrow_limit, 2000000000000);
     assert_eq!(bank.config.risk_tier, RiskTier::Collateral);
     assert_eq!(bank.config.asset_tag, ASSET_TAG_DEFAULT);
 
     assert_eq!(bank.config.config_flags, 0);
-    assert_eq!(bank.config._pad1, [0; 5]);
+    assert_eq!(bank.config.oracle_price_bounds_exponent, 0);
+    assert_eq!(bank.config.oracle_fixed_price_max_deviation, 0);
     assert_eq!(bank.config.total_asset_value_init_limit, 0);
     assert_eq!(bank.config.oracle_max_age, 300);
-    assert_eq!(bank.config._padding0, [0; 2]);
+    assert_eq!(bank.config.oracle_max_age_relaxed, 0);
 
     assert_eq!(bank.config.oracle_max_confidence, 0);
-    assert_eq!(bank.config._padding1, [0; 32]);
+    assert_eq!(bank.config.liquidation_liquidator_fee, 0);
+    assert_eq!(bank.config.liquidation_insurance_fee, 0);
+    assert_eq!(bank.config.liquidation_max_liquidator_fee, 0);
+    assert_eq!(bank.config.liquidation_fee_ramp, 0);
+    assert_eq!(bank.config.liquidation_close_factor, 0);
+    assert_eq!(bank.config.oracle_max_deviation, 0);
+    assert_eq!(bank.config.oracle_price_min, 0);
+    assert_eq!(bank.config.oracle_price_max, 0);
 
     assert_eq!(
         I80F48::from(bank.emissions_rate),
@@ -669,8 +686,18 @@ async fn bank_field_values_reg() -> anyhow::Result<()> {
 
     assert_eq!(bank.lending_position_count, 0);
     assert_eq!(bank.borrowing_position_count, 0);
-    assert_eq!(bank._padding_0, [0; 16]);
-    assert_eq!(bank._padding_1, [[0, 0]; 19]);
+    assert_eq!(bank.oracle_stale_liquidation_haircut, 0);
+    assert_eq!(bank._padding_0, [0; 12]);
+    assert_eq!(bank.adaptive_plateau_rate, I80F48::ZERO.into());
+    assert_eq!(bank.fixed_term_liabilities, I80F48::ZERO.into());
+    assert_eq!(bank.fixed_term_interest_per_year, I80F48::ZERO.into());
+    assert_eq!(bank.fixed_term_premium, 0);
+    assert_eq!(bank.fixed_term_max_duration, 0);
+    assert_eq!(bank.oracle_ema_last_update, 0);
+    assert_eq!(bank.oracle_ema_price, I80F48::ZERO.into());
+    assert_eq!(bank.exchange_rate, ExchangeRateSettings::default());
+    assert_eq!(bank.oracle_confidence, OracleConfidenceSettings::default());
+    assert_eq!(bank._padding_1, [[0, 0]; 8]);
 
     Ok(())
 }
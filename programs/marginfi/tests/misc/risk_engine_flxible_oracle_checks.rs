@@ -1,16 +1,28 @@
+use fixed::types::I80F48;
 use fixed_macro::types::I80F48;
 use fixtures::{
     assert_custom_error,
+    bank::BankFixture,
     test::{
         BankMint, TestBankSetting, TestFixture, TestSettings, DEFAULT_SOL_TEST_BANK_CONFIG,
-        PYTH_SOL_EQUIVALENT_FEED, PYTH_SOL_FEED, PYTH_USDC_FEED,
+        PYTH_MNDE_FEED, PYTH_SOL_EQUIVALENT_FEED, PYTH_SOL_FEED, PYTH_USDC_FEED,
+        SWITCH_PULL_SOL_REAL_FEED,
     },
 };
 use marginfi::{
     prelude::MarginfiError,
-    state::marginfi_group::{BankConfig, BankConfigOpt, BankVaultType},
+    state::{
+        exchange_rate::{ExchangeRateConfig, EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL},
+        health_cache::{ORACLE_SOURCE_FALLBACK, ORACLE_SOURCE_PRIMARY},
+        marginfi_group::{
+            BankConfig, BankConfigOpt, BankOperationalState, BankVaultType, OracleFixedPrice,
+            OraclePriceBounds,
+        },
+        price::OracleSetup,
+    },
 };
 use solana_program_test::tokio;
+use solana_sdk::pubkey::Pubkey;
 
 #[tokio::test]
 /// Usdc deposits $5000 SOLE and $500 USDC, borrowing $990 SOL should fail due to stale oracle
@@ -412,3 +424,1031 @@ async fn re_bankruptcy_fail() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+/// SOL bank prices off the median of three feeds: borrowing goes through with one of them stale,
+/// but not with two
+async fn re_median_oracle_one_stale_source() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_median(
+            sol_bank,
+            &[PYTH_SOL_FEED, PYTH_SOL_EQUIVALENT_FEED, PYTH_MNDE_FEED],
+        )
+        .await?;
+    let sol_bank_config = sol_bank.load().await.config;
+    assert_eq!(sol_bank_config.oracle_setup, OracleSetup::Median);
+    assert_eq!(sol_bank_config.get_median_oracle_sources_len(), 3);
+
+    test_f.set_time(0);
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Fund SOL borrower
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(500).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+
+    // Make one SOL source stale
+    test_f.set_pyth_oracle_timestamp(PYTH_USDC_FEED, 120).await;
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 0).await;
+    test_f
+        .set_pyth_oracle_timestamp(PYTH_SOL_EQUIVALENT_FEED, 120)
+        .await;
+    test_f.set_pyth_oracle_timestamp(PYTH_MNDE_FEED, 120).await;
+    test_f.advance_time(120).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 20, 1)
+        .await;
+    assert!(res.is_ok());
+
+    // Make a second SOL source stale
+    test_f.set_pyth_oracle_timestamp(PYTH_MNDE_FEED, 0).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 20, 2)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(
+        res.unwrap_err(),
+        MarginfiError::OracleMedianInsufficientSources
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+/// Liquidating a position whose collateral bank uses a median oracle, which takes more oracle
+/// accounts per bank than the usual one
+async fn re_median_oracle_liquidation() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings {
+        banks: vec![
+            TestBankSetting {
+                mint: BankMint::Usdc,
+                ..Default::default()
+            },
+            TestBankSetting {
+                mint: BankMint::Sol,
+                config: Some(BankConfig {
+                    asset_weight_init: I80F48!(1).into(),
+                    asset_weight_maint: I80F48!(1).into(),
+                    ..*DEFAULT_SOL_TEST_BANK_CONFIG
+                }),
+            },
+        ],
+        protocol_fees: false,
+    }))
+    .await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_median(
+            sol_bank_f,
+            &[PYTH_SOL_FEED, PYTH_SOL_EQUIVALENT_FEED],
+        )
+        .await?;
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL worth $1000 and borrows $999
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await?;
+
+    let lender_ma = lender_mfi_account_f.load().await;
+    assert!(lender_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+/// Median oracles need 2-3 distinct sources
+async fn re_median_oracle_invalid_setup() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_median(sol_bank, &[PYTH_SOL_FEED])
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidOracleSetup);
+
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_median(sol_bank, &[PYTH_SOL_FEED, PYTH_SOL_FEED])
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidOracleSetup);
+
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_median(
+            sol_bank,
+            &[
+                PYTH_SOL_FEED,
+                PYTH_SOL_EQUIVALENT_FEED,
+                PYTH_MNDE_FEED,
+                PYTH_USDC_FEED,
+            ],
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::WrongNumberOfOracleAccounts);
+
+    Ok(())
+}
+
+#[tokio::test]
+/// SOLE priced through a token/SOL feed (10 SOL per SOLE) times SOL/USD ($10), i.e. $100 per SOLE
+async fn re_cross_rate_oracle() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_eq_bank = test_f.get_bank(&BankMint::SolEquivalent);
+
+    // Needs two distinct feeds
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_cross_rate(
+            sol_eq_bank,
+            PYTH_SOL_FEED,
+            PYTH_SOL_FEED,
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidOracleSetup);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_cross_rate(
+            sol_eq_bank,
+            PYTH_SOL_EQUIVALENT_FEED,
+            PYTH_SOL_FEED,
+        )
+        .await?;
+    let sol_eq_bank_config = sol_eq_bank.load().await.config;
+    assert_eq!(sol_eq_bank_config.oracle_setup, OracleSetup::CrossRate);
+    assert_eq!(sol_eq_bank_config.oracle_keys[0], PYTH_SOL_EQUIVALENT_FEED);
+    assert_eq!(sol_eq_bank_config.oracle_keys[1], PYTH_SOL_FEED);
+
+    // Fund USDC lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank, 1_000, None)
+        .await?;
+
+    // 1 SOLE backs more than $10 (its SOL/USD price alone) but less than $100
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_sol_eq = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(1)
+        .await;
+    let borrower_token_account_f_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_sol_eq.key, sol_eq_bank, 1, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_usdc.key, usdc_bank, 80)
+        .await?;
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_usdc.key, usdc_bank, 30, 1)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    set_collateral_weight_below_one(sol_eq_bank).await?;
+
+    // Fund liquidator
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(100).await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_f_usdc.key, usdc_bank, 100, None)
+        .await?;
+
+    // SOL/USD halving halves SOLE too, putting the borrower underwater
+    test_f.set_pyth_oracle_spot_price(PYTH_SOL_FEED, 5.0).await;
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_eq_bank, 0.1, usdc_bank)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// SOLE priced as SOL ($10) times a stake pool's 1.1 SOL per SOLE: 10 SOLE back a $105 borrow,
+/// but not once the pool's rate jumps faster than allowed or goes stale
+async fn re_exchange_rate_oracle() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_eq_bank = test_f.get_bank(&BankMint::SolEquivalent);
+
+    let stake_pool = Pubkey::new_unique();
+    let sol_eq_mint = sol_eq_bank.mint.key;
+    test_f.set_spl_stake_pool(stake_pool, sol_eq_mint, 1_100, 1_000, 0);
+    let exchange_rate_config = ExchangeRateConfig {
+        source: EXCHANGE_RATE_SOURCE_SPL_STAKE_POOL,
+        max_age_epochs: 1,
+        max_change_per_epoch: u32::MAX / 100,
+        ..Default::default()
+    };
+
+    // The rate account must be an actual stake pool
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_exchange_rate(
+            sol_eq_bank,
+            PYTH_SOL_FEED,
+            PYTH_USDC_FEED,
+            exchange_rate_config,
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidOracleSetup);
+
+    // And the pool that issues the bank's token
+    let other_stake_pool = Pubkey::new_unique();
+    test_f.set_spl_stake_pool(other_stake_pool, test_f.sol_mint.key, 1_100, 1_000, 0);
+    let res = test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_exchange_rate(
+            sol_eq_bank,
+            PYTH_SOL_FEED,
+            other_stake_pool,
+            exchange_rate_config,
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidOracleSetup);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_exchange_rate(
+            sol_eq_bank,
+            PYTH_SOL_FEED,
+            stake_pool,
+            exchange_rate_config,
+        )
+        .await?;
+    let sol_eq_bank_state = sol_eq_bank.load().await;
+    assert_eq!(
+        sol_eq_bank_state.config.oracle_setup,
+        OracleSetup::ExchangeRate
+    );
+    assert_eq!(sol_eq_bank_state.config.oracle_keys[0], PYTH_SOL_FEED);
+    assert_eq!(sol_eq_bank_state.config.oracle_keys[1], stake_pool);
+    assert_eq!(
+        I80F48::from(sol_eq_bank_state.exchange_rate.last_rate),
+        I80F48::from_num(1_100) / I80F48::from_num(1_000)
+    );
+
+    // Fund USDC lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank, 1_000, None)
+        .await?;
+
+    // 10 SOLE are worth $110, not the $100 their SOL feed alone would say
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_sol_eq = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(10)
+        .await;
+    let borrower_token_account_f_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_sol_eq.key, sol_eq_bank, 10, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_usdc.key, usdc_bank, 105)
+        .await?;
+
+    // 1.1 -> 1.3 is far more than 1% in one epoch: the rate is rejected, not used
+    test_f.set_spl_stake_pool(stake_pool, sol_eq_mint, 1_300, 1_000, 0);
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_usdc.key, usdc_bank, 1, 1)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    // A pool that hasn't been updated for two epochs is stale
+    test_f.set_spl_stake_pool(stake_pool, sol_eq_mint, 1_100, 1_000, 0);
+    test_f.set_epoch(2).await;
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_usdc.key, usdc_bank, 1, 2)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    // Once cranked, the pool's rate is usable again
+    test_f.set_spl_stake_pool(stake_pool, sol_eq_mint, 1_100, 1_000, 2);
+    borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_usdc.key, usdc_bank, 1, 3)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// With oracles older than the strict 100s window but within a relaxed 300s one, borrows fail but
+/// liquidations go through, and only because the stale collateral gets the 5% haircut. The
+/// liquidator's own health is still checked with fresh prices only.
+async fn re_oracle_relaxed_max_age_liquidation() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    for bank in [usdc_bank, sol_bank] {
+        bank.update_config(
+            BankConfigOpt {
+                oracle_max_age_relaxed: Some(300),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+        assert_eq!(bank.load().await.config.get_oracle_max_age_relaxed(), 300);
+    }
+
+    test_f.set_time(0);
+    test_f.set_pyth_oracle_timestamp(PYTH_USDC_FEED, 0).await;
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 0).await;
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 100, None)
+        .await?;
+
+    // Borrow 8 SOL against 100 USDC
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_sol.key, sol_bank, 8)
+        .await?;
+
+    usdc_bank
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.9).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Fund liquidator
+    let sol_eq_bank = test_f.get_bank(&BankMint::SolEquivalent);
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_f_sol_eq = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(20)
+        .await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_f_sol_eq.key, sol_eq_bank, 20, None)
+        .await?;
+
+    // Fresh prices: $90 of weighted collateral against $88 of debt
+    test_f.set_pyth_oracle_spot_price(PYTH_SOL_FEED, 11.).await;
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 10, sol_bank)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::HealthyAccount);
+
+    test_f.advance_time(120).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 1, 1)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::PythPushStalePrice);
+
+    // The liquidator can't take on debt it can only price with a stale oracle
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 8, sol_bank)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::PythPushStalePrice);
+
+    // Stale collateral: $85.50 of weighted collateral against $88 of debt
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 120).await;
+    test_f
+        .set_pyth_oracle_timestamp(PYTH_SOL_EQUIVALENT_FEED, 120)
+        .await;
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 9, sol_bank)
+        .await?;
+
+    // Past the relaxed window, nothing goes
+    test_f.advance_time(300).await;
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 1, sol_bank)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::PythPushStalePrice);
+
+    Ok(())
+}
+
+#[tokio::test]
+/// SOL bank falls back to a Switchboard feed (SOL @ ~$155) while its Pyth feed (SOL @ $10) is
+/// stale, and the health cache records which feed priced the borrow
+async fn re_oracle_fallback() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_fallback(sol_bank, SWITCH_PULL_SOL_REAL_FEED)
+        .await?;
+    assert!(sol_bank.load().await.config.has_oracle_fallback());
+
+    test_f.set_time(0);
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Fund SOL borrower
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(500).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+
+    // Make the primary SOL oracle stale
+    test_f.set_pyth_oracle_timestamp(PYTH_USDC_FEED, 120).await;
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 0).await;
+    test_f.advance_time(120).await;
+
+    // 2 SOL at the fallback price is ~$312
+    borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 2, 1)
+        .await?;
+
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let sol_index = borrower_ma
+        .lending_account
+        .balances
+        .iter()
+        .filter(|balance| balance.is_active())
+        .position(|balance| balance.bank_pk == sol_bank.key)
+        .unwrap();
+    let health_cache = borrower_ma.health_cache;
+    assert_eq!(
+        health_cache.oracle_sources[sol_index],
+        ORACLE_SOURCE_FALLBACK
+    );
+    let sol_price = f64::from_le_bytes(health_cache.prices[sol_index]);
+    assert!(sol_price > 155. && sol_price < 157.);
+
+    // 4 SOL at the fallback price is more than the $500 of collateral
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 2, 2)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    // Back on the primary oracle
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 120).await;
+    borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 2, 3)
+        .await?;
+    let health_cache = borrower_mfi_account_f.load().await.health_cache;
+    assert_eq!(
+        health_cache.oracle_sources[sol_index],
+        ORACLE_SOURCE_PRIMARY
+    );
+
+    // Without the fallback, a stale primary fails as usual
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_fallback(sol_bank, Pubkey::default())
+        .await?;
+    assert!(!sol_bank.load().await.config.has_oracle_fallback());
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, 0).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 2, 4)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::PythPushStalePrice);
+
+    Ok(())
+}
+
+#[tokio::test]
+/// USDC spot drifts 10% from its EMA, past the bank's 5% max deviation: USDC stops counting as
+/// collateral for new borrows, while deposits and repays still go through
+async fn re_oracle_deviation_blocks_borrow() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    usdc_bank
+        .update_config(
+            BankConfigOpt {
+                oracle_max_deviation: Some(u32::MAX / 20),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Fund SOL borrower
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+
+    test_f.set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.9).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 10, 1)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 100, None)
+        .await?;
+
+    // Back within 5% of the EMA
+    test_f
+        .set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.96)
+        .await;
+    borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 10, 2)
+        .await?;
+
+    test_f.set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.9).await;
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 1, 3)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    borrower_mfi_account_f
+        .try_bank_repay(borrower_token_account_f_sol.key, sol_bank, 5, None)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// SOL spot drops to $9 against a $10 EMA. The liquidatee (485 USDC @ $0.97, 45 SOL borrowed) is
+/// only liquidatable once the SOL bank values liabilities at the worse of the two prices
+async fn re_oracle_deviation_worse_price_liquidation() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    sol_bank
+        .update_config(
+            BankConfigOpt {
+                oracle_max_deviation: Some(u32::MAX / 20),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Borrow 45 SOL against 500 USDC
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(500).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_sol.key, sol_bank, 45)
+        .await?;
+
+    set_collateral_weight_below_one(usdc_bank).await?;
+
+    // Fund liquidator
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_f_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_f_usdc.key, usdc_bank, 1_000, None)
+        .await?;
+
+    test_f
+        .set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.97)
+        .await;
+    test_f.set_pyth_oracle_spot_price(PYTH_SOL_FEED, 9.).await;
+
+    // At spot, $436.50 of weighted collateral against $405 of debt
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 10, sol_bank)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::HealthyAccount);
+
+    // At the EMA, $450 of debt
+    sol_bank
+        .update_config(
+            BankConfigOpt {
+                oracle_deviation_use_worse_price: Some(true),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+    assert!(sol_bank
+        .load()
+        .await
+        .config
+        .uses_worse_price_on_oracle_deviation());
+
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 9, sol_bank)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// USDC prints at $0.02, outside the bank's $0.50-$2 bounds: the print is rejected instead of
+/// making the borrower liquidatable
+async fn re_oracle_price_out_of_bounds() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_price_bounds(
+            usdc_bank,
+            OraclePriceBounds {
+                min: 50,
+                max: 200,
+                exponent: -2,
+            },
+        )
+        .await?;
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Borrow 40 SOL against 500 USDC
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(500).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_sol.key, sol_bank, 40)
+        .await?;
+
+    set_collateral_weight_below_one(usdc_bank).await?;
+
+    // Fund liquidator
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_f_sol =
+        test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_f_sol.key, sol_bank, 100, None)
+        .await?;
+
+    test_f
+        .set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.02)
+        .await;
+
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 10, sol_bank)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::OraclePriceOutOfBounds);
+
+    // USDC collateral counts for nothing while its price is rejected
+    let res = borrower_mfi_account_f
+        .try_bank_borrow_with_nonce(borrower_token_account_f_sol.key, sol_bank, 1, 1)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    // Without bounds, the print is taken at face value
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_oracle_price_bounds(
+            usdc_bank,
+            OraclePriceBounds::default(),
+        )
+        .await?;
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 9, sol_bank)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// A fixed-price bank is valued at its fixed price until its live feed depegs, then at the live
+/// price, and is set to reduce only once the depeg is recorded
+async fn re_fixed_price_oracle_depeg() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+
+    // A fixed-price bank needs a price and a depeg threshold
+    let res = usdc_bank
+        .update_config(
+            BankConfigOpt::default(),
+            Some((OracleSetup::Fixed as u8, PYTH_USDC_FEED)),
+        )
+        .await;
+    assert!(res.is_err());
+
+    // $1, depegged beyond 2%
+    usdc_bank
+        .update_config(
+            BankConfigOpt {
+                oracle_fixed_price: Some(OracleFixedPrice {
+                    price: 1,
+                    exponent: 0,
+                    max_deviation: u32::MAX / 50,
+                }),
+                ..BankConfigOpt::default()
+            },
+            Some((OracleSetup::Fixed as u8, PYTH_USDC_FEED)),
+        )
+        .await?;
+
+    // Fund SOL lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f
+        .sol_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 1_000, None)
+        .await?;
+
+    // Borrow 40 SOL against 500 USDC
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(600).await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 500, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_sol.key, sol_bank, 40)
+        .await?;
+
+    set_collateral_weight_below_one(usdc_bank).await?;
+
+    // Fund liquidator
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_f_sol =
+        test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_f_sol.key, sol_bank, 100, None)
+        .await?;
+
+    // Within the threshold the live price is ignored
+    test_f
+        .set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.99)
+        .await;
+    test_f
+        .marginfi_group
+        .try_accrue_interest_with_oracle(usdc_bank)
+        .await?;
+    assert_eq!(
+        usdc_bank.load().await.config.operational_state,
+        BankOperationalState::Operational
+    );
+
+    // Depegged: the bank is valued at the live price, the borrower is now underwater
+    test_f.set_pyth_oracle_spot_price(PYTH_USDC_FEED, 0.5).await;
+    test_f
+        .marginfi_group
+        .try_accrue_interest_with_oracle(usdc_bank)
+        .await?;
+    assert_eq!(
+        usdc_bank.load().await.config.operational_state,
+        BankOperationalState::ReduceOnly
+    );
+
+    let res = borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_usdc.key, usdc_bank, 100, None)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::BankReduceOnly);
+
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, usdc_bank, 10, sol_bank)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+/// Switchboard pull banks keep an on-chain EMA, recorded on accrue (with the oracle passed) only.
+/// The risk engine reads it without writing to the bank.
+async fn re_switchboard_oracle_ema() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_swb_bank = test_f.get_bank(&BankMint::SolSwbPull);
+
+    test_f.set_time(1_000);
+    test_f
+        .set_pyth_oracle_timestamp(PYTH_USDC_FEED, 1_000)
+        .await;
+
+    test_f
+        .marginfi_group
+        .try_accrue_interest_with_oracle(sol_swb_bank)
+        .await?;
+    let bank = sol_swb_bank.load().await;
+    assert_eq!(bank.oracle_ema_last_update, 1_000);
+    let ema_price: f64 = I80F48::from(bank.oracle_ema_price).to_num();
+    assert!(ema_price > 155. && ema_price < 157.);
+
+    // Fund USDC lender
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank, 1_000, None)
+        .await?;
+
+    test_f.advance_time(600).await;
+    test_f
+        .set_pyth_oracle_timestamp(PYTH_USDC_FEED, 1_600)
+        .await;
+
+    // Borrowing USDC only reads the SOL bank, so its EMA is untouched
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_f_sol = test_f.sol_mint.create_token_account_and_mint_to(10).await;
+    let borrower_token_account_f_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_f_sol.key, sol_swb_bank, 10, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_f_usdc.key, usdc_bank, 100)
+        .await?;
+    assert_eq!(sol_swb_bank.load().await.oracle_ema_last_update, 1_000);
+
+    // Neither does withdrawing from the SOL bank, which is writable
+    borrower_mfi_account_f
+        .try_bank_withdraw(borrower_token_account_f_sol.key, sol_swb_bank, 1, None)
+        .await?;
+    assert_eq!(sol_swb_bank.load().await.oracle_ema_last_update, 1_000);
+
+    // Accruing with the oracle passed does
+    test_f
+        .marginfi_group
+        .try_accrue_interest_with_oracle(sol_swb_bank)
+        .await?;
+    let bank = sol_swb_bank.load().await;
+    assert_eq!(bank.oracle_ema_last_update, 1_600);
+    let ema_price_after: f64 = I80F48::from(bank.oracle_ema_price).to_num();
+    assert!((ema_price_after - ema_price).abs() < 0.000001);
+
+    Ok(())
+}
+
+/// Collateral must count for less than the liabilities it repays (net of fees), or seizing it
+/// would only make the liquidatee's health worse
+async fn set_collateral_weight_below_one(bank: &BankFixture) -> anyhow::Result<()> {
+    bank.update_config(
+        BankConfigOpt {
+            asset_weight_init: Some(I80F48!(0.5).into()),
+            asset_weight_maint: Some(I80F48!(0.9).into()),
+            ..Default::default()
+        },
+        None,
+    )
+    .await
+}
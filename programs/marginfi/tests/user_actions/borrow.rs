@@ -14,6 +14,7 @@ use marginfi::{
 };
 use pretty_assertions::assert_eq;
 use solana_program_test::*;
+use solana_sdk::{signer::Signer, transaction::Transaction};
 use test_case::test_case;
 
 #[test_case(100., 9., BankMint::Usdc, BankMint::Sol)]
@@ -604,3 +605,95 @@ async fn emode_borrows() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn marginfi_account_borrow_unordered_remaining_accounts() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+    let usdc_bank = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank = test_f.get_bank(&BankMint::Sol);
+    let pyusd_bank = test_f.get_bank(&BankMint::PyUSD);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_sol.key, sol_bank, 100, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(1_000)
+        .await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_usdc.key, usdc_bank, 1_000, None)
+        .await?;
+
+    // Oracles ahead of their banks, and an unrelated bank in between
+    let borrower_token_account_sol = test_f.sol_mint.create_empty_token_account().await;
+    let mut borrow_ix = borrower_mfi_account_f
+        .make_bank_borrow_ix(borrower_token_account_sol.key, sol_bank, 1)
+        .await;
+    let mut risk_metas = borrower_mfi_account_f
+        .load_observation_account_metas(vec![sol_bank.key], vec![])
+        .await;
+    borrow_ix
+        .accounts
+        .truncate(borrow_ix.accounts.len() - risk_metas.len());
+    risk_metas.reverse();
+    let extra_metas = borrower_mfi_account_f
+        .load_account_metas_for_banks(vec![pyusd_bank.key])
+        .await;
+    risk_metas.splice(1..1, extra_metas);
+    borrow_ix.accounts.extend(risk_metas);
+
+    let tx = {
+        let ctx = test_f.context.borrow();
+        Transaction::new_signed_with_payer(
+            &[borrow_ix],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        )
+    };
+    let banks_client = test_f.context.borrow().banks_client.clone();
+    let res = banks_client.process_transaction_with_preflight(tx).await;
+    assert!(res.is_ok());
+
+    let marginfi_account = borrower_mfi_account_f.load().await;
+    let balance = marginfi_account
+        .lending_account
+        .get_balance(&sol_bank.key)
+        .unwrap();
+    let liabilities = sol_bank
+        .load()
+        .await
+        .get_liability_amount(balance.liability_shares.into())?;
+    assert_eq!(liabilities, I80F48::from(native!(1, "SOL")));
+
+    // Leaving out a bank the account has a balance in still fails
+    let mut borrow_ix = borrower_mfi_account_f
+        .make_bank_borrow_ix(borrower_token_account_sol.key, sol_bank, 1)
+        .await;
+    let usdc_metas = borrower_mfi_account_f
+        .load_account_metas_for_banks(vec![usdc_bank.key])
+        .await;
+    borrow_ix.accounts.retain(|meta| {
+        !usdc_metas
+            .iter()
+            .any(|usdc_meta| usdc_meta.pubkey == meta.pubkey)
+    });
+    let tx = {
+        let ctx = test_f.context.borrow();
+        Transaction::new_signed_with_payer(
+            &[borrow_ix],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        )
+    };
+    let banks_client = test_f.context.borrow().banks_client.clone();
+    let res = banks_client.process_transaction_with_preflight(tx).await;
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidBankAccount);
+
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+use fixtures::{assert_custom_error, assert_eq_noise, native, prelude::*};
+use marginfi::{
+    prelude::*,
+    state::marginfi_group::{Bank, BankConfigOpt, BankVaultType},
+};
+use pretty_assertions::assert_eq;
+use solana_program_test::*;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, signer::Signer, transaction::Transaction,
+};
+
+fn deleverage_test_settings() -> TestSettings {
+    TestSettings {
+        banks: vec![
+            TestBankSetting {
+                mint: BankMint::Usdc,
+                ..TestBankSetting::default()
+            },
+            TestBankSetting {
+                mint: BankMint::Sol,
+                ..TestBankSetting::default()
+            },
+            TestBankSetting {
+                mint: BankMint::SolEquivalent,
+                ..TestBankSetting::default()
+            },
+            TestBankSetting {
+                mint: BankMint::SolEquivalent1,
+                ..TestBankSetting::default()
+            },
+        ],
+        protocol_fees: false,
+    }
+}
+
+#[tokio::test]
+async fn marginfi_account_deleverage_success() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(deleverage_test_settings())).await;
+
+    // Two banks of the same mint, e.g. with different risk settings
+    let asset_bank_f = test_f.get_bank(&BankMint::SolEquivalent);
+    let liab_bank_f = test_f.get_bank(&BankMint::SolEquivalent1);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(200)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account.key, liab_bank_f, 200, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(100)
+        .await;
+
+    // Borrower deposits 100 SOL_EQ worth $1000 and borrows 90 SOL_EQ worth $900
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account.key, asset_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account.key, liab_bank_f, 90)
+        .await?;
+
+    // Borrower is now underwater ($500 maint assets vs $900 liabs)
+    asset_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Repaying first and withdrawing after isn't possible below the initial requirement
+    let res = borrower_mfi_account_f
+        .try_bank_withdraw(borrower_token_account.key, asset_bank_f, 10, None)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::RiskEngineInitRejected);
+
+    // 1% of the repayment goes to the insurance fund
+    test_f
+        .marginfi_group
+        .try_configure_deleverage(u32::MAX / 100)
+        .await?;
+    let group = test_f.marginfi_group.load().await;
+    assert_eq!(group.deleverage_insurance_fee, u32::MAX / 100);
+
+    let asset_vault = asset_bank_f
+        .get_vault_token_account(BankVaultType::Liquidity)
+        .await;
+    let liab_vault = liab_bank_f
+        .get_vault_token_account(BankVaultType::Liquidity)
+        .await;
+    let asset_vault_balance_pre = asset_vault.balance().await;
+    let liab_vault_balance_pre = liab_vault.balance().await;
+    let wallet_balance_pre = borrower_token_account.balance().await;
+    let destination_token_account = test_f
+        .sol_equivalent_mint
+        .create_empty_token_account()
+        .await;
+
+    // Borrower repays 20 SOL_EQ from their wallet and takes out 20 SOL_EQ of collateral
+    borrower_mfi_account_f
+        .try_deleverage(
+            asset_bank_f,
+            20,
+            liab_bank_f,
+            borrower_token_account.key,
+            destination_token_account.key,
+        )
+        .await?;
+
+    assert_eq!(
+        borrower_token_account.balance().await,
+        wallet_balance_pre - native!(20, "SOL_EQ")
+    );
+    assert_eq!(
+        destination_token_account.balance().await,
+        native!(20, "SOL_EQ")
+    );
+    assert_eq!(
+        asset_vault.balance().await,
+        asset_vault_balance_pre - native!(20, "SOL_EQ")
+    );
+    assert_eq_noise!(
+        liab_vault.balance().await as i64,
+        (liab_vault_balance_pre + native!(19.8, "SOL_EQ", f64)) as i64,
+        1
+    );
+
+    let asset_bank: Bank = asset_bank_f.load().await;
+    let liab_bank: Bank = liab_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_asset = borrower_ma
+        .lending_account
+        .get_balance(&asset_bank_f.key)
+        .unwrap();
+    let borrower_liab = borrower_ma
+        .lending_account
+        .get_balance(&liab_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        asset_bank.get_asset_amount(borrower_asset.asset_shares.into())?,
+        I80F48::from(native!(80, "SOL_EQ")),
+        native!(0.00001, "SOL_EQ", f64)
+    );
+    assert_eq_noise!(
+        liab_bank.get_liability_amount(borrower_liab.liability_shares.into())?,
+        I80F48::from(native!(70.2, "SOL_EQ", f64)),
+        native!(0.00001, "SOL_EQ", f64)
+    );
+
+    let insurance_fund = liab_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund.balance().await as i64,
+        native!(0.2, "SOL_EQ", f64) as i64,
+        1
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_deleverage_unordered_remaining_accounts() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(deleverage_test_settings())).await;
+
+    let asset_bank_f = test_f.get_bank(&BankMint::SolEquivalent);
+    let liab_bank_f = test_f.get_bank(&BankMint::SolEquivalent1);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(200)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account.key, liab_bank_f, 200, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(100)
+        .await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account.key, asset_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account.key, liab_bank_f, 90)
+        .await?;
+
+    asset_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Oracles ahead of their banks, and an unrelated bank in between
+    let mut deleverage_ix = borrower_mfi_account_f
+        .make_deleverage_ix(
+            asset_bank_f,
+            20,
+            liab_bank_f,
+            borrower_token_account.key,
+            borrower_token_account.key,
+        )
+        .await;
+    let mut risk_metas = borrower_mfi_account_f
+        .load_observation_account_metas(vec![], vec![])
+        .await;
+    deleverage_ix
+        .accounts
+        .truncate(deleverage_ix.accounts.len() - risk_metas.len());
+    risk_metas.reverse();
+    let extra_metas = borrower_mfi_account_f
+        .load_account_metas_for_banks(vec![sol_bank_f.key])
+        .await;
+    risk_metas.splice(1..1, extra_metas);
+    deleverage_ix.accounts.extend(risk_metas);
+
+    let tx = {
+        let ctx = test_f.context.borrow();
+        Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(1_400_000),
+                deleverage_ix,
+            ],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        )
+    };
+    let banks_client = test_f.context.borrow().banks_client.clone();
+    let res = banks_client.process_transaction_with_preflight(tx).await;
+    assert!(res.is_ok());
+
+    // No deleverage fee configured, the full 20 SOL_EQ repays debt
+    let liab_bank: Bank = liab_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_liab = borrower_ma
+        .lending_account
+        .get_balance(&liab_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        liab_bank.get_liability_amount(borrower_liab.liability_shares.into())?,
+        I80F48::from(native!(70, "SOL_EQ")),
+        native!(0.00001, "SOL_EQ", f64)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_deleverage_cross_asset() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(deleverage_test_settings())).await;
+
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    // Borrower deposits 100 SOL worth $1000 and borrows 800 USDC
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 800)
+        .await?;
+
+    // Borrower is now underwater ($500 maint assets vs $800 liabs)
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    test_f
+        .marginfi_group
+        .try_configure_deleverage(u32::MAX / 100)
+        .await?;
+
+    let sol_vault = sol_bank_f
+        .get_vault_token_account(BankVaultType::Liquidity)
+        .await;
+    let usdc_vault = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Liquidity)
+        .await;
+    let sol_vault_balance_pre = sol_vault.balance().await;
+    let usdc_vault_balance_pre = usdc_vault.balance().await;
+    let sol_wallet_balance_pre = borrower_token_account_sol.balance().await;
+
+    // Borrower pays 200 USDC, the oracle value of 20 SOL, and takes the 20 SOL out
+    borrower_mfi_account_f
+        .try_deleverage(
+            sol_bank_f,
+            20,
+            usdc_bank_f,
+            borrower_token_account_usdc.key,
+            borrower_token_account_sol.key,
+        )
+        .await?;
+
+    assert_eq_noise!(
+        borrower_token_account_usdc.balance().await as i64,
+        native!(600, "USDC") as i64,
+        1
+    );
+    assert_eq!(
+        borrower_token_account_sol.balance().await,
+        sol_wallet_balance_pre + native!(20, "SOL")
+    );
+    assert_eq!(
+        sol_vault.balance().await,
+        sol_vault_balance_pre - native!(20, "SOL")
+    );
+    // 1% of the payment goes to the insurance fund
+    assert_eq_noise!(
+        usdc_vault.balance().await as i64,
+        (usdc_vault_balance_pre + native!(198, "USDC")) as i64,
+        1
+    );
+    let insurance_fund = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund.balance().await as i64,
+        native!(2, "USDC") as i64,
+        1
+    );
+
+    let sol_bank: Bank = sol_bank_f.load().await;
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_sol = borrower_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .unwrap();
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_bank.get_asset_amount(borrower_sol.asset_shares.into())?,
+        I80F48::from(native!(80, "SOL")),
+        native!(0.00001, "SOL", f64)
+    );
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(602, "USDC")),
+        native!(0.001, "USDC", f64)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use fixtures::{
+    assert_custom_error,
+    test::{BankMint, TestFixture, TestSettings},
+};
+use marginfi::{
+    errors::MarginfiError,
+    state::marginfi_account::{
+        MarginfiAccount, ACCOUNT_EXTENDED, MARGINFI_ACCOUNT_EXTENDED_LEN,
+        MAX_LENDING_ACCOUNT_BALANCES_EXTENDED,
+    },
+};
+use solana_program_test::tokio;
+
+#[tokio::test]
+async fn marginfi_account_extend_success() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    // Liquidity to borrow from
+    let lender_f = test_f.create_marginfi_account().await;
+    let lender_sol_account = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    lender_f
+        .try_bank_deposit(lender_sol_account.key, sol_bank_f, 100, None)
+        .await?;
+
+    let marginfi_account_f = test_f.create_marginfi_account().await;
+    let usdc_account = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    let sol_account = test_f.sol_mint.create_token_account_and_mint_to(0).await;
+    marginfi_account_f
+        .try_bank_deposit(usdc_account.key, usdc_bank_f, 1_000, None)
+        .await?;
+    marginfi_account_f
+        .try_bank_borrow(sol_account.key, sol_bank_f, 1)
+        .await?;
+    let balances_before = marginfi_account_f.load_balances().await;
+
+    marginfi_account_f.try_extend().await?;
+
+    let account = test_f
+        .context
+        .borrow_mut()
+        .banks_client
+        .get_account(marginfi_account_f.key)
+        .await?
+        .unwrap();
+    assert_eq!(account.data.len(), MARGINFI_ACCOUNT_EXTENDED_LEN);
+    let marginfi_account: MarginfiAccount = marginfi_account_f.load().await;
+    assert!(marginfi_account.get_flag(ACCOUNT_EXTENDED));
+    assert!(marginfi_account
+        .lending_account
+        .balances
+        .iter()
+        .all(|balance| !balance.is_active()));
+
+    // Same balances, in the same order, just in the extension
+    let balances = marginfi_account_f.load_balances().await;
+    assert_eq!(balances.len(), MAX_LENDING_ACCOUNT_BALANCES_EXTENDED);
+    assert!(balances[..balances_before.len()] == balances_before[..]);
+    assert!(balances[balances_before.len()..]
+        .iter()
+        .all(|balance| !balance.is_active()));
+
+    let res = marginfi_account_f.try_extend().await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::AccountAlreadyExtended);
+
+    // The extended account works as usual, including health checks
+    marginfi_account_f
+        .try_bank_deposit(usdc_account.key, usdc_bank_f, 1_000, None)
+        .await?;
+    marginfi_account_f
+        .try_bank_borrow(sol_account.key, sol_bank_f, 1)
+        .await?;
+    marginfi_account_f
+        .try_bank_withdraw(usdc_account.key, usdc_bank_f, 500, None)
+        .await?;
+    marginfi_account_f
+        .try_bank_repay(sol_account.key, sol_bank_f, 2, Some(true))
+        .await?;
+
+    let balances = marginfi_account_f.load_balances().await;
+    let active_bank_pks: Vec<_> = balances
+        .iter()
+        .filter(|balance| balance.is_active())
+        .map(|balance| balance.bank_pk)
+        .collect();
+    assert_eq!(active_bank_pks, vec![usdc_bank_f.key]);
+
+    Ok(())
+}
@@ -0,0 +1,370 @@
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+use fixtures::{assert_custom_error, assert_eq_noise, native, prelude::*};
+use marginfi::{
+    prelude::*,
+    state::{
+        bank_cache::u32_to_apr,
+        marginfi_group::{Bank, BankConfigOpt, InterestRateConfigOpt},
+    },
+};
+use pretty_assertions::assert_eq;
+use solana_program_test::*;
+
+const DAY: u32 = 24 * 60 * 60;
+
+#[tokio::test]
+async fn marginfi_account_fixed_term_borrow_roll_repay() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    // Banks don't offer fixed terms by default
+    let res = borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 100, 7 * DAY)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::FixedTermBorrowingDisabled);
+
+    // Up to 30 days, at the variable rate + 2% APR
+    let premium = u32::MAX / 500;
+    usdc_bank_f
+        .update_config(
+            BankConfigOpt {
+                fixed_term_premium: Some(premium),
+                fixed_term_max_duration: Some(30 * DAY),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    let res = borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 100, 31 * DAY)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::InvalidFixedTermDuration);
+
+    let start = test_f.get_clock().await.unix_timestamp;
+    borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 100, 7 * DAY)
+        .await?;
+
+    assert_eq!(
+        borrower_token_account_usdc.balance().await,
+        native!(100, "USDC")
+    );
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let fixed_term = borrower_ma
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert!(borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .is_none());
+    // Priced at the utilization after the borrow, which counts the fixed-term debt
+    let locked_rate: f64 =
+        (u32_to_apr(usdc_bank.cache.borrowing_rate) + u32_to_apr(premium)).to_num();
+    assert_eq_noise!(
+        fixed_term.fixed_term_rate_bps as f64,
+        locked_rate * 10_000.,
+        1.
+    );
+    assert_eq!(
+        fixed_term.fixed_term_maturity as i64,
+        start + 7 * DAY as i64
+    );
+    assert_eq!(fixed_term.fixed_term_principal, native!(100, "USDC"));
+    assert_eq!(I80F48::from(fixed_term.liability_shares), I80F48::ZERO);
+    assert_eq!(
+        I80F48::from(usdc_bank.fixed_term_liabilities),
+        I80F48::from(native!(100, "USDC"))
+    );
+    assert_eq!(usdc_bank.borrowing_position_count, 1);
+
+    // One fixed-term borrow per bank
+    let res = borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 10, 7 * DAY)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::FixedTermPositionExists);
+
+    // Can't roll early
+    let res = borrower_mfi_account_f
+        .try_roll_fixed_term(usdc_bank_f, 7 * DAY)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::FixedTermPositionNotMatured);
+
+    // Past maturity, the account can't take on more risk until the borrow is rolled or repaid
+    test_f.advance_time(8 * DAY as i64).await;
+    let now = test_f.get_clock().await.unix_timestamp;
+    test_f.set_pyth_oracle_timestamp(PYTH_USDC_FEED, now).await;
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, now).await;
+
+    let res = borrower_mfi_account_f
+        .try_bank_withdraw(borrower_token_account_sol.key, sol_bank_f, 1, None)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::FixedTermPositionMatured);
+
+    borrower_mfi_account_f
+        .try_roll_fixed_term(usdc_bank_f, 7 * DAY)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_withdraw(borrower_token_account_sol.key, sol_bank_f, 1, None)
+        .await?;
+
+    // 8 days of interest at the locked rate rolled into the principal
+    let locked_rate: f64 = fixed_term.get_fixed_term_rate().to_num();
+    let owed = 100. * (1. + locked_rate * 8. / 365.);
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let fixed_term = borrower_ma
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq!(fixed_term.fixed_term_maturity as i64, now + 7 * DAY as i64);
+    assert_eq_noise!(
+        fixed_term.fixed_term_principal as i64,
+        native!(owed, "USDC", f64) as i64,
+        native!(0.0001, "USDC", f64) as i64
+    );
+
+    // Repay in full, the interest went to depositors
+    let funding_account = test_f.usdc_mint.create_token_account_and_mint_to(200).await;
+    borrower_mfi_account_f
+        .try_repay_fixed_term(funding_account.key, usdc_bank_f, 0, Some(true))
+        .await?;
+    assert_eq_noise!(
+        funding_account.balance().await as i64,
+        native!(200. - owed, "USDC", f64) as i64,
+        native!(0.0001, "USDC", f64) as i64
+    );
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    assert!(borrower_ma
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .is_none());
+    assert_eq!(usdc_bank.borrowing_position_count, 0);
+    assert_eq_noise!(
+        I80F48::from(usdc_bank.fixed_term_liabilities),
+        I80F48::ZERO,
+        I80F48::ONE
+    );
+    assert_eq_noise!(
+        usdc_bank.get_asset_amount(usdc_bank.total_asset_shares.into())?,
+        I80F48::from(native!(1_900. + owed, "USDC", f64)),
+        I80F48::from(native!(0.0001, "USDC", f64))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_fixed_term_borrow_origination_fee() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    usdc_bank_f
+        .update_config(
+            BankConfigOpt {
+                fixed_term_max_duration: Some(30 * DAY),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+    test_f
+        .marginfi_group
+        .try_lending_pool_configure_bank_interest_only(
+            usdc_bank_f,
+            InterestRateConfigOpt {
+                protocol_origination_fee: Some(I80F48!(0.01).into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let fees_pre = I80F48::from(usdc_bank.collected_group_fees_outstanding)
+        + I80F48::from(usdc_bank.collected_program_fees_outstanding);
+
+    borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 100, 7 * DAY)
+        .await?;
+
+    // Like a variable-rate borrow, the fee is owed on top of the amount received
+    assert_eq!(
+        borrower_token_account_usdc.balance().await,
+        native!(100, "USDC")
+    );
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let fixed_term = borrower_ma
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq!(fixed_term.fixed_term_principal, native!(101, "USDC"));
+
+    // And split between program and group fees
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    assert_eq!(
+        I80F48::from(usdc_bank.fixed_term_liabilities),
+        I80F48::from(native!(101, "USDC"))
+    );
+    let fees_post = I80F48::from(usdc_bank.collected_group_fees_outstanding)
+        + I80F48::from(usdc_bank.collected_program_fees_outstanding);
+    assert_eq_noise!(
+        fees_post - fees_pre,
+        I80F48::from(native!(1, "USDC")),
+        I80F48::ONE
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_fixed_term_matured_liquidation() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    usdc_bank_f
+        .update_config(
+            BankConfigOpt {
+                fixed_term_max_duration: Some(30 * DAY),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.9).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    // Borrower has $900 of maintenance collateral against a $100 fixed-term borrow
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_borrow_fixed_term(borrower_token_account_usdc.key, usdc_bank_f, 100, 7 * DAY)
+        .await?;
+
+    let liquidator_mfi_account_f = test_f.create_marginfi_account().await;
+    let liquidator_token_account_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(200).await;
+    liquidator_mfi_account_f
+        .try_bank_deposit(liquidator_token_account_usdc.key, usdc_bank_f, 200, None)
+        .await?;
+
+    // Healthy and not yet due
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::HealthyAccount);
+
+    test_f.advance_time(8 * DAY as i64).await;
+    let now = test_f.get_clock().await.unix_timestamp;
+    test_f.set_pyth_oracle_timestamp(PYTH_USDC_FEED, now).await;
+    test_f.set_pyth_oracle_timestamp(PYTH_SOL_FEED, now).await;
+
+    // Past maturity the borrow is due, so liquidators can repay it even though the account is
+    // still healthy
+    let principal_pre = borrower_mfi_account_f
+        .load()
+        .await
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .unwrap()
+        .fixed_term_principal;
+    liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await?;
+
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let fixed_term = borrower_ma
+        .lending_account
+        .get_fixed_term_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert!(fixed_term.fixed_term_principal < principal_pre);
+    assert!(fixed_term.fixed_term_principal > 0);
+    let sol_bank: Bank = sol_bank_f.load().await;
+    let borrower_sol = borrower_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_bank.get_asset_amount(borrower_sol.asset_shares.into())?,
+        I80F48::from(native!(99, "SOL")),
+        I80F48::ONE
+    );
+
+    // The liquidation can't exhaust the debt
+    let res = liquidator_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 20, usdc_bank_f)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::OperationRepayOnly);
+
+    Ok(())
+}
@@ -13,6 +13,9 @@ use marginfi::{
 };
 use pretty_assertions::assert_eq;
 use solana_program_test::*;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, signer::Signer, transaction::Transaction,
+};
 use test_case::test_case;
 
 #[test_case(100., 9.9, 1., BankMint::Usdc, BankMint::Sol)]
@@ -254,6 +257,760 @@ async fn marginfi_account_liquidation_success(
     Ok(())
 }
 
+#[tokio::test]
+async fn marginfi_account_liquidation_custom_bank_fees() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL worth of $1000
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    // Borrower borrows $999
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    // Bring down the borrower's health, and have the SOL bank pay 10% to liquidators and 1% to
+    // insurance. The fees always come from the bank whose collateral is seized.
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                liquidation_liquidator_fee: Some(u32::MAX / 10),
+                liquidation_insurance_fee: Some(u32::MAX / 100),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await?;
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let depositor_ma = lender_mfi_account_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+
+    // Depositor pays $9 (1 SOL at $10 with a 10% discount) for 1 SOL
+    let depositor_usdc = depositor_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_asset_amount(depositor_usdc.asset_shares.into())?,
+        I80F48::from(native!(1991, "USDC")),
+        native!(0.00001, "USDC", f64)
+    );
+
+    // Borrower's debt is reduced by $8.90, after the 1% insurance fee
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(990.1, "USDC", f64)),
+        native!(0.00001, "USDC", f64)
+    );
+
+    let insurance_fund_usdc = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund_usdc.balance().await as i64,
+        native!(0.1, "USDC", f64) as i64,
+        1
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_ramped_liquidator_fee() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL worth of $1000
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    // Borrower borrows $999
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    // Bring the borrower ~50% underwater ($500 maint assets vs $999 liabs). The SOL bank ramps
+    // the liquidator fee up to 20% for accounts 10% or more underwater.
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                liquidation_max_liquidator_fee: Some(u32::MAX / 5),
+                liquidation_fee_ramp: Some(u32::MAX / 10),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await?;
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let depositor_ma = lender_mfi_account_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+
+    // Depositor pays $8 (1 SOL at $10 with the capped 20% discount) for 1 SOL
+    let depositor_usdc = depositor_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_asset_amount(depositor_usdc.asset_shares.into())?,
+        I80F48::from(native!(1992, "USDC")),
+        native!(0.00001, "USDC", f64)
+    );
+
+    // Borrower's debt is reduced by $7.75, after the default 2.5% insurance fee
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(991.25, "USDC", f64)),
+        native!(0.00001, "USDC", f64)
+    );
+
+    let insurance_fund_usdc = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund_usdc.balance().await as i64,
+        native!(0.25, "USDC", f64) as i64,
+        1
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_close_factor() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL worth of $1000
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    // Borrower borrows $999
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    // Borrower is now ~50% underwater ($500 maint assets vs $999 liabs)
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.5).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // At most 50% of a liability can be repaid at once, unless 60% or more underwater
+    test_f
+        .marginfi_group
+        .try_configure_liquidation(u32::MAX / 2, u32::MAX / 5 * 3)
+        .await?;
+    let group = test_f.marginfi_group.load().await;
+    assert_eq!(group.liquidation_close_factor, u32::MAX / 2);
+    assert_eq!(group.liquidation_full_close_threshold, u32::MAX / 5 * 3);
+
+    // 60 SOL repays $570 of the $999 debt, more than half
+    let res = lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 60, usdc_bank_f)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(
+        res.unwrap_err(),
+        MarginfiError::LiquidationCloseFactorExceeded
+    );
+
+    // The USDC bank allows 75% of its liabilities to be repaid at once
+    usdc_bank_f
+        .update_config(
+            BankConfigOpt {
+                liquidation_close_factor: Some(u32::MAX / 4 * 3),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+    lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 60, usdc_bank_f)
+        .await?;
+
+    // Back to the group's 50%. $429 of debt remains against $200 of maint assets (~53% underwater)
+    usdc_bank_f
+        .update_config(
+            BankConfigOpt {
+                liquidation_close_factor: Some(0),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // 30 SOL repays $285, more than half
+    let res = lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 30, usdc_bank_f)
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(
+        res.unwrap_err(),
+        MarginfiError::LiquidationCloseFactorExceeded
+    );
+
+    // Once past the 50% deep underwater threshold, the close factor no longer applies
+    test_f
+        .marginfi_group
+        .try_configure_liquidation(u32::MAX / 2, u32::MAX / 2)
+        .await?;
+    lender_mfi_account_f
+        .try_liquidate(&borrower_mfi_account_f, sol_bank_f, 30, usdc_bank_f)
+        .await?;
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(144, "USDC")),
+        native!(0.00001, "USDC", f64)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_batch() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+    let sol_eq_bank_f = test_f.get_bank(&BankMint::SolEquivalent);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_sol_eq = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(100)
+        .await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL and 100 SOL-equivalent, worth $2000
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol_eq.key, sol_eq_bank_f, 100, None)
+        .await?;
+
+    // Borrower borrows $999
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    // Bring down the borrower's health: $500 maint assets vs $999 liabs
+    for bank_f in [sol_bank_f, sol_eq_bank_f] {
+        bank_f
+            .update_config(
+                BankConfigOpt {
+                    asset_weight_init: Some(I80F48!(0.25).into()),
+                    asset_weight_maint: Some(I80F48!(0.25).into()),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+    }
+
+    // Each leg repays $9.50 on its own, under a 1% close factor ($9.99), but not both together
+    test_f
+        .marginfi_group
+        .try_configure_liquidation(u32::MAX / 100, 0)
+        .await?;
+    let res = lender_mfi_account_f
+        .try_liquidate_batch(
+            &borrower_mfi_account_f,
+            vec![
+                (sol_bank_f, 1, usdc_bank_f),
+                (sol_eq_bank_f, 1, usdc_bank_f),
+            ],
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(
+        res.unwrap_err(),
+        MarginfiError::LiquidationCloseFactorExceeded
+    );
+
+    test_f
+        .marginfi_group
+        .try_configure_liquidation(0, 0)
+        .await?;
+    lender_mfi_account_f
+        .try_liquidate_batch(
+            &borrower_mfi_account_f,
+            vec![
+                (sol_bank_f, 1, usdc_bank_f),
+                (sol_eq_bank_f, 1, usdc_bank_f),
+            ],
+        )
+        .await?;
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let sol_bank: Bank = sol_bank_f.load().await;
+    let sol_eq_bank: Bank = sol_eq_bank_f.load().await;
+    let depositor_ma = lender_mfi_account_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+
+    // Depositor pays $9.75 per leg, and receives 1 SOL and 1 SOL-equivalent
+    let depositor_usdc = depositor_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_asset_amount(depositor_usdc.asset_shares.into())?,
+        I80F48::from(native!(1980.5, "USDC", f64)),
+        native!(0.00001, "USDC", f64)
+    );
+    let depositor_sol = depositor_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_bank.get_asset_amount(depositor_sol.asset_shares.into())?,
+        I80F48::from(native!(1, "SOL")),
+        native!(0.00001, "SOL", f64)
+    );
+    let depositor_sol_eq = depositor_ma
+        .lending_account
+        .get_balance(&sol_eq_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_eq_bank.get_asset_amount(depositor_sol_eq.asset_shares.into())?,
+        I80F48::from(native!(1, "SOL_EQ")),
+        native!(0.00001, "SOL_EQ", f64)
+    );
+
+    // Borrower's debt is reduced by $9.50 per leg
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(980, "USDC")),
+        native!(0.00001, "USDC", f64)
+    );
+
+    let insurance_fund_usdc = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund_usdc.balance().await as i64,
+        native!(0.5, "USDC", f64) as i64,
+        1
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_unordered_remaining_accounts() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+    let pyusd_bank_f = test_f.get_bank(&BankMint::PyUSD);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.25).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Both accounts' risk accounts mixed together, oracles ahead of their banks, and an unrelated
+    // bank in between
+    let mut liquidate_ix = lender_mfi_account_f
+        .make_liquidate_ix(&borrower_mfi_account_f, sol_bank_f, 1, usdc_bank_f)
+        .await;
+    let num_risk_metas = lender_mfi_account_f
+        .load_observation_account_metas(vec![sol_bank_f.key, usdc_bank_f.key], vec![])
+        .await
+        .len()
+        + borrower_mfi_account_f
+            .load_observation_account_metas(vec![], vec![])
+            .await
+            .len();
+    let mut risk_metas = liquidate_ix
+        .accounts
+        .split_off(liquidate_ix.accounts.len() - num_risk_metas);
+    risk_metas.reverse();
+    let extra_metas = lender_mfi_account_f
+        .load_account_metas_for_banks(vec![pyusd_bank_f.key])
+        .await;
+    risk_metas.splice(1..1, extra_metas);
+    liquidate_ix.accounts.extend(risk_metas);
+
+    let tx = {
+        let ctx = test_f.context.borrow();
+        Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(1_400_000),
+                liquidate_ix,
+            ],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        )
+    };
+    let banks_client = test_f.context.borrow().banks_client.clone();
+    let res = banks_client.process_transaction_with_preflight(tx).await;
+    assert!(res.is_ok());
+
+    let sol_bank: Bank = sol_bank_f.load().await;
+    let depositor_ma = lender_mfi_account_f.load().await;
+    let depositor_sol = depositor_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_bank.get_asset_amount(depositor_sol.asset_shares.into())?,
+        I80F48::from(native!(1, "SOL")),
+        native!(0.00001, "SOL", f64)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_batch_unordered_remaining_accounts() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+    let sol_eq_bank_f = test_f.get_bank(&BankMint::SolEquivalent);
+    let pyusd_bank_f = test_f.get_bank(&BankMint::PyUSD);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_sol_eq = test_f
+        .sol_equivalent_mint
+        .create_token_account_and_mint_to(100)
+        .await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol_eq.key, sol_eq_bank_f, 100, None)
+        .await?;
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    for bank_f in [sol_bank_f, sol_eq_bank_f] {
+        bank_f
+            .update_config(
+                BankConfigOpt {
+                    asset_weight_init: Some(I80F48!(0.25).into()),
+                    asset_weight_maint: Some(I80F48!(0.25).into()),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+    }
+
+    // The legs' vaults stay in front, everything after them in any order
+    let mut liquidate_ix = lender_mfi_account_f
+        .make_liquidate_batch_ix(
+            &borrower_mfi_account_f,
+            vec![
+                (sol_bank_f, 1, usdc_bank_f),
+                (sol_eq_bank_f, 1, usdc_bank_f),
+            ],
+        )
+        .await;
+    let num_risk_metas = lender_mfi_account_f
+        .load_observation_account_metas(
+            vec![sol_bank_f.key, sol_eq_bank_f.key, usdc_bank_f.key],
+            vec![],
+        )
+        .await
+        .len()
+        + borrower_mfi_account_f
+            .load_observation_account_metas(vec![], vec![])
+            .await
+            .len();
+    let mut risk_metas = liquidate_ix
+        .accounts
+        .split_off(liquidate_ix.accounts.len() - num_risk_metas);
+    risk_metas.reverse();
+    let extra_metas = lender_mfi_account_f
+        .load_account_metas_for_banks(vec![pyusd_bank_f.key])
+        .await;
+    risk_metas.splice(1..1, extra_metas);
+    liquidate_ix.accounts.extend(risk_metas);
+
+    let tx = {
+        let ctx = test_f.context.borrow();
+        Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(1_400_000),
+                liquidate_ix,
+            ],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        )
+    };
+    let banks_client = test_f.context.borrow().banks_client.clone();
+    let res = banks_client.process_transaction_with_preflight(tx).await;
+    assert!(res.is_ok());
+
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(980, "USDC")),
+        native!(0.00001, "USDC", f64)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marginfi_account_liquidation_direct() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    let borrower_token_account_usdc = test_f.usdc_mint.create_empty_token_account().await;
+
+    // Borrower deposits 100 SOL worth of $1000
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 100, None)
+        .await?;
+
+    // Borrower borrows $999
+    borrower_mfi_account_f
+        .try_bank_borrow(borrower_token_account_usdc.key, usdc_bank_f, 999)
+        .await?;
+
+    // The liquidator has no marginfi account, only token accounts
+    let liquidator_token_account_usdc =
+        test_f.usdc_mint.create_token_account_and_mint_to(100).await;
+    let liquidator_token_account_sol = test_f.sol_mint.create_empty_token_account().await;
+
+    let res = borrower_mfi_account_f
+        .try_liquidate_direct(
+            sol_bank_f,
+            1,
+            usdc_bank_f,
+            liquidator_token_account_usdc.key,
+            liquidator_token_account_sol.key,
+        )
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::HealthyAccount);
+
+    // Synthetically bring down the borrower account health by reducing the asset weights of the SOL bank
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.25).into()),
+                asset_weight_maint: Some(I80F48!(0.25).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    borrower_mfi_account_f
+        .try_liquidate_direct(
+            sol_bank_f,
+            1,
+            usdc_bank_f,
+            liquidator_token_account_usdc.key,
+            liquidator_token_account_sol.key,
+        )
+        .await?;
+
+    // Liquidator pays 1 SOL * $10 with a 2.5% discount and receives the SOL directly
+    assert_eq!(
+        liquidator_token_account_usdc.balance().await,
+        native!(90.25, "USDC", f64)
+    );
+    assert_eq!(
+        liquidator_token_account_sol.balance().await,
+        native!(1, "SOL")
+    );
+
+    // The borrower loses 1 SOL and is credited $9.75 minus the 2.5% insurance fee
+    let sol_bank: Bank = sol_bank_f.load().await;
+    let usdc_bank: Bank = usdc_bank_f.load().await;
+    let borrower_ma = borrower_mfi_account_f.load().await;
+    let borrower_sol = borrower_ma
+        .lending_account
+        .get_balance(&sol_bank_f.key)
+        .unwrap();
+    let borrower_usdc = borrower_ma
+        .lending_account
+        .get_balance(&usdc_bank_f.key)
+        .unwrap();
+    assert_eq_noise!(
+        sol_bank.get_asset_amount(borrower_sol.asset_shares.into())?,
+        I80F48::from(native!(99, "SOL")),
+        native!(0.00001, "SOL", f64)
+    );
+    assert_eq_noise!(
+        usdc_bank.get_liability_amount(borrower_usdc.liability_shares.into())?,
+        I80F48::from(native!(989.5, "USDC", f64)),
+        native!(0.00001, "USDC", f64)
+    );
+
+    let insurance_fund_usdc = usdc_bank_f
+        .get_vault_token_account(BankVaultType::Insurance)
+        .await;
+    assert_eq_noise!(
+        insurance_fund_usdc.balance().await as i64,
+        native!(0.25, "USDC", f64) as i64,
+        1
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn marginfi_account_liquidation_success_many_balances() -> anyhow::Result<()> {
     let test_f = TestFixture::new(Some(TestSettings::many_banks_10())).await;
@@ -2,8 +2,12 @@ mod borrow;
 mod close_account;
 mod close_balance;
 mod create_account;
+mod deleverage;
 mod deposit;
+mod extend_account;
+mod fixed_term;
 mod flash_loan;
 mod liquidate;
 mod repay;
+mod simulate_health;
 mod withdraw;
@@ -0,0 +1,87 @@
+use fixed::types::I80F48;
+use fixed_macro::types::I80F48;
+use fixtures::{assert_custom_error, assert_eq_noise, native, prelude::*};
+use marginfi::{prelude::*, state::marginfi_group::BankConfigOpt};
+use pretty_assertions::assert_eq;
+use solana_program_test::*;
+
+#[tokio::test]
+async fn marginfi_account_simulate_health() -> anyhow::Result<()> {
+    let test_f = TestFixture::new(Some(TestSettings::all_banks_payer_not_admin())).await;
+
+    let usdc_bank_f = test_f.get_bank(&BankMint::Usdc);
+    let sol_bank_f = test_f.get_bank(&BankMint::Sol);
+
+    let lender_mfi_account_f = test_f.create_marginfi_account().await;
+    let lender_token_account_usdc = test_f
+        .usdc_mint
+        .create_token_account_and_mint_to(2_000)
+        .await;
+    lender_mfi_account_f
+        .try_bank_deposit(lender_token_account_usdc.key, usdc_bank_f, 2_000, None)
+        .await?;
+
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                asset_weight_init: Some(I80F48!(0.5).into()),
+                asset_weight_maint: Some(I80F48!(0.75).into()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    // Borrower deposits 10 SOL worth $100
+    let borrower_mfi_account_f = test_f.create_marginfi_account().await;
+    let borrower_token_account_sol = test_f.sol_mint.create_token_account_and_mint_to(100).await;
+    borrower_mfi_account_f
+        .try_bank_deposit(borrower_token_account_sol.key, sol_bank_f, 10, None)
+        .await?;
+    let account_before = borrower_mfi_account_f.load().await;
+
+    // Borrowing $60 would fail the initial requirement ($50), but not the maintenance one ($75)
+    let health = borrower_mfi_account_f
+        .try_simulate_health(vec![(usdc_bank_f, -60.)])
+        .await?;
+    assert_eq!(
+        health.init_err,
+        u32::from(MarginfiError::RiskEngineInitRejected)
+    );
+    assert_eq_noise!(I80F48::from(health.asset_value_init), I80F48!(50));
+    assert_eq_noise!(I80F48::from(health.liability_value_init), I80F48!(60));
+    assert_eq_noise!(I80F48::from(health.asset_value_maint), I80F48!(75));
+    assert_eq_noise!(I80F48::from(health.liability_value_maint), I80F48!(60));
+    assert_eq_noise!(I80F48::from(health.asset_value_equity), I80F48!(100));
+    assert_eq_noise!(I80F48::from(health.liability_value_equity), I80F48!(60));
+
+    // With another 10 SOL deposited first, it passes
+    let health = borrower_mfi_account_f
+        .try_simulate_health(vec![(sol_bank_f, 10.), (usdc_bank_f, -60.)])
+        .await?;
+    assert_eq!(health.init_err, 0);
+    assert_eq_noise!(I80F48::from(health.asset_value_init), I80F48!(100));
+    assert_eq_noise!(I80F48::from(health.liability_value_init), I80F48!(60));
+
+    // Nothing was written
+    let account_after = borrower_mfi_account_f.load().await;
+    assert!(account_after.lending_account.balances == account_before.lending_account.balances);
+
+    // Deltas still go through the usual balance checks
+    sol_bank_f
+        .update_config(
+            BankConfigOpt {
+                deposit_limit: Some(native!(15, "SOL")),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+    let res = borrower_mfi_account_f
+        .try_simulate_health(vec![(sol_bank_f, 10.)])
+        .await;
+    assert!(res.is_err());
+    assert_custom_error!(res.unwrap_err(), MarginfiError::BankAssetCapacityExceeded);
+
+    Ok(())
+}
@@ -3,6 +3,7 @@ use crate::prelude::{
     get_emissions_authority_address, get_emissions_token_account_address, MintFixture,
     TokenAccountFixture,
 };
+use crate::test::get_oracle_keys_for_bank_config;
 use anchor_lang::{
     prelude::{AccountMeta, Pubkey},
     InstructionData, ToAccountMetas,
@@ -58,19 +59,26 @@ impl BankFixture {
 
     pub async fn get_price(&self) -> f64 {
         let bank = self.load().await;
-        let oracle_key = bank.config.oracle_keys[0];
-        let mut oracle_account = self
-            .ctx
-            .borrow_mut()
-            .banks_client
-            .get_account(oracle_key)
-            .await
-            .unwrap()
-            .unwrap();
-        let ai = (&oracle_key, &mut oracle_account).into_account_info();
-        let oracle_adapter =
-            OraclePriceFeedAdapter::try_from_bank_config(&bank.config, &[ai], &Clock::default())
+        let oracle_keys = get_oracle_keys_for_bank_config(&bank.config);
+        let mut oracle_accounts = vec![];
+        for oracle_key in oracle_keys.iter() {
+            let oracle_account = self
+                .ctx
+                .borrow_mut()
+                .banks_client
+                .get_account(*oracle_key)
+                .await
+                .unwrap()
                 .unwrap();
+            oracle_accounts.push(oracle_account);
+        }
+        let ais = oracle_keys
+            .iter()
+            .zip(oracle_accounts.iter_mut())
+            .map(|(oracle_key, oracle_account)| (oracle_key, oracle_account).into_account_info())
+            .collect::<Vec<_>>();
+        let oracle_adapter =
+            OraclePriceFeedAdapter::try_from_bank(&bank, &ais, &Clock::default()).unwrap();
 
         oracle_adapter
             .get_price_of_type(
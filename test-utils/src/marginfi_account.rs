@@ -2,8 +2,12 @@ use super::{bank::BankFixture, prelude::*};
 use crate::ui_to_native;
 use anchor_lang::{prelude::*, system_program, InstructionData, ToAccountMetas};
 use fixed::types::I80F48;
+use marginfi::instructions::{SimulatedBalanceDelta, SimulatedHealth};
 use marginfi::state::{
-    marginfi_account::MarginfiAccount,
+    marginfi_account::{
+        Balance, LendingAccountExtension, MarginfiAccount, ACCOUNT_EXTENDED,
+        MARGINFI_ACCOUNT_EXTENDED_LEN,
+    },
     marginfi_group::{Bank, BankVaultType},
 };
 use solana_program::{instruction::Instruction, sysvar};
@@ -426,18 +430,15 @@ impl MarginfiAccountFixture {
         Ok(())
     }
 
-    pub async fn try_liquidate<T: Into<f64> + Copy>(
+    pub async fn make_liquidate_ix<T: Into<f64> + Copy>(
         &self,
         liquidatee: &MarginfiAccountFixture,
         asset_bank_fixture: &BankFixture,
         asset_ui_amount: T,
         liab_bank_fixture: &BankFixture,
-    ) -> std::result::Result<(), BanksClientError> {
+    ) -> Instruction {
         let marginfi_account = self.load().await;
 
-        let asset_bank = asset_bank_fixture.load().await;
-        let liab_bank = liab_bank_fixture.load().await;
-
         let mut accounts = marginfi::accounts::LendingAccountLiquidate {
             group: marginfi_account.group,
             asset_bank: asset_bank_fixture.key,
@@ -458,21 +459,6 @@ impl MarginfiAccountFixture {
             accounts.push(AccountMeta::new_readonly(liab_bank_fixture.mint.key, false));
         }
 
-        let oracle_accounts = vec![asset_bank.config, liab_bank.config]
-            .iter()
-            .map(|config| {
-                AccountMeta::new_readonly(
-                    {
-                        get_oracle_id_from_feed_id(config.oracle_keys[0])
-                            .unwrap_or(config.oracle_keys[0])
-                    },
-                    false,
-                )
-            })
-            .collect::<Vec<AccountMeta>>();
-
-        accounts.extend(oracle_accounts);
-
         let mut ix = Instruction {
             program_id: marginfi::id(),
             accounts,
@@ -525,6 +511,222 @@ impl MarginfiAccountFixture {
                 .await,
         );
 
+        ix
+    }
+
+    pub async fn try_liquidate<T: Into<f64> + Copy>(
+        &self,
+        liquidatee: &MarginfiAccountFixture,
+        asset_bank_fixture: &BankFixture,
+        asset_ui_amount: T,
+        liab_bank_fixture: &BankFixture,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = self
+            .make_liquidate_ix(
+                liquidatee,
+                asset_bank_fixture,
+                asset_ui_amount,
+                liab_bank_fixture,
+            )
+            .await;
+
+        let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[compute_budget_ix, ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
+    pub async fn make_liquidate_batch_ix<T: Into<f64> + Copy>(
+        &self,
+        liquidatee: &MarginfiAccountFixture,
+        legs: Vec<(&BankFixture, T, &BankFixture)>,
+    ) -> Instruction {
+        let marginfi_account = self.load().await;
+
+        let mut accounts = marginfi::accounts::LendingAccountLiquidateBatch {
+            group: marginfi_account.group,
+            liquidator_marginfi_account: self.key,
+            authority: self.ctx.borrow().payer.pubkey(),
+            liquidatee_marginfi_account: liquidatee.key,
+        }
+        .to_account_metas(Some(true));
+
+        let mut ix_legs = vec![];
+        let mut leg_banks = vec![];
+        for (asset_bank_fixture, asset_ui_amount, liab_bank_fixture) in legs.iter() {
+            ix_legs.push(marginfi::instructions::LiquidationLeg {
+                asset_bank: asset_bank_fixture.key,
+                liab_bank: liab_bank_fixture.key,
+                asset_amount: ui_to_native!(
+                    (*asset_ui_amount).into(),
+                    asset_bank_fixture.mint.mint.decimals
+                ),
+            });
+            leg_banks.push(asset_bank_fixture.key);
+            leg_banks.push(liab_bank_fixture.key);
+
+            accounts.extend([
+                AccountMeta::new(
+                    liab_bank_fixture
+                        .get_vault_authority(BankVaultType::Liquidity)
+                        .0,
+                    false,
+                ),
+                AccountMeta::new(
+                    liab_bank_fixture.get_vault(BankVaultType::Liquidity).0,
+                    false,
+                ),
+                AccountMeta::new(
+                    liab_bank_fixture.get_vault(BankVaultType::Insurance).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(liab_bank_fixture.get_token_program(), false),
+            ]);
+            if liab_bank_fixture.mint.token_program == anchor_spl::token_2022::ID {
+                accounts.push(AccountMeta::new_readonly(liab_bank_fixture.mint.key, false));
+            }
+        }
+
+        let mut ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingAccountLiquidateBatch { legs: ix_legs }.data(),
+        };
+
+        for (_, _, liab_bank_fixture) in legs.iter() {
+            if liab_bank_fixture.mint.token_program == anchor_spl::token_2022::ID {
+                let payer = self.ctx.borrow().payer.pubkey();
+                let fetch_account_data_fn = |key| async move {
+                    Ok(self
+                        .ctx
+                        .borrow_mut()
+                        .banks_client
+                        .get_account(key)
+                        .await
+                        .map(|acc| acc.map(|a| a.data))?)
+                };
+
+                let _ = spl_transfer_hook_interface::offchain::add_extra_account_metas_for_execute(
+                    &mut ix,
+                    &super::transfer_hook::TEST_HOOK_ID,
+                    &liab_bank_fixture.mint.key,
+                    &liab_bank_fixture.mint.key,
+                    &liab_bank_fixture.mint.key,
+                    &payer,
+                    0,
+                    fetch_account_data_fn,
+                )
+                .await;
+            }
+        }
+
+        ix.accounts.extend_from_slice(
+            &self
+                .load_observation_account_metas(leg_banks.clone(), vec![])
+                .await,
+        );
+
+        // The program reads (and writes) the leg banks out of the liquidatee's accounts
+        let mut liquidatee_metas = liquidatee
+            .load_observation_account_metas(vec![], vec![])
+            .await;
+        for meta in liquidatee_metas.iter_mut() {
+            if leg_banks.contains(&meta.pubkey) {
+                meta.is_writable = true;
+            }
+        }
+        ix.accounts.extend_from_slice(&liquidatee_metas);
+
+        ix
+    }
+
+    /// Each leg is (asset bank, asset ui amount, liability bank)
+    pub async fn try_liquidate_batch<T: Into<f64> + Copy>(
+        &self,
+        liquidatee: &MarginfiAccountFixture,
+        legs: Vec<(&BankFixture, T, &BankFixture)>,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = self.make_liquidate_batch_ix(liquidatee, legs).await;
+
+        let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[compute_budget_ix, ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
+    /// Liquidate `self` without a liquidator marginfi account, the payer settles through
+    /// `liab_token_account` and receives collateral into `asset_token_account`
+    pub async fn try_liquidate_direct<T: Into<f64> + Copy>(
+        &self,
+        asset_bank_fixture: &BankFixture,
+        asset_ui_amount: T,
+        liab_bank_fixture: &BankFixture,
+        liab_token_account: Pubkey,
+        asset_token_account: Pubkey,
+    ) -> std::result::Result<(), BanksClientError> {
+        let marginfi_account = self.load().await;
+
+        let mut accounts = marginfi::accounts::LendingAccountLiquidateDirect {
+            group: marginfi_account.group,
+            asset_bank: asset_bank_fixture.key,
+            liab_bank: liab_bank_fixture.key,
+            liquidatee_marginfi_account: self.key,
+            authority: self.ctx.borrow().payer.pubkey(),
+            liquidator_liab_token_account: liab_token_account,
+            liquidator_asset_token_account: asset_token_account,
+            asset_bank_liquidity_vault_authority: asset_bank_fixture
+                .get_vault_authority(BankVaultType::Liquidity)
+                .0,
+            asset_bank_liquidity_vault: asset_bank_fixture.get_vault(BankVaultType::Liquidity).0,
+            liab_bank_liquidity_vault_authority: liab_bank_fixture
+                .get_vault_authority(BankVaultType::Liquidity)
+                .0,
+            liab_bank_liquidity_vault: liab_bank_fixture.get_vault(BankVaultType::Liquidity).0,
+            liab_bank_insurance_vault: liab_bank_fixture.get_vault(BankVaultType::Insurance).0,
+            asset_token_program: asset_bank_fixture.get_token_program(),
+            liab_token_program: liab_bank_fixture.get_token_program(),
+        }
+        .to_account_metas(Some(true));
+
+        for bank_fixture in [liab_bank_fixture, asset_bank_fixture] {
+            if bank_fixture.mint.token_program == anchor_spl::token_2022::ID {
+                accounts.push(AccountMeta::new_readonly(bank_fixture.mint.key, false));
+            }
+        }
+
+        let mut ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingAccountLiquidateDirect {
+                asset_amount: ui_to_native!(
+                    asset_ui_amount.into(),
+                    asset_bank_fixture.mint.mint.decimals
+                ),
+            }
+            .data(),
+        };
+
+        ix.accounts
+            .extend_from_slice(&self.load_observation_account_metas(vec![], vec![]).await);
+
         let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
 
         let ctx = self.ctx.borrow_mut();
@@ -540,6 +742,236 @@ impl MarginfiAccountFixture {
             .await
     }
 
+    pub async fn make_deleverage_ix<T: Into<f64> + Copy>(
+        &self,
+        asset_bank_fixture: &BankFixture,
+        asset_ui_amount: T,
+        liab_bank_fixture: &BankFixture,
+        funding_account: Pubkey,
+        destination_account: Pubkey,
+    ) -> Instruction {
+        let marginfi_account = self.load().await;
+
+        let mut accounts = marginfi::accounts::LendingAccountDeleverage {
+            group: marginfi_account.group,
+            marginfi_account: self.key,
+            authority: self.ctx.borrow().payer.pubkey(),
+            asset_bank: asset_bank_fixture.key,
+            liab_bank: liab_bank_fixture.key,
+            signer_token_account: funding_account,
+            destination_token_account: destination_account,
+            asset_bank_liquidity_vault_authority: asset_bank_fixture
+                .get_vault_authority(BankVaultType::Liquidity)
+                .0,
+            asset_bank_liquidity_vault: asset_bank_fixture.get_vault(BankVaultType::Liquidity).0,
+            liab_bank_liquidity_vault_authority: liab_bank_fixture
+                .get_vault_authority(BankVaultType::Liquidity)
+                .0,
+            liab_bank_liquidity_vault: liab_bank_fixture.get_vault(BankVaultType::Liquidity).0,
+            liab_bank_insurance_vault: liab_bank_fixture.get_vault(BankVaultType::Insurance).0,
+            asset_token_program: asset_bank_fixture.get_token_program(),
+            liab_token_program: liab_bank_fixture.get_token_program(),
+        }
+        .to_account_metas(Some(true));
+
+        if liab_bank_fixture.mint.token_program == anchor_spl::token_2022::ID {
+            accounts.push(AccountMeta::new_readonly(liab_bank_fixture.mint.key, false));
+        }
+        if asset_bank_fixture.mint.token_program == anchor_spl::token_2022::ID {
+            accounts.push(AccountMeta::new_readonly(
+                asset_bank_fixture.mint.key,
+                false,
+            ));
+        }
+
+        let mut ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingAccountDeleverage {
+                asset_amount: ui_to_native!(
+                    asset_ui_amount.into(),
+                    asset_bank_fixture.mint.mint.decimals
+                ),
+            }
+            .data(),
+        };
+
+        ix.accounts
+            .extend_from_slice(&self.load_observation_account_metas(vec![], vec![]).await);
+
+        ix
+    }
+
+    /// Repay `liab_bank_fixture` from `funding_account` and withdraw `asset_ui_amount` of
+    /// collateral from `asset_bank_fixture` to `destination_account`
+    pub async fn try_deleverage<T: Into<f64> + Copy>(
+        &self,
+        asset_bank_fixture: &BankFixture,
+        asset_ui_amount: T,
+        liab_bank_fixture: &BankFixture,
+        funding_account: Pubkey,
+        destination_account: Pubkey,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = self
+            .make_deleverage_ix(
+                asset_bank_fixture,
+                asset_ui_amount,
+                liab_bank_fixture,
+                funding_account,
+                destination_account,
+            )
+            .await;
+
+        let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[compute_budget_ix, ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
+    pub async fn try_borrow_fixed_term<T: Into<f64>>(
+        &self,
+        destination_account: Pubkey,
+        bank: &BankFixture,
+        ui_amount: T,
+        duration: u32,
+    ) -> std::result::Result<(), BanksClientError> {
+        let marginfi_account = self.load().await;
+
+        let mut accounts = marginfi::accounts::LendingAccountBorrowFixedTerm {
+            group: marginfi_account.group,
+            marginfi_account: self.key,
+            authority: self.ctx.borrow().payer.pubkey(),
+            bank: bank.key,
+            destination_token_account: destination_account,
+            liquidity_vault: bank.get_vault(BankVaultType::Liquidity).0,
+            bank_liquidity_vault_authority: bank.get_vault_authority(BankVaultType::Liquidity).0,
+            token_program: bank.get_token_program(),
+        }
+        .to_account_metas(Some(true));
+        if bank.mint.token_program == anchor_spl::token_2022::ID {
+            accounts.push(AccountMeta::new_readonly(bank.mint.key, false));
+        }
+
+        accounts.extend(
+            self.load_observation_account_metas(vec![bank.key], vec![])
+                .await,
+        );
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingAccountBorrowFixedTerm {
+                amount: ui_to_native!(ui_amount.into(), bank.mint.mint.decimals),
+                duration,
+            }
+            .data(),
+        };
+
+        let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[compute_budget_ix, ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
+    pub async fn try_repay_fixed_term<T: Into<f64>>(
+        &self,
+        funding_account: Pubkey,
+        bank: &BankFixture,
+        ui_amount: T,
+        repay_all: Option<bool>,
+    ) -> std::result::Result<(), BanksClientError> {
+        let marginfi_account = self.load().await;
+
+        let mut accounts = marginfi::accounts::LendingAccountRepayFixedTerm {
+            group: marginfi_account.group,
+            marginfi_account: self.key,
+            authority: self.ctx.borrow().payer.pubkey(),
+            bank: bank.key,
+            signer_token_account: funding_account,
+            liquidity_vault: bank.get_vault(BankVaultType::Liquidity).0,
+            token_program: bank.get_token_program(),
+        }
+        .to_account_metas(Some(true));
+        if bank.mint.token_program == anchor_spl::token_2022::ID {
+            accounts.push(AccountMeta::new_readonly(bank.mint.key, false));
+        }
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingAccountRepayFixedTerm {
+                amount: ui_to_native!(ui_amount.into(), bank.mint.mint.decimals),
+                repay_all,
+            }
+            .data(),
+        };
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
+    pub async fn try_roll_fixed_term(
+        &self,
+        bank: &BankFixture,
+        duration: u32,
+    ) -> std::result::Result<(), BanksClientError> {
+        let marginfi_account = self.load().await;
+
+        let mut ix = Instruction {
+            program_id: marginfi::id(),
+            accounts: marginfi::accounts::LendingAccountRollFixedTerm {
+                group: marginfi_account.group,
+                marginfi_account: self.key,
+                authority: self.ctx.borrow().payer.pubkey(),
+                bank: bank.key,
+            }
+            .to_account_metas(Some(true)),
+            data: marginfi::instruction::LendingAccountRollFixedTerm { duration }.data(),
+        };
+
+        ix.accounts
+            .extend_from_slice(&self.load_observation_account_metas(vec![], vec![]).await);
+
+        let ctx = self.ctx.borrow_mut();
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
     pub async fn try_withdraw_emissions(
         &self,
         bank: &BankFixture,
@@ -652,25 +1084,70 @@ impl MarginfiAccountFixture {
             .await
     }
 
+    /// Health after hypothetically changing the balance in each bank by its ui amount (positive to
+    /// deposit/repay, negative to withdraw/borrow), see `lending_account_simulate_health`
+    pub async fn try_simulate_health(
+        &self,
+        deltas: Vec<(&BankFixture, f64)>,
+    ) -> std::result::Result<SimulatedHealth, BanksClientError> {
+        let remaining_accounts = self
+            .load_observation_account_metas(
+                deltas.iter().map(|(bank, _)| bank.key).collect(),
+                vec![],
+            )
+            .await;
+
+        let mut ix = Instruction {
+            program_id: marginfi::id(),
+            accounts: marginfi::accounts::LendingAccountSimulateHealth {
+                marginfi_account: self.key,
+            }
+            .to_account_metas(Some(true)),
+            data: marginfi::instruction::LendingAccountSimulateHealth {
+                deltas: deltas
+                    .iter()
+                    .map(|(bank, ui_amount)| SimulatedBalanceDelta {
+                        bank: bank.key,
+                        amount: ui_to_native!(ui_amount.abs(), bank.mint.mint.decimals) as i64
+                            * ui_amount.signum() as i64,
+                    })
+                    .collect(),
+            }
+            .data(),
+        };
+        ix.accounts.extend_from_slice(&remaining_accounts);
+
+        let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+        let (tx, banks_client) = {
+            let ctx = self.ctx.borrow();
+            let tx = Transaction::new_signed_with_payer(
+                &[compute_budget_ix, ix],
+                Some(&ctx.payer.pubkey()),
+                &[&ctx.payer],
+                ctx.last_blockhash,
+            );
+            (tx, ctx.banks_client.clone())
+        };
+
+        let simulation = banks_client.simulate_transaction(tx).await?;
+        if let Some(Err(err)) = simulation.result {
+            return Err(BanksClientError::TransactionError(err));
+        }
+        let return_data = simulation
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .expect("simulate health sets return data");
+
+        Ok(SimulatedHealth::try_from_slice(&return_data.data).unwrap())
+    }
+
     pub async fn load_observation_account_metas(
         &self,
         include_banks: Vec<Pubkey>,
         exclude_banks: Vec<Pubkey>,
     ) -> Vec<AccountMeta> {
-        let marginfi_account = self.load().await;
         // Check all active banks in marginfi account balances
-        let mut bank_pks = marginfi_account
-            .lending_account
-            .balances
-            .iter()
-            .filter_map(|balance| {
-                if balance.is_active() {
-                    Some(balance.bank_pk)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+        let mut bank_pks = self.load_active_balance_bank_pks().await;
 
         // Add bank pks in include_banks if they are not already in bank_pks
         // (and exclude the ones contained in exclude_banks)
@@ -681,6 +1158,27 @@ impl MarginfiAccountFixture {
         }
         bank_pks.retain(|bank_pk| !exclude_banks.contains(bank_pk));
 
+        self.load_account_metas_for_banks(bank_pks).await
+    }
+
+    /// Bank of every active balance, in balance order. A bank appears once even if the account has
+    /// both a variable-rate and a fixed-term balance in it.
+    pub async fn load_active_balance_bank_pks(&self) -> Vec<Pubkey> {
+        let mut bank_pks: Vec<Pubkey> = vec![];
+        for balance in self.load_balances().await.iter() {
+            if balance.is_active() && !bank_pks.contains(&balance.bank_pk) {
+                bank_pks.push(balance.bank_pk);
+            }
+        }
+
+        bank_pks
+    }
+
+    /// Bank and oracle metas for each of `bank_pks`, sorted the same way as the account's balances
+    pub async fn load_account_metas_for_banks(
+        &self,
+        mut bank_pks: Vec<Pubkey>,
+    ) -> Vec<AccountMeta> {
         // Sort all bank_pks in descending order
         bank_pks.sort_by(|a, b| b.cmp(a));
 
@@ -696,23 +1194,22 @@ impl MarginfiAccountFixture {
             .iter()
             .zip(bank_pks.iter())
             .flat_map(|(bank, bank_pk)| {
-                let oracle_key = {
-                    let oracle_key = bank.config.oracle_keys[0];
-                    get_oracle_id_from_feed_id(oracle_key).unwrap_or(oracle_key)
-                };
-
-                vec![
-                    AccountMeta {
-                        pubkey: *bank_pk,
-                        is_signer: false,
-                        is_writable: false,
-                    },
-                    AccountMeta {
-                        pubkey: oracle_key,
-                        is_signer: false,
-                        is_writable: false,
-                    },
-                ]
+                let mut metas = vec![AccountMeta {
+                    pubkey: *bank_pk,
+                    is_signer: false,
+                    is_writable: false,
+                }];
+                metas.extend(
+                    get_oracle_keys_for_bank_config(&bank.config)
+                        .into_iter()
+                        .map(|oracle_key| AccountMeta {
+                            pubkey: oracle_key,
+                            is_signer: false,
+                            is_writable: false,
+                        }),
+                );
+
+                metas
             })
             .collect::<Vec<_>>();
         account_metas
@@ -721,11 +1218,8 @@ impl MarginfiAccountFixture {
     pub async fn set_account(&self, mfi_account: &MarginfiAccount) -> anyhow::Result<()> {
         let mut ctx = self.ctx.borrow_mut();
         let mut account = ctx.banks_client.get_account(self.key).await?.unwrap();
-        let mut discriminator = account.data[..8].to_vec();
-        let mut new_data = vec![];
-        new_data.append(&mut discriminator);
-        new_data.append(&mut bytemuck::bytes_of(mfi_account).to_vec());
-        account.data = new_data;
+        // Leaves the extension of an extended account as is
+        account.data[8..Self::get_size()].copy_from_slice(bytemuck::bytes_of(mfi_account));
         ctx.set_account(&self.key, &account.into());
 
         Ok(())
@@ -735,10 +1229,65 @@ impl MarginfiAccountFixture {
         load_and_deserialize::<MarginfiAccount>(self.ctx.clone(), &self.key).await
     }
 
+    /// The account's `LendingAccountExtension`, if it was extended
+    pub async fn load_extension(&self) -> Option<LendingAccountExtension> {
+        let account = self
+            .ctx
+            .borrow_mut()
+            .banks_client
+            .get_account(self.key)
+            .await
+            .unwrap()
+            .unwrap();
+        let marginfi_account: MarginfiAccount =
+            bytemuck::pod_read_unaligned(&account.data[8..Self::get_size()]);
+
+        marginfi_account.get_flag(ACCOUNT_EXTENDED).then(|| {
+            bytemuck::pod_read_unaligned(
+                &account.data[Self::get_size()..MARGINFI_ACCOUNT_EXTENDED_LEN],
+            )
+        })
+    }
+
+    /// All balance slots of the account, from the extension if it was extended
+    pub async fn load_balances(&self) -> Vec<Balance> {
+        match self.load_extension().await {
+            Some(extension) => extension.balances.to_vec(),
+            None => self.load().await.lending_account.balances.to_vec(),
+        }
+    }
+
     pub fn get_size() -> usize {
         mem::size_of::<MarginfiAccount>() + 8
     }
 
+    pub async fn try_extend(&self) -> std::result::Result<(), BanksClientError> {
+        let ctx = self.ctx.borrow_mut();
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts: marginfi::accounts::MarginfiAccountExtend {
+                marginfi_account: self.key,
+                authority: ctx.payer.pubkey(),
+                fee_payer: ctx.payer.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(Some(true)),
+            data: marginfi::instruction::MarginfiAccountExtend {}.data(),
+        };
+
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&ctx.payer.pubkey()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client
+            .process_transaction_with_preflight_and_commitment(tx, CommitmentLevel::Confirmed)
+            .await
+    }
+
     async fn build_transfer_account(
         &self,
         new_marginfi_account: Pubkey,
@@ -1,5 +1,6 @@
 use super::{bank::BankFixture, marginfi_account::MarginfiAccountFixture};
 use crate::prelude::{get_oracle_id_from_feed_id, MintFixture};
+use crate::test::get_oracle_keys_for_bank_config;
 use crate::utils::*;
 use anchor_lang::{prelude::*, solana_program::system_program, InstructionData};
 
@@ -12,12 +13,15 @@ use marginfi::constants::{
     PROTOCOL_FEE_RATE_DEFAULT,
 };
 use marginfi::state::emode::{EmodeEntry, MAX_EMODE_ENTRIES};
+use marginfi::state::exchange_rate::ExchangeRateConfig;
 use marginfi::state::fee_state::FeeState;
 use marginfi::state::marginfi_group::BankConfigCompact;
 use marginfi::state::price::OracleSetup;
 use marginfi::{
     prelude::MarginfiGroup,
-    state::marginfi_group::{BankConfig, BankConfigOpt, BankVaultType, InterestRateConfigOpt},
+    state::marginfi_group::{
+        BankConfig, BankConfigOpt, BankVaultType, InterestRateConfigOpt, OraclePriceBounds,
+    },
 };
 use solana_program::sysvar;
 use solana_program_test::*;
@@ -375,6 +379,157 @@ impl MarginfiGroupFixture {
         }
     }
 
+    /// Switches `bank` to `OracleSetup::Median` over `sources`
+    pub async fn try_lending_pool_configure_bank_oracle_median(
+        &self,
+        bank: &BankFixture,
+        sources: &[Pubkey],
+    ) -> Result<(), BanksClientError> {
+        self.try_lending_pool_configure_bank_oracle_sources(bank, OracleSetup::Median, sources)
+            .await
+    }
+
+    /// Switches `bank` to `OracleSetup::CrossRate` over `token_sol_feed` x `sol_usd_feed`
+    pub async fn try_lending_pool_configure_bank_oracle_cross_rate(
+        &self,
+        bank: &BankFixture,
+        token_sol_feed: Pubkey,
+        sol_usd_feed: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        self.try_lending_pool_configure_bank_oracle_sources(
+            bank,
+            OracleSetup::CrossRate,
+            &[token_sol_feed, sol_usd_feed],
+        )
+        .await
+    }
+
+    async fn try_lending_pool_configure_bank_oracle_sources(
+        &self,
+        bank: &BankFixture,
+        setup: OracleSetup,
+        sources: &[Pubkey],
+    ) -> Result<(), BanksClientError> {
+        let mut accounts = marginfi::accounts::LendingPoolConfigureBankOracle {
+            bank: bank.key,
+            group: self.key,
+            admin: self.ctx.borrow().payer.pubkey(),
+        }
+        .to_account_metas(Some(true));
+        accounts.extend(
+            sources
+                .iter()
+                .map(|source| AccountMeta::new_readonly(*source, false)),
+        );
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingPoolConfigureBankOracle {
+                setup: setup as u8,
+                oracle: sources.first().copied().unwrap_or_default(),
+            }
+            .data(),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey().clone()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Switches `bank` to `OracleSetup::ExchangeRate`, `base_feed` times the rate in `rate_account`
+    pub async fn try_lending_pool_configure_bank_oracle_exchange_rate(
+        &self,
+        bank: &BankFixture,
+        base_feed: Pubkey,
+        rate_account: Pubkey,
+        config: ExchangeRateConfig,
+    ) -> Result<(), BanksClientError> {
+        let mut accounts = marginfi::accounts::LendingPoolConfigureBankOracleExchangeRate {
+            bank: bank.key,
+            group: self.key,
+            admin: self.ctx.borrow().payer.pubkey(),
+        }
+        .to_account_metas(Some(true));
+        accounts.push(AccountMeta::new_readonly(base_feed, false));
+        accounts.push(AccountMeta::new_readonly(rate_account, false));
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingPoolConfigureBankOracleExchangeRate { config }
+                .data(),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey().clone()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets `bank`'s fallback oracle, or removes it with `Pubkey::default()`
+    pub async fn try_lending_pool_configure_bank_oracle_fallback(
+        &self,
+        bank: &BankFixture,
+        fallback: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let bank_config = bank.load().await.config;
+
+        let mut accounts = marginfi::accounts::LendingPoolConfigureBankOracleFallback {
+            bank: bank.key,
+            group: self.key,
+            admin: self.ctx.borrow().payer.pubkey(),
+        }
+        .to_account_metas(Some(true));
+        accounts.push(AccountMeta::new_readonly(
+            get_oracle_id_from_feed_id(bank_config.oracle_keys[0])
+                .unwrap_or(bank_config.oracle_keys[0]),
+            false,
+        ));
+        if fallback != Pubkey::default() {
+            accounts.push(AccountMeta::new_readonly(fallback, false));
+        }
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingPoolConfigureBankOracleFallback { fallback }.data(),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey().clone()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn try_lending_pool_configure_bank(
         &self,
         bank: &BankFixture,
@@ -447,6 +602,7 @@ impl MarginfiGroupFixture {
         deposit_limit: Option<u64>,
         borrow_limit: Option<u64>,
         total_asset_value_init_limit: Option<u64>,
+        oracle_price_bounds: Option<OraclePriceBounds>,
     ) -> Instruction {
         let accounts = marginfi::accounts::LendingPoolConfigureBankLimitsOnly {
             group: self.key,
@@ -462,6 +618,7 @@ impl MarginfiGroupFixture {
                 deposit_limit,
                 borrow_limit,
                 total_asset_value_init_limit,
+                oracle_price_bounds,
             }
             .data(),
         }
@@ -479,6 +636,35 @@ impl MarginfiGroupFixture {
             deposit_limit,
             borrow_limit,
             total_asset_value_init_limit,
+            None,
+        );
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn try_lending_pool_configure_bank_oracle_price_bounds(
+        &self,
+        bank: &BankFixture,
+        oracle_price_bounds: OraclePriceBounds,
+    ) -> Result<(), BanksClientError> {
+        let ix = self.make_lending_pool_configure_bank_limits_only_ix(
+            bank,
+            None,
+            None,
+            None,
+            Some(oracle_price_bounds),
         );
         let tx = Transaction::new_signed_with_payer(
             &[ix],
@@ -589,6 +775,40 @@ impl MarginfiGroupFixture {
         Ok(())
     }
 
+    /// Also passes the bank's oracle, which updates its on-chain oracle EMA if it keeps one
+    pub async fn try_accrue_interest_with_oracle(&self, bank: &BankFixture) -> Result<()> {
+        let bank_config = bank.load().await.config;
+        let ctx = self.ctx.borrow_mut();
+
+        let mut accounts = marginfi::accounts::LendingPoolAccrueBankInterest {
+            group: self.key,
+            bank: bank.key,
+        }
+        .to_account_metas(Some(true));
+        accounts.extend(
+            get_oracle_keys_for_bank_config(&bank_config)
+                .into_iter()
+                .map(|key| AccountMeta::new_readonly(key, false)),
+        );
+
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts,
+            data: marginfi::instruction::LendingPoolAccrueBankInterest {}.data(),
+        };
+
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&ctx.payer.pubkey().clone()),
+            &[&ctx.payer],
+            ctx.last_blockhash,
+        );
+
+        ctx.banks_client.process_transaction(tx).await?;
+
+        Ok(())
+    }
+
     pub async fn try_update(
         &self,
         new_admin: Pubkey,
@@ -632,6 +852,74 @@ impl MarginfiGroupFixture {
         Ok(())
     }
 
+    pub async fn try_configure_liquidation(
+        &self,
+        close_factor: u32,
+        full_close_threshold: u32,
+    ) -> Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts: marginfi::accounts::MarginfiGroupConfigureLiquidation {
+                marginfi_group: self.key,
+                admin: self.ctx.borrow().payer.pubkey(),
+            }
+            .to_account_metas(Some(true)),
+            data: marginfi::instruction::MarginfiGroupConfigureLiquidation {
+                close_factor,
+                full_close_threshold,
+            }
+            .data(),
+        };
+
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey().clone()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn try_configure_deleverage(
+        &self,
+        deleverage_insurance_fee: u32,
+    ) -> Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: marginfi::id(),
+            accounts: marginfi::accounts::MarginfiGroupConfigureDeleverage {
+                marginfi_group: self.key,
+                admin: self.ctx.borrow().payer.pubkey(),
+            }
+            .to_account_metas(Some(true)),
+            data: marginfi::instruction::MarginfiGroupConfigureDeleverage {
+                deleverage_insurance_fee,
+            }
+            .data(),
+        };
+
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.ctx.borrow().payer.pubkey().clone()),
+            &[&self.ctx.borrow().payer],
+            self.ctx.borrow().last_blockhash,
+        );
+
+        self.ctx
+            .borrow_mut()
+            .banks_client
+            .process_transaction(tx)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn try_collect_fees(&self, bank: &BankFixture) -> Result<()> {
         let ctx = self.ctx.borrow_mut();
 
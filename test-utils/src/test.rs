@@ -11,7 +11,7 @@ use solana_sdk::{account::AccountSharedData, entrypoint::ProgramResult};
 use fixed_macro::types::I80F48;
 use lazy_static::lazy_static;
 use marginfi::{
-    constants::{MAX_ORACLE_KEYS, PYTH_PUSH_MIGRATED},
+    constants::{MAX_ORACLE_KEYS, PYTH_PUSH_MIGRATED, SPL_STAKE_POOL_ID},
     state::{
         marginfi_group::{BankConfig, BankOperationalState, InterestRateConfig, RiskTier},
         price::OracleSetup,
@@ -231,6 +231,26 @@ pub fn get_oracle_id_from_feed_id(feed_id: Pubkey) -> Option<Pubkey> {
     }
 }
 
+/// Oracle accounts that follow a bank with this config in remaining accounts
+pub fn get_oracle_keys_for_bank_config(config: &BankConfig) -> Vec<Pubkey> {
+    if config.oracle_setup == OracleSetup::Median {
+        config.oracle_keys[..config.get_median_oracle_sources_len()].to_vec()
+    } else if matches!(
+        config.oracle_setup,
+        OracleSetup::CrossRate | OracleSetup::ExchangeRate
+    ) {
+        config.oracle_keys[..2].to_vec()
+    } else {
+        let oracle_key = config.oracle_keys[0];
+        let mut oracle_keys = vec![get_oracle_id_from_feed_id(oracle_key).unwrap_or(oracle_key)];
+        if config.has_oracle_fallback() {
+            oracle_keys.push(config.oracle_keys[1]);
+        }
+
+        oracle_keys
+    }
+}
+
 pub fn create_oracle_key_array(pyth_oracle: Pubkey) -> [Pubkey; MAX_ORACLE_KEYS] {
     let mut keys = [Pubkey::default(); MAX_ORACLE_KEYS];
     keys[0] = pyth_oracle;
@@ -709,6 +729,43 @@ impl TestFixture {
         self.context.borrow_mut().set_sysvar(&clock);
     }
 
+    pub async fn set_epoch(&self, epoch: u64) {
+        let mut clock: Clock = self
+            .context
+            .borrow_mut()
+            .banks_client
+            .get_sysvar()
+            .await
+            .unwrap();
+        clock.epoch = epoch;
+        self.context.borrow_mut().set_sysvar(&clock);
+    }
+
+    /// Creates (or overwrites) an SPL stake pool account at `address` with just the fields an
+    /// exchange rate is read from set
+    pub fn set_spl_stake_pool(
+        &self,
+        address: Pubkey,
+        pool_mint: Pubkey,
+        total_lamports: u64,
+        pool_token_supply: u64,
+        last_update_epoch: u64,
+    ) {
+        // `StakePool` account type, pool mint at 162, then total lamports, pool token supply, and
+        // last update epoch at 258, 266, and 274
+        let mut data = vec![0u8; 611];
+        data[0] = 1;
+        data[162..194].copy_from_slice(pool_mint.as_ref());
+        data[258..266].copy_from_slice(&total_lamports.to_le_bytes());
+        data[266..274].copy_from_slice(&pool_token_supply.to_le_bytes());
+        data[274..282].copy_from_slice(&last_update_epoch.to_le_bytes());
+
+        let mut account = AccountSharedData::new(1_000_000_000, data.len(), &SPL_STAKE_POOL_ID);
+        account.set_data_from_slice(&data);
+
+        self.context.borrow_mut().set_account(&address, &account);
+    }
+
     pub async fn set_pyth_oracle_timestamp(&self, address: Pubkey, timestamp: i64) {
         let mut ctx = self.context.borrow_mut();
 
@@ -741,6 +798,39 @@ impl TestFixture {
         ctx.set_account(&address, &aso);
     }
 
+    /// Moves the oracle's spot price to `ui_price`, leaving the EMA where it was
+    pub async fn set_pyth_oracle_spot_price(&self, address: Pubkey, ui_price: f64) {
+        let mut ctx = self.context.borrow_mut();
+
+        let mut account = ctx
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let data = account.data.as_mut_slice();
+        let mut price_update = PriceUpdateV2::deserialize(&mut &data[8..]).unwrap();
+
+        let exponent = price_update.price_message.exponent;
+        price_update.price_message.price = (ui_price * 10_f64.powi(-exponent)) as i64;
+
+        let mut data = vec![];
+        let mut account_data = vec![];
+
+        data.extend_from_slice(PriceUpdateV2::DISCRIMINATOR);
+
+        price_update.serialize(&mut account_data).unwrap();
+
+        data.extend_from_slice(&account_data);
+
+        let mut aso = AccountSharedData::from(account);
+
+        aso.set_data_from_slice(data.as_slice());
+
+        ctx.set_account(&address, &aso);
+    }
+
     pub async fn advance_time(&self, seconds: i64) {
         let mut clock: Clock = self
             .context